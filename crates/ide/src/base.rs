@@ -1,4 +1,6 @@
+use crate::Ty;
 use salsa::Durability;
+use smol_str::SmolStr;
 use std::collections::HashMap;
 use std::fmt;
 use std::path::{Component, Path, PathBuf};
@@ -192,6 +194,13 @@ pub struct FlakeInfo {
     pub input_store_paths: HashMap<String, VfsPath>,
 }
 
+/// A resolved entry of Nix search paths, where `<prefix/rest>` resolves to `path/rest`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPath {
+    pub prefix: SmolStr,
+    pub path: VfsPath,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub struct InFile<T> {
     pub file_id: FileId,
@@ -244,6 +253,11 @@ impl FileRange {
     }
 }
 
+/// Get a supertrait object of a database, since trait objects cannot be converted implicitly.
+pub trait Upcast<T: ?Sized> {
+    fn upcast(&self) -> &T;
+}
+
 #[salsa::query_group(SourceDatabaseStorage)]
 pub trait SourceDatabase {
     #[salsa::input]
@@ -259,6 +273,15 @@ pub trait SourceDatabase {
 
     #[salsa::input]
     fn flake_graph(&self) -> Arc<FlakeGraph>;
+
+    /// Search paths for `<name>` lookups, in priority order.
+    #[salsa::input]
+    fn search_paths(&self) -> Arc<Vec<SearchPath>>;
+
+    /// Names in the global scope besides builtins, like ones injected by wrappers,
+    /// with their types.
+    #[salsa::input]
+    fn extra_globals(&self) -> Arc<HashMap<SmolStr, Ty>>;
 }
 
 fn source_root_flake_info(db: &dyn SourceDatabase, sid: SourceRootId) -> Option<Arc<FlakeInfo>> {
@@ -268,6 +291,8 @@ fn source_root_flake_info(db: &dyn SourceDatabase, sid: SourceRootId) -> Option<
 #[derive(Default, Clone, PartialEq, Eq)]
 pub struct Change {
     pub flake_graph: Option<FlakeGraph>,
+    pub search_paths: Option<Vec<SearchPath>>,
+    pub extra_globals: Option<HashMap<SmolStr, Ty>>,
     pub roots: Option<Vec<SourceRoot>>,
     pub file_changes: Vec<(FileId, Arc<str>)>,
 }
@@ -281,6 +306,14 @@ impl Change {
         self.flake_graph = Some(graph);
     }
 
+    pub fn set_search_paths(&mut self, search_paths: Vec<SearchPath>) {
+        self.search_paths = Some(search_paths);
+    }
+
+    pub fn set_extra_globals(&mut self, extra_globals: HashMap<SmolStr, Ty>) {
+        self.extra_globals = Some(extra_globals);
+    }
+
     pub fn set_roots(&mut self, roots: Vec<SourceRoot>) {
         self.roots = Some(roots);
     }
@@ -293,6 +326,12 @@ impl Change {
         if let Some(flake_graph) = self.flake_graph {
             db.set_flake_graph_with_durability(Arc::new(flake_graph), Durability::MEDIUM);
         }
+        if let Some(search_paths) = self.search_paths {
+            db.set_search_paths_with_durability(Arc::new(search_paths), Durability::HIGH);
+        }
+        if let Some(extra_globals) = self.extra_globals {
+            db.set_extra_globals_with_durability(Arc::new(extra_globals), Durability::HIGH);
+        }
         if let Some(roots) = self.roots {
             u32::try_from(roots.len()).expect("Length overflow");
             for (sid, root) in (0u32..).map(SourceRootId).zip(roots) {
@@ -112,7 +112,7 @@ pub(crate) fn liveness_check_query(
                             visited_withs.insert(expr, ());
                         }
                     }
-                    Some(ResolveResult::Builtin(_)) | None => {}
+                    Some(ResolveResult::Builtin(_) | ResolveResult::ExtraGlobal(_)) | None => {}
                 },
                 Expr::LetIn(bindings, body) => {
                     // Pre-mark all let-binding.
@@ -61,6 +61,7 @@ pub trait DefDatabase: SourceDatabase {
 }
 
 fn parse(db: &dyn DefDatabase, file_id: FileId) -> Parse {
+    let _span = tracing::info_span!("parse", ?file_id).entered();
     let content = db.file_content(file_id);
     syntax::parse_file(&content)
 }
@@ -176,6 +177,34 @@ impl Module {
     }
 }
 
+/// Files transitively referenced by any of `files`, including `files` themselves.
+pub(crate) fn reachable_files(db: &dyn DefDatabase, files: &[FileId]) -> HashSet<FileId> {
+    let mut ret = files.iter().copied().collect::<HashSet<_>>();
+    let mut stack = files.to_vec();
+    while let Some(file) = stack.pop() {
+        for &referenced in db.module_references(file).iter() {
+            if ret.insert(referenced) {
+                stack.push(referenced);
+            }
+        }
+    }
+    ret
+}
+
+/// Resolve an imported path to a file in the source root of the importing file.
+/// A directory resolves to its `default.nix`.
+pub(crate) fn file_for_import(
+    db: &dyn DefDatabase,
+    file: FileId,
+    mut vpath: VfsPath,
+) -> Option<FileId> {
+    let source_root = db.source_root(db.file_source_root(file));
+    source_root.file_for_path(&vpath).or_else(|| {
+        vpath.push_segment(DEFAULT_IMPORT_FILE);
+        source_root.file_for_path(&vpath)
+    })
+}
+
 pub type AstPtr = syntax::SyntaxNodePtr;
 
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
@@ -382,6 +411,16 @@ pub enum ModuleKind {
         /// NB. `self` parameter is special and is excluded here.
         param_inputs: HashMap<SmolStr, NameId>,
     },
+    /// Flake definition `flake.nix` whose outputs are built by `mkFlake` of flake-parts.
+    FlakeParts {
+        /// Explicit inputs defined in top-level `inputs`.
+        explicit_inputs: HashMap<SmolStr, NameId>,
+        /// Implicit inputs introduced in the pat-parameter of `outputs`.
+        /// NB. `self` parameter is special and is excluded here.
+        param_inputs: HashMap<SmolStr, NameId>,
+        /// The name of the input providing `lib.mkFlake`, typically `flake-parts`.
+        flake_parts_input: SmolStr,
+    },
 }
 
 fn module_kind(db: &dyn DefDatabase, file_id: FileId) -> Arc<ModuleKind> {
@@ -392,6 +431,7 @@ fn module_kind(db: &dyn DefDatabase, file_id: FileId) -> Arc<ModuleKind> {
         if flake_info.flake_file == file_id {
             let mut explicit_inputs = HashMap::new();
             let mut param_inputs = HashMap::new();
+            let mut flake_parts_input = None;
             if let Expr::Attrset(flake_set) = &module[module.entry_expr()] {
                 for &(name_id, value) in flake_set.statics.iter() {
                     let BindingValue::Expr(value_expr) = value else { continue };
@@ -407,20 +447,33 @@ fn module_kind(db: &dyn DefDatabase, file_id: FileId) -> Arc<ModuleKind> {
                                 .collect();
                         }
                         "outputs" => {
-                            let Expr::Lambda(_, Some(pat), _) = &module[value_expr] else { continue };
-                            param_inputs = pat
-                                .fields
-                                .iter()
-                                .filter_map(|&(name_id, _)| name_id)
-                                .map(|name_id| (module[name_id].text.clone(), name_id))
-                                // Exclude `self`.
-                                .filter(|(name, _)| name != "self")
-                                .collect();
+                            let Expr::Lambda(param, pat, body) = &module[value_expr] else {
+                                continue;
+                            };
+                            if let Some(pat) = pat {
+                                param_inputs = pat
+                                    .fields
+                                    .iter()
+                                    .filter_map(|&(name_id, _)| name_id)
+                                    .map(|name_id| (module[name_id].text.clone(), name_id))
+                                    // Exclude `self`.
+                                    .filter(|(name, _)| name != "self")
+                                    .collect();
+                            }
+                            flake_parts_input =
+                                detect_flake_parts(&module, *param, &param_inputs, *body);
                         }
                         _ => {}
                     }
                 }
             }
+            if let Some(flake_parts_input) = flake_parts_input {
+                return Arc::new(ModuleKind::FlakeParts {
+                    explicit_inputs,
+                    param_inputs,
+                    flake_parts_input,
+                });
+            }
             return Arc::new(ModuleKind::FlakeNix {
                 explicit_inputs,
                 param_inputs,
@@ -430,3 +483,35 @@ fn module_kind(db: &dyn DefDatabase, file_id: FileId) -> Arc<ModuleKind> {
 
     Arc::new(ModuleKind::Unknown)
 }
+
+/// Check if the body of `outputs` is exactly a call of flake-parts,
+/// `inputs.flake-parts.lib.mkFlake { .. } { .. }` or `flake-parts.lib.mkFlake { .. } { .. }`,
+/// and return the name of the input providing `mkFlake`.
+/// This is only a heuristic. Anything more complex is not recognized.
+fn detect_flake_parts(
+    module: &Module,
+    param: Option<NameId>,
+    param_inputs: &HashMap<SmolStr, NameId>,
+    body: ExprId,
+) -> Option<SmolStr> {
+    let Expr::Apply(func, _) = &module[body] else { return None };
+    let Expr::Apply(mk_flake, _) = &module[*func] else { return None };
+    let Expr::Select(set, path, None) = &module[*mk_flake] else { return None };
+    let Expr::Reference(set_name) = &module[*set] else { return None };
+    let path = path
+        .iter()
+        .map(|&attr| match &module[attr] {
+            Expr::Literal(Literal::String(s)) => Some(&**s),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    match &*path {
+        [input, "lib", "mkFlake"]
+            if param.map_or(false, |param| module[param].text == *set_name) =>
+        {
+            Some((*input).into())
+        }
+        ["lib", "mkFlake"] if param_inputs.contains_key(set_name) => Some(set_name.clone()),
+        _ => None,
+    }
+}
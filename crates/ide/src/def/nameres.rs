@@ -1,5 +1,5 @@
 use super::{BindingValue, Bindings, DefDatabase, Expr, ExprId, Module, NameId};
-use crate::{Diagnostic, DiagnosticKind, FileId};
+use crate::{Diagnostic, DiagnosticKind, FileId, Ty};
 use builtin::ALL_BUILTINS;
 use if_chain::if_chain;
 use la_arena::{Arena, ArenaMap, Idx};
@@ -53,7 +53,12 @@ impl ModuleScopes {
     }
 
     /// Resolve a name in the scope of an Expr.
-    fn resolve_name(&self, expr_id: ExprId, name: &SmolStr) -> Option<ResolveResult> {
+    fn resolve_name(
+        &self,
+        expr_id: ExprId,
+        name: &SmolStr,
+        extra_globals: &HashMap<SmolStr, Ty>,
+    ) -> Option<ResolveResult> {
         let scope = self.scope_for_expr(expr_id)?;
         // 1. Local defs.
         if let Some(name) = self
@@ -68,7 +73,11 @@ impl ModuleScopes {
                 return Some(ResolveResult::Builtin(name));
             }
         }
-        // 3. "with" exprs.
+        // 3. Configured global names.
+        if extra_globals.contains_key(name) {
+            return Some(ResolveResult::ExtraGlobal(name.clone()));
+        }
+        // 4. "with" exprs.
         let withs = self
             .ancestors(scope)
             .filter_map(|data| data.as_with())
@@ -187,6 +196,8 @@ pub enum ResolveResult {
     Definition(NameId),
     /// Reference to a builtin value.
     Builtin(&'static str),
+    /// Reference to a global name configured besides builtins.
+    ExtraGlobal(SmolStr),
     /// Attr of one of some `with` expressions, from innermost to outermost.
     /// It must not be empty.
     WithExprs(Vec<ExprId>),
@@ -234,12 +245,15 @@ impl NameResolution {
     pub(crate) fn name_resolution_query(db: &dyn DefDatabase, file_id: FileId) -> Arc<Self> {
         let module = db.module(file_id);
         let scopes = db.scopes(file_id);
+        let extra_globals = db.extra_globals();
         let mut resolve_map = module
             .exprs()
             .filter_map(|(e, kind)| {
                 match kind {
                     // Inherited attrs are also translated into Expr::References.
-                    Expr::Reference(name) => Some((e, scopes.resolve_name(e, name))),
+                    Expr::Reference(name) => {
+                        Some((e, scopes.resolve_name(e, name, &extra_globals)))
+                    }
                     _ => None,
                 }
             })
@@ -289,6 +303,7 @@ impl NameResolution {
     pub fn check_builtin<'db>(&self, expr: ExprId, module: &'db Module) -> Option<&'db str> {
         match self.get(expr)? {
             ResolveResult::Builtin(b) => return Some(b),
+            ResolveResult::ExtraGlobal(_) => {}
             ResolveResult::Definition(name) => {
                 if self.inherited_builtins.contains(name) {
                     return Some(&module[*name].text);
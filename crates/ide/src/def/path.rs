@@ -19,8 +19,9 @@ impl Path {
         let data = path.data(db);
         let file = match &data.anchor {
             &PathAnchor::Relative(file) => file,
+            PathAnchor::Search(name) => return resolve_search_path(db, name, &data),
             // TODO
-            PathAnchor::Absolute | PathAnchor::Home | PathAnchor::Search(_) => return None,
+            PathAnchor::Absolute | PathAnchor::Home => return None,
         };
         let sid = db.file_source_root(file);
         let root = db.source_root(sid);
@@ -41,6 +42,29 @@ impl Path {
     }
 }
 
+/// Resolve `<name/relative>` with the first search path whose prefix matches.
+fn resolve_search_path(db: &dyn DefDatabase, name: &str, data: &PathData) -> Option<VfsPath> {
+    let mut full = VfsPath::root();
+    full.push_segment(name);
+    full.append(&data.relative);
+    let full = &full.as_str()[1..];
+
+    db.search_paths().iter().find_map(|search_path| {
+        let rest = full.strip_prefix(&*search_path.prefix)?;
+        if !rest.is_empty() && !rest.starts_with('/') {
+            return None;
+        }
+        let mut vpath = search_path.path.clone();
+        for _ in 0..data.supers {
+            vpath.pop()?;
+        }
+        if !rest.is_empty() {
+            vpath.append(&VfsPath::new(rest).ok()?);
+        }
+        Some(vpath)
+    })
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PathData {
     anchor: PathAnchor,
@@ -70,6 +94,10 @@ impl PathData {
             relative,
         }
     }
+
+    pub(crate) fn anchor(&self) -> &PathAnchor {
+        &self.anchor
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -83,7 +111,10 @@ pub enum PathAnchor {
 #[cfg(test)]
 mod tests {
     use super::{PathAnchor, PathData};
-    use crate::{FileId, VfsPath};
+    use crate::def::{DefDatabase, Expr, Literal};
+    use crate::tests::TestDB;
+    use crate::{Change, FileId, SearchPath, VfsPath};
+    use nix_interop::nix_path::{parse_nix_path, resolve_search_path_entries};
 
     #[test]
     #[rustfmt::skip]
@@ -113,4 +144,44 @@ mod tests {
         assert_eq!(norm("foo/./bar/../.baz"), path("/foo/.baz"));
         assert_eq!(norm("../../foo"), path("/foo"));
     }
+
+    #[test]
+    fn resolve_search_path() {
+        let entries = parse_nix_path("nixpkgs=/nix/store/abc-nixpkgs:nixpkgs/lib=/lib:foo=/foo");
+        let search_paths = resolve_search_path_entries(&entries, |_, _| None)
+            .into_iter()
+            .map(|(prefix, path)| SearchPath {
+                prefix: prefix.into(),
+                path: VfsPath::try_from(&*path).unwrap(),
+            })
+            .collect::<Vec<_>>();
+
+        let resolve = |src: &str| {
+            let (mut db, file) = TestDB::single_file(src).unwrap();
+            let mut change = Change::default();
+            change.set_search_paths(search_paths.clone());
+            change.apply(&mut db);
+            let module = db.module(file);
+            let path = match module[module.entry_expr()] {
+                Expr::Literal(Literal::Path(path)) => path,
+                _ => panic!("Not a path"),
+            };
+            path.resolve(&db).map(|vpath| vpath.as_str().to_owned())
+        };
+        assert_eq!(
+            resolve("<nixpkgs>").as_deref(),
+            Some("/nix/store/abc-nixpkgs")
+        );
+        assert_eq!(
+            resolve("<nixpkgs/pkgs>").as_deref(),
+            Some("/nix/store/abc-nixpkgs/pkgs")
+        );
+        // The first matching entry wins.
+        assert_eq!(
+            resolve("<nixpkgs/lib>").as_deref(),
+            Some("/nix/store/abc-nixpkgs/lib")
+        );
+        assert_eq!(resolve("<foobar>"), None);
+        assert_eq!(resolve("<bar>"), None);
+    }
 }
@@ -64,6 +64,33 @@ baz/../../bar.nix + ../default.nix
     }
 }
 
+#[test]
+fn reachable_files() {
+    let (db, f) = TestDB::from_fixture(
+        "
+#- /default.nix
+import ./lib
+
+#- /lib/default.nix
+import ./foo.nix
+
+#- /lib/foo.nix
+42
+
+#- /other.nix
+import ./lib/foo.nix
+    ",
+    )
+    .unwrap();
+
+    let got = super::reachable_files(&db, &[f["/default.nix"]]);
+    let expect = ["/default.nix", "/lib/default.nix", "/lib/foo.nix"]
+        .map(|path| f[path])
+        .into_iter()
+        .collect::<HashSet<_>>();
+    assert_eq!(got, expect);
+}
+
 #[test]
 fn source_root_closure() {
     let (db, f) = TestDB::from_fixture(
@@ -150,3 +177,23 @@ fn module_kind() {
         HashSet::from_iter(["nixpkgs".into(), "nix".into()]),
     );
 }
+
+#[test]
+fn module_kind_flake_parts() {
+    let (db, f) = TestDB::from_fixture(
+        r#"
+#- /flake.nix input:parts=/nix/store/eeee
+{
+    inputs.parts.url = "github:hercules-ci/flake-parts";
+    outputs = { self, parts }: parts.lib.mkFlake { inputs = { }; } { };
+}
+        "#,
+    )
+    .unwrap();
+
+    let module_kind = db.module_kind(f["/flake.nix"]);
+    let ModuleKind::FlakeParts { flake_parts_input, .. } = &*module_kind else {
+        panic!("Unexpected module kind: {module_kind:?}");
+    };
+    assert_eq!(flake_parts_input, "parts");
+}
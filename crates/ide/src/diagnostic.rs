@@ -1,5 +1,6 @@
 use crate::FileRange;
 use core::fmt;
+use std::collections::HashSet;
 use syntax::{ErrorKind as SynErrorKind, TextRange};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -7,6 +8,10 @@ pub struct Diagnostic {
     pub range: TextRange,
     pub kind: DiagnosticKind,
     pub notes: Vec<(FileRange, String)>,
+    /// The message specific to this diagnostic, replacing the general one of the kind.
+    pub message: Option<String>,
+    /// Reported as an error regardless of the default severity of the kind.
+    pub elevated: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,12 +37,41 @@ pub enum DiagnosticKind {
     UnusedBinding,
     UnusedWith,
     UnusedRec,
+
+    // Recursion.
+    InheritCycle,
+
+    // Style.
+    TopLevelWith,
+    MissingRec,
+    LongLine,
+    DeepNesting,
+    ShadowedParam,
+    BrokenBinding,
+
+    // Flakes.
+    ImpureBuiltin,
+
+    // Nixpkgs.
+    UnknownLicense,
+
+    MissingFetcherHash,
+    PlaceholderFetcherHash,
+
+    // Modules.
+    MissingOptionField,
+    MissingOptionRecommendedField,
+
+    // Type checking.
+    TypeMismatch,
+    MissingAttr,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Severity {
     Error,
     Warning,
+    Hint,
     IncompleteSyntax,
 }
 
@@ -47,9 +81,16 @@ impl Diagnostic {
             range,
             kind,
             notes: Vec::new(),
+            message: None,
+            elevated: false,
         }
     }
 
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
     pub fn with_note(mut self, frange: FileRange, message: impl Into<String>) -> Self {
         self.notes.push((frange, message.into()));
         self
@@ -71,16 +112,36 @@ impl Diagnostic {
             DiagnosticKind::UnusedBinding => "unused_binding",
             DiagnosticKind::UnusedWith => "unused_with",
             DiagnosticKind::UnusedRec => "unused_rec",
+            DiagnosticKind::InheritCycle => "inherit_cycle",
+            DiagnosticKind::TopLevelWith => "top_level_with",
+            DiagnosticKind::MissingRec => "missing_rec",
+            DiagnosticKind::LongLine => "long_line",
+            DiagnosticKind::DeepNesting => "deep_nesting",
+            DiagnosticKind::ShadowedParam => "shadowed_param",
+            DiagnosticKind::BrokenBinding => "broken_binding",
+            DiagnosticKind::ImpureBuiltin => "impure_builtin",
+            DiagnosticKind::UnknownLicense => "unknown_license",
+            DiagnosticKind::MissingFetcherHash => "missing_fetcher_hash",
+            DiagnosticKind::PlaceholderFetcherHash => "placeholder_fetcher_hash",
+            DiagnosticKind::MissingOptionField => "missing_option_field",
+            DiagnosticKind::MissingOptionRecommendedField => "missing_option_recommended_field",
+            DiagnosticKind::TypeMismatch => "type_mismatch",
+            DiagnosticKind::MissingAttr => "missing_attr",
         }
     }
 
     pub fn severity(&self) -> Severity {
+        if self.elevated {
+            return Severity::Error;
+        }
         match self.kind {
             DiagnosticKind::SyntaxError(_)
             | DiagnosticKind::InvalidDynamic
             | DiagnosticKind::DuplicatedKey
             | DiagnosticKind::DuplicatedParam
-            | DiagnosticKind::UndefinedName => Severity::Error,
+            | DiagnosticKind::UndefinedName
+            | DiagnosticKind::MissingFetcherHash
+            | DiagnosticKind::MissingOptionField => Severity::Error,
             DiagnosticKind::EmptyInherit
             | DiagnosticKind::EmptyLetIn
             | DiagnosticKind::LetAttrset
@@ -89,11 +150,27 @@ impl Diagnostic {
             | DiagnosticKind::MergeRecAttrset
             | DiagnosticKind::UnusedBinding
             | DiagnosticKind::UnusedWith
-            | DiagnosticKind::UnusedRec => Severity::Warning,
+            | DiagnosticKind::UnusedRec
+            | DiagnosticKind::InheritCycle
+            | DiagnosticKind::ImpureBuiltin
+            | DiagnosticKind::TypeMismatch
+            | DiagnosticKind::MissingAttr
+            | DiagnosticKind::UnknownLicense => Severity::Warning,
+            DiagnosticKind::TopLevelWith
+            | DiagnosticKind::MissingRec
+            | DiagnosticKind::LongLine
+            | DiagnosticKind::DeepNesting
+            | DiagnosticKind::ShadowedParam
+            | DiagnosticKind::BrokenBinding
+            | DiagnosticKind::PlaceholderFetcherHash
+            | DiagnosticKind::MissingOptionRecommendedField => Severity::Hint,
         }
     }
 
     pub fn message(&self) -> String {
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
         match self.kind {
             DiagnosticKind::SyntaxError(kind) => return kind.to_string(),
 
@@ -120,6 +197,43 @@ impl Diagnostic {
             DiagnosticKind::UnusedBinding => "Unused binding",
             DiagnosticKind::UnusedWith => "Unused `with`",
             DiagnosticKind::UnusedRec => "Unused `rec`",
+
+            DiagnosticKind::InheritCycle => {
+                "Infinite recursion: the inherited attribute strictly depends on itself through `rec` bindings. Only dependencies forced during evaluation are considered"
+            }
+
+            DiagnosticKind::TopLevelWith => {
+                "Top-level `with` can unexpectedly shadow names. Consider `let inherit (...) ...; in` instead"
+            }
+            DiagnosticKind::MissingRec => {
+                "The name is only defined as a sibling attribute. Did you mean a `rec` attrset?"
+            }
+            DiagnosticKind::LongLine => "Line is longer than the configured maximum width",
+            DiagnosticKind::DeepNesting => {
+                "Expression is nested too deeply. Consider extracting it into a `let` binding"
+            }
+            DiagnosticKind::ShadowedParam => {
+                "The parameter shadows a parameter of an outer curried lambda, making it inaccessible"
+            }
+            DiagnosticKind::BrokenBinding => "The value of the binding contains an error",
+
+            DiagnosticKind::ImpureBuiltin => {
+                "Impure builtin is unavailable or stubbed in pure evaluation mode of flakes"
+            }
+
+            DiagnosticKind::UnknownLicense => "Unknown license name of `lib.licenses`",
+            DiagnosticKind::MissingFetcherHash => "Fetcher call misses a fixed-output hash",
+            DiagnosticKind::PlaceholderFetcherHash => {
+                "Placeholder hash of fetcher, which always fails the build"
+            }
+
+            DiagnosticKind::MissingOptionField => "Option declaration misses a required field",
+            DiagnosticKind::MissingOptionRecommendedField => {
+                "Option declaration misses a recommended field"
+            }
+
+            DiagnosticKind::TypeMismatch => "Argument type mismatches the builtin signature",
+            DiagnosticKind::MissingAttr => "Attribute not found in the attrset",
         }
         .into()
     }
@@ -146,6 +260,9 @@ impl Diagnostic {
         impl fmt::Display for Wrapper<'_> {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
                 write!(f, "{:?}: {:?}", self.0.range, self.0.kind)?;
+                if let Some(message) = &self.0.message {
+                    write!(f, ": {message}")?;
+                }
                 for (frange, msg) in &self.0.notes {
                     // Currently all related information is in the same file.
                     // Ignore the FileId here.
@@ -158,6 +275,16 @@ impl Diagnostic {
     }
 }
 
+/// Elevate diagnostics whose codes are listed in `error_on` to errors.
+/// This is used to gate specific diagnostics in CI.
+pub fn elevate_diagnostics(diags: &mut [Diagnostic], error_on: &HashSet<String>) {
+    for diag in diags {
+        if error_on.contains(diag.code()) {
+            diag.elevated = true;
+        }
+    }
+}
+
 impl From<syntax::Error> for Diagnostic {
     fn from(err: syntax::Error) -> Self {
         Self::new(err.range, DiagnosticKind::SyntaxError(err.kind))
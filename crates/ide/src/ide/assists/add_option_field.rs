@@ -0,0 +1,92 @@
+//! Add a missing field to an option declaration, with a skeleton value.
+//!
+//! ```nix
+//! lib.mkOption { default = 1; }
+//! ```
+//! =>
+//! ```nix
+//! lib.mkOption { default = 1; type = lib.types.anything; }
+//! ```
+use super::{AssistKind, AssistsCtx};
+use crate::ide::diagnostics::option_missing_fields;
+use crate::TextEdit;
+use syntax::ast::{self, AstNode};
+use syntax::TextRange;
+
+pub(super) fn add_option_field(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let apply = ctx.covering_node::<ast::Apply>()?;
+    let (set, missing) = option_missing_fields(ctx.db, ctx.frange.file_id, &apply)?;
+    let pos = match set.bindings().last() {
+        Some(binding) => binding.syntax().text_range().end(),
+        None => set.l_curly_token()?.text_range().end(),
+    };
+
+    for field in missing {
+        ctx.add(
+            "add_option_field",
+            format!("Add `{}`", field.name),
+            AssistKind::QuickFix,
+            vec![TextEdit {
+                delete: TextRange::empty(pos),
+                insert: format!(" {} = {};", field.name, field.skeleton).into(),
+            }],
+        );
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    define_check_assist!(super::add_option_field);
+
+    #[test]
+    fn type_field() {
+        check(
+            r#"lib: lib.mkOption$0 { description = "A"; }"#,
+            expect![[r#"lib: lib.mkOption { description = "A"; type = lib.types.anything; }"#]],
+        );
+        check(
+            r#"{ mkOption, types }: mkOption$0 { description = "A"; }"#,
+            expect![[
+                r#"{ mkOption, types }: mkOption { description = "A"; type = types.anything; }"#
+            ]],
+        );
+        check(
+            r#"{ lib, mkOption }: mkOption$0 { description = "A"; }"#,
+            expect![[
+                r#"{ lib, mkOption }: mkOption { description = "A"; type = lib.types.anything; }"#
+            ]],
+        );
+        check(
+            r#"lib: with lib; mkOption$0 { description = "A"; }"#,
+            expect![[r#"lib: with lib; mkOption { description = "A"; type = types.anything; }"#]],
+        );
+    }
+
+    #[test]
+    fn description_field() {
+        check(
+            "lib: lib.mkOption { $0}",
+            expect![[r#"lib: lib.mkOption { description = ""; }"#]],
+        );
+        check(
+            "{ mkOption, types }: (mkOption { type = types.int; }$0)",
+            expect![[r#"{ mkOption, types }: (mkOption { type = types.int; description = ""; })"#]],
+        );
+    }
+
+    #[test]
+    fn no_missing() {
+        check_no(r#"lib: lib.mkOption$0 { type = lib.types.int; description = "A"; }"#);
+        check_no("lib: lib.mkEnableOption$0 { }");
+    }
+
+    #[test]
+    fn unresolved() {
+        check_no(r#"mkOption$0 { description = "A"; }"#);
+        check_no(r#"let mkOption = x: x; in mkOption$0 { description = "A"; }"#);
+    }
+}
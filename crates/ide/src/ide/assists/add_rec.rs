@@ -0,0 +1,56 @@
+//! Add `rec` to an attrset when an undefined name refers to its sibling attribute.
+//!
+//! ```nix
+//! { foo = 1; bar = foo + 1; }
+//! ```
+//! =>
+//! ```nix
+//! rec { foo = 1; bar = foo + 1; }
+//! ```
+use super::{AssistKind, AssistsCtx};
+use crate::ide::diagnostics::attrset_missing_rec;
+use crate::TextEdit;
+use syntax::ast::{self, AstNode};
+use syntax::TextRange;
+
+pub(super) fn add_rec(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let node = ctx.covering_node::<ast::Ref>()?;
+    let (set, _) = attrset_missing_rec(ctx.db, ctx.frange.file_id, &node)?;
+    let pos = set.syntax().text_range().start();
+
+    ctx.add(
+        "add_rec",
+        "Add `rec` to the attrset",
+        AssistKind::QuickFix,
+        vec![TextEdit {
+            delete: TextRange::empty(pos),
+            insert: "rec ".into(),
+        }],
+    );
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    define_check_assist!(super::add_rec);
+
+    #[test]
+    fn simple() {
+        check(
+            "{ foo = 1; bar = f$0oo + 1; }",
+            expect!["rec { foo = 1; bar = foo + 1; }"],
+        );
+        check(
+            "{ foo = 1; bar = { baz = $0foo; }; }",
+            expect!["rec { foo = 1; bar = { baz = foo; }; }"],
+        );
+
+        check_no("rec { foo = 1; bar = f$0oo + 1; }");
+        check_no("{ foo = f$0oo; }");
+        check_no("{ foo = 1; bar = b$0az; }");
+        check_no("let foo = 1; in { foo = 1; bar = f$0oo; }");
+    }
+}
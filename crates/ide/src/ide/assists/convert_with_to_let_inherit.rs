@@ -0,0 +1,95 @@
+//! Convert `with env;` into `let inherit (env) ...; in` with all names used from it.
+//!
+//! ```nix
+//! { lib, ... }: with lib; mkIf true (mkDefault 1)
+//! ```
+//! =>
+//! ```nix
+//! { lib, ... }: let inherit (lib) mkDefault mkIf; in mkIf true (mkDefault 1)
+//! ```
+//!
+//! It is not applicable if any of the names may come from another `with`.
+use super::{AssistKind, AssistsCtx};
+use crate::def::{AstPtr, Expr, ResolveResult};
+use crate::TextEdit;
+use std::collections::BTreeSet;
+use syntax::ast::{self, AstNode};
+
+pub(super) fn convert_with_to_let_inherit(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let node = ctx.covering_node::<ast::With>()?;
+    let env = node.environment()?;
+    let header_range = node
+        .with_token()?
+        .text_range()
+        .cover(node.semicolon_token()?.text_range());
+    // Only trigger on the `with ...;` header, not the body.
+    if !header_range.contains_range(ctx.frange.range) {
+        return None;
+    }
+
+    let file_id = ctx.frange.file_id;
+    let with_expr = ctx
+        .db
+        .source_map(file_id)
+        .expr_for_node(AstPtr::new(node.syntax()))?;
+    let module = ctx.db.module(file_id);
+    let nameres = ctx.db.name_resolution(file_id);
+    let mut names = BTreeSet::new();
+    for &expr in ctx.db.name_reference(file_id).with_references(with_expr)? {
+        // The name may also come from other `with`s. Moving it out would change the semantics.
+        match nameres.get(expr)? {
+            ResolveResult::WithExprs(withs) if withs.len() == 1 => {}
+            _ => return None,
+        }
+        let Expr::Reference(name) = &module[expr] else { return None };
+        names.insert(name.clone());
+    }
+
+    let mut insert = format!("let inherit ({})", env.syntax());
+    for name in &names {
+        insert += " ";
+        insert += name;
+    }
+    insert += "; in";
+
+    ctx.add(
+        "convert_with_to_let_inherit",
+        "Convert `with` to `let inherit`",
+        AssistKind::QuickFix,
+        vec![TextEdit {
+            delete: header_range,
+            insert: insert.into(),
+        }],
+    );
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    define_check_assist!(super::convert_with_to_let_inherit);
+
+    #[test]
+    fn top_level() {
+        check(
+            "{ lib, cond }: $0with lib; mkIf cond (mkDefault (mkIf cond 1))",
+            expect!["{ lib, cond }: let inherit (lib) mkDefault mkIf; in mkIf cond (mkDefault (mkIf cond 1))"],
+        );
+        check(
+            "{ pkgs }: with pkgs.lib$0; [ id ]",
+            expect!["{ pkgs }: let inherit (pkgs.lib) id; in [ id ]"],
+        );
+    }
+
+    #[test]
+    fn not_applicable() {
+        // Unused.
+        check_no("{ lib }: with lib$0; 1");
+        // Inside the body.
+        check_no("{ lib }: with lib; mk$0If");
+        // Ambiguous.
+        check_no("{ a, b }: with a$0; with b; foo");
+    }
+}
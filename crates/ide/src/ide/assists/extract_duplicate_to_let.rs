@@ -0,0 +1,199 @@
+//! Extract an expression repeated inside an attrset into a shared `let` binding.
+//!
+//! ```nix
+//! { a = fetchurl "https://example.com"; b = fetchurl "https://example.com"; }
+//! ```
+//! =>
+//! ```nix
+//! let extracted = fetchurl "https://example.com"; in { a = extracted; b = extracted; }
+//! ```
+//!
+//! Duplicates are compared by their syntax trees, ignoring whitespaces and comments.
+//! It is not applicable if any occurrence references names defined inside the attrset.
+use super::{AssistKind, AssistsCtx};
+use crate::def::{AstPtr, ResolveResult};
+use crate::TextEdit;
+use std::collections::HashSet;
+use syntax::ast::{self, AstNode};
+use syntax::{SyntaxKind, SyntaxNode, TextRange};
+
+const NAME_PREFIX: &str = "extracted";
+
+pub(super) fn extract_duplicate_to_let(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let expr = ctx.covering_node::<ast::Expr>()?;
+    if is_trivial(&expr) || is_attr_name(expr.syntax()) {
+        return None;
+    }
+    let set = expr
+        .syntax()
+        .ancestors()
+        .skip(1)
+        .find_map(ast::AttrSet::cast)?;
+    let set_range = set.syntax().text_range();
+
+    let key = significant_tokens(expr.syntax());
+    let mut occurrences = Vec::<SyntaxNode>::new();
+    for node in set.syntax().descendants() {
+        if node.kind() != expr.syntax().kind()
+            || is_attr_name(&node)
+            || significant_tokens(&node) != key
+            // Nested or overlapping duplicates.
+            || occurrences.last().map_or(false, |prev| {
+                prev.text_range().end() > node.text_range().start()
+            })
+        {
+            continue;
+        }
+        occurrences.push(node);
+    }
+    if occurrences.len() < 2 {
+        return None;
+    }
+
+    // All names used by the expression must be still visible outside the attrset.
+    let file_id = ctx.frange.file_id;
+    let source_map = ctx.db.source_map(file_id);
+    let nameres = ctx.db.name_resolution(file_id);
+    for occurrence in &occurrences {
+        for node in occurrence
+            .descendants()
+            .filter(|n| n.kind() == SyntaxKind::REF)
+        {
+            let ref_expr = source_map.expr_for_node(AstPtr::new(&node))?;
+            let defined_inside = match nameres.get(ref_expr) {
+                None | Some(ResolveResult::Builtin(_) | ResolveResult::ExtraGlobal(_)) => false,
+                Some(&ResolveResult::Definition(name)) => source_map
+                    .nodes_for_name(name)
+                    .any(|ptr| set_range.contains_range(ptr.text_range())),
+                Some(ResolveResult::WithExprs(withs)) => withs.iter().any(|&with| {
+                    source_map
+                        .node_for_expr(with)
+                        .map_or(true, |ptr| set_range.contains_range(ptr.text_range()))
+                }),
+            };
+            if defined_inside {
+                return None;
+            }
+        }
+    }
+
+    // The fresh name must not shadow any name, nor capture any reference inside the attrset.
+    let module = ctx.db.module(file_id);
+    let ref_names = set
+        .syntax()
+        .descendants()
+        .filter_map(|node| Some(ast::Ref::cast(node)?.token()?.text().to_owned()))
+        .collect::<Vec<_>>();
+    let used_names = module
+        .names()
+        .map(|(_, name)| &*name.text)
+        .chain(ref_names.iter().map(|name| &**name))
+        .collect::<HashSet<&str>>();
+    let name = (0..)
+        .map(|i| match i {
+            0 => NAME_PREFIX.to_owned(),
+            _ => format!("{NAME_PREFIX}{i}"),
+        })
+        .find(|name| !used_names.contains(&**name))?;
+
+    // `let` cannot be an argument or operand without parentheses.
+    let need_paren = !matches!(
+        set.syntax().parent().map(|p| p.kind()),
+        None | Some(
+            SyntaxKind::SOURCE_FILE
+                | SyntaxKind::ATTR_PATH_VALUE
+                | SyntaxKind::LAMBDA
+                | SyntaxKind::LET_IN
+                | SyntaxKind::PAREN
+                | SyntaxKind::WITH
+                | SyntaxKind::ASSERT
+        )
+    );
+    let (open, close) = if need_paren { ("(", ")") } else { ("", "") };
+
+    let mut edits = vec![TextEdit {
+        delete: TextRange::empty(set_range.start()),
+        insert: format!("{open}let {name} = {}; in ", occurrences[0]).into(),
+    }];
+    edits.extend(occurrences.iter().map(|node| TextEdit {
+        delete: node.text_range(),
+        insert: name.as_str().into(),
+    }));
+    if need_paren {
+        edits.push(TextEdit {
+            delete: TextRange::empty(set_range.end()),
+            insert: close.into(),
+        });
+    }
+
+    ctx.add(
+        "extract_duplicate_to_let",
+        format!("Extract {} duplicates to `let` binding", occurrences.len()),
+        AssistKind::RefactorExtract,
+        edits,
+    );
+
+    Some(())
+}
+
+/// Names and constants are not worth extracting.
+fn is_trivial(expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::Ref(_) | ast::Expr::Literal(_))
+}
+
+/// Strings used as attribute names, like `"a"` in `{ "a" = 1; }`.
+fn is_attr_name(node: &SyntaxNode) -> bool {
+    node.parent().map_or(false, |p| {
+        matches!(p.kind(), SyntaxKind::ATTR_PATH | SyntaxKind::INHERIT)
+    })
+}
+
+fn significant_tokens(node: &SyntaxNode) -> Vec<(SyntaxKind, String)> {
+    node.descendants_with_tokens()
+        .filter_map(|elem| elem.into_token())
+        .filter(|tok| !tok.kind().is_whitespace())
+        .map(|tok| (tok.kind(), tok.text().to_owned()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    define_check_assist!(super::extract_duplicate_to_let);
+
+    #[test]
+    fn literal() {
+        check(
+            r#"{ a = "https://$0example.com"; b = "https://example.com"; }"#,
+            expect![[
+                r#"let extracted = "https://example.com"; in { a = extracted; b = extracted; }"#
+            ]],
+        );
+        check(
+            r#"f { a = [ 1 2 ]; b.c = $0[ 1 /* two */ 2 ]; }"#,
+            expect!["f (let extracted = [ 1 2 ]; in { a = extracted; b.c = extracted; })"],
+        );
+    }
+
+    #[test]
+    fn fresh_name() {
+        check(
+            r#"{ extracted = 1; a = $0[ "x" ]; b = [ "x" ]; }"#,
+            expect![[
+                r#"let extracted1 = [ "x" ]; in { extracted = 1; a = extracted1; b = extracted1; }"#
+            ]],
+        );
+    }
+
+    #[test]
+    fn not_applicable() {
+        // No duplicates.
+        check_no(r#"{ a = "$0foo"; b = "bar"; }"#);
+        // Trivial.
+        check_no("{ a = $042; b = 42; }");
+        // Names defined inside the attrset.
+        check_no("rec { x = 1; a = $0[ x ]; b = [ x ]; }");
+        check_no("{ a = x: $0[ x ]; b = x: [ x ]; }");
+    }
+}
@@ -0,0 +1,101 @@
+//! Fix the selection of an attribute which doesn't exist in the attrset.
+//!
+//! Replace it with the closest-named attribute:
+//! ```nix
+//! let a = { foo = 1; }; in a.fooo
+//! ```
+//! =>
+//! ```nix
+//! let a = { foo = 1; }; in a.foo
+//! ```
+//!
+//! Or add a default:
+//! ```nix
+//! let a = { foo = 1; }; in a.bar
+//! ```
+//! =>
+//! ```nix
+//! let a = { foo = 1; }; in a.bar or null
+//! ```
+use super::{AssistKind, AssistsCtx};
+use crate::ide::diagnostics::closest_name;
+use crate::ide::goto_definition::select_missing_attr;
+use crate::TextEdit;
+use syntax::ast::{self, AstNode};
+use syntax::semantic::escape_literal_attr;
+use syntax::TextRange;
+
+pub(super) fn fix_missing_attr(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let select = ctx.covering_node::<ast::Select>()?;
+    let (attr, names) = select_missing_attr(ctx.db, ctx.frange.file_id, &select)?;
+
+    let attr_text = attr.syntax().to_string();
+    if let Some(closest) = closest_name(&attr_text, names.iter().map(|name| &**name)) {
+        ctx.add(
+            "fix_missing_attr",
+            format!("Replace with `{closest}`"),
+            AssistKind::QuickFix,
+            vec![TextEdit {
+                delete: attr.syntax().text_range(),
+                insert: escape_literal_attr(closest).as_ref().into(),
+            }],
+        );
+    }
+
+    ctx.add(
+        "fix_missing_attr",
+        "Add `or null`",
+        AssistKind::QuickFix,
+        vec![TextEdit {
+            delete: TextRange::empty(select.syntax().text_range().end()),
+            insert: " or null".into(),
+        }],
+    );
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::ide::assists::assists;
+    use crate::tests::TestDB;
+    use expect_test::expect;
+
+    define_check_assist!(super::fix_missing_attr);
+
+    #[test]
+    fn add_default() {
+        check(
+            "let a = { foo = 1; }; in a.$0bar",
+            expect!["let a = { foo = 1; }; in a.bar or null"],
+        );
+        check(
+            "let a = { foo = 1; }; in a.$0fooo",
+            expect!["let a = { foo = 1; }; in a.fooo or null"],
+        );
+
+        check_no("let a = { foo = 1; }; in a.$0foo");
+        check_no("let a = { foo = 1; }; in a.$0bar or 2");
+        check_no("let a = { foo = 1; }; in a ? bar && a.$0bar");
+    }
+
+    #[test]
+    fn closest() {
+        let (db, f) =
+            TestDB::from_fixture("let a = { foo = 1; bar = 2; }; in a.$0fooo + 1").unwrap();
+        let assists = assists(&db, f.unwrap_single_range_marker(), None)
+            .into_iter()
+            .filter(|assist| assist.id == "fix_missing_attr")
+            .collect::<Vec<_>>();
+        let labels = assists
+            .iter()
+            .map(|assist| &*assist.label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels, ["Replace with `foo`", "Add `or null`"]);
+
+        let mut src = db.file_content(f[0].file_id).to_string();
+        assists[0].edits.apply(f[0].file_id, &mut src);
+        expect!["let a = { foo = 1; bar = 2; }; in a.foo + 1"].assert_eq(&src);
+    }
+}
@@ -0,0 +1,54 @@
+//! Replace an unknown license name in `meta.license` with the closest known one.
+//!
+//! ```nix
+//! { meta.license = lib.licenses.mitt; }
+//! ```
+//! =>
+//! ```nix
+//! { meta.license = lib.licenses.mit; }
+//! ```
+use super::{AssistKind, AssistsCtx};
+use crate::ide::diagnostics::select_unknown_license;
+use crate::TextEdit;
+use syntax::ast::{self, AstNode};
+use syntax::semantic::escape_literal_attr;
+
+pub(super) fn fix_unknown_license(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let select = ctx.covering_node::<ast::Select>()?;
+    let (attr, closest) = select_unknown_license(&select)?;
+    let closest = closest?;
+
+    ctx.add(
+        "fix_unknown_license",
+        format!("Replace with `{closest}`"),
+        AssistKind::QuickFix,
+        vec![TextEdit {
+            delete: attr.syntax().text_range(),
+            insert: escape_literal_attr(closest).as_ref().into(),
+        }],
+    );
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    define_check_assist!(super::fix_unknown_license);
+
+    #[test]
+    fn simple() {
+        check(
+            "lib: { meta.license = lib.licenses.$0mitt; }",
+            expect!["lib: { meta.license = lib.licenses.mit; }"],
+        );
+        check(
+            "lib: { meta.license = with lib; [ licenses.$0gpl3Pls ]; }",
+            expect!["lib: { meta.license = with lib; [ licenses.gpl3Plus ]; }"],
+        );
+
+        check_no("lib: { meta.license = lib.licenses.$0mit; }");
+        check_no("lib: { meta.license = lib.licenses.$0whatever; }");
+    }
+}
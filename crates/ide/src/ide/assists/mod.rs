@@ -12,9 +12,16 @@ macro_rules! define_check_assist {
     };
 }
 
+mod add_option_field;
+mod add_rec;
 mod add_to_top_level_lambda_param;
 mod convert_to_inherit;
+mod convert_with_to_let_inherit;
+mod extract_duplicate_to_let;
+mod fix_missing_attr;
+mod fix_unknown_license;
 mod flatten_attrset;
+mod organize_imports;
 mod pack_bindings;
 mod remove_empty_inherit;
 
@@ -35,21 +42,58 @@ pub struct Assist {
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum AssistKind {
     QuickFix,
+    RefactorExtract,
     RefactorRewrite,
+    SourceOrganizeImports,
 }
 
-pub(crate) fn assists(db: &dyn DefDatabase, frange: FileRange) -> Vec<Assist> {
-    let handlers = [
-        add_to_top_level_lambda_param::add_to_top_level_lambda_param,
-        convert_to_inherit::convert_to_inherit,
-        flatten_attrset::flatten_attrset,
-        pack_bindings::pack_bindings,
-        remove_empty_inherit::remove_empty_inherit,
+impl AssistKind {
+    pub const ALL: [Self; 4] = [
+        Self::QuickFix,
+        Self::RefactorExtract,
+        Self::RefactorRewrite,
+        Self::SourceOrganizeImports,
+    ];
+}
+
+/// Collect applicable assists. If `only` is given, handlers of other kinds are not run at all.
+pub(crate) fn assists(
+    db: &dyn DefDatabase,
+    frange: FileRange,
+    only: Option<&[AssistKind]>,
+) -> Vec<Assist> {
+    use AssistKind::*;
+
+    // Each handler only produces assists of the kind next to it.
+    let handlers: [(AssistKind, fn(&mut AssistsCtx) -> Option<()>); 12] = [
+        (QuickFix, add_option_field::add_option_field),
+        (QuickFix, add_rec::add_rec),
+        (
+            QuickFix,
+            add_to_top_level_lambda_param::add_to_top_level_lambda_param,
+        ),
+        (RefactorRewrite, convert_to_inherit::convert_to_inherit),
+        (
+            QuickFix,
+            convert_with_to_let_inherit::convert_with_to_let_inherit,
+        ),
+        (
+            RefactorExtract,
+            extract_duplicate_to_let::extract_duplicate_to_let,
+        ),
+        (QuickFix, fix_missing_attr::fix_missing_attr),
+        (QuickFix, fix_unknown_license::fix_unknown_license),
+        (RefactorRewrite, flatten_attrset::flatten_attrset),
+        (SourceOrganizeImports, organize_imports::organize_imports),
+        (RefactorRewrite, pack_bindings::pack_bindings),
+        (QuickFix, remove_empty_inherit::remove_empty_inherit),
     ];
 
     let mut ctx = AssistsCtx::new(db, frange);
-    for h in handlers {
-        h(&mut ctx);
+    for (kind, h) in handlers {
+        if only.map_or(true, |kinds| kinds.contains(&kind)) {
+            h(&mut ctx);
+        }
     }
     ctx.assists
 }
@@ -147,6 +191,27 @@ mod tests {
         expect.assert_eq(&got);
     }
 
+    #[test]
+    fn only_kinds() {
+        let (db, f) =
+            TestDB::from_fixture("{ imports = [ ./b.nix ./a.nix ]; $0foo = foo; }").unwrap();
+        let frange = f.unwrap_single_range_marker();
+        let ids = |only: Option<&[AssistKind]>| {
+            assists(&db, frange, only)
+                .into_iter()
+                .map(|assist| assist.id)
+                .collect::<Vec<_>>()
+        };
+        let all = ids(None);
+        assert!(all.iter().any(|id| id == "convert_to_inherit"), "{all:?}");
+        assert!(all.iter().any(|id| id == "organize_imports"), "{all:?}");
+        assert_eq!(
+            ids(Some(&[AssistKind::SourceOrganizeImports])),
+            ["organize_imports"]
+        );
+        assert_eq!(ids(Some(&[])), Vec::<String>::new());
+    }
+
     #[track_caller]
     pub(crate) fn check_assist_no(handler: fn(&mut AssistsCtx) -> Option<()>, fixture: &str) {
         if let Some(got) = try_apply_assist(handler, fixture) {
@@ -0,0 +1,240 @@
+//! Sort entries of `imports` lists by their resolved paths and remove exact duplicates.
+//! Computed entries are left in place. Comments before an entry, or after it on the same line,
+//! are moved along with it.
+//!
+//! ```nix
+//! { imports = [ ./b.nix ./a.nix (import ./c.nix) ./b.nix ]; }
+//! ```
+//! =>
+//! ```nix
+//! { imports = [ ./a.nix ./b.nix (import ./c.nix) ]; }
+//! ```
+use super::{AssistKind, AssistsCtx};
+use crate::def::{AstPtr, Expr, Literal, Module, ModuleSourceMap};
+use crate::{DefDatabase, TextEdit};
+use std::collections::HashSet;
+use syntax::ast::{self, AstNode, HasStringParts, LiteralKind, StringPart};
+use syntax::semantic::AttrKind;
+use syntax::{SyntaxKind, TextRange};
+
+pub(super) fn organize_imports(ctx: &mut AssistsCtx<'_>) -> Option<()> {
+    let file_id = ctx.frange.file_id;
+    let module = ctx.db.module(file_id);
+    let source_map = ctx.db.source_map(file_id);
+    let edits = ctx
+        .ast
+        .syntax()
+        .descendants()
+        .filter_map(ast::AttrpathValue::cast)
+        .filter_map(|binding| {
+            let mut attrs = binding.attrpath()?.attrs();
+            let (Some(attr), None) = (attrs.next(), attrs.next()) else { return None };
+            let AttrKind::Static(Some(name)) = AttrKind::of(attr) else { return None };
+            let ast::Expr::List(list) = binding.value()? else { return None };
+            (name == "imports").then_some(list)
+        })
+        .flat_map(|list| organize_list(ctx.db, &module, &source_map, &list))
+        .collect::<Vec<_>>();
+    if edits.is_empty() {
+        return None;
+    }
+
+    ctx.add(
+        "organize_imports",
+        "Sort and deduplicate `imports`",
+        AssistKind::SourceOrganizeImports,
+        edits,
+    );
+
+    Some(())
+}
+
+/// A literal entry of the list, with comments attached to it.
+struct Entry {
+    /// The resolved path of the entry, to be sorted by.
+    key: String,
+    /// The source of the entry itself, to find duplicates.
+    source: String,
+    /// The source of the entry with its comments.
+    text: String,
+    range: TextRange,
+    /// The range to delete for a duplicate, including the whitespace before it.
+    delete_range: TextRange,
+}
+
+fn organize_list(
+    db: &dyn DefDatabase,
+    module: &Module,
+    source_map: &ModuleSourceMap,
+    list: &ast::List,
+) -> Vec<TextEdit> {
+    let entries = list
+        .elements()
+        .filter(is_literal_path)
+        .map(|elem| {
+            let key = path_key(db, module, source_map, &elem);
+            entry(list, &elem, key)
+        })
+        .collect::<Vec<_>>();
+    let mut sorted = entries.iter().collect::<Vec<_>>();
+    // The sort is stable, so the first of duplicates is kept.
+    sorted.sort_by(|lhs, rhs| lhs.key.cmp(&rhs.key));
+    let mut seen = HashSet::new();
+    sorted.retain(|&entry| seen.insert(&entry.source));
+
+    entries
+        .iter()
+        .enumerate()
+        .filter_map(|(i, entry)| match sorted.get(i) {
+            Some(new) if new.text == entry.text => None,
+            Some(new) => Some(TextEdit {
+                delete: entry.range,
+                insert: new.text.as_str().into(),
+            }),
+            None => Some(TextEdit {
+                delete: entry.delete_range,
+                insert: "".into(),
+            }),
+        })
+        .collect()
+}
+
+fn is_literal_path(elem: &ast::Expr) -> bool {
+    match elem {
+        ast::Expr::Literal(lit) => {
+            matches!(
+                lit.kind(),
+                Some(LiteralKind::Path | LiteralKind::SearchPath)
+            )
+        }
+        ast::Expr::String(s) => s
+            .string_parts()
+            .all(|part| !matches!(part, StringPart::Dynamic(_))),
+        _ => false,
+    }
+}
+
+/// The path text to sort by: the resolved path of a path literal, or the content of a string.
+/// Falls back to the source of the entry if it cannot be resolved.
+fn path_key(
+    db: &dyn DefDatabase,
+    module: &Module,
+    source_map: &ModuleSourceMap,
+    elem: &ast::Expr,
+) -> String {
+    let key = source_map
+        .expr_for_node(AstPtr::new(elem.syntax()))
+        .and_then(|expr| match &module[expr] {
+            &Expr::Literal(Literal::Path(path)) => Some(path.resolve(db)?.as_str().to_owned()),
+            Expr::Literal(Literal::String(s)) => Some(s.to_string()),
+            _ => None,
+        });
+    key.unwrap_or_else(|| elem.syntax().to_string())
+}
+
+fn entry(list: &ast::List, elem: &ast::Expr, key: String) -> Entry {
+    use SyntaxKind::{COMMENT, SPACE};
+
+    let node = elem.syntax();
+    let mut first = node.clone().into();
+    let mut last = node.clone().into();
+
+    // Comments on lines before the entry. A comment on the same line as the previous entry
+    // belongs to that entry instead.
+    let trivia = std::iter::successors(node.prev_sibling_or_token(), |e| e.prev_sibling_or_token())
+        .take_while(|e| matches!(e.kind(), SPACE | COMMENT))
+        .collect::<Vec<_>>();
+    let mut after_newline = false;
+    for elem in trivia.into_iter().rev() {
+        match elem.as_token() {
+            Some(tok) if tok.kind() == SPACE => after_newline |= tok.text().contains('\n'),
+            Some(_) if after_newline => {
+                first = elem;
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    // A comment after the entry on the same line.
+    let trailing = node
+        .next_sibling_or_token()
+        .and_then(|e| match e.as_token() {
+            Some(tok) if tok.kind() == SPACE && !tok.text().contains('\n') => {
+                e.next_sibling_or_token()
+            }
+            _ => Some(e),
+        });
+    if let Some(comment) = trailing.filter(|e| e.kind() == COMMENT) {
+        last = comment;
+    }
+
+    let range = TextRange::new(first.text_range().start(), last.text_range().end());
+    let delete_start = first
+        .prev_sibling_or_token()
+        .filter(|e| e.kind() == SPACE)
+        .map_or(range.start(), |e| e.text_range().start());
+    let list_start = list.syntax().text_range().start();
+    let list_text = list.syntax().to_string();
+    Entry {
+        key,
+        source: node.to_string(),
+        text: list_text[range - list_start].to_owned(),
+        range,
+        delete_range: TextRange::new(delete_start, range.end()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    define_check_assist!(super::organize_imports);
+
+    #[test]
+    fn simple() {
+        check(
+            "{ imports = [ ./b.nix $0./a.nix <c> ./b.nix ]; }",
+            expect!["{ imports = [ ./a.nix ./b.nix <c> ]; }"],
+        );
+    }
+
+    #[test]
+    fn comments_and_computed() {
+        check(
+            r#"
+$0{
+  imports = [
+    ./b.nix
+    # The hardware.
+    ./a.nix
+    (import ./c.nix)
+    ./b.nix # Duplicated.
+    "${dir}/d.nix"
+    "/a.nix"
+    ./c.nix
+  ];
+}
+"#,
+            expect![[r#"
+                {
+                  imports = [
+                    # The hardware.
+                    ./a.nix
+                    "/a.nix"
+                    (import ./c.nix)
+                    ./b.nix
+                    "${dir}/d.nix"
+                    ./c.nix
+                  ];
+                }
+            "#]],
+        );
+    }
+
+    #[test]
+    fn sorted() {
+        check_no("{ imports = [ $0./a.nix ./b.nix (import ./a.nix) ]; }");
+        check_no("{ $0imports = import ./a.nix; foo = [ ./b.nix ./a.nix ]; }");
+    }
+}
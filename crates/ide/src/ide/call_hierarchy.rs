@@ -0,0 +1,370 @@
+use super::NavigationTarget;
+use crate::def::{AstPtr, BindingValue, Expr, ExprId, Module, NameId, ResolveResult};
+use crate::{DefDatabase, FileId, FilePos};
+use smol_str::SmolStr;
+use std::collections::HashMap;
+use syntax::ast::{self, AstNode};
+use syntax::{best_token_at_offset, SyntaxKind, TextRange, TextSize};
+
+/// The placeholder name of lambdas which are not bound to any name.
+const ANONYMOUS_NAME: &str = "<lambda>";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallHierarchyItem {
+    pub name: SmolStr,
+    pub target: NavigationTarget,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallHierarchyCall {
+    /// The caller for incoming calls, or the callee for outgoing calls.
+    pub item: CallHierarchyItem,
+    /// The ranges of callee references inside the caller.
+    pub ranges: Vec<TextRange>,
+}
+
+/// Functions of a file and calls between them.
+struct CallGraph {
+    /// Sorted by the start of focus ranges.
+    items: Vec<CallHierarchyItem>,
+    /// The item defined by each name.
+    name_items: HashMap<NameId, usize>,
+    /// `(caller, callee, range of the callee reference)`.
+    calls: Vec<(usize, usize, TextRange)>,
+}
+
+impl CallGraph {
+    fn item_at(&self, focus_start: TextSize) -> Option<usize> {
+        self.items
+            .iter()
+            .position(|item| item.target.focus_range.start() == focus_start)
+    }
+}
+
+pub(crate) fn prepare_call_hierarchy(
+    db: &dyn DefDatabase,
+    FilePos { file_id, pos }: FilePos,
+    top_level_only: bool,
+) -> Option<CallHierarchyItem> {
+    let graph = call_graph(db, file_id, top_level_only);
+
+    // The function defined or referenced under the cursor.
+    let parse = db.parse(file_id);
+    let tok = best_token_at_offset(&parse.syntax_node(), pos)?;
+    if tok.kind() == SyntaxKind::IDENT {
+        let source_map = db.source_map(file_id);
+        let node = tok.parent()?;
+        let name = source_map.name_for_node(AstPtr::new(&node)).or_else(|| {
+            let expr = source_map.expr_for_node(AstPtr::new(&node))?;
+            match db.name_resolution(file_id).get(expr)? {
+                &ResolveResult::Definition(name) => Some(name),
+                _ => None,
+            }
+        });
+        if let Some(&idx) = name.and_then(|name| graph.name_items.get(&name)) {
+            return Some(graph.items[idx].clone());
+        }
+    }
+
+    // Otherwise, the innermost function enclosing the cursor.
+    graph
+        .items
+        .into_iter()
+        .filter(|item| item.target.full_range.contains_inclusive(pos))
+        .min_by_key(|item| item.target.full_range.len())
+}
+
+pub(crate) fn incoming_calls(
+    db: &dyn DefDatabase,
+    FilePos { file_id, pos }: FilePos,
+    top_level_only: bool,
+) -> Option<Vec<CallHierarchyCall>> {
+    let graph = call_graph(db, file_id, top_level_only);
+    let target = graph.item_at(pos)?;
+    Some(group_calls(
+        &graph,
+        graph
+            .calls
+            .iter()
+            .filter(|&&(_, callee, _)| callee == target)
+            .map(|&(caller, _, range)| (caller, range)),
+    ))
+}
+
+pub(crate) fn outgoing_calls(
+    db: &dyn DefDatabase,
+    FilePos { file_id, pos }: FilePos,
+    top_level_only: bool,
+) -> Option<Vec<CallHierarchyCall>> {
+    let graph = call_graph(db, file_id, top_level_only);
+    let source = graph.item_at(pos)?;
+    Some(group_calls(
+        &graph,
+        graph
+            .calls
+            .iter()
+            .filter(|&&(caller, _, _)| caller == source)
+            .map(|&(_, callee, range)| (callee, range)),
+    ))
+}
+
+fn group_calls(
+    graph: &CallGraph,
+    calls: impl Iterator<Item = (usize, TextRange)>,
+) -> Vec<CallHierarchyCall> {
+    let mut ranges = vec![Vec::new(); graph.items.len()];
+    for (idx, range) in calls {
+        ranges[idx].push(range);
+    }
+    ranges
+        .into_iter()
+        .enumerate()
+        .filter(|(_, ranges)| !ranges.is_empty())
+        .map(|(idx, ranges)| CallHierarchyCall {
+            item: graph.items[idx].clone(),
+            ranges,
+        })
+        .collect()
+}
+
+fn call_graph(db: &dyn DefDatabase, file_id: FileId, top_level_only: bool) -> CallGraph {
+    let module = db.module(file_id);
+    let source_map = db.source_map(file_id);
+    let nameres = db.name_resolution(file_id);
+    let root = db.parse(file_id).syntax_node();
+
+    // Lambdas bound to names, like `f = x: x;`.
+    let mut lambda_names = HashMap::new();
+    for (_, kind) in module.exprs() {
+        let bindings = match kind {
+            Expr::LetIn(bindings, _)
+            | Expr::Attrset(bindings)
+            | Expr::RecAttrset(bindings)
+            | Expr::LetAttrset(bindings) => bindings,
+            _ => continue,
+        };
+        for &(name, value) in bindings.statics.iter() {
+            if let BindingValue::Expr(value) = value {
+                if matches!(module[value], Expr::Lambda(..)) {
+                    lambda_names.insert(value, name);
+                }
+            }
+        }
+    }
+
+    let lambdas: Vec<ExprId> = if top_level_only {
+        top_level_lambdas(&module)
+    } else {
+        module
+            .exprs()
+            .filter(|(_, kind)| matches!(kind, Expr::Lambda(..)))
+            .map(|(e, _)| e)
+            .collect()
+    };
+
+    let mut items = lambdas
+        .into_iter()
+        .filter_map(|lambda| {
+            let lambda_node = source_map.node_for_expr(lambda)?.to_node(&root);
+            let (name, target) = match lambda_names.get(&lambda) {
+                Some(&name) => {
+                    let name_node = source_map.nodes_for_name(name).next()?.to_node(&root);
+                    let full_node = name_node
+                        .ancestors()
+                        .find(|n| n.kind() == SyntaxKind::ATTR_PATH_VALUE)?;
+                    let target = NavigationTarget {
+                        file_id,
+                        full_range: full_node.text_range(),
+                        focus_range: name_node.text_range(),
+                    };
+                    (Some(name), target)
+                }
+                None => {
+                    let param = ast::Lambda::cast(lambda_node.clone())?.param()?;
+                    let target = NavigationTarget {
+                        file_id,
+                        full_range: lambda_node.text_range(),
+                        focus_range: param.syntax().text_range(),
+                    };
+                    (None, target)
+                }
+            };
+            Some((lambda, name, target))
+        })
+        .collect::<Vec<_>>();
+    items.sort_by_key(|(_, _, target)| target.focus_range.start());
+
+    let lambda_items = items
+        .iter()
+        .enumerate()
+        .map(|(idx, &(lambda, ..))| (lambda, idx))
+        .collect::<HashMap<_, _>>();
+    let name_items = items
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, &(_, name, _))| Some((name?, idx)))
+        .collect::<HashMap<_, _>>();
+
+    // The innermost item containing each expression.
+    let mut owners = HashMap::new();
+    let mut stack = vec![(module.entry_expr(), None)];
+    while let Some((expr, owner)) = stack.pop() {
+        let owner = lambda_items.get(&expr).copied().or(owner);
+        if let Some(owner) = owner {
+            owners.insert(expr, owner);
+        }
+        module[expr].walk_child_exprs(|child| stack.push((child, owner)));
+    }
+
+    // Only count the innermost application of curried calls, like `f a` in `f a b`.
+    let mut calls = Vec::new();
+    for (expr, kind) in module.exprs() {
+        let &Expr::Apply(func, _) = kind else { continue };
+        let Some(&caller) = owners.get(&expr) else { continue };
+        let Some(&ResolveResult::Definition(name)) = nameres.get(func) else { continue };
+        let Some(&callee) = name_items.get(&name) else { continue };
+        let Some(ptr) = source_map.node_for_expr(func) else { continue };
+        calls.push((caller, callee, ptr.text_range()));
+    }
+    calls.sort_by_key(|&(_, _, range)| range.start());
+
+    let items = items
+        .into_iter()
+        .map(|(_, name, target)| CallHierarchyItem {
+            name: name.map_or_else(|| ANONYMOUS_NAME.into(), |name| module[name].text.clone()),
+            target,
+        })
+        .collect();
+
+    CallGraph {
+        items,
+        name_items,
+        calls,
+    }
+}
+
+/// Lambdas bound at the top level of a file, including attributes of nested attrsets,
+/// but excluding ones defined inside other functions.
+fn top_level_lambdas(module: &Module) -> Vec<ExprId> {
+    let mut ret = Vec::new();
+    let mut stack = vec![module.entry_expr()];
+    while let Some(expr) = stack.pop() {
+        let bindings = match &module[expr] {
+            // The whole file is usually a function taking arguments, like `{ lib }: { .. }`.
+            Expr::Lambda(_, _, body) if expr == module.entry_expr() => {
+                stack.push(*body);
+                continue;
+            }
+            Expr::With(_, body) | Expr::Assert(_, body) => {
+                stack.push(*body);
+                continue;
+            }
+            Expr::LetIn(bindings, body) => {
+                stack.push(*body);
+                bindings
+            }
+            Expr::Attrset(bindings) | Expr::RecAttrset(bindings) | Expr::LetAttrset(bindings) => {
+                bindings
+            }
+            _ => continue,
+        };
+        for &(_, value) in bindings.statics.iter() {
+            if let BindingValue::Expr(value) = value {
+                if matches!(module[value], Expr::Lambda(..)) {
+                    ret.push(value);
+                } else {
+                    stack.push(value);
+                }
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestDB;
+    use crate::FilePos;
+    use expect_test::{expect, Expect};
+
+    const SRC: &str = "
+let
+  add = a: b: a + b;
+  twice = f: x: f (f x);
+  main = twice (x: add x 1) 0;
+in main
+";
+
+    #[track_caller]
+    fn check(top_level_only: bool, name: &str, expect: Expect) {
+        let (db, file_id) = TestDB::single_file(SRC).unwrap();
+        let pos = SRC.find(&format!("{name} =")).unwrap().try_into().unwrap();
+        let fpos = FilePos::new(file_id, pos);
+        let item = super::prepare_call_hierarchy(&db, fpos, top_level_only).unwrap();
+        assert_eq!(item.name, name);
+
+        let focus = FilePos::new(file_id, item.target.focus_range.start());
+        let incoming = super::incoming_calls(&db, focus, top_level_only).unwrap();
+        let outgoing = super::outgoing_calls(&db, focus, top_level_only).unwrap();
+        let got = incoming
+            .into_iter()
+            .map(|call| ("<-", call))
+            .chain(outgoing.into_iter().map(|call| ("->", call)))
+            .map(|(dir, call)| {
+                let ranges = call
+                    .ranges
+                    .iter()
+                    .map(|r| format!("{r:?}"))
+                    .collect::<Vec<_>>();
+                format!("{dir} {} {}\n", call.item.name, ranges.join(" "))
+            })
+            .collect::<String>();
+        expect.assert_eq(&got);
+    }
+
+    #[test]
+    fn top_level_only() {
+        check(
+            true,
+            "add",
+            expect![[r#"
+                <- main 70..73
+            "#]],
+        );
+        check(
+            true,
+            "main",
+            expect![[r#"
+                -> add 70..73
+                -> twice 60..65
+            "#]],
+        );
+    }
+
+    #[test]
+    fn inline_lambda() {
+        check(
+            false,
+            "add",
+            expect![[r#"
+                <- <lambda> 70..73
+            "#]],
+        );
+        check(
+            false,
+            "main",
+            expect![[r#"
+                -> twice 60..65
+            "#]],
+        );
+    }
+
+    #[test]
+    fn enclosing_item() {
+        let (db, f) = TestDB::from_fixture("let f = x: g (y: $0y); g = y: y; in f").unwrap();
+        let item = super::prepare_call_hierarchy(&db, f[0], true).unwrap();
+        assert_eq!(item.name, "f");
+        let item = super::prepare_call_hierarchy(&db, f[0], false).unwrap();
+        assert_eq!(item.name, "<lambda>");
+    }
+}
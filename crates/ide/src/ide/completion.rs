@@ -1,12 +1,20 @@
-use crate::def::{AstPtr, BindingValue, Expr, NameKind};
-use crate::ty::{AttrSource, Ty};
+use super::diagnostics::license_attr;
+use super::goto_definition::flake_outputs_lambda;
+use super::module_option::{attrset_path, option_declarations};
+use super::pattern_bindings::pattern_bindings;
+use crate::def::{reachable_files, AstPtr, BindingValue, Expr, ExprId, NameKind};
+use crate::ty::{known, AttrSource, Ty};
 use crate::{FileId, FilePos, TyDatabase};
 use builtin::{BuiltinKind, ALL_BUILTINS};
 use either::Either::{Left, Right};
 use smol_str::SmolStr;
+use std::collections::HashSet;
+use std::fmt::Write;
 use syntax::ast::{self, AstNode, Attr};
-use syntax::semantic::AttrKind;
-use syntax::{best_token_at_offset, match_ast, SyntaxKind, SyntaxNode, TextRange, T};
+use syntax::semantic::{function_name, AttrKind};
+use syntax::{
+    best_token_at_offset, match_ast, SyntaxKind, SyntaxNode, SyntaxToken, TextRange, TextSize, T,
+};
 
 #[rustfmt::skip]
 const EXPR_POS_KEYWORDS: &[&str] = &[
@@ -22,6 +30,14 @@ const EXPR_POS_KEYWORDS: &[&str] = &[
     "with",
 ];
 
+/// Systems commonly used as keys of flake outputs, like `packages.x86_64-linux`.
+const FLAKE_SYSTEMS: &[&str] = &[
+    "aarch64-darwin",
+    "aarch64-linux",
+    "x86_64-darwin",
+    "x86_64-linux",
+];
+
 /// A single completion variant in the editor pop-up.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CompletionItem {
@@ -33,10 +49,15 @@ pub struct CompletionItem {
     pub replace: SmolStr,
     /// What item (struct, function, etc) are we completing.
     pub kind: CompletionItemKind,
-    /// A brief summary.
+    /// Where the item comes from, for ranking.
+    pub category: CompletionCategory,
+    /// A brief summary, like the inferred type of names and fields.
     pub brief: Option<String>,
     /// The detailed documentation.
     pub doc: Option<String>,
+    /// For functions, a snippet applying it to placeholder arguments, like `f $1 $2`.
+    /// It can replace `replace` if the client prefers arguments to be inserted.
+    pub call_snippet: Option<String>,
 }
 
 /// The type of the completion item.
@@ -49,6 +70,22 @@ pub enum CompletionItemKind {
     BuiltinConst,
     BuiltinFunction,
     BuiltinAttrset,
+    /// The `replace` text is a snippet in the LSP snippet syntax.
+    Snippet,
+}
+
+/// The source of a completion item. Items are ranked by their categories first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CompletionCategory {
+    /// Names bound in the current file, like `let` bindings and parameters.
+    Local,
+    Builtin,
+    /// Attributes from `with` environments.
+    With,
+    /// Fields expected by inferred or known types.
+    Schema,
+    /// Keywords and syntax snippets.
+    Keyword,
 }
 
 impl From<BuiltinKind> for CompletionItemKind {
@@ -77,6 +114,7 @@ pub(crate) fn completions(
     fpos @ FilePos { file_id, pos }: FilePos,
     trigger_char: Option<char>,
 ) -> Option<Vec<CompletionItem>> {
+    let _span = tracing::info_span!("completion", ?file_id).entered();
     let parse = db.parse(file_id);
 
     if let Some(items) =
@@ -86,6 +124,17 @@ pub(crate) fn completions(
     }
 
     let tok = best_token_at_offset(&parse.syntax_node(), pos)?;
+
+    // An empty field position in a lambda pattern, like `{ a, | }: body`.
+    if let Some(pat_node) = empty_pat_field_at(&parse.syntax_node(), pos) {
+        return complete_pat_param(db, file_id, TextRange::empty(pos), None, pat_node);
+    }
+
+    // Inside string literals.
+    if let Some(items) = complete_string(db, file_id, &tok, pos) {
+        return Some(items);
+    }
+
     let source_range = match tok.kind() {
         T![.] => TextRange::empty(pos),
         SyntaxKind::IDENT => tok.text_range(),
@@ -103,7 +152,13 @@ pub(crate) fn completions(
     })?;
 
     match node {
-        Left(ref_node) => complete_expr(db, file_id, source_range, ref_node),
+        Left(ref_node) => {
+            let expr = db
+                .source_map(file_id)
+                .expr_for_node(AstPtr::new(ref_node.syntax()))?;
+            let prefix = ref_node.token()?.text().to_owned();
+            complete_expr(db, file_id, source_range, ref_node.syntax(), expr, &prefix)
+        }
         Right(name_node) => {
             match_ast! {
                 match (name_node.syntax().parent()?) {
@@ -111,11 +166,8 @@ pub(crate) fn completions(
                         complete_attrpath(db, file_id, source_range, name_node, path_node)
                     },
                     ast::PatField(pat_field_node) => {
-                        let lambda_node = pat_field_node
-                            .syntax()
-                            .ancestors()
-                            .find_map(ast::Lambda::cast)?;
-                        complete_pat_param(db, file_id, source_range, name_node, lambda_node)
+                        let pat_node = ast::Pat::cast(pat_field_node.syntax().parent()?)?;
+                        complete_pat_param(db, file_id, source_range, Some(name_node), pat_node)
                     },
                     _ => None,
                 }
@@ -165,22 +217,147 @@ fn complete_trigger(
     complete_attrpath(db, file_id, source_range, name_node, path_node)
 }
 
+/// Complete in string literals.
+/// Inside an empty interpolation `"${|}"`, names in scope are completed as expressions.
+/// Otherwise, only the interpolation snippet is offered.
+fn complete_string(
+    db: &dyn TyDatabase,
+    file_id: FileId,
+    tok: &SyntaxToken,
+    pos: TextSize,
+) -> Option<Vec<CompletionItem>> {
+    let parent = tok.parent()?;
+    if let Some(dynamic) = ast::Dynamic::cast(parent.clone()) {
+        // Non-empty interpolations are handled as normal expressions.
+        if dynamic.expr().is_some()
+            || pos < dynamic.dollar_l_curly_token()?.text_range().end()
+            || dynamic
+                .r_curly_token()
+                .map_or(false, |tok| tok.text_range().start() < pos)
+        {
+            return None;
+        }
+        let source_map = db.source_map(file_id);
+        // The interpolation itself is missing. Use the scope of the containing expression.
+        let expr = dynamic
+            .syntax()
+            .ancestors()
+            .find_map(|node| source_map.expr_for_node(AstPtr::new(&node)))?;
+        if let Some(items) = complete_flake_system(db, file_id, &dynamic, expr) {
+            return Some(items);
+        }
+        return complete_expr(db, file_id, TextRange::empty(pos), &parent, expr, "");
+    }
+
+    if !matches!(parent.kind(), SyntaxKind::STRING | SyntaxKind::INDENT_STRING)
+        // Attribute names cannot be interpolated strings.
+        || parent.parent().map_or(false, |p| p.kind() == SyntaxKind::ATTR_PATH)
+    {
+        return None;
+    }
+    // Must be between the quotes.
+    let start_quote = parent.first_token()?;
+    let end_quote = parent.last_token()?;
+    if pos < start_quote.text_range().end()
+        || (end_quote != start_quote
+            && matches!(end_quote.kind(), T!['"'] | T!["''"])
+            && end_quote.text_range().start() < pos)
+    {
+        return None;
+    }
+    Some(vec![CompletionItem {
+        label: "${}".into(),
+        source_range: TextRange::empty(pos),
+        replace: "\\${$0}".into(),
+        kind: CompletionItemKind::Snippet,
+        category: CompletionCategory::Keyword,
+        brief: Some("Interpolation".into()),
+        doc: None,
+        call_snippet: None,
+    }])
+}
+
+/// Complete an empty interpolated attribute name inside flake outputs, like `packages.${|}`.
+/// Only names likely to hold a system are offered, as well as literal systems.
+fn complete_flake_system(
+    db: &dyn TyDatabase,
+    file_id: FileId,
+    dynamic: &ast::Dynamic,
+    expr_id: ExprId,
+) -> Option<Vec<CompletionItem>> {
+    ast::Attrpath::cast(dynamic.syntax().parent()?)?;
+    let outputs = flake_outputs_lambda(db.upcast(), file_id)?;
+    let source_map = db.source_map(file_id);
+    let dynamic_range = dynamic.syntax().text_range();
+    if !source_map
+        .node_for_expr(outputs)?
+        .text_range()
+        .contains_range(dynamic_range)
+    {
+        return None;
+    }
+
+    let module = db.module(file_id);
+    let scopes = db.scopes(file_id);
+    let infer = db.infer(file_id);
+    let scope_id = scopes.scope_for_expr(expr_id)?;
+    let source_range = TextRange::empty(dynamic.dollar_l_curly_token()?.text_range().end());
+
+    // Names in current scopes, which are either called `system` or known to be strings.
+    let mut items = scopes
+        .ancestors(scope_id)
+        .filter_map(|scope| scope.as_definitions())
+        .flatten()
+        .filter_map(|(text, name)| {
+            let ty = infer.ty_for_name(*name);
+            (text == "system" || ty == Ty::String).then(|| CompletionItem {
+                label: text.clone(),
+                source_range,
+                replace: text.clone(),
+                kind: module[*name].kind.into(),
+                category: CompletionCategory::Local,
+                brief: Some(ty.display().to_string()),
+                doc: None,
+                call_snippet: None,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Literal systems replace the whole interpolation.
+    items.extend(FLAKE_SYSTEMS.iter().map(|&system| CompletionItem {
+        label: system.into(),
+        source_range: dynamic_range,
+        replace: system.into(),
+        kind: CompletionItemKind::Field,
+        category: CompletionCategory::Schema,
+        brief: Some("System".into()),
+        doc: None,
+        call_snippet: None,
+    }));
+
+    // Inner names shadow outer ones.
+    items.sort_by(|lhs, rhs| (&lhs.label, lhs.category).cmp(&(&rhs.label, rhs.category)));
+    items.dedup_by(|lhs, rhs| lhs.label == rhs.label);
+
+    Some(items)
+}
+
 fn complete_expr(
     db: &dyn TyDatabase,
     file_id: FileId,
     source_range: TextRange,
-    ref_node: ast::Ref,
+    node: &SyntaxNode,
+    expr_id: ExprId,
+    prefix: &str,
 ) -> Option<Vec<CompletionItem>> {
     let module = db.module(file_id);
-    let source_map = db.source_map(file_id);
-    let expr_id = source_map.expr_for_node(AstPtr::new(ref_node.syntax()))?;
     let scopes = db.scopes(file_id);
+    let infer = db.infer(file_id);
     let scope_id = scopes.scope_for_expr(expr_id)?;
 
-    let prefix = SmolStr::from(ref_node.token()?.text());
     let mut items = Vec::new();
     let mut feed = |compe: CompletionItem| {
-        if can_complete(&prefix, &compe.replace) {
+        if can_complete(prefix, &compe.replace) {
             items.push(compe);
         }
     };
@@ -192,21 +369,11 @@ fn complete_expr(
         .for_each(&mut feed);
 
     // Contectual keywords.
-    if ref_node
-        .syntax()
-        .ancestors()
-        .find_map(ast::IfThenElse::cast)
-        .is_some()
-    {
+    if node.ancestors().find_map(ast::IfThenElse::cast).is_some() {
         feed(keyword_to_completion("then", source_range));
         feed(keyword_to_completion("else", source_range));
     }
-    if ref_node
-        .syntax()
-        .ancestors()
-        .find_map(ast::LetIn::cast)
-        .is_some()
-    {
+    if node.ancestors().find_map(ast::LetIn::cast).is_some() {
         feed(keyword_to_completion("in", source_range));
     }
 
@@ -215,13 +382,42 @@ fn complete_expr(
         .ancestors(scope_id)
         .filter_map(|scope| scope.as_definitions())
         .flatten()
-        .map(|(text, name)| CompletionItem {
-            label: text.clone(),
-            source_range,
-            replace: text.clone(),
-            kind: module[*name].kind.into(),
-            brief: None,
-            doc: None,
+        .map(|(text, name)| {
+            let ty = infer.ty_for_name(*name);
+            CompletionItem {
+                label: text.clone(),
+                source_range,
+                replace: text.clone(),
+                kind: module[*name].kind.into(),
+                category: CompletionCategory::Local,
+                brief: Some(ty.display().to_string()),
+                doc: None,
+                call_snippet: call_snippet(text, &ty),
+            }
+        })
+        .for_each(&mut feed);
+
+    // Attributes of `with` environments with known types.
+    scopes
+        .ancestors(scope_id)
+        .filter_map(|scope| scope.as_with())
+        .filter_map(|with_expr| match &module[with_expr] {
+            &Expr::With(env, _) => infer.ty_for_expr(env).as_attrset().cloned(),
+            _ => None,
+        })
+        .flat_map(|set| {
+            set.iter()
+                .map(|(name, ty, _)| CompletionItem {
+                    label: name.clone(),
+                    source_range,
+                    replace: name.clone(),
+                    kind: CompletionItemKind::Field,
+                    category: CompletionCategory::With,
+                    brief: Some(ty.display().to_string()),
+                    doc: None,
+                    call_snippet: call_snippet(name, ty),
+                })
+                .collect::<Vec<_>>()
         })
         .for_each(&mut feed);
 
@@ -234,13 +430,37 @@ fn complete_expr(
             source_range,
             replace: name.into(),
             kind: b.kind.into(),
+            category: CompletionCategory::Builtin,
             brief: Some(b.summary.into()),
             doc: b.doc.map(|s| s.to_owned()),
+            call_snippet: known::BUILTINS
+                .as_attrset()
+                .and_then(|set| call_snippet(name, set.get(name)?)),
         })
         .for_each(&mut feed);
 
-    // TODO: Better sorting.
-    items.sort_by(|lhs, rhs| lhs.label.cmp(&rhs.label));
+    // Configured global names.
+    db.extra_globals()
+        .iter()
+        .map(|(name, ty)| CompletionItem {
+            label: name.clone(),
+            source_range,
+            replace: name.clone(),
+            kind: match ty {
+                Ty::Lambda(..) => CompletionItemKind::BuiltinFunction,
+                Ty::Attrset(_) => CompletionItemKind::BuiltinAttrset,
+                _ => CompletionItemKind::BuiltinConst,
+            },
+            category: CompletionCategory::Builtin,
+            brief: Some(ty.display().to_string()),
+            doc: None,
+            call_snippet: call_snippet(name, ty),
+        })
+        .for_each(&mut feed);
+
+    // Keep the highest ranked item among ones with the same label,
+    // since local names shadow builtins, and both shadow `with` attributes.
+    items.sort_by(|lhs, rhs| (&lhs.label, lhs.category).cmp(&(&rhs.label, rhs.category)));
     items.dedup_by(|lhs, rhs| lhs.label == rhs.label);
 
     Some(items)
@@ -315,8 +535,10 @@ fn complete_attrpath(
                             source_range,
                             replace: name,
                             kind: CompletionItemKind::LetBinding,
+                            category: CompletionCategory::Local,
                             brief: None,
                             doc: None,
+                            call_snippet: None,
                         }),
                 );
             }
@@ -363,24 +585,264 @@ fn complete_attrpath(
                         AttrSource::Unknown => CompletionItemKind::Field,
                         AttrSource::Name(name) => module[name].kind.into(),
                     },
+                    category: match src {
+                        AttrSource::Unknown => CompletionCategory::Schema,
+                        AttrSource::Name(_) => CompletionCategory::Local,
+                    },
                     brief: Some(ty.display().to_string()),
                     doc: None,
+                    call_snippet: None,
                 }),
         );
 
         Some(())
     })();
 
+    complete_callee_param_fields(
+        db,
+        file_id,
+        source_range,
+        &name_node,
+        &path_node,
+        &container_node,
+        &mut items,
+    );
+    complete_module_option(
+        db,
+        file_id,
+        source_range,
+        &name_node,
+        &path_node,
+        &container_node,
+        &mut items,
+    );
+    complete_license(source_range, &name_node, &container_node, &mut items);
+
     Some(items)
 }
 
+/// Complete fields expected by the callee when defining the attrset argument of a call,
+/// like `stdenv.mkDerivation { p| }`, or `f { | }` where `f = { a, b }: ...`.
+/// The attrset may also be returned by a function argument,
+/// like `stdenv.mkDerivation (finalAttrs: { p| })`.
+/// Fields already defined in the attrset are skipped.
+fn complete_callee_param_fields(
+    db: &dyn TyDatabase,
+    file_id: FileId,
+    source_range: TextRange,
+    name_node: &ast::Name,
+    path_node: &ast::Attrpath,
+    container_node: &SyntaxNode,
+    items: &mut Vec<CompletionItem>,
+) -> Option<()> {
+    if path_node.attrs().next()?.syntax() != name_node.syntax() {
+        return None;
+    }
+    let set = ast::AttrSet::cast(container_node.clone())?;
+    let mut arg = set.syntax().clone();
+    let mut parent = arg.parent()?;
+    let mut is_lambda_body = false;
+    loop {
+        if let Some(lambda) = ast::Lambda::cast(parent.clone()) {
+            if is_lambda_body || lambda.body()?.syntax() != &arg {
+                return None;
+            }
+            is_lambda_body = true;
+        } else if !ast::Paren::can_cast(parent.kind()) {
+            break;
+        }
+        arg = parent;
+        parent = arg.parent()?;
+    }
+    let apply = ast::Apply::cast(parent)?;
+    if apply.argument()?.syntax() != &arg {
+        return None;
+    }
+
+    let func = apply.function()?.flatten_paren()?;
+    let param_ty = match function_name(&func).as_deref().and_then(known::arg_schema) {
+        // Known builders accept both forms.
+        Some(schema) => schema.clone(),
+        None => {
+            let func_expr = db
+                .source_map(file_id)
+                .expr_for_node(AstPtr::new(func.syntax()))?;
+            let Ty::Lambda(param, _) = db.infer(file_id).ty_for_expr(func_expr) else {
+                return None;
+            };
+            match &*param {
+                Ty::Lambda(_, ret) if is_lambda_body => Ty::clone(ret),
+                _ if is_lambda_body => return None,
+                param => param.clone(),
+            }
+        }
+    };
+
+    let current_input = name_node
+        .token()
+        .map_or(String::new(), |tok| tok.text().into());
+    let defined = set
+        .bindings()
+        .flat_map(|binding| match binding {
+            ast::Binding::AttrpathValue(b) => b
+                .attrpath()
+                .into_iter()
+                .flat_map(|p| p.attrs().take(1))
+                .collect(),
+            ast::Binding::Inherit(i) => i.attrs().collect::<Vec<_>>(),
+        })
+        .filter_map(|attr| match AttrKind::of(attr) {
+            AttrKind::Static(name) => name,
+            AttrKind::Dynamic(_) => None,
+        })
+        .collect::<HashSet<_>>();
+
+    for (name, ty, _) in param_ty.as_attrset()?.iter() {
+        if *name == current_input
+            || defined.contains(&**name)
+            || items.iter().any(|item| item.label == *name)
+        {
+            continue;
+        }
+        items.push(CompletionItem {
+            label: name.clone(),
+            source_range,
+            replace: name.clone(),
+            kind: CompletionItemKind::Field,
+            category: CompletionCategory::Schema,
+            brief: Some(ty.display().to_string()),
+            doc: None,
+            call_snippet: None,
+        });
+    }
+
+    Some(())
+}
+
+/// Complete options declared by `mkOption` in the current file, or in files imported from it or
+/// from the flake file, when defining them under `config`,
+/// like `config.services.foo.|` or `config = { services.foo.| }`.
+/// Items already produced from types only get documentation attached.
+fn complete_module_option(
+    db: &dyn TyDatabase,
+    file_id: FileId,
+    source_range: TextRange,
+    name_node: &ast::Name,
+    path_node: &ast::Attrpath,
+    container_node: &SyntaxNode,
+    items: &mut Vec<CompletionItem>,
+) -> Option<()> {
+    let mut path = match_ast! {
+        match container_node {
+            ast::AttrSet(n) => attrset_path(n.syntax())?,
+            ast::Select(n) => match n.set()?.flatten_paren()? {
+                ast::Expr::Ref(r) if r.token()?.text() == "config" => vec!["config".into()],
+                _ => return None,
+            },
+            _ => return None,
+        }
+    };
+    for attr in path_node.attrs() {
+        if attr.syntax() == name_node.syntax() {
+            break;
+        }
+        let AttrKind::Static(Some(name)) = AttrKind::of(attr) else { return None };
+        path.push(name);
+    }
+    if path.first().map(|s| &**s) != Some("config") {
+        return None;
+    }
+
+    // Options may be declared in other modules loaded by the flake, like
+    // `nixosSystem { modules = [ ./options.nix ./config.nix ]; }`.
+    // Declarations of the current file come first.
+    let mut roots = vec![file_id];
+    roots.extend(
+        db.source_root_flake_info(db.file_source_root(file_id))
+            .map(|info| info.flake_file),
+    );
+    let mut files = reachable_files(db.upcast(), &roots)
+        .into_iter()
+        .collect::<Vec<_>>();
+    files.sort_by_key(|&file| (file != file_id, file));
+    let decls = files
+        .into_iter()
+        .flat_map(|file| option_declarations(&db.parse(file).syntax_node()));
+
+    let current_input = name_node
+        .token()
+        .map_or(String::new(), |tok| tok.text().into());
+    for decl in decls {
+        let Some(rest) = decl.path.strip_prefix(&path[1..]) else { continue };
+        let Some(name) = rest.first().filter(|&name| *name != current_input) else { continue };
+        let doc = if rest.len() == 1 { decl.doc } else { None };
+        match items.iter_mut().find(|item| item.label == name) {
+            Some(item) => {
+                if item.doc.is_none() {
+                    item.doc = doc;
+                }
+            }
+            None => items.push(CompletionItem {
+                label: name.into(),
+                source_range,
+                replace: name.into(),
+                kind: CompletionItemKind::Field,
+                category: CompletionCategory::Schema,
+                brief: None,
+                doc,
+                call_snippet: None,
+            }),
+        }
+    }
+    Some(())
+}
+
+/// Complete license names when selecting from `lib.licenses`, like `lib.licenses.m|`.
+fn complete_license(
+    source_range: TextRange,
+    name_node: &ast::Name,
+    container_node: &SyntaxNode,
+    items: &mut Vec<CompletionItem>,
+) -> Option<()> {
+    let select = ast::Select::cast(container_node.clone())?;
+    if license_attr(&select)?.syntax() != name_node.syntax() {
+        return None;
+    }
+    items.extend(known::LICENSES.iter().map(|&name| CompletionItem {
+        label: name.into(),
+        source_range,
+        replace: name.into(),
+        kind: CompletionItemKind::Field,
+        category: CompletionCategory::Schema,
+        brief: None,
+        doc: None,
+        call_snippet: None,
+    }));
+    Some(())
+}
+
+/// Find the lambda pattern if `pos` is a place to insert a new field,
+/// that is, just after `{` or `,` of the pattern.
+fn empty_pat_field_at(root_node: &SyntaxNode, pos: TextSize) -> Option<ast::Pat> {
+    let tok = root_node.token_at_offset(pos).left_biased()?;
+    let prev_tok = std::iter::successors(Some(tok), |tok| tok.prev_token())
+        .find(|tok| !tok.kind().is_whitespace())?;
+    if !matches!(prev_tok.kind(), T!['{'] | T![,]) {
+        return None;
+    }
+    ast::Pat::cast(prev_tok.parent()?)
+}
+
+/// Complete fields of a lambda pattern, from the inferred type of its argument.
+/// `name_node` is the field being typed, if any.
 fn complete_pat_param(
     db: &dyn TyDatabase,
     file_id: FileId,
     source_range: TextRange,
-    name_node: ast::Name,
-    lambda_node: ast::Lambda,
+    name_node: Option<ast::Name>,
+    pat_node: ast::Pat,
 ) -> Option<Vec<CompletionItem>> {
+    let lambda_node = pat_node.syntax().ancestors().find_map(ast::Lambda::cast)?;
     let source_map = db.source_map(file_id);
     let infer = db.infer(file_id);
     let lambda_expr = source_map.expr_for_node(AstPtr::new(lambda_node.syntax()))?;
@@ -388,42 +850,82 @@ fn complete_pat_param(
     let Ty::Lambda(arg_ty, _) = lambda_ty else { return None };
     let arg_set = arg_ty.as_attrset()?;
 
-    let name_tok = name_node.token()?;
-    let prefix = name_tok.text();
+    let prefix = match &name_node {
+        Some(name_node) => SmolStr::from(name_node.token()?.text()),
+        None => SmolStr::default(),
+    };
+    // Fields already in the pattern, except the current one.
+    let module = db.module(file_id);
+    let current_range = name_node.as_ref().map(|node| node.syntax().text_range());
+    let existing_fields = pattern_bindings(db, FilePos::new(file_id, source_range.start()))
+        .into_iter()
+        .filter(|&(_, name, _)| {
+            module[name].kind == NameKind::PatField
+                && source_map
+                    .nodes_for_name(name)
+                    .all(|ptr| Some(ptr.text_range()) != current_range)
+        })
+        .map(|(text, ..)| text)
+        .collect::<HashSet<_>>();
 
     let items = arg_set
         .iter()
-        .filter(|(name, ..)| prefix != name.as_str() && can_complete(prefix, name))
+        .filter(|(name, ..)| {
+            prefix != **name && !existing_fields.contains(*name) && can_complete(&prefix, name)
+        })
         .map(|(name, ty, _)| CompletionItem {
             label: name.clone(),
             source_range,
             replace: name.clone(),
             kind: CompletionItemKind::Param,
+            category: CompletionCategory::Schema,
             brief: Some(ty.display().to_string()),
             doc: None,
+            call_snippet: None,
         })
         .collect();
     Some(items)
 }
+
+/// Build a snippet applying a function to a placeholder for each curried argument.
+/// Arguments expecting functions are parenthesized, like `map ($1) $2`.
+fn call_snippet(name: &str, mut ty: &Ty) -> Option<String> {
+    let mut snippet = name.to_owned();
+    let mut arity = 0;
+    while let Ty::Lambda(arg, ret) = ty {
+        arity += 1;
+        if matches!(**arg, Ty::Lambda(..)) {
+            write!(snippet, " (${arity})").unwrap();
+        } else {
+            write!(snippet, " ${arity}").unwrap();
+        }
+        ty = ret;
+    }
+    (arity != 0).then_some(snippet)
+}
+
 fn keyword_to_completion(kw: &str, source_range: TextRange) -> CompletionItem {
     CompletionItem {
         label: kw.into(),
         source_range,
         replace: kw.into(),
         kind: CompletionItemKind::Keyword,
+        category: CompletionCategory::Keyword,
         brief: None,
         doc: None,
+        call_snippet: None,
     }
 }
 
-// Subsequence matching.
+// Case-insensitive subsequence matching, the loosest one.
+// The server filters items further according to `completion.matching`.
 fn can_complete(prefix: &str, replace: &str) -> bool {
     let mut rest = prefix.as_bytes();
     if rest.is_empty() {
         return true;
     }
     for b in replace.bytes() {
-        if rest.first().unwrap() == &b {
+        if rest.first().unwrap().eq_ignore_ascii_case(&b) {
             rest = &rest[1..];
             if rest.is_empty() {
                 return true;
@@ -435,8 +937,10 @@ fn can_complete(prefix: &str, replace: &str) -> bool {
 
 #[cfg(test)]
 mod tests {
+    use super::{CompletionCategory, CompletionItemKind};
     use crate::base::SourceDatabase;
     use crate::tests::TestDB;
+    use crate::{Change, Ty};
     use expect_test::{expect, Expect};
 
     #[track_caller]
@@ -469,6 +973,28 @@ mod tests {
         check_trigger(fixture, None, label, expect);
     }
 
+    #[track_caller]
+    fn check_brief(fixture: &str, label: &str, expect: Expect) {
+        let (db, f) = TestDB::from_fixture(fixture).unwrap();
+        let compes = super::completions(&db, f[0], None).expect("No completion");
+        let item = compes
+            .iter()
+            .find(|item| item.label == label)
+            .expect("No expected completion");
+        expect.assert_eq(item.brief.as_deref().unwrap_or_default());
+    }
+
+    #[track_caller]
+    fn check_doc(fixture: &str, label: &str, expect: Expect) {
+        let (db, f) = TestDB::from_fixture(fixture).unwrap();
+        let compes = super::completions(&db, f[0], None).expect("No completion");
+        let item = compes
+            .iter()
+            .find(|item| item.label == label)
+            .expect("No expected completion");
+        expect.assert_eq(item.doc.as_deref().unwrap_or_default());
+    }
+
     #[test]
     fn keyword() {
         check("l$0", "let", expect!["(Keyword) let"]);
@@ -517,6 +1043,51 @@ mod tests {
         check_no("attrN$0", "attrNames");
     }
 
+    #[test]
+    fn with_attr() {
+        check(
+            "with { foo = 1; }; f$0",
+            "foo",
+            expect!["(Field) with { foo = 1; }; foo"],
+        );
+        check(
+            "with builtins; attrN$0",
+            "attrNames",
+            expect!["(Field) with builtins; attrNames"],
+        );
+    }
+
+    #[test]
+    fn ranking() {
+        let category = |fixture: &str, label: &str| {
+            let (db, f) = TestDB::from_fixture(fixture).unwrap();
+            let compes = super::completions(&db, f[0], None).expect("No completion");
+            let mut items = compes.into_iter().filter(|item| item.label == label);
+            let item = items.next().expect("No expected completion");
+            assert_eq!(items.next(), None, "Duplicated completions");
+            (item.category, item.kind)
+        };
+
+        // Local bindings shadow builtins, and both shadow `with` attributes.
+        assert_eq!(
+            category("let toString = 1; in toS$0", "toString"),
+            (CompletionCategory::Local, CompletionItemKind::LetBinding),
+        );
+        assert_eq!(
+            category("with { toString = 1; }; toS$0", "toString"),
+            (
+                CompletionCategory::Builtin,
+                CompletionItemKind::BuiltinFunction
+            ),
+        );
+        assert_eq!(
+            category("with { foo = 1; }; let foo = 1; in f$0", "foo"),
+            (CompletionCategory::Local, CompletionItemKind::LetBinding),
+        );
+        assert!(CompletionCategory::Local < CompletionCategory::Builtin);
+        assert!(CompletionCategory::Builtin < CompletionCategory::With);
+    }
+
     #[test]
     fn inherit() {
         check("{ i$0 }", "inherit", expect!["(Keyword) { inherit }"]);
@@ -718,4 +1289,261 @@ mod tests {
                 }"#]],
         );
     }
+
+    #[test]
+    fn parameter_definition_empty() {
+        check(
+            "({ $0 }: 42) { a = 1; b = 2; }",
+            "a",
+            expect!["(Param) ({ a }: 42) { a = 1; b = 2; }"],
+        );
+        check(
+            "({ a, $0 }: 42) { a = 1; b = 2; }",
+            "b",
+            expect!["(Param) ({ a, b }: 42) { a = 1; b = 2; }"],
+        );
+        check(
+            "({ a, b$0 }: 42) { a = 1; bar = 2; }",
+            "bar",
+            expect!["(Param) ({ a, bar }: 42) { a = 1; bar = 2; }"],
+        );
+        // Already in the pattern.
+        check_no("({ a, $0 }: 42) { a = 1; b = 2; }", "a");
+        check_no("({ a, b$0 }: 42) { a = 1; bar = 2; }", "a");
+        // Not at a field position.
+        check_no("({ a, ... $0}: 42) { a = 1; b = 2; }", "b");
+        check_no("$0({ }: 42) { a = 1; b = 2; }", "a");
+        // Unknown argument.
+        check_no("x: ({ $0 }: 42) x", "a");
+    }
+
+    #[test]
+    fn string_interpolation_snippet() {
+        check(r#""foo $0""#, "${}", expect![[r#"(Snippet) "foo \${$0}""#]]);
+        check(r#""$0""#, "${}", expect![[r#"(Snippet) "\${$0}""#]]);
+        check(
+            "''\n  foo$0\n''",
+            "${}",
+            expect![[r#"
+            (Snippet) ''
+              foo\${$0}
+            ''"#]],
+        );
+        // Nothing else is offered in strings.
+        check_no(r#"let foo = 1; in "f$0""#, "foo");
+        check_no(r#""f$0""#, "false");
+        // Outside of quotes.
+        check_no(r#"$0"foo""#, "${}");
+        check_no(r#""foo"$0"#, "${}");
+    }
+
+    #[test]
+    fn string_interpolation_empty() {
+        check(
+            r#"let foo = 1; in "${$0}""#,
+            "foo",
+            expect![[r#"(LetBinding) let foo = 1; in "${foo}""#]],
+        );
+        check(
+            r#"foo: "bar ${$0} baz""#,
+            "foo",
+            expect![[r#"(Param) foo: "bar ${foo} baz""#]],
+        );
+        check(
+            r#""${$0}""#,
+            "true",
+            expect![[r#"(BuiltinConst) "${true}""#]],
+        );
+        check_no(r#""${$0}""#, "${}");
+    }
+
+    #[test]
+    fn flake_output_system() {
+        let fixture = r#"
+#- /flake.nix input:nixpkgs=/nix/store/eeee
+{
+    outputs = { self, nixpkgs }: let
+        system = "x86_64-linux";
+        pkgs = nixpkgs.legacyPackages.${system};
+    in {
+        packages.${$0}.default = pkgs.hello;
+    };
+}
+        "#;
+        check(
+            fixture,
+            "system",
+            expect![[r#"
+                (LetBinding) {
+                    outputs = { self, nixpkgs }: let
+                        system = "x86_64-linux";
+                        pkgs = nixpkgs.legacyPackages.${system};
+                    in {
+                        packages.${system}.default = pkgs.hello;
+                    };
+                }"#]],
+        );
+        check(
+            fixture,
+            "aarch64-linux",
+            expect![[r#"
+                (Field) {
+                    outputs = { self, nixpkgs }: let
+                        system = "x86_64-linux";
+                        pkgs = nixpkgs.legacyPackages.${system};
+                    in {
+                        packages.aarch64-linux.default = pkgs.hello;
+                    };
+                }"#]],
+        );
+        check_no(fixture, "pkgs");
+        check_no(fixture, "nixpkgs");
+        // Not in flake outputs.
+        check_no(r#"{ packages.${$0}.default = 1; }"#, "x86_64-linux");
+    }
+
+    #[test]
+    fn call_snippet() {
+        let (db, f) = TestDB::from_fixture("let foo = a: b: a + b; in f$0").unwrap();
+        let compes = super::completions(&db, f[0], None).expect("No completion");
+        let item = compes.iter().find(|item| item.label == "foo").unwrap();
+        assert_eq!(item.call_snippet.as_deref(), Some("foo $1 $2"));
+        let item = compes.iter().find(|item| item.label == "false").unwrap();
+        assert_eq!(item.call_snippet, None);
+
+        let (db, f) = TestDB::from_fixture("ma$0").unwrap();
+        let compes = super::completions(&db, f[0], None).expect("No completion");
+        let item = compes.iter().find(|item| item.label == "map").unwrap();
+        assert_eq!(item.call_snippet.as_deref(), Some("map ($1) $2"));
+    }
+
+    #[test]
+    fn extra_globals() {
+        let (mut db, f) = TestDB::from_fixture("hostName$0").unwrap();
+        let mut change = Change::default();
+        change.set_extra_globals([("hostName".into(), Ty::String)].into_iter().collect());
+        change.apply(&mut db);
+
+        assert_eq!(
+            crate::ide::diagnostics::diagnostics(&db, f[0].file_id),
+            Vec::new()
+        );
+        let compes = super::completions(&db, f[0], None).expect("No completion");
+        let item = compes.iter().find(|item| item.label == "hostName").unwrap();
+        assert_eq!(item.kind, CompletionItemKind::BuiltinConst);
+        assert_eq!(item.brief.as_deref(), Some("string"));
+    }
+
+    #[test]
+    fn brief_type() {
+        check_brief("let foo = 1; in f$0", "foo", expect!["int"]);
+        check_brief(
+            "let foo = a: if a then 1 else 2; in f$0",
+            "foo",
+            expect!["bool → int"],
+        );
+        check_brief("{ foo }: f$0", "foo", expect!["?"]);
+        check_brief("{ foo.bar = true; }.foo.b$0", "bar", expect!["bool"]);
+        check_brief(
+            "let a.foo = { bar = 1; }; in a.f$0",
+            "foo",
+            expect!["{ bar: int }"],
+        );
+    }
+
+    #[test]
+    fn callee_param_fields() {
+        check_brief(
+            r#"stdenv: stdenv.mkDerivation { pname = "foo"; v$0 }"#,
+            "version",
+            expect!["string"],
+        );
+        check_brief(
+            "stdenv: stdenv.mkDerivation ({ b$0 })",
+            "buildInputs",
+            expect!["[{ }]"],
+        );
+        check_brief(
+            r#"stdenv: stdenv.mkDerivation (finalAttrs: { pname = "foo"; v$0 })"#,
+            "version",
+            expect!["string"],
+        );
+        check(
+            "stdenv: stdenv.mkDerivation { s$0 }",
+            "src",
+            expect!["(Field) stdenv: stdenv.mkDerivation { src }"],
+        );
+        check_no(
+            r#"stdenv: stdenv.mkDerivation { pname = "foo"; p$0 }"#,
+            "pname",
+        );
+        check_no("f: f { p$0 }", "pname");
+    }
+
+    #[test]
+    fn module_option() {
+        let decls = r#"
+{ lib, ... }: {
+  options.services.foo = {
+    enable = lib.mkOption { description = "Whether to enable foo."; };
+    port = lib.mkOption { type = lib.types.port; description = "${name}"; };
+  };
+"#;
+        check_doc(
+            &format!("{decls} config.services.foo.e$0 = true; }}"),
+            "enable",
+            expect!["Whether to enable foo."],
+        );
+        check_doc(
+            &format!("{decls} config = lib.mkIf true {{ services.foo.p$0 = 1; }}; }}"),
+            "port",
+            expect!["Type: `lib.types.port`"],
+        );
+        check_doc(
+            &format!("{decls} config.s$0 = {{ }}; }}"),
+            "services",
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn license() {
+        check(
+            "{ meta.license = lib.licenses.gpl2C$0; }",
+            "gpl2Classpath",
+            expect!["(Field) { meta.license = lib.licenses.gpl2Classpath; }"],
+        );
+        check(
+            "with lib; { meta.license = [ licenses.hpndS$0 ]; }",
+            "hpndSellVariant",
+            expect!["(Field) with lib; { meta.license = [ licenses.hpndSellVariant ]; }"],
+        );
+        check_no("{ meta.license = lib.foo.m$0; }", "mit");
+    }
+
+    #[test]
+    fn module_option_from_flake() {
+        check_doc(
+            r#"
+#- /flake.nix input:nixpkgs=/nix/store/eeee
+{
+  outputs = { nixpkgs, ... }: {
+    nixosConfigurations.foo = nixpkgs.lib.nixosSystem {
+      modules = [ ./options.nix ./config.nix ];
+    };
+  };
+}
+
+#- /config.nix
+{ ... }: { config.services.foo.e$0 = true; }
+
+#- /options.nix
+{ lib, ... }: {
+  options.services.foo.enable = lib.mkOption { description = "Whether to enable foo."; };
+}
+            "#,
+            "enable",
+            expect!["Whether to enable foo."],
+        );
+    }
 }
@@ -0,0 +1,277 @@
+//! A tiny constant folder for the pure subset of expressions: literals, arithmetic of numbers,
+//! string concatenation, boolean logic, comparisons, and lists or attrsets of them.
+//!
+//! It is bounded by the size of the expression. No names are followed except builtin constants,
+//! thus it never recurses into functions, bindings or imports.
+use crate::def::{BinaryOp, BindingValue, Expr, ExprId, Literal, Module, NameResolution, UnaryOp};
+use smol_str::SmolStr;
+use std::cmp::Ordering;
+use std::fmt::{self, Write};
+use syntax::semantic::escape_literal_attr;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(SmolStr),
+    List(Vec<Value>),
+    /// Sorted by names, as Nix does.
+    Attrset(Vec<(SmolStr, Value)>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => f.write_str("null"),
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Int(i) => write!(f, "{i}"),
+            Self::Float(x) => write!(f, "{x}"),
+            Self::String(s) => {
+                f.write_char('"')?;
+                let mut chars = s.chars().peekable();
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '"' => f.write_str("\\\"")?,
+                        '\\' => f.write_str("\\\\")?,
+                        '\n' => f.write_str("\\n")?,
+                        '\r' => f.write_str("\\r")?,
+                        '\t' => f.write_str("\\t")?,
+                        '$' if chars.peek() == Some(&'{') => f.write_str("\\$")?,
+                        ch => f.write_char(ch)?,
+                    }
+                }
+                f.write_char('"')
+            }
+            Self::List(elems) => {
+                f.write_str("[")?;
+                for elem in elems {
+                    write!(f, " {elem}")?;
+                }
+                f.write_str(" ]")
+            }
+            Self::Attrset(fields) => {
+                f.write_str("{")?;
+                for (name, value) in fields {
+                    write!(f, " {} = {value};", escape_literal_attr(name))?;
+                }
+                f.write_str(" }")
+            }
+        }
+    }
+}
+
+/// Fold an expression into a value, or `None` if it is not a constant of the supported subset.
+pub(crate) fn fold(module: &Module, nameres: &NameResolution, expr: ExprId) -> Option<Value> {
+    match &module[expr] {
+        Expr::Literal(Literal::Int(i)) => Some(Value::Int(*i)),
+        Expr::Literal(Literal::Float(x)) => Some(Value::Float(x.0)),
+        Expr::Literal(Literal::String(s)) => Some(Value::String(s.clone())),
+        Expr::Reference(_) => match nameres.check_builtin(expr, module)? {
+            "null" => Some(Value::Null),
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        &Expr::Unary(Some(op), operand) => match (op, fold(module, nameres, operand)?) {
+            (UnaryOp::Negate, Value::Int(i)) => i.checked_neg().map(Value::Int),
+            (UnaryOp::Negate, Value::Float(x)) => Some(Value::Float(-x)),
+            (UnaryOp::Not, Value::Bool(b)) => Some(Value::Bool(!b)),
+            _ => None,
+        },
+        &Expr::Binary(Some(op), lhs, rhs) => {
+            let lhs = fold(module, nameres, lhs)?;
+            let rhs = fold(module, nameres, rhs)?;
+            fold_binary(op, lhs, rhs)
+        }
+        Expr::List(elems) => elems
+            .iter()
+            .map(|&elem| fold(module, nameres, elem))
+            .collect::<Option<Vec<_>>>()
+            .map(Value::List),
+        Expr::Attrset(bindings) => {
+            if !bindings.inherit_froms.is_empty() || !bindings.dynamics.is_empty() {
+                return None;
+            }
+            let mut fields = bindings
+                .statics
+                .iter()
+                .map(|&(name, value)| match value {
+                    BindingValue::Expr(value) => {
+                        Some((module[name].text.clone(), fold(module, nameres, value)?))
+                    }
+                    BindingValue::Inherit(_) | BindingValue::InheritFrom(_) => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            fields.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+            Some(Value::Attrset(fields))
+        }
+        _ => None,
+    }
+}
+
+fn fold_binary(op: BinaryOp, lhs: Value, rhs: Value) -> Option<Value> {
+    match op {
+        BinaryOp::Equal => return Some(Value::Bool(values_eq(&lhs, &rhs))),
+        BinaryOp::NotEqual => return Some(Value::Bool(!values_eq(&lhs, &rhs))),
+        BinaryOp::Less => return compare(&lhs, &rhs).map(|ord| Value::Bool(ord.is_lt())),
+        BinaryOp::Greater => return compare(&lhs, &rhs).map(|ord| Value::Bool(ord.is_gt())),
+        BinaryOp::LessEqual => return compare(&lhs, &rhs).map(|ord| Value::Bool(ord.is_le())),
+        BinaryOp::GreaterEqual => return compare(&lhs, &rhs).map(|ord| Value::Bool(ord.is_ge())),
+        _ => {}
+    }
+    match (lhs, rhs) {
+        (Value::Bool(a), Value::Bool(b)) => match op {
+            BinaryOp::And => Some(Value::Bool(a && b)),
+            BinaryOp::Or => Some(Value::Bool(a || b)),
+            BinaryOp::Imply => Some(Value::Bool(!a || b)),
+            _ => None,
+        },
+        (Value::String(a), Value::String(b)) if op == BinaryOp::Add => {
+            Some(Value::String(format!("{a}{b}").into()))
+        }
+        (Value::List(mut a), Value::List(b)) if op == BinaryOp::Concat => {
+            a.extend(b);
+            Some(Value::List(a))
+        }
+        (Value::Attrset(a), Value::Attrset(b)) if op == BinaryOp::Update => {
+            // Fields of the right operand take precedence.
+            let kept = a
+                .into_iter()
+                .filter(|(name, _)| b.binary_search_by(|(other, _)| other.cmp(name)).is_err())
+                .collect::<Vec<_>>();
+            let mut fields = b;
+            fields.extend(kept);
+            fields.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+            Some(Value::Attrset(fields))
+        }
+        (Value::Int(a), Value::Int(b)) => match op {
+            BinaryOp::Add => a.checked_add(b),
+            BinaryOp::Sub => a.checked_sub(b),
+            BinaryOp::Mul => a.checked_mul(b),
+            // Division by zero is an evaluation error.
+            BinaryOp::Div => a.checked_div(b),
+            _ => None,
+        }
+        .map(Value::Int),
+        (Value::Int(a), Value::Float(b)) => fold_float(op, a as f64, b),
+        (Value::Float(a), Value::Int(b)) => fold_float(op, a, b as f64),
+        (Value::Float(a), Value::Float(b)) => fold_float(op, a, b),
+        _ => None,
+    }
+}
+
+/// Equality of Nix, where integers and floats are compared by their numeric values.
+fn values_eq(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs, rhs) {
+        (&Value::Int(a), &Value::Float(b)) | (&Value::Float(b), &Value::Int(a)) => a as f64 == b,
+        (Value::List(a), Value::List(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| values_eq(a, b))
+        }
+        (Value::Attrset(a), Value::Attrset(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|((n1, v1), (n2, v2))| n1 == n2 && values_eq(v1, v2))
+        }
+        _ => lhs == rhs,
+    }
+}
+
+/// Ordering of numbers and strings. Other comparisons are left unfolded.
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (&Value::Int(a), &Value::Float(b)) => (a as f64).partial_cmp(&b),
+        (&Value::Float(a), &Value::Int(b)) => a.partial_cmp(&(b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn fold_float(op: BinaryOp, a: f64, b: f64) -> Option<Value> {
+    let ret = match op {
+        BinaryOp::Add => a + b,
+        BinaryOp::Sub => a - b,
+        BinaryOp::Mul => a * b,
+        BinaryOp::Div if b != 0.0 => a / b,
+        _ => return None,
+    };
+    Some(Value::Float(ret))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::def::DefDatabase;
+    use crate::tests::TestDB;
+
+    #[track_caller]
+    fn check(src: &str, expect: Option<&str>) {
+        let (db, file) = TestDB::single_file(src).unwrap();
+        let module = db.module(file);
+        let nameres = db.name_resolution(file);
+        let got = super::fold(&module, &nameres, module.entry_expr()).map(|v| v.to_string());
+        assert_eq!(got.as_deref(), expect, "{}", db.file_content(file));
+    }
+
+    #[test]
+    fn arithmetic() {
+        check("1 + 2", Some("3"));
+        check("8 * 1024 - -1", Some("8193"));
+        check("7 / 2", Some("3"));
+        check("1 + 0.5", Some("1.5"));
+        check("3.0 * 2", Some("6"));
+        check("1 / 0", None);
+        check("9223372036854775807 + 1", None);
+    }
+
+    #[test]
+    fn string_concat() {
+        check(r#""foo" + "-" + "bar""#, Some(r#""foo-bar""#));
+        check(r#""a" + 1"#, None);
+        check(r#""a" + ./b"#, None);
+    }
+
+    #[test]
+    fn logic() {
+        check("true && !false", Some("true"));
+        check("false -> null", None);
+        check("1 == 1.0 && [ 1 ] != [ 2 ] && 2 < 3.5", Some("true"));
+        check(r#""b" >= "a""#, Some("true"));
+        check("[ 1 ] < [ 2 ]", None);
+    }
+
+    #[test]
+    fn list_attrset_ops() {
+        check("[ 1 ] ++ [ (1 + 1) ]", Some("[ 1 2 ]"));
+        check("{ a = 1; b = 2; } // { a = 3; }", Some("{ a = 3; b = 2; }"));
+    }
+
+    #[test]
+    fn not_folded() {
+        check("x: 1 + 1", None);
+        check("let a = 1; in a + 1", None);
+        check("import ./a.nix + 1", None);
+        check("builtins.currentTime + 1", None);
+        check("{ a = 1; }.a + 1", None);
+        check("if true then 1 else 2", None);
+    }
+
+    #[test]
+    fn attrset() {
+        check(
+            r#"{ b = [ 1 (2 + 3) ]; a.c = "x${""}"; a.d = null; }"#,
+            None,
+        );
+        check(
+            r#"{ b = [ 1 (2 + 3) ]; a.c = "\${x}"; a.d = null; "e f" = true; }"#,
+            Some(r#"{ a = { c = "\${x}"; d = null; }; b = [ 1 5 ]; "e f" = true; }"#),
+        );
+        check("{ inherit a; }", None);
+        check("rec { a = 1; }", None);
+        check("{ ${x} = 1; }", None);
+    }
+}
@@ -1,6 +1,56 @@
-use crate::{DefDatabase, Diagnostic, FileId};
+use super::goto_definition::{binding_value_of, unguarded_select_missing_attr, GuardKey};
+use super::module_option::literal_string;
+use super::pattern_bindings::lambda_param_names;
+use crate::def::{
+    AstPtr, BinaryOp, BindingValue, Expr, ExprId, Literal, Module, NameId, NameResolution,
+    ResolveResult,
+};
+use crate::ty::known::{self, BUILTINS, IMPURE_BUILTINS, LICENSES};
+use crate::ty::Ty;
+use crate::{DefDatabase, Diagnostic, DiagnosticKind, FileId, FileRange, TyDatabase};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use syntax::ast::{self, AstNode};
+use syntax::rowan::WalkEvent;
+use syntax::semantic::{function_name, AttrKind};
+use syntax::{SyntaxKind, SyntaxNode, TextRange};
 
-pub(crate) fn diagnostics(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+/// Module combinators taking an attrset of option fields.
+const OPTION_COMBINATORS: &[OptionCombinator] = &[OptionCombinator {
+    name: "mkOption",
+    required: &[OptionField {
+        name: "type",
+        skeleton: "types.anything",
+        from_lib: true,
+    }],
+    recommended: &[OptionField {
+        name: "description",
+        skeleton: "\"\"",
+        from_lib: false,
+    }],
+}];
+
+struct OptionCombinator {
+    name: &'static str,
+    required: &'static [OptionField],
+    recommended: &'static [OptionField],
+}
+
+/// A field of the attrset argument, with a skeleton value to be inserted by the quickfix.
+struct OptionField {
+    name: &'static str,
+    skeleton: &'static str,
+    /// Whether the skeleton is an attribute path of `lib`, to be qualified at the call site.
+    from_lib: bool,
+}
+
+/// Builtins whose parameters are unions, which are approximated by one of the variants
+/// in their known signatures. Their arguments are not checked.
+const UNION_PARAM_BUILTINS: &[&str] = &["fetchGit", "fetchMercurial", "fetchTarball", "fetchTree"];
+
+pub(crate) fn diagnostics(db: &dyn TyDatabase, file: FileId) -> Vec<Diagnostic> {
+    let _span = tracing::info_span!("diagnostics", file_id = ?file).entered();
+    let def_db = db.upcast();
     let mut diags = Vec::new();
 
     // Parsing.
@@ -12,19 +62,922 @@ pub(crate) fn diagnostics(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic>
     diags.extend(module.diagnostics().iter().cloned());
 
     // Name resolution.
-    diags.extend(db.name_resolution(file).to_diagnostics(db, file));
+    diags.extend(db.name_resolution(file).to_diagnostics(def_db, file));
 
     // Liveness check.
     let liveness = db.liveness_check(file);
-    diags.extend(liveness.to_diagnostics(db, file));
+    diags.extend(liveness.to_diagnostics(def_db, file));
+
+    // Recursion.
+    diags.extend(inherit_cycle(def_db, file));
+
+    // Style.
+    diags.extend(top_level_with(def_db, file));
+    diags.extend(missing_rec(def_db, file));
+    diags.extend(shadowed_param(def_db, file));
+
+    // Flakes.
+    diags.extend(impure_builtins(def_db, file));
+
+    // Nixpkgs.
+    diags.extend(unknown_license(def_db, file));
+    diags.extend(fetcher_hash(def_db, file));
+
+    // Modules.
+    diags.extend(missing_option_field(def_db, file));
+
+    // Type checking.
+    diags.extend(builtin_type_mismatch(db, file));
+    diags.extend(missing_attr(def_db, file));
+
+    diags
+}
+
+/// A call of a fetcher taking an attrset with a fixed-output hash, like `fetchFromGitHub { }`.
+pub(crate) struct FetcherCall {
+    pub name: String,
+    pub set: ast::AttrSet,
+    /// The binding of the hash field, if any.
+    pub hash: Option<ast::AttrpathValue>,
+}
+
+/// Recognize a fetcher call by the function name. Attrsets with dynamic attributes or inherited
+/// hashes are skipped since their hashes are not statically known.
+pub(crate) fn fetcher_call(apply: &ast::Apply) -> Option<FetcherCall> {
+    let name = function_name(&apply.function()?.flatten_paren()?)?;
+    if !known::HASHED_FETCHERS.contains(&&*name) {
+        return None;
+    }
+    let ast::Expr::AttrSet(set) = apply.argument()?.flatten_paren()? else { return None };
+
+    let mut hash = None;
+    for binding in set.bindings() {
+        match binding {
+            ast::Binding::AttrpathValue(b) => {
+                let mut attrs = b.attrpath()?.attrs();
+                let AttrKind::Static(Some(field)) = AttrKind::of(attrs.next()?) else {
+                    return None;
+                };
+                if attrs.next().is_none() && known::FETCHER_HASH_FIELDS.contains(&&*field) {
+                    hash = Some(b);
+                }
+            }
+            ast::Binding::Inherit(i) => {
+                for attr in i.attrs() {
+                    let AttrKind::Static(Some(field)) = AttrKind::of(attr) else { return None };
+                    if known::FETCHER_HASH_FIELDS.contains(&&*field) {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+    Some(FetcherCall { name, set, hash })
+}
+
+/// Check if the hash is a placeholder, like `""` or `lib.fakeHash`.
+fn is_placeholder_hash(expr: &ast::Expr) -> bool {
+    if let Some(s) = literal_string(expr) {
+        return known::FAKE_HASHES.contains(&&*s);
+    }
+    matches!(
+        function_name(expr).as_deref(),
+        Some("fakeHash" | "fakeSha256")
+    )
+}
+
+fn fetcher_hash(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    for apply in db
+        .parse(file)
+        .syntax_node()
+        .descendants()
+        .filter_map(ast::Apply::cast)
+    {
+        let Some(call) = fetcher_call(&apply) else { continue };
+        match call.hash.and_then(|b| b.value()) {
+            None => {
+                let Some(func) = apply.function() else { continue };
+                diags.push(
+                    Diagnostic::new(
+                        func.syntax().text_range(),
+                        DiagnosticKind::MissingFetcherHash,
+                    )
+                    .with_note(
+                        FileRange::new(file, call.set.syntax().text_range()),
+                        "Missing `hash`",
+                    ),
+                );
+            }
+            Some(value) if is_placeholder_hash(&value) => diags.push(Diagnostic::new(
+                value.syntax().text_range(),
+                DiagnosticKind::PlaceholderFetcherHash,
+            )),
+            Some(_) => {}
+        }
+    }
+    diags
+}
+
+/// A field missing in the attrset argument of an option combinator, like `mkOption { }`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MissingOptionField {
+    pub name: &'static str,
+    pub skeleton: String,
+    pub required: bool,
+}
+
+/// Get the attrset argument and its missing fields, if `apply` is a call of an option
+/// combinator, like `lib.mkOption { ... }`. The callee must resolve to a `lib` function.
+/// Attrsets with dynamic attributes are skipped since their fields are not statically known.
+pub(crate) fn option_missing_fields(
+    db: &dyn DefDatabase,
+    file: FileId,
+    apply: &ast::Apply,
+) -> Option<(ast::AttrSet, Vec<MissingOptionField>)> {
+    let func = apply.function()?.flatten_paren()?;
+    let name = function_name(&func)?;
+    let combinator = OPTION_COMBINATORS.iter().find(|c| c.name == name)?;
+    let ast::Expr::AttrSet(set) = apply.argument()?.flatten_paren()? else { return None };
+    let lib_prefix = lib_prefix(db, file, &func)?;
+
+    let mut defined = HashSet::new();
+    for binding in set.bindings() {
+        let attrs = match binding {
+            ast::Binding::AttrpathValue(b) => b.attrpath()?.attrs().take(1).collect::<Vec<_>>(),
+            ast::Binding::Inherit(i) => i.attrs().collect(),
+        };
+        for attr in attrs {
+            let AttrKind::Static(Some(field)) = AttrKind::of(attr) else { return None };
+            defined.insert(field);
+        }
+    }
+
+    let missing = combinator
+        .required
+        .iter()
+        .map(|field| (field, true))
+        .chain(combinator.recommended.iter().map(|field| (field, false)))
+        .filter(|(field, _)| !defined.contains(field.name))
+        .map(|(field, required)| MissingOptionField {
+            name: field.name,
+            skeleton: if field.from_lib {
+                format!("{lib_prefix}{}", field.skeleton)
+            } else {
+                field.skeleton.into()
+            },
+            required,
+        })
+        .collect();
+    Some((set, missing))
+}
+
+/// Check if the callee resolves to a function from `lib`, and get the prefix to access other
+/// attributes of `lib` at the call site: `lib.` for `lib.mkOption`, or an empty string for
+/// `mkOption` from `{ mkOption, types, ... }:` or `with lib;`.
+fn lib_prefix(db: &dyn DefDatabase, file: FileId, func: &ast::Expr) -> Option<String> {
+    let module = db.module(file);
+    let nameres = db.name_resolution(file);
+    let expr = db
+        .source_map(file)
+        .expr_for_node(AstPtr::new(func.syntax()))?;
+    match (func, &module[expr]) {
+        // Like `lib.mkOption` or `lib.options.mkOption`.
+        (ast::Expr::Select(sel), &Expr::Select(set, ..)) => {
+            if let Expr::Reference(_) = &module[set] {
+                nameres.get(set)?;
+            }
+            Some(format!("{}.", sel.set()?.syntax()))
+        }
+        (ast::Expr::Ref(_), Expr::Reference(_)) => {
+            match nameres.get(expr)? {
+                ResolveResult::Definition(name) => {
+                    // A local function of the same name, like `let mkOption = x: x; in`.
+                    if let Some(BindingValue::Expr(value)) = binding_value_of(&module, *name) {
+                        if !matches!(module[value], Expr::Reference(_) | Expr::Select(..)) {
+                            return None;
+                        }
+                    }
+                }
+                ResolveResult::WithExprs(_) | ResolveResult::ExtraGlobal(_) => {}
+                ResolveResult::Builtin(_) => return None,
+            }
+            let scopes = db.scopes(file);
+            let scope = scopes.scope_for_expr(expr)?;
+            let in_scope = |name: &str| {
+                scopes.ancestors(scope).any(|data| {
+                    data.as_with().is_some()
+                        || data
+                            .as_definitions()
+                            .map_or(false, |defs| defs.contains_key(name))
+                })
+            };
+            let prefix = if !in_scope("types") && in_scope("lib") {
+                "lib."
+            } else {
+                ""
+            };
+            Some(prefix.into())
+        }
+        _ => None,
+    }
+}
+
+fn missing_option_field(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    for apply in db
+        .parse(file)
+        .syntax_node()
+        .descendants()
+        .filter_map(ast::Apply::cast)
+    {
+        let Some((set, missing)) = option_missing_fields(db, file, &apply) else { continue };
+        let Some(func) = apply.function() else { continue };
+        for field in missing {
+            let kind = if field.required {
+                DiagnosticKind::MissingOptionField
+            } else {
+                DiagnosticKind::MissingOptionRecommendedField
+            };
+            let note = format!("Missing `{}`", field.name);
+            diags.push(
+                Diagnostic::new(func.syntax().text_range(), kind)
+                    .with_note(FileRange::new(file, set.syntax().text_range()), note),
+            );
+        }
+    }
+    diags
+}
+
+/// Parse errors of the file with messages, without running any semantic checks.
+/// They are also included in [`diagnostics`].
+pub(crate) fn syntax_errors(db: &dyn DefDatabase, file: FileId) -> Vec<(TextRange, String)> {
+    db.parse(file)
+        .errors()
+        .iter()
+        .map(|err| (err.range, err.kind.to_string()))
+        .collect()
+}
+
+/// Report parameters of curried lambdas shadowing earlier ones in the same chain, like the
+/// second `a` in `a: b: a: a`. Duplicates inside a single pattern are reported by lowering.
+/// Names starting with `_` are intentionally unused, thus never reported.
+fn shadowed_param(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    let module = db.module(file);
+    let source_map = db.source_map(file);
+
+    // Lambdas which are bodies of other lambdas, thus not heads of chains.
+    let curried = module
+        .exprs()
+        .filter_map(|(_, kind)| match kind {
+            &Expr::Lambda(_, _, body) if matches!(module[body], Expr::Lambda(..)) => Some(body),
+            _ => None,
+        })
+        .collect::<HashSet<_>>();
+
+    let mut diags = Vec::new();
+    for (expr, _) in module.exprs() {
+        if curried.contains(&expr) {
+            continue;
+        }
+        let mut defined = HashMap::new();
+        let mut cur = expr;
+        while let Expr::Lambda(param, pat, body) = &module[cur] {
+            let names = lambda_param_names(*param, pat.as_ref()).collect::<Vec<_>>();
+            for &name in &names {
+                let text = &module[name].text;
+                if text.starts_with('_') {
+                    continue;
+                }
+                let Some(&prev) = defined.get(text) else { continue };
+                let ptr = source_map.nodes_for_name(name).next();
+                let prev_ptr = source_map.nodes_for_name(prev).next();
+                let Some((ptr, prev_ptr)) = ptr.zip(prev_ptr) else { continue };
+                diags.push(
+                    Diagnostic::new(ptr.text_range(), DiagnosticKind::ShadowedParam).with_note(
+                        FileRange::new(file, prev_ptr.text_range()),
+                        "Previously defined here",
+                    ),
+                );
+            }
+            defined.extend(
+                names
+                    .into_iter()
+                    .map(|name| (module[name].text.clone(), name)),
+            );
+            cur = *body;
+        }
+    }
+    diags
+}
+
+/// Report the `with` expression directly under the top-level lambdas, like,
+/// `{ lib, ... }: with lib; { ... }`.
+/// Unused ones are already reported by liveness check.
+fn top_level_with(db: &dyn DefDatabase, file: FileId) -> Option<Diagnostic> {
+    let module = db.module(file);
+    let mut expr = module.entry_expr();
+    while let Expr::Lambda(_, _, body) = &module[expr] {
+        expr = *body;
+    }
+    if !matches!(module[expr], Expr::With(..)) {
+        return None;
+    }
+    db.name_reference(file).with_references(expr)?;
+
+    let ptr = db.source_map(file).node_for_expr(expr)?;
+    let node = ast::With::cast(ptr.to_node(&db.parse(file).syntax_node()))?;
+    let header_range = node
+        .with_token()?
+        .text_range()
+        .cover(node.semicolon_token()?.text_range());
+    Some(Diagnostic::new(header_range, DiagnosticKind::TopLevelWith))
+}
+
+/// Report undefined names which would be resolved to a sibling attribute if the attrset were `rec`.
+fn missing_rec(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    let module = db.module(file);
+    let source_map = db.source_map(file);
+    let nameres = db.name_resolution(file);
+    let root = db.parse(file).syntax_node();
+    module
+        .exprs()
+        .filter(|&(expr, kind)| matches!(kind, Expr::Reference(_)) && nameres.get(expr).is_none())
+        .filter_map(|(expr, _)| {
+            let ptr = source_map.node_for_expr(expr)?;
+            let ref_node = ast::Ref::cast(ptr.to_node(&root))?;
+            let (_, name) = attrset_missing_rec(db, file, &ref_node)?;
+            let mut diag = Diagnostic::new(ptr.text_range(), DiagnosticKind::MissingRec);
+            for ptr in source_map.nodes_for_name(name) {
+                diag = diag.with_note(
+                    FileRange::new(file, ptr.text_range()),
+                    "Sibling attribute defined here",
+                );
+            }
+            Some(diag)
+        })
+        .collect()
+}
+
+/// Find the non-`rec` attrset, which would resolve the undefined reference to its attribute
+/// if it were `rec`. Returns the attrset and the name of the sibling attribute.
+///
+/// This is conservative. The reference must be inside the value of a binding of the attrset,
+/// and must not be the binding itself, which would cause infinite recursion.
+pub(crate) fn attrset_missing_rec(
+    db: &dyn DefDatabase,
+    file: FileId,
+    ref_node: &ast::Ref,
+) -> Option<(ast::AttrSet, NameId)> {
+    let source_map = db.source_map(file);
+    let ref_expr = source_map.expr_for_node(AstPtr::new(ref_node.syntax()))?;
+    if db.name_resolution(file).get(ref_expr).is_some() {
+        return None;
+    }
+    let module = db.module(file);
+    let Expr::Reference(text) = &module[ref_expr] else { return None };
+
+    let ref_range = ref_node.syntax().text_range();
+    let mut prev = ref_node.syntax().clone();
+    for node in ref_node.syntax().ancestors().skip(1) {
+        let binding = ast::AttrpathValue::cast(mem::replace(&mut prev, node.clone()));
+        let (Some(binding), Some(set)) = (binding, ast::AttrSet::cast(node)) else { continue };
+        if !binding
+            .value()
+            .map_or(false, |value| value.syntax().text_range().contains_range(ref_range))
+        {
+            continue;
+        }
+        // `{ a = a; }` is never intended to be recursive.
+        let first_attr = binding.attrpath().and_then(|path| path.attrs().next());
+        if first_attr.map_or(false, |attr| attr.syntax().text() == text.as_str()) {
+            return None;
+        }
 
+        let set_expr = source_map.expr_for_node(AstPtr::new(set.syntax()))?;
+        let Expr::Attrset(bindings) = &module[set_expr] else { continue };
+        if let Some(&(name, _)) = bindings
+            .statics
+            .iter()
+            .find(|&&(name, _)| module[name].text == *text)
+        {
+            return Some((set, name));
+        }
+    }
+    None
+}
+
+/// Report `inherit (...)` attributes of `rec` attrsets which strictly depend on themselves,
+/// like `rec { inherit (a) b; a = b; }`, which is an infinite recursion once evaluated.
+///
+/// This is conservative. Only dependencies which must be forced to evaluate a binding into its
+/// weak head normal form are followed, so lazy self-references like
+/// `rec { inherit (self) x; self = { x = 1; }; }` are fine.
+fn inherit_cycle(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    let module = db.module(file);
+    let source_map = db.source_map(file);
+    let nameres = db.name_resolution(file);
+
+    let mut diags = Vec::new();
+    for (_, kind) in module.exprs() {
+        let Expr::RecAttrset(bindings) = kind else { continue };
+        let deps = bindings
+            .statics
+            .iter()
+            .map(|&(name, value)| {
+                let (BindingValue::Inherit(e)
+                | BindingValue::InheritFrom(e)
+                | BindingValue::Expr(e)) = value;
+                let mut deps = Vec::new();
+                strict_deps(&module, &nameres, e, &mut deps);
+                (name, deps)
+            })
+            .collect::<HashMap<_, _>>();
+        let reachable = |from: NameId| {
+            let mut visited = HashSet::new();
+            let mut stack = deps[&from].clone();
+            while let Some(name) = stack.pop() {
+                if visited.insert(name) {
+                    stack.extend(deps.get(&name).into_iter().flatten());
+                }
+            }
+            visited
+        };
+
+        for &(name, value) in bindings.statics.iter() {
+            if !matches!(value, BindingValue::InheritFrom(_)) {
+                continue;
+            }
+            let reached = reachable(name);
+            if !reached.contains(&name) {
+                continue;
+            }
+            let Some(ptr) = source_map.nodes_for_name(name).next() else { continue };
+            let mut cycle = reached
+                .into_iter()
+                .filter(|&other| other != name && reachable(other).contains(&name))
+                .filter_map(|other| source_map.nodes_for_name(other).next())
+                .map(|ptr| ptr.text_range())
+                .collect::<Vec<_>>();
+            cycle.sort_by_key(|range| range.start());
+            let mut diag = Diagnostic::new(ptr.text_range(), DiagnosticKind::InheritCycle);
+            for range in cycle {
+                diag = diag.with_note(FileRange::new(file, range), "Part of the cycle");
+            }
+            diags.push(diag);
+        }
+    }
+    diags
+}
+
+/// Collect names which must be forced to evaluate `expr` into its weak head normal form.
+/// Operands of short-circuiting operators, branches and function arguments are lazy.
+fn strict_deps(module: &Module, nameres: &NameResolution, expr: ExprId, out: &mut Vec<NameId>) {
+    match &module[expr] {
+        Expr::Reference(_) => {
+            if let Some(&ResolveResult::Definition(name)) = nameres.get(expr) {
+                out.push(name);
+            }
+        }
+        &Expr::Select(set, ..)
+        | &Expr::HasAttr(set, _)
+        | &Expr::Apply(set, _)
+        | &Expr::Unary(_, set)
+        | &Expr::IfThenElse(set, ..)
+        | &Expr::With(_, set)
+        | &Expr::LetIn(_, set) => strict_deps(module, nameres, set, out),
+        &Expr::Binary(op, lhs, rhs) => {
+            strict_deps(module, nameres, lhs, out);
+            if !matches!(
+                op,
+                None | Some(BinaryOp::And | BinaryOp::Or | BinaryOp::Imply)
+            ) {
+                strict_deps(module, nameres, rhs, out);
+            }
+        }
+        &Expr::Assert(cond, body) => {
+            strict_deps(module, nameres, cond, out);
+            strict_deps(module, nameres, body, out);
+        }
+        Expr::StringInterpolation(parts) | Expr::PathInterpolation(parts) => {
+            for &e in parts.iter() {
+                strict_deps(module, nameres, e, out);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Report attrsets, lists and `let`s nested deeper than `max_depth`.
+/// Only the outermost ones exceeding the limit are reported, on their opening tokens.
+pub(crate) fn deep_nesting(
+    db: &dyn DefDatabase,
+    file: FileId,
+    max_depth: usize,
+) -> Vec<Diagnostic> {
+    fn is_nesting(node: &SyntaxNode) -> bool {
+        matches!(
+            node.kind(),
+            SyntaxKind::ATTR_SET | SyntaxKind::LIST | SyntaxKind::LET_IN
+        )
+    }
+
+    let mut diags = Vec::new();
+    let mut depth = 0usize;
+    let mut preorder = db.parse(file).syntax_node().preorder();
+    while let Some(event) = preorder.next() {
+        match event {
+            WalkEvent::Enter(node) if is_nesting(&node) => {
+                depth += 1;
+                if depth > max_depth {
+                    if let Some(tok) = node.first_token() {
+                        diags.push(Diagnostic::new(
+                            tok.text_range(),
+                            DiagnosticKind::DeepNesting,
+                        ));
+                    }
+                    preorder.skip_subtree();
+                }
+            }
+            WalkEvent::Leave(node) if is_nesting(&node) => depth -= 1,
+            _ => {}
+        }
+    }
+    diags
+}
+
+/// Report bindings whose values contain definite errors, with notes on the errors.
+/// These are undefined names anywhere in the value, and `throw` or `abort` at its top.
+/// Errors inside nested attrsets and `let` bindings are only reported on the inner bindings.
+pub(crate) fn broken_bindings(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    fn undefined_refs(
+        module: &Module,
+        nameres: &NameResolution,
+        expr: ExprId,
+        out: &mut Vec<ExprId>,
+    ) {
+        match &module[expr] {
+            Expr::Reference(_) if nameres.get(expr).is_none() => out.push(expr),
+            Expr::Attrset(_) | Expr::RecAttrset(_) | Expr::LetAttrset(_) => {}
+            &Expr::LetIn(_, body) => undefined_refs(module, nameres, body, out),
+            e => e.walk_child_exprs(|child| undefined_refs(module, nameres, child, out)),
+        }
+    }
+
+    let module = db.module(file);
+    let source_map = db.source_map(file);
+    let nameres = db.name_resolution(file);
+
+    let mut diags = Vec::new();
+    for (_, kind) in module.exprs() {
+        let (Expr::Attrset(bindings) | Expr::RecAttrset(bindings) | Expr::LetIn(bindings, _)) =
+            kind
+        else {
+            continue;
+        };
+        for &(name, value) in bindings.statics.iter() {
+            let (BindingValue::Inherit(value)
+            | BindingValue::InheritFrom(value)
+            | BindingValue::Expr(value)) = value;
+            let mut errors = Vec::new();
+            if let &Expr::Apply(func, _) = &module[value] {
+                match builtin_ref(&module, &nameres, func) {
+                    Some("throw") => errors.push((value, "Always throws".to_owned())),
+                    Some("abort") => errors.push((value, "Always aborts".to_owned())),
+                    _ => {}
+                }
+            }
+            let mut refs = Vec::new();
+            undefined_refs(&module, &nameres, value, &mut refs);
+            errors.extend(refs.into_iter().filter_map(|e| match &module[e] {
+                Expr::Reference(text) => Some((e, format!("Undefined name `{text}`"))),
+                _ => None,
+            }));
+            if errors.is_empty() {
+                continue;
+            }
+
+            let Some(ptr) = source_map.nodes_for_name(name).next() else { continue };
+            let mut diag = Diagnostic::new(ptr.text_range(), DiagnosticKind::BrokenBinding);
+            for (e, msg) in errors {
+                if let Some(ptr) = source_map.node_for_expr(e) {
+                    diag = diag.with_note(FileRange::new(file, ptr.text_range()), msg);
+                }
+            }
+            diags.push(diag);
+        }
+    }
+    diags.sort_by_key(|diag| diag.range.start());
     diags
 }
 
+/// Report impure builtins used in flake workspaces.
+fn impure_builtins(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    if db
+        .source_root_flake_info(db.file_source_root(file))
+        .is_none()
+    {
+        return Vec::new();
+    }
+
+    let module = db.module(file);
+    let source_map = db.source_map(file);
+    let nameres = db.name_resolution(file);
+    module
+        .exprs()
+        .filter_map(|(expr, _)| {
+            let name = builtin_ref(&module, &nameres, expr)?;
+            if !IMPURE_BUILTINS.contains(&name) {
+                return None;
+            }
+            let ptr = source_map.node_for_expr(expr)?;
+            Some(Diagnostic::new(
+                ptr.text_range(),
+                DiagnosticKind::ImpureBuiltin,
+            ))
+        })
+        .collect()
+}
+
+/// Report arguments of builtin calls whose inferred types definitely mismatch
+/// the known signatures, like `builtins.attrNames 5`. Unknown types are skipped.
+fn builtin_type_mismatch(db: &dyn TyDatabase, file: FileId) -> Vec<Diagnostic> {
+    let module = db.module(file);
+    let source_map = db.source_map(file);
+    let nameres = db.name_resolution(file);
+    let infer = db.infer(file);
+    let builtins = BUILTINS.as_attrset().unwrap();
+    module
+        .exprs()
+        .filter_map(|(expr, kind)| {
+            let &Expr::Apply(mut func, arg) = kind else { return None };
+            // `builtins.elem x list` applies `list` as the second argument.
+            let mut arg_idx = 0;
+            while let &Expr::Apply(inner, _) = &module[func] {
+                func = inner;
+                arg_idx += 1;
+            }
+            let name = builtin_ref(&module, &nameres, func)?;
+            if UNION_PARAM_BUILTINS.contains(&name) {
+                return None;
+            }
+
+            let mut ty = builtins.get(name)?;
+            for _ in 0..arg_idx {
+                let Ty::Lambda(_, ret) = ty else { return None };
+                ty = ret;
+            }
+            let Ty::Lambda(param_ty, _) = ty else { return None };
+            let arg_ty = infer.ty_for_expr(arg);
+            if !is_type_mismatch(param_ty, &arg_ty) {
+                return None;
+            }
+
+            let arg_range = source_map.node_for_expr(arg)?.text_range();
+            let func_range = source_map.node_for_expr(func)?.text_range();
+            let diag = Diagnostic::new(arg_range, DiagnosticKind::TypeMismatch).with_note(
+                FileRange::new(file, func_range),
+                format!(
+                    "`builtins.{name}` expects `{}`, but got `{}`",
+                    param_ty.display(),
+                    arg_ty.display(),
+                ),
+            );
+            Some(diag)
+        })
+        .collect()
+}
+
+/// Report selections of attributes which provably don't exist in statically known attrsets,
+/// like `let a.b = 1; in a.c`. Available attributes are listed in a note.
+fn missing_attr(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    let module = db.module(file);
+    let nameres = db.name_resolution(file);
+    let source_map = db.source_map(file);
+    let root = db.parse(file).syntax_node();
+    let mut selects = Vec::new();
+    unguarded_selects(
+        &module,
+        &nameres,
+        module.entry_expr(),
+        &mut Vec::new(),
+        &mut selects,
+    );
+    selects
+        .into_iter()
+        .filter_map(|expr| {
+            let select = ast::Select::cast(source_map.node_for_expr(expr)?.to_node(&root))?;
+            let (attr, names) = unguarded_select_missing_attr(db, file, &select)?;
+            let attr_text = attr.syntax().to_string();
+            let message = if names.is_empty() {
+                format!("Attribute `{attr_text}` not found. The attrset has no attributes")
+            } else {
+                let names = names
+                    .iter()
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>();
+                format!(
+                    "Attribute `{attr_text}` not found. Available attributes: {}",
+                    names.join(", "),
+                )
+            };
+            let diag = Diagnostic::new(attr.syntax().text_range(), DiagnosticKind::MissingAttr)
+                .with_message(message);
+            Some(diag)
+        })
+        .collect()
+}
+
+/// Collect select-expressions without `or` defaults in the expression, skipping ones only
+/// evaluated after `?` checks on their sets. `guards` are sets checked by enclosing conditions.
+fn unguarded_selects(
+    module: &Module,
+    nameres: &NameResolution,
+    expr: ExprId,
+    guards: &mut Vec<GuardKey>,
+    out: &mut Vec<ExprId>,
+) {
+    let (cond, guarded_body, rest) = match module[expr] {
+        Expr::IfThenElse(cond, then_body, else_body) => (cond, then_body, Some(else_body)),
+        Expr::Binary(Some(BinaryOp::And), lhs, rhs) => (lhs, rhs, None),
+        ref kind => {
+            if let &Expr::Select(set, _, None) = kind {
+                let is_guarded =
+                    GuardKey::of(module, nameres, set).map_or(false, |key| guards.contains(&key));
+                if !is_guarded {
+                    out.push(expr);
+                }
+            }
+            kind.walk_child_exprs(|e| unguarded_selects(module, nameres, e, guards, out));
+            return;
+        }
+    };
+    unguarded_selects(module, nameres, cond, guards, out);
+    let len = guards.len();
+    GuardKey::collect_has_attrs(module, nameres, cond, guards);
+    unguarded_selects(module, nameres, guarded_body, guards, out);
+    guards.truncate(len);
+    if let Some(rest) = rest {
+        unguarded_selects(module, nameres, rest, guards, out);
+    }
+}
+
+/// Report unknown license names in `meta.license`, like `lib.licenses.mitt`.
+fn unknown_license(db: &dyn DefDatabase, file: FileId) -> Vec<Diagnostic> {
+    db.parse(file)
+        .syntax_node()
+        .descendants()
+        .filter_map(ast::Select::cast)
+        .filter_map(|select| {
+            let (attr, closest) = select_unknown_license(&select)?;
+            let range = attr.syntax().text_range();
+            let mut diag = Diagnostic::new(range, DiagnosticKind::UnknownLicense);
+            if let Some(closest) = closest {
+                let note = format!("Did you mean `{closest}`?");
+                diag = diag.with_note(FileRange::new(file, range), note);
+            }
+            Some(diag)
+        })
+        .collect()
+}
+
+/// Check if the select-expression is an unknown license of `lib.licenses.<name>` or
+/// `licenses.<name>` in `meta.license`, possibly inside a list.
+/// Return the `<name>` attribute and the closest known license name.
+/// Other forms, like SPDX strings or custom license attrsets, are not checked.
+pub(crate) fn select_unknown_license(
+    select: &ast::Select,
+) -> Option<(ast::Attr, Option<&'static str>)> {
+    if select.or_token().is_some() {
+        return None;
+    }
+    let name_attr = license_attr(select)?;
+    let name = static_attr_name(name_attr.clone())?;
+    if LICENSES.contains(&&*name) || is_meta_license(select.syntax()).is_none() {
+        return None;
+    }
+    Some((name_attr, closest_name(&name, LICENSES.iter().copied())))
+}
+
+/// Get the `<name>` attribute of a select-expression `lib.licenses.<name>` or `licenses.<name>`.
+pub(crate) fn license_attr(select: &ast::Select) -> Option<ast::Attr> {
+    let ast::Expr::Ref(set) = select.set()? else { return None };
+    let mut attrs = select.attrpath()?.attrs().collect::<Vec<_>>();
+    let name_attr = attrs.pop()?;
+    let mut path = vec![set.token()?.text().to_owned()];
+    for attr in attrs {
+        path.push(static_attr_name(attr)?);
+    }
+    let is_licenses = matches!(&*path, [lib, licenses] if lib == "lib" && licenses == "licenses")
+        || matches!(&*path, [licenses] if licenses == "licenses");
+    is_licenses.then_some(name_attr)
+}
+
+/// Check if the expression is the value of `meta.license`, or an element of the list value,
+/// possibly wrapped in parentheses or `with lib;`.
+fn is_meta_license(expr: &SyntaxNode) -> Option<()> {
+    let mut node = expr.parent()?;
+    while matches!(
+        node.kind(),
+        SyntaxKind::LIST | SyntaxKind::PAREN | SyntaxKind::WITH
+    ) {
+        node = node.parent()?;
+    }
+    let path = attrpath_value_names(&ast::AttrpathValue::cast(node.clone())?)?;
+    match &*path {
+        [.., meta, license] if meta == "meta" && license == "license" => Some(()),
+        // `meta = { license = ...; };`
+        [license] if license == "license" => {
+            let set = ast::AttrSet::cast(node.parent()?)?;
+            let parent = ast::AttrpathValue::cast(set.syntax().parent()?)?;
+            let parent_path = attrpath_value_names(&parent)?;
+            (parent_path.last()? == "meta").then_some(())
+        }
+        _ => None,
+    }
+}
+
+fn attrpath_value_names(node: &ast::AttrpathValue) -> Option<Vec<String>> {
+    node.attrpath()?.attrs().map(static_attr_name).collect()
+}
+
+fn static_attr_name(attr: ast::Attr) -> Option<String> {
+    match AttrKind::of(attr) {
+        AttrKind::Static(name) => name,
+        AttrKind::Dynamic(_) => None,
+    }
+}
+
+/// Find the candidate closest to `name`, if it's close enough to be a typo.
+pub(crate) fn closest_name<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|cand| (edit_distance(name, cand), cand))
+        .filter(|&(dist, cand)| dist <= cand.chars().count().max(3) / 3)
+        .min()
+        .map(|(_, cand)| cand)
+}
+
+/// The Levenshtein distance between two strings, in chars.
+fn edit_distance(lhs: &str, rhs: &str) -> usize {
+    let rhs = rhs.chars().collect::<Vec<_>>();
+    let mut row = (0..=rhs.len()).collect::<Vec<_>>();
+    for (i, a) in lhs.chars().enumerate() {
+        let mut diag = row[0];
+        row[0] = i + 1;
+        for (j, &b) in rhs.iter().enumerate() {
+            let next = (diag + usize::from(a != b))
+                .min(row[j] + 1)
+                .min(row[j + 1] + 1);
+            diag = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[rhs.len()]
+}
+
+/// Check if a value of type `arg` is definitely rejected by a parameter of type `param`.
+/// Known signatures only approximate the real ones, so coercible types are allowed.
+fn is_type_mismatch(param: &Ty, arg: &Ty) -> bool {
+    match (param, arg) {
+        (Ty::Unknown | Ty::Param(_) | Ty::Union(_), _)
+        | (_, Ty::Unknown | Ty::Param(_) | Ty::Union(_)) => false,
+        // `number` is approximated by `float`.
+        (Ty::Float, Ty::Int) => false,
+        // Strings and paths are coerced into each other, and so are derivations into strings.
+        (Ty::String | Ty::Path, Ty::String | Ty::Path | Ty::Attrset(_)) => false,
+        // Attrsets with `__functor` are callable.
+        (Ty::Lambda(..), Ty::Attrset(_)) => false,
+        _ => mem::discriminant(param) != mem::discriminant(arg),
+    }
+}
+
+/// Get the builtin name referenced by `expr`, either directly like `with builtins; currentTime`
+/// or by selection like `builtins.currentTime`.
+fn builtin_ref<'m>(module: &'m Module, nameres: &NameResolution, expr: ExprId) -> Option<&'m str> {
+    match &module[expr] {
+        Expr::Reference(_) => nameres.check_builtin(expr, module),
+        Expr::Select(set, path, None) if path.len() == 1 => {
+            if nameres.check_builtin(*set, module)? != "builtins" {
+                return None;
+            }
+            match &module[path[0]] {
+                Expr::Literal(Literal::String(name)) => Some(name),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::tests::TestDB;
     use expect_test::{expect, Expect};
+    use syntax::TextRange;
 
     fn check(fixture: &str, expect: Expect) {
         let (db, file_id) = TestDB::single_file(fixture).unwrap();
@@ -46,6 +999,20 @@ mod tests {
         check("1 == 2 == 3", expect!["7..9: SyntaxError(MultipleNoAssoc)"]);
     }
 
+    #[test]
+    fn syntax_errors_only() {
+        let (db, file) = TestDB::single_file("1 == 2 == 3").unwrap();
+        let errors = super::syntax_errors(&db, file);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, TextRange::new(7.into(), 9.into()));
+        assert_eq!(errors[0].1, super::diagnostics(&db, file)[0].message());
+
+        // Semantic issues only.
+        let (db, file) = TestDB::single_file("let a = 1; in b").unwrap();
+        assert_eq!(super::syntax_errors(&db, file), Vec::new());
+        assert_eq!(super::diagnostics(&db, file).len(), 2);
+    }
+
     #[test]
     fn lower_error() {
         check(
@@ -57,6 +1024,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn duplicated_param() {
+        check(
+            "{ a, a }: a",
+            expect![[r#"
+                5..6: DuplicatedParam
+                    2..3: Previously defined here
+            "#]],
+        );
+    }
+
+    #[test]
+    fn shadowed_param() {
+        check(
+            "a: b: a: a + b",
+            expect![[r#"
+                6..7: ShadowedParam
+                    0..1: Previously defined here
+            "#]],
+        );
+        check(
+            "{ a }: { b, a ? 1 }: _: _: a + b",
+            expect![[r#"
+                12..13: ShadowedParam
+                    2..3: Previously defined here
+            "#]],
+        );
+    }
+
     #[test]
     fn name_resolution() {
         check("a", expect!["0..1: UndefinedName"]);
@@ -73,4 +1069,299 @@ mod tests {
             "#]],
         );
     }
+
+    #[test]
+    fn top_level_with() {
+        check("{ lib }: with lib; foo", expect!["9..18: TopLevelWith"]);
+        check(
+            "with lib; foo",
+            expect![[r#"
+                5..8: UndefinedName
+                0..9: TopLevelWith
+            "#]],
+        );
+    }
+
+    #[test]
+    fn missing_rec() {
+        check(
+            "{ a = 1; b = a + 1; }",
+            expect![[r#"
+                13..14: UndefinedName
+                13..14: MissingRec
+                    2..3: Sibling attribute defined here
+            "#]],
+        );
+        check(
+            "{ a.b = 1; c = { d = a.b; }; }",
+            expect![[r#"
+                21..22: UndefinedName
+                21..22: MissingRec
+                    2..3: Sibling attribute defined here
+            "#]],
+        );
+        // Self reference.
+        check("{ a = a; }", expect!["6..7: UndefinedName"]);
+        // Not in the value of a binding.
+        check("{ a = 1; ${a} = 2; }", expect!["11..12: UndefinedName"]);
+        // Not a sibling.
+        check(
+            "{ a = 1; b = { c = d; }; }",
+            expect!["19..20: UndefinedName"],
+        );
+    }
+
+    #[test]
+    fn inherit_cycle() {
+        check(
+            "rec { inherit (a) b; a = b; }",
+            expect![[r#"
+                18..19: InheritCycle
+                    21..22: Part of the cycle
+            "#]],
+        );
+        check("rec { inherit (x) x; }", expect!["18..19: InheritCycle"]);
+        check(
+            "rec { inherit (a // { }) b; a = b.x; }",
+            expect![[r#"
+                25..26: InheritCycle
+                    28..29: Part of the cycle
+            "#]],
+        );
+
+        // Lazy self references.
+        for src in [
+            "rec { inherit (self) x; self = { x = 1; }; }",
+            "rec { inherit (a) b; a = { c = b; }; }",
+            "rec { inherit (a) b; a = f b; f = x: { b = x; }; }",
+            "rec { inherit (a) b; a = if true then { b = 1; } else b; }",
+        ] {
+            let (db, file) = TestDB::single_file(src).unwrap();
+            assert_eq!(super::diagnostics(&db, file), Vec::new(), "{src}");
+        }
+    }
+
+    #[test]
+    fn impure_builtin() {
+        check(
+            "#- /flake.nix input:nixpkgs=/nix/store/eeee
+{ outputs = _: builtins.currentTime; }",
+            expect!["15..35: ImpureBuiltin"],
+        );
+        check(
+            "#- /flake.nix input:nixpkgs=/nix/store/eeee
+{ outputs = _: with builtins; [ currentSystem (getEnv \"HOME\") ]; }",
+            expect![[r#"
+                32..45: ImpureBuiltin
+                47..53: ImpureBuiltin
+            "#]],
+        );
+
+        // Not in a flake.
+        let (db, file) = TestDB::single_file("builtins.currentTime").unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let (db, file) = TestDB::single_file("{ a = { b = { c = [ 1 ]; }; d = [ ]; }; }").unwrap();
+        let got = super::deep_nesting(&db, file, 2)
+            .iter()
+            .map(|d| d.debug_display().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(got, ["12..13: DeepNesting", "32..33: DeepNesting"]);
+        assert_eq!(super::deep_nesting(&db, file, 4), Vec::new());
+    }
+
+    #[test]
+    fn broken_bindings() {
+        let (db, file) =
+            TestDB::single_file(r#"let a = b + 1; c = { d = throw "no"; }; e = 1; in a"#).unwrap();
+        let got = super::broken_bindings(&db, file)
+            .iter()
+            .map(|d| format!("{}\n", d.debug_display()))
+            .collect::<String>();
+        expect![[r#"
+            4..5: BrokenBinding
+                8..9: Undefined name `b`
+            21..22: BrokenBinding
+                25..35: Always throws
+        "#]]
+        .assert_eq(&got);
+    }
+
+    #[test]
+    fn builtin_type_mismatch() {
+        check(
+            "builtins.attrNames 5",
+            expect![[r#"
+                19..20: TypeMismatch
+                    0..18: `builtins.attrNames` expects `{ }`, but got `int`
+            "#]],
+        );
+        check(
+            r#"let x = 1; in builtins.elem x "str""#,
+            expect![[r#"
+                30..35: TypeMismatch
+                    14..27: `builtins.elem` expects `[a]`, but got `string`
+            "#]],
+        );
+
+        // Coercible or unknown arguments.
+        let (db, file) = TestDB::single_file(
+            r#"x: [ (builtins.add 1 2.0) (builtins.readFile "/a") (builtins.length x) ]"#,
+        )
+        .unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+    }
+
+    #[test]
+    fn missing_attr() {
+        check(
+            "let a = { foo = 1; bar.baz = 2; }; in a.bar.qux",
+            expect![[r#"
+                44..47: MissingAttr: Attribute `qux` not found. Available attributes: `baz`
+            "#]],
+        );
+        check(
+            "let a = { foo = 1; bar = 2; }; in a.fooo",
+            expect![[r#"
+                36..40: MissingAttr: Attribute `fooo` not found. Available attributes: `bar`, `foo`
+            "#]],
+        );
+        // The guard checks another `b`.
+        check(
+            "let b = { }; in if b ? bar then (let b = { }; in b.bar) else null",
+            expect![[r#"
+                51..54: MissingAttr: Attribute `bar` not found. The attrset has no attributes
+            "#]],
+        );
+
+        // Guarded, defaulted or incomplete attrsets.
+        let (db, file) = TestDB::single_file(
+            r#"let a = { foo = 1; ${x} = 2; }; b = { c = { }; }; x = "x"; in [
+              (if b ? bar then b.bar else null)
+              (b ? bar && b.bar)
+              (b.c ? bar && (b).c.bar)
+              b.bar or null
+              a.bar
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+    }
+
+    #[test]
+    fn unknown_license() {
+        check(
+            "lib: { meta.license = lib.licenses.mitt; }",
+            expect![[r#"
+                35..39: UnknownLicense
+                    35..39: Did you mean `mit`?
+            "#]],
+        );
+        check(
+            "lib: { meta = { license = [ lib.licenses.asl20 lib.licenses.gpl3Pls ]; }; }",
+            expect![[r#"
+                60..67: UnknownLicense
+                    60..67: Did you mean `gpl3Plus`?
+            "#]],
+        );
+
+        // Known names, other forms, or not in `meta.license`.
+        let (db, file) = TestDB::single_file(
+            r#"lib: [
+              { meta.license = lib.licenses.mit; }
+              { meta.license = [ lib.licenses.tcltk lib.licenses.elastic20 ]; }
+              { meta.license = [ lib.licenses.gpl2Classpath lib.licenses.hpndSellVariant ]; }
+              { meta.license = "MIT"; }
+              { meta.license = { fullName = "Custom"; }; }
+              { meta.license = lib.licenses.foo or null; }
+              { license = lib.licenses.mitt; }
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+    }
+
+    #[test]
+    fn fetcher_hash() {
+        check(
+            r#"pkgs: pkgs.fetchFromGitHub { owner = "a"; repo = "b"; rev = "c"; }"#,
+            expect![[r#"
+                6..26: MissingFetcherHash
+                    27..66: Missing `hash`
+            "#]],
+        );
+        check(
+            r#"lib: fetchgit: [
+              (fetchgit { url = "a"; rev = "b"; sha256 = ""; })
+              (fetchgit { url = "a"; rev = "b"; hash = lib.fakeHash; })
+            ]"#,
+            expect![[r#"
+                74..76: PlaceholderFetcherHash
+                136..148: PlaceholderFetcherHash
+            "#]],
+        );
+
+        // Real, inherited, or dynamic.
+        let (db, file) = TestDB::single_file(
+            r#"lib: fetchFromGitHub: hash: [
+              (fetchFromGitHub { rev = "a"; hash = "sha256-abc="; })
+              (fetchFromGitHub { rev = "a"; inherit hash; })
+              (fetchFromGitHub { ${lib.name} = "a"; })
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+    }
+
+    #[test]
+    fn missing_option_field() {
+        check(
+            r#"lib: lib.mkOption { default = 1; description = "A number."; }"#,
+            expect![[r#"
+                5..17: MissingOptionField
+                    18..61: Missing `type`
+            "#]],
+        );
+        check(
+            "{ lib, ... }: { options.a = lib.mkOption { type = lib.types.int; }; }",
+            expect![[r#"
+                28..40: MissingOptionRecommendedField
+                    41..66: Missing `description`
+            "#]],
+        );
+
+        // Complete, dynamic, or not an attrset.
+        let (db, file) = TestDB::single_file(
+            r#"lib: [
+              (lib.mkOption { inherit (lib.types) type; description = "A"; })
+              (lib.mkOption { ${lib.name} = 1; })
+              (lib.mkEnableOption "foo")
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+
+        // Resolved by name.
+        check(
+            "{ mkOption, ... }: mkOption { }",
+            expect![[r#"
+                19..27: MissingOptionField
+                    28..31: Missing `type`
+                19..27: MissingOptionRecommendedField
+                    28..31: Missing `description`
+            "#]],
+        );
+        let (db, file) = TestDB::single_file("let mkOption = x: x; in mkOption { }").unwrap();
+        assert_eq!(super::diagnostics(&db, file), Vec::new());
+    }
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(super::edit_distance("fooo", "foo"), 1);
+        assert_eq!(super::edit_distance("kitten", "sitting"), 3);
+        assert_eq!(super::edit_distance("", "abc"), 3);
+    }
 }
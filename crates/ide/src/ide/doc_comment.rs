@@ -0,0 +1,129 @@
+//! Documentation from comments preceding definitions.
+use syntax::{SyntaxKind, SyntaxNode, SyntaxToken};
+
+/// How comments preceding a definition are parsed into documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocCommentStyle {
+    /// Adjacent `#` line comments or `/* */` block comments, with markers stripped.
+    Plain,
+    /// Only the `/** */` comment in the [nixdoc] format, rendered as Markdown
+    /// with `@param` and `@return` tags recognized.
+    ///
+    /// [nixdoc]: https://github.com/nix-community/nixdoc
+    Nixdoc,
+}
+
+/// The documentation of the definition containing the name node, from comments just before it.
+/// Comments separated from the definition by an empty line are not included.
+pub(crate) fn doc_comment(name_node: &SyntaxNode, style: DocCommentStyle) -> Option<String> {
+    use SyntaxKind::{ATTR_PATH_VALUE, COMMENT, INHERIT, LAMBDA, PAT_FIELD, SPACE};
+
+    let def_node = name_node.ancestors().find_map(|node| match node.kind() {
+        ATTR_PATH_VALUE | INHERIT | PAT_FIELD => Some(Some(node)),
+        // A simple lambda parameter has no place for documentation.
+        LAMBDA => Some(None),
+        _ => None,
+    })??;
+
+    let mut comments = Vec::new();
+    let first_tok = def_node.first_token()?;
+    for tok in std::iter::successors(first_tok.prev_token(), SyntaxToken::prev_token) {
+        match tok.kind() {
+            SPACE if tok.text().matches('\n').count() > 1 => break,
+            SPACE => {}
+            // A comment after code on its line, like `a = 1; # foo`, belongs to that code.
+            COMMENT if is_trailing_comment(&tok) => break,
+            COMMENT => comments.push(tok),
+            _ => break,
+        }
+    }
+    comments.reverse();
+
+    let doc = match style {
+        DocCommentStyle::Plain => comments
+            .iter()
+            .map(|tok| plain_comment_text(tok.text()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        DocCommentStyle::Nixdoc => {
+            let text = comments.last()?.text();
+            let body = text.strip_prefix("/**")?.strip_suffix("*/")?;
+            render_nixdoc(&strip_common_indent(body))
+        }
+    };
+    let doc = doc.trim();
+    (!doc.is_empty()).then(|| doc.to_owned())
+}
+
+fn is_trailing_comment(tok: &SyntaxToken) -> bool {
+    use SyntaxKind::{COMMENT, SPACE};
+
+    let mut prev = tok.prev_token();
+    if let Some(space) = prev.as_ref().filter(|t| t.kind() == SPACE) {
+        if space.text().contains('\n') {
+            return false;
+        }
+        prev = space.prev_token();
+    }
+    prev.map_or(false, |t| !matches!(t.kind(), SPACE | COMMENT))
+}
+
+fn plain_comment_text(text: &str) -> String {
+    if let Some(line) = text.strip_prefix('#') {
+        return line.strip_prefix(' ').unwrap_or(line).to_owned();
+    }
+    let body = text
+        .strip_prefix("/**")
+        .or_else(|| text.strip_prefix("/*"))
+        .unwrap_or(text);
+    let body = body.strip_suffix("*/").unwrap_or(body);
+    strip_common_indent(body)
+}
+
+/// Remove the common indentation of non-blank lines, and leading and trailing blank lines.
+fn strip_common_indent(text: &str) -> String {
+    let indent = text
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default().trim();
+    let rest = lines.map(|line| line.get(indent..).unwrap_or(line.trim_start()).trim_end());
+    std::iter::once(first)
+        .chain(rest)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_owned()
+}
+
+/// Render `@param name description` and `@return description` tags as Markdown lists.
+/// Other lines are Markdown already.
+fn render_nixdoc(body: &str) -> String {
+    let mut text = Vec::new();
+    let mut params = Vec::new();
+    let mut ret = None;
+    for line in body.lines() {
+        if let Some(param) = line.strip_prefix("@param ") {
+            let (name, desc) = param.trim().split_once(' ').unwrap_or((param.trim(), ""));
+            params.push(format!("- `{name}`: {}", desc.trim()));
+        } else if let Some(desc) = line.strip_prefix("@return ") {
+            ret = Some(desc.trim());
+        } else {
+            text.push(line);
+        }
+    }
+    let mut out = text.join("\n").trim().to_owned();
+    if !params.is_empty() {
+        out += "\n\n# Parameters\n\n";
+        out += &params.join("\n");
+    }
+    if let Some(ret) = ret {
+        out += "\n\n# Returns\n\n";
+        out += ret;
+    }
+    out
+}
@@ -0,0 +1,253 @@
+//! Sources of fetcher calls to be prefetched for filling their fixed-output hashes.
+use super::diagnostics::fetcher_call;
+use super::module_option::literal_string;
+use crate::{DefDatabase, FileRange};
+use syntax::ast::{self, AstNode};
+use syntax::semantic::AttrKind;
+use syntax::{best_token_at_offset, TextRange};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FetcherPrefetch {
+    /// The URL of the source archive, whose hash is calculated after unpacking,
+    /// or a flake reference of a git repository if `git` is set.
+    pub url: String,
+    /// Whether `url` is a flake reference like `git+https://...?rev=...`, whose checkout
+    /// without `.git` is hashed.
+    pub git: bool,
+    /// The range to be replaced by the `hash` binding.
+    /// It is the existing hash binding, or an empty range to insert a new one.
+    pub range: TextRange,
+    /// Whether the binding to insert needs a leading space.
+    pub leading_space: bool,
+}
+
+/// Get the source of the innermost fetcher call covering the range, if all fields locating it
+/// are literals. Calls with options changing the checkout, like `leaveDotGit`,
+/// are not supported.
+pub(crate) fn fetcher_prefetch(
+    db: &dyn DefDatabase,
+    FileRange { file_id, range }: FileRange,
+) -> Option<FetcherPrefetch> {
+    let root = db.parse(file_id).syntax_node();
+    let elem = if range.is_empty() {
+        best_token_at_offset(&root, range.start())?.into()
+    } else {
+        root.covering_element(range)
+    };
+    let call = elem
+        .ancestors()
+        .filter_map(ast::Apply::cast)
+        .find_map(|apply| fetcher_call(&apply))?;
+
+    let field_value = |name: &str| {
+        call.set.bindings().find_map(|binding| {
+            let ast::Binding::AttrpathValue(b) = binding else { return None };
+            let mut attrs = b.attrpath()?.attrs();
+            let (Some(attr), None) = (attrs.next(), attrs.next()) else { return None };
+            let AttrKind::Static(Some(field)) = AttrKind::of(attr) else { return None };
+            (field == name).then(|| b.value())?
+        })
+    };
+    let field = |name: &str| literal_string(&field_value(name)?);
+    // `None` for a non-literal value, and `Some(default)` if the field is missing.
+    let bool_field = |name: &str, default: bool| match field_value(name) {
+        None => Some(default),
+        Some(ast::Expr::Ref(r)) => match r.token()?.text() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None,
+        },
+        Some(_) => None,
+    };
+    let rev = || match field("tag") {
+        Some(tag) => Some(format!("refs/tags/{tag}")),
+        None => field("rev"),
+    };
+    if GIT_UNSUPPORTED_FIELDS
+        .iter()
+        .any(|&name| field_value(name).is_some())
+    {
+        return None;
+    }
+    let (url, git) = match &*call.name {
+        "fetchFromGitHub" => {
+            let owner = field("owner")?;
+            let repo = field("repo")?;
+            let rev = rev()?;
+            // These options make `fetchFromGitHub` clone the repository by `fetchgit`
+            // instead of downloading the archive.
+            let submodules = bool_field("fetchSubmodules", false)?;
+            if submodules || bool_field("forceFetchGit", false)? {
+                let url = format!("https://github.com/{owner}/{repo}.git");
+                (git_flake_ref(&url, &rev, submodules)?, true)
+            } else {
+                let url = format!("https://github.com/{owner}/{repo}/archive/{rev}.tar.gz");
+                (url, false)
+            }
+        }
+        "fetchgit" => {
+            // Submodules are fetched by default.
+            let submodules = bool_field("fetchSubmodules", true)?;
+            let url = field("url")?;
+            (git_flake_ref(&url, &rev()?, submodules)?, true)
+        }
+        _ => return None,
+    };
+
+    let range = match &call.hash {
+        Some(binding) => binding.syntax().text_range(),
+        None => {
+            let pos = match call.set.bindings().last() {
+                Some(binding) => binding.syntax().text_range().end(),
+                None => call.set.l_curly_token()?.text_range().end(),
+            };
+            TextRange::empty(pos)
+        }
+    };
+    Some(FetcherPrefetch {
+        url,
+        git,
+        range,
+        leading_space: call.hash.is_none(),
+    })
+}
+
+/// Fields of `fetchgit` changing the content of the checkout. They are passed through by
+/// `fetchFromGitHub`, which also switches to `fetchgit` for them.
+const GIT_UNSUPPORTED_FIELDS: &[&str] = &[
+    "deepClone",
+    "fetchLFS",
+    "leaveDotGit",
+    "postFetch",
+    "sparseCheckout",
+];
+
+/// The flake reference of a git repository at `rev`, which is either a full commit hash or a
+/// full ref name like `refs/tags/v1`.
+fn git_flake_ref(url: &str, rev: &str, submodules: bool) -> Option<String> {
+    if !url.contains("://") || url.contains(['?', '#']) {
+        return None;
+    }
+    let is_commit = rev.len() == 40 && rev.bytes().all(|b| b.is_ascii_hexdigit());
+    let rev_param = if is_commit {
+        format!("rev={rev}")
+    } else if rev.starts_with("refs/") && !rev.contains(['?', '#', '&']) {
+        format!("ref={rev}")
+    } else {
+        return None;
+    };
+    let url = url.strip_prefix("git+").unwrap_or(url);
+    let submodules = if submodules { "&submodules=1" } else { "" };
+    Some(format!("git+{url}?{rev_param}{submodules}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::tests::TestDB;
+    use expect_test::{expect, Expect};
+
+    #[track_caller]
+    fn check(fixture: &str, expect: Expect) {
+        let (db, f) = TestDB::from_fixture(fixture).unwrap();
+        let mut src = db.file_content(f[0].file_id).to_string();
+        let got = match super::fetcher_prefetch(&db, f.unwrap_single_range_marker()) {
+            None => String::new(),
+            Some(prefetch) => {
+                let range = usize::from(prefetch.range.start())..usize::from(prefetch.range.end());
+                let space = if prefetch.leading_space { " " } else { "" };
+                src.replace_range(range, &format!("{space}hash = \"sha256-xyz=\";"));
+                format!("{}\n{src}", prefetch.url)
+            }
+        };
+        expect.assert_eq(&got);
+    }
+
+    #[test]
+    fn github() {
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; $0rev = "c"; }"#,
+            expect![[r#"
+                https://github.com/a/b/archive/c.tar.gz
+                fetchFromGitHub { owner = "a"; repo = "b"; rev = "c"; hash = "sha256-xyz="; }"#]],
+        );
+        check(
+            r#"$0fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; sha256 = ""; }"#,
+            expect![[r#"
+                https://github.com/a/b/archive/refs/tags/v1.tar.gz
+                fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; hash = "sha256-xyz="; }"#]],
+        );
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = $0b; rev = "c"; }"#,
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn github_git() {
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; $0fetchSubmodules = true; }"#,
+            expect![[r#"
+                git+https://github.com/a/b.git?ref=refs/tags/v1&submodules=1
+                fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; fetchSubmodules = true; hash = "sha256-xyz="; }"#]],
+        );
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; $0forceFetchGit = true; }"#,
+            expect![[r#"
+                git+https://github.com/a/b.git?ref=refs/tags/v1
+                fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; forceFetchGit = true; hash = "sha256-xyz="; }"#]],
+        );
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; rev = "c"; $0fetchSubmodules = false; }"#,
+            expect![[r#"
+                https://github.com/a/b/archive/c.tar.gz
+                fetchFromGitHub { owner = "a"; repo = "b"; rev = "c"; fetchSubmodules = false; hash = "sha256-xyz="; }"#]],
+        );
+
+        // Checkouts with `.git`, or unknown options.
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; $0leaveDotGit = true; }"#,
+            expect![""],
+        );
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; $0deepClone = true; }"#,
+            expect![""],
+        );
+        check(
+            r#"fetchFromGitHub { owner = "a"; repo = "b"; tag = "v1"; $0fetchSubmodules = x; }"#,
+            expect![""],
+        );
+    }
+
+    #[test]
+    fn git() {
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        check(
+            &format!(r#"fetchgit {{ url = "https://a/b.git"; $0rev = "{rev}"; hash = ""; }}"#),
+            expect![[r#"
+                git+https://a/b.git?rev=0123456789abcdef0123456789abcdef01234567&submodules=1
+                fetchgit { url = "https://a/b.git"; rev = "0123456789abcdef0123456789abcdef01234567"; hash = "sha256-xyz="; }"#]],
+        );
+        check(
+            r#"fetchgit { url = "https://a/b"; tag = "v1"; fetchSubmodules = false; }$0"#,
+            expect![[r#"
+                git+https://a/b?ref=refs/tags/v1
+                fetchgit { url = "https://a/b"; tag = "v1"; fetchSubmodules = false; hash = "sha256-xyz="; }"#]],
+        );
+
+        // Short or ambiguous revisions.
+        check(
+            r#"fetchgit { url = "https://a/b"; rev = $0"c"; }"#,
+            expect![""],
+        );
+        check(
+            r#"fetchgit { url = "https://a/b"; rev = $0"main"; }"#,
+            expect![""],
+        );
+        // Checkouts with `.git` or post-processing.
+        check(
+            &format!(r#"fetchgit {{ url = "https://a/b"; rev = $0"{rev}"; leaveDotGit = true; }}"#),
+            expect![""],
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Foldable regions of a file: multi-line syntax constructs, block comments,
+//! and regions between comment markers like `# region` and `# endregion`.
+use crate::{DefDatabase, FileId};
+use syntax::{SyntaxKind, TextRange};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FoldingRange {
+    pub range: TextRange,
+    pub kind: FoldingRangeKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FoldingRangeKind {
+    /// A syntax construct, like an attrset or a list.
+    Block,
+    Comment,
+    /// Between a pair of region markers.
+    Region,
+}
+
+/// Kinds of nodes to be folded when they span multiple lines.
+const FOLDABLE_NODES: &[SyntaxKind] = &[
+    SyntaxKind::ATTR_SET,
+    SyntaxKind::INDENT_STRING,
+    SyntaxKind::LET_IN,
+    SyntaxKind::LIST,
+    SyntaxKind::PAT,
+];
+
+/// Collect folding ranges of a file, sorted by their start.
+/// Ranges inside a single line are included, to be filtered by the caller.
+///
+/// `region_markers` are pairs of the opening and closing texts of comment markers,
+/// matched as whole words after `#` and spaces, like `("region", "endregion")` for
+/// `# region foo`, but not `# regional`. Pairs can be nested. Unbalanced markers are ignored.
+pub(crate) fn folding_ranges(
+    db: &dyn DefDatabase,
+    file: FileId,
+    region_markers: &[(String, String)],
+) -> Vec<FoldingRange> {
+    let root = db.parse(file).syntax_node();
+
+    let mut ret = root
+        .descendants()
+        .filter(|node| FOLDABLE_NODES.contains(&node.kind()))
+        .map(|node| FoldingRange {
+            range: node.text_range(),
+            kind: FoldingRangeKind::Block,
+        })
+        .collect::<Vec<_>>();
+
+    // Open markers for each pair, as stacks of ranges.
+    let mut open_markers = vec![Vec::new(); region_markers.len()];
+    for tok in root
+        .descendants_with_tokens()
+        .filter_map(|elem| elem.into_token())
+        .filter(|tok| tok.kind() == SyntaxKind::COMMENT)
+    {
+        let text = tok.text();
+        let Some(line) = text.strip_prefix('#') else {
+            ret.push(FoldingRange {
+                range: tok.text_range(),
+                kind: FoldingRangeKind::Comment,
+            });
+            continue;
+        };
+        let line = line.trim_start();
+        // Closing markers are checked first, since they may start with opening ones.
+        if let Some(i) = region_markers
+            .iter()
+            .position(|(_, end)| starts_with_marker(line, end))
+        {
+            if let Some(start) = open_markers[i].pop() {
+                ret.push(FoldingRange {
+                    range: TextRange::new(start, tok.text_range().end()),
+                    kind: FoldingRangeKind::Region,
+                });
+            }
+        } else if let Some(i) = region_markers
+            .iter()
+            .position(|(start, _)| starts_with_marker(line, start))
+        {
+            open_markers[i].push(tok.text_range().start());
+        }
+    }
+
+    ret.sort_by_key(|fold| (fold.range.start(), fold.range.end()));
+    ret
+}
+
+fn starts_with_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::tests::TestDB;
+    use expect_test::{expect, Expect};
+    use std::fmt::Write;
+
+    #[track_caller]
+    fn check(src: &str, expect: Expect) {
+        let (db, file) = TestDB::single_file(src).unwrap();
+        let markers = [("region", "endregion"), ("{{{", "}}}")]
+            .map(|(start, end)| (start.to_owned(), end.to_owned()));
+        let src = db.file_content(file);
+        let mut got = String::new();
+        for fold in super::folding_ranges(&db, file, &markers) {
+            let text = &src[fold.range];
+            let first_line = text.lines().next().unwrap();
+            writeln!(got, "{:?} {:?}", fold.kind, first_line).unwrap();
+        }
+        expect.assert_eq(&got);
+    }
+
+    #[test]
+    fn structural() {
+        check(
+            "
+let
+  a = { b = 1; };
+  c = [
+    1
+  ];
+  /* multi-line
+     comment */
+  d = ''
+    foo
+  '';
+in a",
+            expect![[r#"
+                Block "let"
+                Block "{ b = 1; }"
+                Block "["
+                Comment "/* multi-line"
+                Block "''"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn region_markers() {
+        check(
+            "
+[
+  # region outer
+  1
+  # region inner
+  2
+  # endregion
+  #{{{ other
+  3
+  #}}}
+  # regional settings
+  # endregion
+  # endregion unbalanced
+  # region unclosed
+]",
+            expect![[r#"
+                Block "["
+                Region "# region outer"
+                Region "# region inner"
+                Region "#{{{ other"
+            "#]],
+        );
+    }
+}
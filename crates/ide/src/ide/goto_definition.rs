@@ -1,14 +1,28 @@
+use super::pattern_bindings::lambda_param_names;
 use super::NavigationTarget;
-use crate::def::{AstPtr, Expr, Literal, ResolveResult};
-use crate::{DefDatabase, FileId, FilePos, ModuleKind, VfsPath};
+use crate::def::{
+    file_for_import, AstPtr, BindingValue, Expr, ExprId, Literal, Module, NameId, NameResolution,
+    ResolveResult,
+};
+use crate::{DefDatabase, FileId, FilePos, FileRange, ModuleKind, VfsPath};
 use nix_interop::FLAKE_FILE;
-use syntax::ast::{self, AstNode};
-use syntax::{best_token_at_offset, match_ast, SyntaxKind, SyntaxToken};
+use smol_str::SmolStr;
+use syntax::ast::{self, AstNode, BinaryOpKind};
+use syntax::semantic::AttrKind;
+use syntax::{best_token_at_offset, match_ast, SyntaxKind, SyntaxToken, TextRange};
+
+/// The maximum steps to follow when statically walking through references and imports.
+/// This prevents infinite loops on recursive definitions like `let a = a; in a.b`.
+const MAX_DEREF_STEPS: usize = 64;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GotoDefinitionResult {
     Path(VfsPath),
-    Targets(Vec<NavigationTarget>),
+    /// Definitions of the token at `origin`.
+    Targets {
+        origin: TextRange,
+        targets: Vec<NavigationTarget>,
+    },
 }
 
 pub(crate) fn goto_definition(
@@ -17,12 +31,28 @@ pub(crate) fn goto_definition(
 ) -> Option<GotoDefinitionResult> {
     let parse = db.parse(file_id);
     let tok = best_token_at_offset(&parse.syntax_node(), pos)?;
+    let origin = tok.text_range();
 
     // Special case for goto flake inputs.
     if let Some(ret) = goto_flake_input(db, file_id, tok.clone()) {
         return Some(ret);
     }
 
+    // Special case for attributes of select-expressions.
+    if let Some(targets) = goto_select_attr(db, file_id, &tok) {
+        return Some(GotoDefinitionResult::Targets { origin, targets });
+    }
+
+    // Special case for attributes of attrset arguments, like `import ./foo.nix { a = 1; }`.
+    if let Some(targets) = goto_param_field(db, file_id, &tok) {
+        return Some(GotoDefinitionResult::Targets { origin, targets });
+    }
+
+    // Special case for output attributes of flakes.
+    if let Some(targets) = goto_flake_output(db, file_id, &tok) {
+        return Some(GotoDefinitionResult::Targets { origin, targets });
+    }
+
     let ptr = tok.parent_ancestors().find_map(|node| {
         match_ast! {
             match node {
@@ -50,23 +80,7 @@ pub(crate) fn goto_definition(
 
     let name_res = db.name_resolution(file_id);
     let targets = match name_res.get(expr_id)? {
-        &ResolveResult::Definition(name) => source_map
-            .nodes_for_name(name)
-            .filter_map(|ptr| {
-                let name_node = ptr.to_node(&parse.syntax_node());
-                let full_node = name_node.ancestors().find(|n| {
-                    matches!(
-                        n.kind(),
-                        SyntaxKind::LAMBDA | SyntaxKind::ATTR_PATH_VALUE | SyntaxKind::INHERIT
-                    )
-                })?;
-                Some(NavigationTarget {
-                    file_id,
-                    focus_range: name_node.text_range(),
-                    full_range: full_node.text_range(),
-                })
-            })
-            .collect(),
+        &ResolveResult::Definition(name) => name_targets(db, file_id, name),
         ResolveResult::WithExprs(withs) => {
             withs
                 .iter()
@@ -93,10 +107,484 @@ pub(crate) fn goto_definition(
                 .collect()
         }
         // Currently builtin names cannot "goto-definition".
-        ResolveResult::Builtin(_) => return None,
+        ResolveResult::Builtin(_) | ResolveResult::ExtraGlobal(_) => return None,
     };
 
-    Some(GotoDefinitionResult::Targets(targets))
+    Some(GotoDefinitionResult::Targets { origin, targets })
+}
+
+/// Resolve the position to ranges of definitions.
+/// Imported paths are resolved to the beginning of the target file, if it is known.
+pub(crate) fn goto_definition_ranges(db: &dyn DefDatabase, fpos: FilePos) -> Vec<FileRange> {
+    match goto_definition(db, fpos) {
+        None => Vec::new(),
+        Some(GotoDefinitionResult::Targets { targets, .. }) => targets
+            .into_iter()
+            .map(|target| FileRange::new(target.file_id, target.focus_range))
+            .collect(),
+        Some(GotoDefinitionResult::Path(vpath)) => file_for_import(db, fpos.file_id, vpath)
+            .map(|file| FileRange::empty(FilePos::new(file, 0.into())))
+            .into_iter()
+            .collect(),
+    }
+}
+
+fn name_targets(db: &dyn DefDatabase, file_id: FileId, name: NameId) -> Vec<NavigationTarget> {
+    let root = db.parse(file_id).syntax_node();
+    db.source_map(file_id)
+        .nodes_for_name(name)
+        .filter_map(|ptr| {
+            let name_node = ptr.to_node(&root);
+            let full_node = name_node.ancestors().find(|n| {
+                matches!(
+                    n.kind(),
+                    SyntaxKind::LAMBDA | SyntaxKind::ATTR_PATH_VALUE | SyntaxKind::INHERIT
+                )
+            })?;
+            Some(NavigationTarget {
+                file_id,
+                focus_range: name_node.text_range(),
+                full_range: full_node.text_range(),
+            })
+        })
+        .collect()
+}
+
+/// Goto the definitions of an attribute in select-expressions, like `set.a.b`.
+/// Attrsets merged by `//` contribute all of their definitions of the attribute selected
+/// from the merged set.
+fn goto_select_attr(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    tok: &SyntaxToken,
+) -> Option<Vec<NavigationTarget>> {
+    let target_attr = tok.parent_ancestors().find_map(ast::Attr::cast)?;
+    let targets = resolve_select_attr_all(db, file_id, &target_attr)?
+        .into_iter()
+        .flat_map(|(file, name)| name_targets(db, file, name))
+        .collect::<Vec<_>>();
+    (!targets.is_empty()).then_some(targets)
+}
+
+/// Like [`resolve_select_attr`], but look into both sides of `//` and return all defining
+/// names of the attribute, in source order of the merged operands.
+///
+/// Since `//` is shallow, only attributes directly selected from the merged set are defined by
+/// multiple operands. Deeper attributes are looked up in the rightmost definition only.
+fn resolve_select_attr_all(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    target_attr: &ast::Attr,
+) -> Option<Vec<(FileId, NameId)>> {
+    let attrpath = ast::Attrpath::cast(target_attr.syntax().parent()?)?;
+    let select = ast::Select::cast(attrpath.syntax().parent()?)?;
+    let set_node = select.set().and_then(ast::Expr::flatten_paren)?;
+    let set_expr = db
+        .source_map(file_id)
+        .expr_for_node(AstPtr::new(set_node.syntax()))?;
+
+    let mut steps = MAX_DEREF_STEPS;
+    let mut set = (file_id, set_expr);
+    for attr in attrpath.attrs() {
+        let AttrKind::Static(Some(key)) = AttrKind::of(attr.clone()) else { return None };
+        let mut attrsets = Vec::new();
+        deref_attrsets(db, set, &mut steps, &mut attrsets);
+        let mut found = attrsets
+            .into_iter()
+            .filter_map(|(file, set_expr)| lookup_attr_in(db, file, set_expr, &key))
+            .collect::<Vec<_>>();
+        if attr.syntax() == target_attr.syntax() {
+            return Some(found.into_iter().map(|(f, n, _)| (f, n)).collect());
+        }
+        // The value comes entirely from the rightmost operand defining the attribute.
+        let (file, name, value) = found.pop()?;
+        set = binding_value_expr(db, file, name, value, &mut steps)?;
+    }
+    None
+}
+
+/// Resolve an attribute in select-expressions, like `b` in `set.a.b`, to its defining name.
+/// The set is statically walked through attrsets, bindings and `import`ed files.
+pub(crate) fn resolve_select_attr(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    target_attr: &ast::Attr,
+) -> Option<(FileId, NameId)> {
+    let attrpath = ast::Attrpath::cast(target_attr.syntax().parent()?)?;
+    let select = ast::Select::cast(attrpath.syntax().parent()?)?;
+    let set_node = select.set().and_then(ast::Expr::flatten_paren)?;
+    let set_expr = db
+        .source_map(file_id)
+        .expr_for_node(AstPtr::new(set_node.syntax()))?;
+
+    let mut steps = MAX_DEREF_STEPS;
+    let mut set = (file_id, set_expr);
+    for attr in attrpath.attrs() {
+        let AttrKind::Static(Some(key)) = AttrKind::of(attr.clone()) else { return None };
+        let (file, name, value) = lookup_attr(db, set, &key, &mut steps)?;
+        if attr.syntax() == target_attr.syntax() {
+            return Some((file, name));
+        }
+        set = binding_value_expr(db, file, name, value, &mut steps)?;
+    }
+    None
+}
+
+/// Find the first attribute of a select-expression which is provably missing in the statically
+/// known attrset, like `c` in `let a.b = 1; in a.c`. Returns the attribute and the sorted names
+/// of all attributes of that attrset.
+///
+/// This is conservative. Attrsets with dynamic attributes are never considered complete, and
+/// selections with `or` defaults or guarded by `?` checks on the same set are skipped.
+pub(crate) fn select_missing_attr(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    select: &ast::Select,
+) -> Option<(ast::Attr, Vec<SmolStr>)> {
+    if is_guarded_by_has_attr(db, file_id, select)? {
+        return None;
+    }
+    unguarded_select_missing_attr(db, file_id, select)
+}
+
+/// Same as [`select_missing_attr`], but assuming the select-expression is not guarded by `?`
+/// checks, which is already known by the caller.
+pub(crate) fn unguarded_select_missing_attr(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    select: &ast::Select,
+) -> Option<(ast::Attr, Vec<SmolStr>)> {
+    if select.or_token().is_some() {
+        return None;
+    }
+    let set_node = select.set().and_then(ast::Expr::flatten_paren)?;
+    let set_expr = db
+        .source_map(file_id)
+        .expr_for_node(AstPtr::new(set_node.syntax()))?;
+
+    let mut steps = MAX_DEREF_STEPS;
+    let mut set = (file_id, set_expr);
+    for attr in select.attrpath()?.attrs() {
+        let AttrKind::Static(Some(key)) = AttrKind::of(attr.clone()) else { return None };
+        let (file, set_expr) = deref_attrset(db, set, &mut steps)?;
+        let module = db.module(file);
+        let (Expr::Attrset(bindings) | Expr::RecAttrset(bindings) | Expr::LetAttrset(bindings)) =
+            &module[set_expr] else { return None };
+        match bindings
+            .statics
+            .iter()
+            .find(|&&(name, _)| module[name].text == key)
+        {
+            Some(&(name, value)) => set = binding_value_expr(db, file, name, value, &mut steps)?,
+            None if bindings.dynamics.is_empty() => {
+                let mut names = bindings
+                    .statics
+                    .iter()
+                    .map(|&(name, _)| module[name].text.clone())
+                    .collect::<Vec<_>>();
+                names.sort();
+                return Some((attr, names));
+            }
+            None => return None,
+        }
+    }
+    None
+}
+
+/// Check if the select-expression is only evaluated after a `?` check on the same set,
+/// like `if a ? b then a.b else null` or `a ? b && a.b`.
+fn is_guarded_by_has_attr(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    select: &ast::Select,
+) -> Option<bool> {
+    let module = db.module(file_id);
+    let nameres = db.name_resolution(file_id);
+    let source_map = db.source_map(file_id);
+    let select_expr = source_map.expr_for_node(AstPtr::new(select.syntax()))?;
+    let &Expr::Select(set, ..) = &module[select_expr] else { return None };
+    let Some(key) = GuardKey::of(&module, &nameres, set) else { return Some(false) };
+
+    let select_range = select.syntax().text_range();
+    let has_check = |cond: Option<ast::Expr>| {
+        let cond = cond.and_then(|cond| source_map.expr_for_node(AstPtr::new(cond.syntax())));
+        let mut guards = Vec::new();
+        if let Some(cond) = cond {
+            GuardKey::collect_has_attrs(&module, &nameres, cond, &mut guards);
+        }
+        guards.contains(&key)
+    };
+    let guarded = select.syntax().ancestors().any(|node| {
+        if let Some(cond) = ast::IfThenElse::cast(node.clone()) {
+            let in_body = cond.then_body().map_or(false, |body| {
+                body.syntax().text_range().contains_range(select_range)
+            });
+            in_body && has_check(cond.condition())
+        } else if let Some(bin) = ast::BinaryOp::cast(node) {
+            let in_rhs = bin.rhs().map_or(false, |rhs| {
+                rhs.syntax().text_range().contains_range(select_range)
+            });
+            in_rhs && bin.op_kind() == Some(BinaryOpKind::And) && has_check(bin.lhs())
+        } else {
+            false
+        }
+    });
+    Some(guarded)
+}
+
+/// The identity of an attrset checked by `?`, as the resolution of the base reference and
+/// the following static attribute names, like `a` and `[b]` for `a.b ? c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct GuardKey(ResolveResult, Vec<SmolStr>);
+
+impl GuardKey {
+    pub(crate) fn of(module: &Module, nameres: &NameResolution, expr: ExprId) -> Option<Self> {
+        match &module[expr] {
+            Expr::Reference(_) => Some(Self(nameres.get(expr)?.clone(), Vec::new())),
+            Expr::Select(set, path, None) => {
+                let mut key = Self::of(module, nameres, *set)?;
+                for &attr in path.iter() {
+                    let Expr::Literal(Literal::String(name)) = &module[attr] else { return None };
+                    key.1.push(name.clone());
+                }
+                Some(key)
+            }
+            _ => None,
+        }
+    }
+
+    /// Collect keys of sets checked by `?` in the condition expression.
+    pub(crate) fn collect_has_attrs(
+        module: &Module,
+        nameres: &NameResolution,
+        cond: ExprId,
+        guards: &mut Vec<Self>,
+    ) {
+        if let &Expr::HasAttr(set, _) = &module[cond] {
+            guards.extend(Self::of(module, nameres, set));
+        }
+        module[cond].walk_child_exprs(|e| Self::collect_has_attrs(module, nameres, e, guards));
+    }
+}
+
+/// Find the static binding of `key` in the attrset which `set` evaluates to.
+fn lookup_attr(
+    db: &dyn DefDatabase,
+    set: (FileId, ExprId),
+    key: &str,
+    steps: &mut usize,
+) -> Option<(FileId, NameId, BindingValue)> {
+    let (file, set) = deref_attrset(db, set, steps)?;
+    lookup_attr_in(db, file, set, key)
+}
+
+/// Find the static binding of `key` in an attrset literal.
+fn lookup_attr_in(
+    db: &dyn DefDatabase,
+    file: FileId,
+    set: ExprId,
+    key: &str,
+) -> Option<(FileId, NameId, BindingValue)> {
+    let module = db.module(file);
+    let (Expr::Attrset(bindings) | Expr::RecAttrset(bindings) | Expr::LetAttrset(bindings)) =
+        &module[set] else { return None };
+    bindings
+        .statics
+        .iter()
+        .find(|&&(name, _)| module[name].text == key)
+        .map(|&(name, value)| (file, name, value))
+}
+
+fn binding_value_expr(
+    db: &dyn DefDatabase,
+    file: FileId,
+    name: NameId,
+    value: BindingValue,
+    steps: &mut usize,
+) -> Option<(FileId, ExprId)> {
+    match value {
+        BindingValue::Expr(e) | BindingValue::Inherit(e) => Some((file, e)),
+        BindingValue::InheritFrom(from) => {
+            let key = db.module(file)[name].text.clone();
+            let (file, name, value) = lookup_attr(db, (file, from), &key, steps)?;
+            binding_value_expr(db, file, name, value, steps)
+        }
+    }
+}
+
+/// Statically follow references and `import`s to find the attrset literal
+/// which the expression evaluates to.
+fn deref_attrset(
+    db: &dyn DefDatabase,
+    set: (FileId, ExprId),
+    steps: &mut usize,
+) -> Option<(FileId, ExprId)> {
+    let (file, expr) = deref_expr(db, set, steps)?;
+    match db.module(file)[expr] {
+        Expr::Attrset(_) | Expr::RecAttrset(_) | Expr::LetAttrset(_) => Some((file, expr)),
+        _ => None,
+    }
+}
+
+/// Like [`deref_attrset`], but collect attrset literals from both operands of `//`.
+fn deref_attrsets(
+    db: &dyn DefDatabase,
+    set: (FileId, ExprId),
+    steps: &mut usize,
+    out: &mut Vec<(FileId, ExprId)>,
+) {
+    let Some((file, expr)) = deref_expr(db, set, steps) else { return };
+    match db.module(file)[expr] {
+        Expr::Attrset(_) | Expr::RecAttrset(_) | Expr::LetAttrset(_) => out.push((file, expr)),
+        Expr::Binary(Some(BinaryOpKind::Update), lhs, rhs) => {
+            deref_attrsets(db, (file, lhs), steps, out);
+            deref_attrsets(db, (file, rhs), steps, out);
+        }
+        _ => {}
+    }
+}
+
+/// Statically follow references, `import`s and bodies of `let`, `with` and `assert`,
+/// until reaching an expression which cannot be followed further.
+fn deref_expr(
+    db: &dyn DefDatabase,
+    (mut file, mut expr): (FileId, ExprId),
+    steps: &mut usize,
+) -> Option<(FileId, ExprId)> {
+    loop {
+        *steps = steps.checked_sub(1)?;
+        let module = db.module(file);
+        match &module[expr] {
+            Expr::LetIn(_, body) | Expr::With(_, body) | Expr::Assert(_, body) => expr = *body,
+            Expr::Reference(_) => {
+                let &ResolveResult::Definition(name) = db.name_resolution(file).get(expr)? else {
+                    return None;
+                };
+                match binding_value_of(&module, name) {
+                    Some(value) => {
+                        (file, expr) = binding_value_expr(db, file, name, value, steps)?;
+                    }
+                    // `self` in the parameter of flake outputs is the outputs themselves.
+                    None => expr = flake_self_outputs(db, file, name)?,
+                }
+            }
+            // `import ./path.nix`
+            &Expr::Apply(func, arg) => {
+                if db.name_resolution(file).check_builtin(func, &module) != Some("import") {
+                    return None;
+                }
+                let &Expr::Literal(Literal::Path(path)) = &module[arg] else { return None };
+                file = file_for_import(db, file, path.resolve(db)?)?;
+                expr = db.module(file).entry_expr();
+            }
+            _ => return Some((file, expr)),
+        }
+    }
+}
+
+/// Find the binding value of a name defined in `let` or attrsets.
+pub(crate) fn binding_value_of(module: &Module, name: NameId) -> Option<BindingValue> {
+    module.exprs().find_map(|(_, kind)| match kind {
+        Expr::LetIn(bindings, _)
+        | Expr::Attrset(bindings)
+        | Expr::RecAttrset(bindings)
+        | Expr::LetAttrset(bindings) => bindings
+            .statics
+            .iter()
+            .find_map(|&(n, value)| (n == name).then_some(value)),
+        _ => None,
+    })
+}
+
+/// Find the `outputs` function of a flake.
+pub(crate) fn flake_outputs_lambda(db: &dyn DefDatabase, file: FileId) -> Option<ExprId> {
+    if !matches!(
+        *db.module_kind(file),
+        ModuleKind::FlakeNix { .. } | ModuleKind::FlakeParts { .. }
+    ) {
+        return None;
+    }
+    let module = db.module(file);
+    let Expr::Attrset(flake_set) = &module[module.entry_expr()] else { return None };
+    let BindingValue::Expr(outputs) = flake_set.get("outputs", &module)? else { return None };
+    matches!(module[outputs], Expr::Lambda(..)).then_some(outputs)
+}
+
+/// Get the body of flake outputs, if `name` is the `self` parameter of it.
+fn flake_self_outputs(db: &dyn DefDatabase, file: FileId, name: NameId) -> Option<ExprId> {
+    let module = db.module(file);
+    let Expr::Lambda(_, Some(pat), body) = &module[flake_outputs_lambda(db, file)?] else {
+        return None;
+    };
+    let is_self = module[name].text == "self"
+        && lambda_param_names(None, Some(pat)).any(|field| field == name);
+    is_self.then_some(*body)
+}
+
+/// Goto the field of the parameter pattern, when the cursor is on the first attribute of a
+/// binding in an attrset passed to a function, like `a` in `import ./foo.nix { a = 1; }`.
+/// The function is statically followed through references and `import`s.
+fn goto_param_field(
+    db: &dyn DefDatabase,
+    file: FileId,
+    tok: &SyntaxToken,
+) -> Option<Vec<NavigationTarget>> {
+    let attr = tok.parent_ancestors().find_map(ast::Attr::cast)?;
+    let attrpath = ast::Attrpath::cast(attr.syntax().parent()?)?;
+    if attrpath.attrs().next()?.syntax() != attr.syntax() {
+        return None;
+    }
+    let binding = ast::AttrpathValue::cast(attrpath.syntax().parent()?)?;
+    let set = ast::AttrSet::cast(binding.syntax().parent()?)?;
+    let apply = ast::Apply::cast(set.syntax().parent()?)?;
+    if apply.argument()?.syntax() != set.syntax() {
+        return None;
+    }
+    let AttrKind::Static(Some(key)) = AttrKind::of(attr) else { return None };
+
+    let apply_expr = db
+        .source_map(file)
+        .expr_for_node(AstPtr::new(apply.syntax()))?;
+    let &Expr::Apply(func, _) = &db.module(file)[apply_expr] else { return None };
+    let mut steps = MAX_DEREF_STEPS;
+    let (lam_file, lam) = deref_expr(db, (file, func), &mut steps)?;
+    let module = db.module(lam_file);
+    let Expr::Lambda(_, Some(pat), _) = &module[lam] else { return None };
+    let name = lambda_param_names(None, Some(pat)).find(|&name| module[name].text == key)?;
+    let targets = name_targets(db, lam_file, name);
+    (!targets.is_empty()).then_some(targets)
+}
+
+/// Goto the definition of an output value, when the cursor is on the output attribute
+/// in the flake outputs function, like `packages.x86_64-linux.default = pkg;`.
+fn goto_flake_output(
+    db: &dyn DefDatabase,
+    file: FileId,
+    tok: &SyntaxToken,
+) -> Option<Vec<NavigationTarget>> {
+    let attr = tok.parent_ancestors().find_map(ast::Attr::cast)?;
+    let attrpath = ast::Attrpath::cast(attr.syntax().parent()?)?;
+    // Only the last attribute is defined as the value.
+    if attrpath.attrs().last()?.syntax() != attr.syntax() {
+        return None;
+    }
+    let binding = ast::AttrpathValue::cast(attrpath.syntax().parent()?)?;
+    ast::AttrSet::cast(binding.syntax().parent()?)?;
+
+    let outputs = flake_outputs_lambda(db, file)?;
+    let source_map = db.source_map(file);
+    let outputs_range = source_map.node_for_expr(outputs)?.text_range();
+    if !outputs_range.contains_range(binding.syntax().text_range()) {
+        return None;
+    }
+
+    let value = binding.value().and_then(ast::Expr::flatten_paren)?;
+    let value_expr = source_map.expr_for_node(AstPtr::new(value.syntax()))?;
+    let &ResolveResult::Definition(name) = db.name_resolution(file).get(value_expr)? else {
+        return None;
+    };
+    let targets = name_targets(db, file, name);
+    (!targets.is_empty()).then_some(targets)
 }
 
 fn goto_flake_input(
@@ -105,7 +593,10 @@ fn goto_flake_input(
     tok: SyntaxToken,
 ) -> Option<GotoDefinitionResult> {
     let module_kind = db.module_kind(file);
-    let ModuleKind::FlakeNix { explicit_inputs, param_inputs } = &*module_kind else { return None };
+    let (ModuleKind::FlakeNix { explicit_inputs, param_inputs }
+    | ModuleKind::FlakeParts { explicit_inputs, param_inputs, .. }) = &*module_kind else {
+        return None;
+    };
     let flake_info = db.source_root_flake_info(db.file_source_root(file))?;
 
     let ptr = tok.parent_ancestors().find_map(|node| {
@@ -155,7 +646,8 @@ mod tests {
         assert_eq!(f.markers().len(), 1, "Missing markers");
         let mut got = match goto_definition(&db, f[0]).expect("No definition") {
             GotoDefinitionResult::Path(path) => format!("file://{}", path.as_str()),
-            GotoDefinitionResult::Targets(targets) => {
+            GotoDefinitionResult::Targets { origin, targets } => {
+                assert!(origin.contains_inclusive(f[0].pos));
                 assert!(!targets.is_empty());
                 targets
                     .into_iter()
@@ -276,6 +768,130 @@ hello
         );
     }
 
+    #[test]
+    fn select_attr() {
+        check("let s = { a = 1; b.c = 2; }; in s.$0a", expect!["<a> = 1;"]);
+        check(
+            "let s = { a = 1; b.c = 2; }; in s.b.$0c",
+            expect!["b.<c> = 2;"],
+        );
+        check(
+            "let s = { a.b = 1; }; t = { inherit (s) a; }; in t.a.$0b",
+            expect!["a.<b> = 1;"],
+        );
+        check("rec { a = { b = 1; }; c = a.$0b; }", expect!["<b> = 1;"]);
+        check("{ a = 1; }.$0a", expect!["<a> = 1;"]);
+        // Definitions on the left are found by name resolution.
+        check("let s = { a = 1; }; in $0s.a", expect!["<s> = { a = 1; };"]);
+
+        check_no("let s = { a = 1; }; in s.$0b");
+        check_no("s: s.$0a");
+        check_no("let s = s; in s.$0a");
+    }
+
+    #[test]
+    fn select_merged_attr() {
+        check(
+            "let s = { a = 1; } // { b = 2; a = 3; }; in s.$0a",
+            expect![[r#"
+                <a> = 1;
+                <a> = 3;
+            "#]],
+        );
+        check(
+            "
+#- /default.nix
+let s = import ./bar.nix // { foo.bar = 2; }; in s.$0foo.bar
+
+#- /bar.nix
+{ foo.bar = 1; }
+            ",
+            expect![[r#"
+                <foo>.bar = 1;
+                <foo>.bar = 2;
+            "#]],
+        );
+        // `//` is shallow. `foo` is entirely from the right operand.
+        check(
+            "
+#- /default.nix
+let s = import ./bar.nix // { foo.bar = 2; }; in s.foo.$0bar
+
+#- /bar.nix
+{ foo.bar = 1; }
+            ",
+            expect!["foo.<bar> = 2;"],
+        );
+        check_no("let s = { a.b = 1; } // { a.c = 2; }; in s.a.$0b");
+        check(
+            "let s = { a = { b = 1; } // { b = 2; }; }; in s.a.$0b",
+            expect![[r#"
+                <b> = 1;
+                <b> = 2;
+            "#]],
+        );
+        check("let s = { a = 1; } // t; in s.$0a", expect!["<a> = 1;"]);
+    }
+
+    #[test]
+    fn import_param_field() {
+        check(
+            "
+#- /default.nix
+import ./foo.nix { $0a = 1; b = 2; }
+
+#- /foo.nix
+{ a, b ? 0 }: a + b
+            ",
+            expect!["{ <a>, b ? 0 }: a + b"],
+        );
+        check(
+            "let f = { a }: a; in f { a$0.b = 1; }",
+            expect!["{ <a> }: a"],
+        );
+        check_no("let f = { a }: a; in f { b$0 = 1; }");
+    }
+
+    #[test]
+    fn select_import() {
+        check(
+            "
+#- /default.nix
+(import ./bar.nix).foo.$0bar
+
+#- /bar.nix
+let baz = 1; in { foo.bar = baz; }
+            ",
+            expect!["foo.<bar> = baz;"],
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let check_ranges = |fixture: &str, expect: Expect| {
+            let (db, f) = TestDB::from_fixture(fixture).unwrap();
+            let got = goto_definition_ranges(&db, f[0])
+                .into_iter()
+                .map(|frange| format!("{:?} {:?}\n", frange.file_id, frange.range))
+                .collect::<String>();
+            expect.assert_eq(&got);
+        };
+
+        check_ranges("let a = 1; in $0a", expect!["FileId(0) 4..5\n"]);
+        check_ranges("$042", expect![""]);
+        check_ranges("let$0 a = 1; in a", expect![""]);
+        check_ranges(
+            "
+#- /default.nix
+import $0./bar
+
+#- /bar/default.nix
+42
+            ",
+            expect!["FileId(1) 0..0\n"],
+        );
+    }
+
     #[test]
     fn flake_input() {
         check(
@@ -354,4 +970,45 @@ hello
             "#,
         );
     }
+
+    #[test]
+    fn flake_output() {
+        check(
+            r#"
+#- /flake.nix input:nixpkgs=/nix/store/eeee
+{
+    outputs = { self, nixpkgs }: let pkg = nixpkgs; in {
+        packages.x86_64-linux.$0default = pkg;
+    };
+}
+            "#,
+            expect!["<pkg> = nixpkgs;"],
+        );
+        check(
+            r#"
+#- /flake.nix input:nixpkgs=/nix/store/eeee
+{
+    outputs = { self, nixpkgs }: {
+        packages.x86_64-linux.default = nixpkgs;
+        checks.x86_64-linux.default = self.packages.x86_64-linux.$0default;
+    };
+}
+            "#,
+            expect!["packages.x86_64-linux.<default> = nixpkgs;"],
+        );
+
+        // Not the last attribute.
+        check_no(
+            r#"
+#- /flake.nix input:nixpkgs=/nix/store/eeee
+{
+    outputs = { self, nixpkgs }: let pkg = nixpkgs; in {
+        $0packages.x86_64-linux.default = pkg;
+    };
+}
+            "#,
+        );
+        // Not in a flake.
+        check_no("let pkg = 1; in { packages.x86_64-linux.$0default = pkg; }");
+    }
 }
@@ -50,7 +50,7 @@ pub(crate) fn highlight_related(db: &dyn DefDatabase, fpos: FilePos) -> Option<V
     }) {
         match db.name_resolution(fpos.file_id).get(ref_expr)? {
             ResolveResult::Definition(name) => *name,
-            ResolveResult::Builtin(_) => return None,
+            ResolveResult::Builtin(_) | ResolveResult::ExtraGlobal(_) => return None,
             // We highlight all effective `with` as definitions and
             // all other Attr references of the innermost `with`.
             ResolveResult::WithExprs(with_exprs) => {
@@ -1,21 +1,102 @@
-use crate::def::{AstPtr, Expr, ResolveResult};
-use crate::{FilePos, NameKind, TyDatabase};
+use super::const_fold;
+use super::doc_comment::{doc_comment, DocCommentStyle};
+use super::goto_definition::binding_value_of;
+use crate::def::{AstPtr, BindingValue, Expr, Literal, ResolveResult};
+use crate::ty::Ty;
+use crate::{FilePos, FileRange, NameKind, TyDatabase, VfsPath};
 use builtin::ALL_BUILTINS;
 use if_chain::if_chain;
-use std::fmt::Write;
 use syntax::ast::{self, AstNode};
 use syntax::semantic::AttrKind;
-use syntax::{best_token_at_offset, match_ast, TextRange};
+use syntax::{best_token_at_offset, match_ast, SyntaxKind, TextRange};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct HoverResult {
+    /// The range of the hovered element.
     pub range: TextRange,
-    pub markup: String,
+    pub kind: HoverKind,
+    /// The name of the symbol, or the attribute name for `with` attributes and fields.
+    /// It is empty for list literals.
+    pub name: String,
+    pub ty: Ty,
+    /// Markdown documentation of builtins, or from comments preceding definitions.
+    pub doc: Option<String>,
+    /// The statically folded value in Nix syntax, for constant expressions and names bound to
+    /// them. It is omitted if it is identical to the source text.
+    pub value: Option<String>,
+    /// The definition of the symbol, resolved in the same way as goto-definition.
+    pub definition: Option<FileRange>,
 }
 
-pub(crate) fn hover(db: &dyn TyDatabase, FilePos { file_id, pos }: FilePos) -> Option<HoverResult> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HoverKind {
+    /// A name defined in source.
+    Name(NameKind),
+    /// An attribute from `with` expressions.
+    /// Contains source texts of environments, from the innermost to the outermost,
+    /// and the index and range of the `with` expression actually providing the attribute,
+    /// if it is known from the types of environments.
+    WithAttr(Vec<String>, Option<(usize, TextRange)>),
+    /// A builtin, which name is without the `builtins.` prefix.
+    Builtin,
+    /// A selected attribute field.
+    Field,
+    /// A path literal, with its resolved path.
+    Path(VfsPath),
+    /// A list literal, hovered on its brackets.
+    List,
+    /// A constant operation, hovered on its operator.
+    Expr,
+}
+
+pub(crate) fn hover(
+    db: &dyn TyDatabase,
+    FilePos { file_id, pos }: FilePos,
+    doc_style: DocCommentStyle,
+) -> Option<HoverResult> {
     let parse = db.parse(file_id);
     let tok = best_token_at_offset(&parse.syntax_node(), pos)?;
+
+    // `[ 1 2 ]`
+    //  ^ [int]
+    if matches!(tok.kind(), SyntaxKind::L_BRACK | SyntaxKind::R_BRACK) {
+        if let Some(list) = tok.parent().and_then(ast::List::cast) {
+            let source_map = db.source_map(file_id);
+            let expr = source_map.expr_for_node(AstPtr::new(list.syntax()))?;
+            return Some(HoverResult {
+                range: list.syntax().text_range(),
+                kind: HoverKind::List,
+                name: String::new(),
+                ty: db.infer(file_id).ty_for_expr(expr),
+                doc: None,
+                value: None,
+                definition: None,
+            });
+        }
+    }
+
+    // `8 * 1024`
+    //    ^ 8192
+    if let Some(op_node) = tok
+        .parent()
+        .filter(|node| matches!(node.kind(), SyntaxKind::BINARY_OP | SyntaxKind::UNARY_OP))
+    {
+        let source_map = db.source_map(file_id);
+        let module = db.module(file_id);
+        let nameres = db.name_resolution(file_id);
+        let expr = source_map.expr_for_node(AstPtr::new(&op_node))?;
+        let value = const_fold::fold(&module, &nameres, expr)?;
+        return Some(HoverResult {
+            range: op_node.text_range(),
+            kind: HoverKind::Expr,
+            name: String::new(),
+            ty: db.infer(file_id).ty_for_expr(expr),
+            doc: None,
+            value: Some(value.to_string()),
+            definition: None,
+        });
+    }
+
     let mut name_node = None;
     let ptr = tok.parent_ancestors().find_map(|node| {
         match_ast! {
@@ -42,12 +123,25 @@ pub(crate) fn hover(db: &dyn TyDatabase, FilePos { file_id, pos }: FilePos) -> O
     let mut name = None;
 
     if let Some(expr) = source_map.expr_for_node(ptr.clone()) {
+        if let &Expr::Literal(Literal::Path(path)) = &module[expr] {
+            return Some(HoverResult {
+                range,
+                kind: HoverKind::Path(db.resolve_path(path)?),
+                name: src[range].to_owned(),
+                ty: Ty::Path,
+                doc: None,
+                value: None,
+                definition: None,
+            });
+        }
+
         if let Some(builtin) = nameres.check_builtin(expr, &module) {
             return hover_builtin(builtin, range);
         }
 
         match nameres.get(expr) {
-            None => {}
+            // Shown as expressions with their types.
+            None | Some(ResolveResult::ExtraGlobal(_)) => {}
             // Covered by `check_builtin`.
             Some(ResolveResult::Builtin(_)) => unreachable!(),
             Some(ResolveResult::WithExprs(withs)) => {
@@ -55,17 +149,46 @@ pub(crate) fn hover(db: &dyn TyDatabase, FilePos { file_id, pos }: FilePos) -> O
                     Expr::Reference(text) => text,
                     _ => return None,
                 };
-                let ty = infer.ty_for_expr(expr).display().to_string();
-                let mut markup = format!("`with` attribute `{text}`\n`{ty}`\nEnvironments:");
-                for (&expr, i) in withs.iter().zip(1..) {
-                    let ptr = source_map.node_for_expr(expr)?;
-                    let with_node = ast::With::cast(ptr.to_node(&parse.syntax_node()))?;
-                    let env_text = with_node
-                        .environment()
-                        .map_or("?", |env_node| &src[env_node.syntax().text_range()]);
-                    write!(markup, "\n{i}. `with {env_text};`").unwrap();
+                let envs = withs
+                    .iter()
+                    .map(|&expr| {
+                        let ptr = source_map.node_for_expr(expr)?;
+                        let with_node = ast::With::cast(ptr.to_node(&parse.syntax_node()))?;
+                        let env_text = with_node
+                            .environment()
+                            .map_or("?", |env_node| &src[env_node.syntax().text_range()]);
+                        Some(env_text.to_owned())
+                    })
+                    .collect::<Option<Vec<_>>>()?;
+
+                // The innermost `with` whose environment has the attribute provides it.
+                // Stop at environments of unknown types or open attrsets, since they may provide
+                // it as well.
+                let mut provider = None;
+                let mut ty = infer.ty_for_expr(expr);
+                for (i, &with_expr) in withs.iter().enumerate() {
+                    let &Expr::With(env, _) = &module[with_expr] else { break };
+                    let env_ty = infer.ty_for_expr(env);
+                    let Some(set) = env_ty.as_attrset().filter(|set| set.is_closed()) else {
+                        break;
+                    };
+                    if let Some(field_ty) = set.get(text) {
+                        let with_range = source_map.node_for_expr(with_expr)?.text_range();
+                        provider = Some((i, with_range));
+                        ty = field_ty.clone();
+                        break;
+                    }
                 }
-                return Some(HoverResult { range, markup });
+
+                return Some(HoverResult {
+                    range,
+                    kind: HoverKind::WithAttr(envs, provider),
+                    name: text.to_string(),
+                    ty,
+                    doc: None,
+                    value: None,
+                    definition: None,
+                });
             }
             Some(ResolveResult::Definition(def)) => {
                 name = Some(*def);
@@ -74,18 +197,30 @@ pub(crate) fn hover(db: &dyn TyDatabase, FilePos { file_id, pos }: FilePos) -> O
     }
 
     if let Some(name) = name.or_else(|| source_map.name_for_node(ptr.clone())) {
-        let ty = infer.ty_for_name(name).display().to_string();
-        let text = &module[name].text;
-        let kind = match module[name].kind {
-            NameKind::LetIn => "Let binding",
-            NameKind::PlainAttrset => "Attrset attribute",
-            NameKind::RecAttrset => "Rec-attrset attribute",
-            NameKind::Param => "Parameter",
-            NameKind::PatField => "Field parameter",
+        let def_ptr = source_map.nodes_for_name(name).next();
+        let definition = def_ptr
+            .as_ref()
+            .map(|ptr| FileRange::new(file_id, ptr.text_range()));
+        let doc =
+            def_ptr.and_then(|ptr| doc_comment(&ptr.to_node(&parse.syntax_node()), doc_style));
+        let value = match binding_value_of(&module, name) {
+            Some(BindingValue::Expr(value)) => const_fold::fold(&module, &nameres, value)
+                .map(|folded| folded.to_string())
+                .filter(|folded| {
+                    source_map
+                        .node_for_expr(value)
+                        .map_or(true, |ptr| *folded != src[ptr.text_range()])
+                }),
+            _ => None,
         };
         return Some(HoverResult {
             range,
-            markup: format!("{kind} `{text}`\n`{ty}`"),
+            kind: HoverKind::Name(module[name].kind),
+            name: module[name].text.to_string(),
+            ty: infer.ty_for_name(name),
+            doc,
+            value,
+            definition,
         });
     }
 
@@ -130,15 +265,17 @@ pub(crate) fn hover(db: &dyn TyDatabase, FilePos { file_id, pos }: FilePos) -> O
                 break;
             }
         }
-        let range = name_node.syntax().text_range();
-        let markup = format!(
-            "Field `{}`\n`{}`",
-            name_node
+        Some(HoverResult {
+            range: name_node.syntax().text_range(),
+            kind: HoverKind::Field,
+            name: name_node
                 .token()
                 .map_or_else(String::new, |t| t.text().into()),
-            ty.display(),
-        );
-        Some(HoverResult { range, markup })
+            ty,
+            doc: None,
+            value: None,
+            definition: None,
+        })
     }) {
         return Some(ret);
     }
@@ -152,32 +289,50 @@ fn hover_builtin(name: &str, range: TextRange) -> Option<HoverResult> {
         .as_attrset()
         .unwrap()
         .get(name)
-        .map_or(String::default(), |ty| ty.display().to_string());
-    let markup = format!(
-        "`builtins.{name}`\n`{ty}`\n\n{}\n{}",
+        .cloned()
+        .unwrap_or(Ty::Unknown);
+    let doc = format!(
+        "{}\n{}",
         b.summary,
         b.doc.unwrap_or("(No documentation from Nix)"),
     );
-    Some(HoverResult { range, markup })
+    Some(HoverResult {
+        range,
+        kind: HoverKind::Builtin,
+        name: name.to_owned(),
+        ty,
+        doc: Some(doc),
+        value: None,
+        definition: None,
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use super::DocCommentStyle;
     use crate::base::SourceDatabase;
     use crate::tests::TestDB;
     use expect_test::{expect, Expect};
+    use std::fmt::Write;
 
     #[track_caller]
     fn check(fixture: &str, full: &str, expect: Expect) {
         let (db, f) = TestDB::from_fixture(fixture).unwrap();
         assert_eq!(f.markers().len(), 1);
-        let ret = super::hover(&db, f[0]).expect("No hover");
+        let ret = super::hover(&db, f[0], DocCommentStyle::Plain).expect("No hover");
         let src = db.file_content(f[0].file_id);
         assert_eq!(full, &src[ret.range]);
-        let mut got = ret.markup.trim().to_string();
-        if got.contains('\n') {
-            got += "\n";
+        let mut got = format!("{:?} `{}`\n{}", ret.kind, ret.name, ret.ty.display());
+        if let Some(def) = ret.definition {
+            write!(got, "\nDefinition: {:?}", def.range).unwrap();
         }
+        if let Some(value) = &ret.value {
+            write!(got, "\nValue: {value}").unwrap();
+        }
+        if let Some(doc) = &ret.doc {
+            write!(got, "\n\n{}", doc.trim()).unwrap();
+        }
+        got += "\n";
         expect.assert_eq(&got);
     }
 
@@ -185,7 +340,14 @@ mod tests {
     fn check_no(fixture: &str) {
         let (db, f) = TestDB::from_fixture(fixture).unwrap();
         assert_eq!(f.markers().len(), 1);
-        assert_eq!(super::hover(&db, f[0]), None);
+        assert_eq!(super::hover(&db, f[0], DocCommentStyle::Plain), None);
+    }
+
+    #[track_caller]
+    fn check_doc(fixture: &str, style: DocCommentStyle, expect: Expect) {
+        let (db, f) = TestDB::from_fixture(fixture).unwrap();
+        let ret = super::hover(&db, f[0], style).expect("No hover");
+        expect.assert_eq(ret.doc.as_deref().unwrap_or_default());
     }
 
     #[test]
@@ -194,48 +356,54 @@ mod tests {
             "let $0a = 1; in a",
             "a",
             expect![[r#"
-                Let binding `a`
-                `int`
+                Name(LetIn) `a`
+                int
+                Definition: 4..5
             "#]],
         );
         check(
             "let a.$0a = 1; in a",
             "a",
             expect![[r#"
-                Attrset attribute `a`
-                `int`
+                Name(PlainAttrset) `a`
+                int
+                Definition: 6..7
             "#]],
         );
         check(
             "{ $0a = 1; }",
             "a",
             expect![[r#"
-                Attrset attribute `a`
-                `int`
+                Name(PlainAttrset) `a`
+                int
+                Definition: 2..3
             "#]],
         );
         check(
             "rec { $0a = 1; }",
             "a",
             expect![[r#"
-                Rec-attrset attribute `a`
-                `int`
+                Name(RecAttrset) `a`
+                int
+                Definition: 6..7
             "#]],
         );
         check(
             "$0a: a",
             "a",
             expect![[r#"
-                Parameter `a`
-                `?`
+                Name(Param) `a`
+                ?
+                Definition: 0..1
             "#]],
         );
         check(
             "{$0a}: a",
             "a",
             expect![[r#"
-                Field parameter `a`
-                `?`
+                Name(PatField) `a`
+                ?
+                Definition: 1..2
             "#]],
         );
     }
@@ -246,40 +414,45 @@ mod tests {
             "let a = 1; in $0a",
             "a",
             expect![[r#"
-                Let binding `a`
-                `int`
+                Name(LetIn) `a`
+                int
+                Definition: 4..5
             "#]],
         );
         check(
             "let a = 1; in { inherit $0a; }",
             "a",
             expect![[r#"
-                Let binding `a`
-                `int`
+                Name(LetIn) `a`
+                int
+                Definition: 4..5
             "#]],
         );
         check(
             "let a = 1; in rec { inherit $0a; }",
             "a",
             expect![[r#"
-                Let binding `a`
-                `int`
+                Name(LetIn) `a`
+                int
+                Definition: 4..5
             "#]],
         );
         check(
             "a: $0a",
             "a",
             expect![[r#"
-                Parameter `a`
-                `?`
+                Name(Param) `a`
+                ?
+                Definition: 0..1
             "#]],
         );
         check(
             "{a}: $0a",
             "a",
             expect![[r#"
-                Field parameter `a`
-                `?`
+                Name(PatField) `a`
+                ?
+                Definition: 1..2
             "#]],
         );
     }
@@ -290,21 +463,61 @@ mod tests {
             "with 1; $0a",
             "a",
             expect![[r#"
-                `with` attribute `a`
-                `?`
-                Environments:
-                1. `with 1;`
+                WithAttr(["1"], None) `a`
+                ?
             "#]],
         );
         check(
             "with 1; with 2; $0a",
             "a",
             expect![[r#"
-                `with` attribute `a`
-                `?`
-                Environments:
-                1. `with 2;`
-                2. `with 1;`
+                WithAttr(["2", "1"], None) `a`
+                ?
+            "#]],
+        );
+    }
+
+    #[test]
+    fn with_provider() {
+        let src = r#"
+let
+    outer = { a = 1; b = true; };
+    inner = { b = "b"; };
+in
+    with outer; with inner; $0{name}
+"#;
+        check(
+            &src.replace("{name}", "a"),
+            "a",
+            expect![[r#"
+                WithAttr(["inner", "outer"], Some((1, 71..96))) `a`
+                int
+            "#]],
+        );
+        check(
+            &src.replace("{name}", "b"),
+            "b",
+            expect![[r#"
+                WithAttr(["inner", "outer"], Some((0, 83..96))) `b`
+                string
+            "#]],
+        );
+        // The inner environment of unknown type may provide it.
+        check(
+            "x: with { a = 1; }; with x; $0a",
+            "a",
+            expect![[r#"
+                WithAttr(["x", "{ a = 1; }"], None) `a`
+                ?
+            "#]],
+        );
+        // So may the inner environment with fields inferred from uses.
+        check(
+            "x: with { a = 1; }; with x; [ x.b $0a ]",
+            "a",
+            expect![[r#"
+                WithAttr(["x", "{ a = 1; }"], None) `a`
+                ?
             "#]],
         );
     }
@@ -315,8 +528,8 @@ mod tests {
             "$0true",
             "true",
             expect![[r#"
-                `builtins.true`
-                `bool`
+                Builtin `true`
+                bool
 
                 `builtins.true`
                 (No documentation from Nix)
@@ -326,8 +539,8 @@ mod tests {
             "$0map",
             "map",
             expect![[r#"
-                `builtins.map`
-                `(? → ?) → [?] → [?]`
+                Builtin `map`
+                (a → b) → [a] → [b]
 
                 `builtins.map f list`
                 Apply the function *f* to each element in the list *list*. For
@@ -348,8 +561,8 @@ mod tests {
             "with { }; with builtins; head$0",
             "head",
             expect![[r#"
-                `builtins.head`
-                `[?] → ?`
+                Builtin `head`
+                [a] → a
 
                 `builtins.head list`
                 Return the first element of a list; abort evaluation if the argument
@@ -365,8 +578,8 @@ mod tests {
             "let inherit (builtins) head; in head$0",
             "head",
             expect![[r#"
-                `builtins.head`
-                `[?] → ?`
+                Builtin `head`
+                [a] → a
 
                 `builtins.head list`
                 Return the first element of a list; abort evaluation if the argument
@@ -382,8 +595,8 @@ mod tests {
             "builtins.head$0",
             "builtins.head",
             expect![[r#"
-                `builtins.head`
-                `[?] → ?`
+                Builtin `head`
+                [a] → a
 
                 `builtins.head list`
                 Return the first element of a list; abort evaluation if the argument
@@ -396,12 +609,12 @@ mod tests {
             "builtins.true$0.trailing",
             "builtins.true",
             expect![[r#"
-            `builtins.true`
-            `bool`
+                Builtin `true`
+                bool
 
-            `builtins.true`
-            (No documentation from Nix)
-        "#]],
+                `builtins.true`
+                (No documentation from Nix)
+            "#]],
         );
 
         // Invalid builtins.
@@ -411,8 +624,8 @@ mod tests {
             "builtins$0.not_exist",
             "builtins",
             expect![[r#"
-                `builtins.builtins`
-                `{ abort: string → ?, add: float → float → float, addErrorContext: string → ? → ?, all: (? → bool) → [?] → bool, any: (? → bool) → [?] → bool, appendContext: (? → bool) → { }, attrNames: { } → [string], attrValues: { } → [?], … }`
+                Builtin `builtins`
+                { abort: string → ?, add: float → float → float, addErrorContext: string → a → a, all: (a → bool) → [a] → bool, any: (a → bool) → [a] → bool, appendContext: (a → bool) → { }, attrNames: { } → [string], attrValues: { } → [a], … }
 
                 `builtins.builtins`
                 (No documentation from Nix)
@@ -420,14 +633,103 @@ mod tests {
         );
     }
 
+    #[test]
+    fn path() {
+        check(
+            "
+#- /foo/default.nix
+$0../bar.nix
+            ",
+            "../bar.nix",
+            expect![[r#"
+                Path(VfsPath("/bar.nix")) `../bar.nix`
+                path
+            "#]],
+        );
+    }
+
+    #[test]
+    fn list() {
+        check(
+            "$0[ 1 2 3 ]",
+            "[ 1 2 3 ]",
+            expect![[r#"
+                List ``
+                [int]
+            "#]],
+        );
+        check(
+            r#"let a = [ 1 "a" $0]; in a"#,
+            r#"[ 1 "a" ]"#,
+            expect![[r#"
+                List ``
+                [int | string]
+            "#]],
+        );
+        check(
+            "let $0a = [ { name = \"foo\"; } ]; in a",
+            "a",
+            expect![[r#"
+                Name(LetIn) `a`
+                [{ name: string }]
+                Definition: 4..5
+            "#]],
+        );
+    }
+
+    #[test]
+    fn constant_value() {
+        check(
+            "8 $0* 1024",
+            "8 * 1024",
+            expect![[r#"
+                Expr ``
+                int
+                Value: 8192
+            "#]],
+        );
+        check(
+            r#"let a = "foo" $0+ "bar"; in a"#,
+            r#""foo" + "bar""#,
+            expect![[r#"
+                Expr ``
+                string
+                Value: "foobar"
+            "#]],
+        );
+        check(
+            "let size = 8 * 1024; in $0size",
+            "size",
+            expect![[r#"
+                Name(LetIn) `size`
+                int
+                Definition: 4..8
+                Value: 8192
+            "#]],
+        );
+        check(
+            "{ $0a = true && !false; }",
+            "a",
+            expect![[r#"
+                Name(PlainAttrset) `a`
+                bool
+                Definition: 2..3
+                Value: true
+            "#]],
+        );
+        check_no("x: x $0+ 1");
+        check_no("builtins.currentTime $0- 1");
+    }
+
     #[test]
     fn attrpath() {
         check(
             "let foo.$0bar = 1; in foo.bar",
             "bar",
             expect![[r#"
-                Attrset attribute `bar`
-                `int`
+                Name(PlainAttrset) `bar`
+                int
+                Definition: 8..11
             "#]],
         );
         check(
@@ -435,7 +737,7 @@ mod tests {
             "bar",
             expect![[r#"
                 Field `bar`
-                `int`
+                int
             "#]],
         );
         check(
@@ -443,8 +745,99 @@ mod tests {
             "bar",
             expect![[r#"
                 Field `bar`
-                `int`
+                int
             "#]],
         );
     }
+
+    #[test]
+    fn doc_comment_plain() {
+        let src = r#"
+let
+  # Add two numbers.
+  # Overflow is not checked.
+  add = a: b: a + b;
+
+  # Unrelated.
+
+  /* Multiply
+     two numbers. */
+  mul = a: b: a * b;
+
+  /** @param a The number. */
+  neg = a: -a;
+in $0add (mul 1 (neg 2))
+"#;
+        check_doc(
+            src,
+            DocCommentStyle::Plain,
+            expect![[r#"
+                Add two numbers.
+                Overflow is not checked."#]],
+        );
+        check_doc(
+            &src.replace("$0add (mul", "add ($0mul"),
+            DocCommentStyle::Plain,
+            expect![[r#"
+                Multiply
+                two numbers."#]],
+        );
+        check_doc(
+            &src.replace("$0add", "add").replace("(neg", "($0neg"),
+            DocCommentStyle::Plain,
+            expect!["@param a The number."],
+        );
+        check_doc(
+            "let a = 1; in { inherit $0a; }",
+            DocCommentStyle::Plain,
+            expect![""],
+        );
+        check_doc(
+            "let\n  a = 1; # Trailing.\n  b = 2;\nin $0b",
+            DocCommentStyle::Plain,
+            expect![""],
+        );
+        check_doc(
+            "let\n  a = 1; # Trailing.\n  # Leading.\n  b = 2;\nin $0b",
+            DocCommentStyle::Plain,
+            expect!["Leading."],
+        );
+    }
+
+    #[test]
+    fn doc_comment_nixdoc() {
+        let src = r#"
+rec {
+  # Not a doc comment.
+  add = a: b: a + b;
+
+  /**
+    Clamp a number into a range.
+
+    @param lo The lower bound.
+    @param hi The upper bound.
+    @return The clamped number.
+  */
+  clamp = lo: hi: x: if x < lo then lo else if x > hi then hi else x;
+
+  x = $0add 1 (clamp 0 1 2);
+}
+"#;
+        check_doc(src, DocCommentStyle::Nixdoc, expect![""]);
+        check_doc(
+            &src.replace("$0add 1 (clamp", "add 1 ($0clamp"),
+            DocCommentStyle::Nixdoc,
+            expect![[r#"
+                Clamp a number into a range.
+
+                # Parameters
+
+                - `lo`: The lower bound.
+                - `hi`: The upper bound.
+
+                # Returns
+
+                The clamped number."#]],
+        );
+    }
 }
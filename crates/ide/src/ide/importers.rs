@@ -0,0 +1,58 @@
+//! Files importing a file by path literals, like before the file is deleted.
+use crate::{DefDatabase, FileId};
+
+/// Files in the same source root referencing `file`, sorted by their paths.
+pub(crate) fn importers(db: &dyn DefDatabase, file: FileId) -> Vec<FileId> {
+    let root = db.source_root(db.file_source_root(file));
+    let mut ret = root
+        .iter()
+        .map(|(importer, _)| importer)
+        .filter(|&importer| importer != file && db.module_references(importer).contains(&file))
+        .collect::<Vec<_>>();
+    ret.sort_by_key(|&importer| root.path_for_file(importer).as_str().to_owned());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::tests::TestDB;
+    use expect_test::expect;
+
+    #[test]
+    fn deleted_module() {
+        let (db, f) = TestDB::from_fixture(
+            "
+#- /default.nix
+{ foo = import ./lib/foo.nix; }
+
+#- /lib/foo.nix
+import ./bar.nix
+
+#- /lib/bar.nix
+{ foo = ./foo.nix; dir = ./.; }
+
+#- /lib/default.nix
+42
+",
+        )
+        .unwrap();
+        let root = db.source_root(db.file_source_root(f["/lib/foo.nix"]));
+        let got = |path| {
+            super::importers(&db, f[path])
+                .into_iter()
+                .map(|file| format!("{}\n", root.path_for_file(file).as_str()))
+                .collect::<String>()
+        };
+        expect![[r#"
+            /default.nix
+            /lib/bar.nix
+        "#]]
+        .assert_eq(&got("/lib/foo.nix"));
+        expect![[r#"
+            /lib/bar.nix
+        "#]]
+        .assert_eq(&got("/lib/default.nix"));
+        expect![""].assert_eq(&got("/default.nix"));
+    }
+}
@@ -0,0 +1,152 @@
+//! Strings containing source code of other languages, like shell scripts in
+//! `writeShellScript "name" ''...''`.
+//!
+//! They are detected syntactically by the names of the called functions, regardless of where
+//! the functions come from.
+use crate::{DefDatabase, FileId};
+use syntax::ast::{self, AstNode};
+use syntax::semantic::{function_name, AttrKind};
+use syntax::TextRange;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Injection {
+    /// The range of the string content, excluding quotes.
+    pub range: TextRange,
+    pub language: InjectionLanguage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InjectionLanguage {
+    Bash,
+}
+
+impl InjectionLanguage {
+    /// The language identifier used by editors.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Bash => "bash",
+        }
+    }
+}
+
+/// Functions taking the script as the second argument, after the name.
+const SCRIPT_FUNCTIONS: &[&str] = &[
+    "writeScript",
+    "writeScriptBin",
+    "writeShellScript",
+    "writeShellScriptBin",
+];
+
+/// Functions taking an attrset with the script in the field `text`.
+const SCRIPT_ATTRSET_FUNCTIONS: &[&str] = &["writeShellApplication"];
+
+pub(crate) fn injections(db: &dyn DefDatabase, file: FileId) -> Vec<Injection> {
+    let root = db.parse(file).syntax_node();
+    let mut ret = root
+        .descendants()
+        .filter_map(ast::Apply::cast)
+        .filter_map(|apply| {
+            let script = script_argument(&apply)?;
+            Some(Injection {
+                range: string_content_range(&script)?,
+                language: InjectionLanguage::Bash,
+            })
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by_key(|inj| inj.range.start());
+    ret
+}
+
+/// The string of the script, if `apply` is the last application of a script function.
+fn script_argument(apply: &ast::Apply) -> Option<ast::Expr> {
+    let arg = apply.argument()?.flatten_paren()?;
+    let mut func = apply.function()?.flatten_paren()?;
+    let mut prev_args = 0;
+    while let ast::Expr::Apply(inner) = &func {
+        prev_args += 1;
+        func = inner.function()?.flatten_paren()?;
+    }
+    let name = function_name(&func)?;
+
+    if prev_args == 1 && SCRIPT_FUNCTIONS.contains(&&*name) {
+        return is_string(&arg).then_some(arg);
+    }
+    if prev_args == 0 && SCRIPT_ATTRSET_FUNCTIONS.contains(&&*name) {
+        let ast::Expr::AttrSet(set) = arg else { return None };
+        return set.bindings().find_map(|binding| {
+            let ast::Binding::AttrpathValue(binding) = binding else { return None };
+            let mut attrs = binding.attrpath()?.attrs();
+            let (Some(attr), None) = (attrs.next(), attrs.next()) else { return None };
+            let AttrKind::Static(Some(field)) = AttrKind::of(attr) else { return None };
+            let value = binding.value()?.flatten_paren()?;
+            (field == "text" && is_string(&value)).then_some(value)
+        });
+    }
+    None
+}
+
+fn is_string(expr: &ast::Expr) -> bool {
+    matches!(expr, ast::Expr::String(_) | ast::Expr::IndentString(_))
+}
+
+fn string_content_range(expr: &ast::Expr) -> Option<TextRange> {
+    let (start, end) = match expr {
+        ast::Expr::String(s) => (s.start_dquote_token()?, s.end_dquote_token()),
+        ast::Expr::IndentString(s) => (s.start_quote2_token()?, s.end_quote2_token()),
+        _ => return None,
+    };
+    // Unterminated strings extend to the end of the node.
+    let end = end.map_or(expr.syntax().text_range().end(), |tok| {
+        tok.text_range().start()
+    });
+    Some(TextRange::new(start.text_range().end(), end))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::tests::TestDB;
+    use expect_test::{expect, Expect};
+
+    #[track_caller]
+    fn check(src: &str, expect: Expect) {
+        let (db, file) = TestDB::single_file(src).unwrap();
+        let src = db.file_content(file);
+        let got = super::injections(&db, file)
+            .into_iter()
+            .map(|inj| format!("{} {:?}\n", inj.language.as_str(), &src[inj.range]))
+            .collect::<String>();
+        expect.assert_eq(&got);
+    }
+
+    #[test]
+    fn shell_script() {
+        check(
+            r#"
+pkgs.writeShellScript "hello" ''
+  echo "hello ${name}"
+''
+            "#,
+            expect![[r#"
+                bash "\n  echo \"hello ${name}\"\n"
+            "#]],
+        );
+        check(
+            r#"[ (writeScriptBin "a" "ls") (pkgs.writeShellScriptBin "b") (f "c" "d") ]"#,
+            expect![[r#"
+                bash "ls"
+            "#]],
+        );
+    }
+
+    #[test]
+    fn shell_application() {
+        check(
+            r#"writeShellApplication { name = "a"; runtimeInputs = [ ]; text = "exit 1"; }"#,
+            expect![[r#"
+                bash "exit 1"
+            "#]],
+        );
+        check(r#"writeShellApplication { name = "a"; } "b""#, expect![""]);
+    }
+}
@@ -0,0 +1,108 @@
+//! Statically folded values of constant expressions, to be shown inline by editors.
+use super::const_fold;
+use crate::def::Expr;
+use crate::{DefDatabase, FileRange};
+use syntax::TextRange;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InlineValue {
+    /// The range of the folded expression.
+    pub range: TextRange,
+    /// The folded value in Nix syntax.
+    pub text: String,
+}
+
+/// Fold the outermost constant expressions inside the range.
+/// Literals and values identical to their source text, like `{ a = 1; }`, are skipped.
+pub(crate) fn inline_values(
+    db: &dyn DefDatabase,
+    FileRange { file_id, range }: FileRange,
+) -> Vec<InlineValue> {
+    let src = db.file_content(file_id);
+    let module = db.module(file_id);
+    let source_map = db.source_map(file_id);
+    let nameres = db.name_resolution(file_id);
+
+    let mut ret = Vec::new();
+    let mut stack = vec![module.entry_expr()];
+    while let Some(expr) = stack.pop() {
+        // Implicit attrsets from attrpaths, like `a` in `{ a.b = 1; }`, have no source.
+        if let Some(expr_range) = source_map.node_for_expr(expr).map(|ptr| ptr.text_range()) {
+            if range.intersect(expr_range).is_none() {
+                continue;
+            }
+            if range.contains_range(expr_range) && !matches!(module[expr], Expr::Literal(_)) {
+                if let Some(value) = const_fold::fold(&module, &nameres, expr) {
+                    let text = value.to_string();
+                    if text != src[expr_range] {
+                        ret.push(InlineValue {
+                            range: expr_range,
+                            text,
+                        });
+                    }
+                    continue;
+                }
+            }
+        }
+        module[expr].walk_child_exprs(|child| stack.push(child));
+    }
+    ret.sort_by_key(|value| value.range.start());
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::base::SourceDatabase;
+    use crate::tests::TestDB;
+    use crate::FileRange;
+    use expect_test::{expect, Expect};
+    use syntax::TextRange;
+
+    #[track_caller]
+    fn check(fixture: &str, expect: Expect) {
+        let (db, f) = TestDB::from_fixture(fixture).unwrap();
+        let file = f.files()[0];
+        let src = db.file_content(file);
+        let range = match f.markers() {
+            [] => TextRange::up_to((src.len() as u32).into()),
+            [start, end] => TextRange::new(start.pos, end.pos),
+            _ => panic!("Expecting zero or two markers"),
+        };
+        let got = super::inline_values(&db, FileRange::new(file, range))
+            .into_iter()
+            .map(|value| format!("{}: {}\n", &src[value.range], value.text))
+            .collect::<String>();
+        expect.assert_eq(&got);
+    }
+
+    #[test]
+    fn arithmetic() {
+        check(
+            "let a = 1 + 2; b = f (8 * 1024); in a",
+            expect![[r#"
+                1 + 2: 3
+                8 * 1024: 8192
+            "#]],
+        );
+    }
+
+    #[test]
+    fn attrset() {
+        check(
+            "{ a = 1; b = { c = 2 * 3; d = [ 1 ]; }; e = f 1; g = { h = 1; }; }",
+            expect![[r#"
+                { c = 2 * 3; d = [ 1 ]; }: { c = 6; d = [ 1 ]; }
+            "#]],
+        );
+    }
+
+    #[test]
+    fn in_range() {
+        check(
+            "[ (1 + 1) $0(2 + 2)$1 (3 + 3) ]",
+            expect![[r#"
+                2 + 2: 4
+            "#]],
+        );
+    }
+}
@@ -1,35 +1,58 @@
 mod assists;
+mod call_hierarchy;
 mod completion;
+mod const_fold;
 mod diagnostics;
+mod doc_comment;
 mod expand_selection;
+mod fetcher_prefetch;
+mod folding_range;
 mod goto_definition;
 mod highlight_related;
 mod hover;
+mod importers;
+mod injection;
+mod inline_value;
 mod links;
+mod module_option;
+mod pattern_bindings;
 mod references;
 mod rename;
 mod symbol_hierarchy;
 mod syntax_highlighting;
 
-use crate::base::SourceDatabaseStorage;
-use crate::def::DefDatabaseStorage;
-use crate::ty::TyDatabaseStorage;
+use crate::base::{SourceDatabaseStorage, Upcast};
+use crate::def::{
+    self, DefDatabaseStorage, ModuleQuery, ModuleWithSourceMapQuery, NameId, NameResolutionQuery,
+    ParseQuery, ScopesQuery, SourceMapQuery,
+};
+use crate::ty::{InferQuery, Ty, TyDatabaseStorage};
 use crate::{
-    Change, Diagnostic, FileId, FilePos, FileRange, FileSet, SourceRoot, VfsPath, WorkspaceEdit,
+    Change, DefDatabase, Diagnostic, FileId, FilePos, FileRange, FileSet, SourceRoot, VfsPath,
+    WorkspaceEdit,
 };
 use nix_interop::DEFAULT_IMPORT_FILE;
-use salsa::{Database, Durability, ParallelDatabase};
+use salsa::{Database, Durability, EventKind, ParallelDatabase, Query};
 use smol_str::SmolStr;
+use std::collections::HashSet;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use syntax::TextRange;
 
 pub use assists::{Assist, AssistKind};
-pub use completion::{CompletionItem, CompletionItemKind};
+pub use call_hierarchy::{CallHierarchyCall, CallHierarchyItem};
+pub use completion::{CompletionCategory, CompletionItem, CompletionItemKind};
+pub use doc_comment::DocCommentStyle;
+pub use fetcher_prefetch::FetcherPrefetch;
+pub use folding_range::{FoldingRange, FoldingRangeKind};
 pub use goto_definition::GotoDefinitionResult;
 pub use highlight_related::HlRelated;
-pub use hover::HoverResult;
+pub use hover::{HoverKind, HoverResult};
+pub use injection::{Injection, InjectionLanguage};
+pub use inline_value::InlineValue;
 pub use links::{Link, LinkTarget};
-pub use rename::RenameResult;
+pub use rename::{RenameError, RenameResult};
 pub use symbol_hierarchy::SymbolTree;
 pub use syntax_highlighting::{HlAttrField, HlKeyword, HlOperator, HlPunct, HlRange, HlTag};
 
@@ -44,21 +67,54 @@ pub use salsa::Cancelled;
 
 pub type Cancellable<T> = Result<T, Cancelled>;
 
+/// A flag to interrupt computations of a single [`Analysis`] snapshot,
+/// without cancelling other snapshots like [`AnalysisHost::request_cancellation`] does.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
 #[salsa::database(SourceDatabaseStorage, DefDatabaseStorage, TyDatabaseStorage)]
 struct RootDatabase {
     storage: salsa::Storage<Self>,
+    cancellation: CancellationToken,
 }
 
-impl salsa::Database for RootDatabase {}
+impl salsa::Database for RootDatabase {
+    fn salsa_event(&self, event: salsa::Event) {
+        // Salsa checks for cancellation before executing or validating each query.
+        // Unwind in the same way as salsa's own cancellation, so it is caught by `with_db`.
+        if matches!(event.kind, EventKind::WillCheckCancellation)
+            && self.cancellation.is_cancelled()
+        {
+            std::panic::resume_unwind(Box::new(Cancelled::PendingWrite));
+        }
+    }
+}
 
 impl salsa::ParallelDatabase for RootDatabase {
     fn snapshot(&self) -> salsa::Snapshot<Self> {
         salsa::Snapshot::new(RootDatabase {
             storage: self.storage.snapshot(),
+            cancellation: CancellationToken::default(),
         })
     }
 }
 
+impl Upcast<dyn DefDatabase> for RootDatabase {
+    fn upcast(&self) -> &(dyn DefDatabase + 'static) {
+        self
+    }
+}
+
 impl fmt::Debug for RootDatabase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RootDatabase").finish_non_exhaustive()
@@ -71,8 +127,11 @@ impl Default for RootDatabase {
 
         let mut db = Self {
             storage: salsa::Storage::default(),
+            cancellation: CancellationToken::default(),
         };
         db.set_flake_graph_with_durability(Default::default(), Durability::MEDIUM);
+        db.set_search_paths_with_durability(Default::default(), Durability::HIGH);
+        db.set_extra_globals_with_durability(Default::default(), Durability::HIGH);
         db
     }
 }
@@ -116,6 +175,20 @@ impl AnalysisHost {
         self.request_cancellation();
         change.apply(&mut self.db);
     }
+
+    /// Limit the number of files whose syntax trees, lowered modules and inference results are
+    /// cached. Results of the least recently used files beyond it are evicted immediately,
+    /// and recomputed on demand.
+    pub fn set_lru_capacity(&mut self, capacity: usize) {
+        let db = &mut self.db;
+        ParseQuery.in_db_mut(db).set_lru_capacity(capacity);
+        ModuleWithSourceMapQuery.in_db_mut(db).set_lru_capacity(capacity);
+        ModuleQuery.in_db_mut(db).set_lru_capacity(capacity);
+        SourceMapQuery.in_db_mut(db).set_lru_capacity(capacity);
+        ScopesQuery.in_db_mut(db).set_lru_capacity(capacity);
+        NameResolutionQuery.in_db_mut(db).set_lru_capacity(capacity);
+        InferQuery.in_db_mut(db).set_lru_capacity(capacity);
+    }
 }
 
 #[derive(Debug)]
@@ -131,6 +204,11 @@ impl Analysis {
         Cancelled::catch(|| f(&self.db))
     }
 
+    /// The token to interrupt in-flight and future computations of this snapshot.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.db.cancellation.clone()
+    }
+
     pub fn expand_selection(&self, frange: FileRange) -> Cancellable<Option<Vec<TextRange>>> {
         self.with_db(|db| expand_selection::expand_selection(db, frange))
     }
@@ -139,18 +217,47 @@ impl Analysis {
         &self,
         file: FileId,
         range: Option<TextRange>,
+        with_injections: bool,
     ) -> Cancellable<Vec<HlRange>> {
-        self.with_db(|db| syntax_highlighting::highlight(db, file, range))
+        self.with_db(|db| syntax_highlighting::highlight(db, file, range, with_injections))
     }
 
     pub fn diagnostics(&self, file: FileId) -> Cancellable<Vec<Diagnostic>> {
         self.with_db(|db| diagnostics::diagnostics(db, file))
     }
 
+    pub fn syntax_errors(&self, file: FileId) -> Cancellable<Vec<(TextRange, String)>> {
+        self.with_db(|db| diagnostics::syntax_errors(db, file))
+    }
+
+    pub fn deep_nesting_diagnostics(
+        &self,
+        file: FileId,
+        max_depth: usize,
+    ) -> Cancellable<Vec<Diagnostic>> {
+        self.with_db(|db| diagnostics::deep_nesting(db, file, max_depth))
+    }
+
+    pub fn broken_binding_diagnostics(&self, file: FileId) -> Cancellable<Vec<Diagnostic>> {
+        self.with_db(|db| diagnostics::broken_bindings(db, file))
+    }
+
     pub fn goto_definition(&self, pos: FilePos) -> Cancellable<Option<GotoDefinitionResult>> {
         self.with_db(|db| goto_definition::goto_definition(db, pos))
     }
 
+    pub fn goto_definition_ranges(&self, pos: FilePos) -> Cancellable<Vec<FileRange>> {
+        self.with_db(|db| goto_definition::goto_definition_ranges(db, pos))
+    }
+
+    pub fn goto_definition_range(&self, pos: FilePos) -> Cancellable<Option<FileRange>> {
+        self.with_db(|db| {
+            goto_definition::goto_definition_ranges(db, pos)
+                .into_iter()
+                .next()
+        })
+    }
+
     pub fn completions(
         &self,
         pos: FilePos,
@@ -175,23 +282,118 @@ impl Analysis {
         self.with_db(|db| rename::rename(db, fpos, new_name))
     }
 
-    pub fn hover(&self, fpos: FilePos) -> Cancellable<Option<HoverResult>> {
-        self.with_db(|db| hover::hover(db, fpos))
+    pub fn rename_files(&self, renames: &[(FileId, VfsPath)]) -> Cancellable<WorkspaceEdit> {
+        self.with_db(|db| rename::rename_files(db, renames))
+    }
+
+    pub fn importers(&self, file: FileId) -> Cancellable<Vec<FileId>> {
+        self.with_db(|db| importers::importers(db, file))
+    }
+
+    pub fn reachable_files(&self, files: &[FileId]) -> Cancellable<HashSet<FileId>> {
+        self.with_db(|db| def::reachable_files(db, files))
+    }
+
+    pub fn hover(
+        &self,
+        fpos: FilePos,
+        doc_style: DocCommentStyle,
+    ) -> Cancellable<Option<HoverResult>> {
+        self.with_db(|db| hover::hover(db, fpos, doc_style))
     }
 
     pub fn symbol_hierarchy(&self, file: FileId) -> Cancellable<Vec<SymbolTree>> {
         self.with_db(|db| symbol_hierarchy::symbol_hierarchy(db, file))
     }
 
+    pub fn attr_path_at(&self, fpos: FilePos) -> Cancellable<Option<String>> {
+        self.with_db(|db| symbol_hierarchy::attr_path_at(db, fpos))
+    }
+
     pub fn links(&self, file: FileId) -> Cancellable<Vec<Link>> {
         self.with_db(|db| links::links(db, file))
     }
 
-    pub fn assists(&self, frange: FileRange) -> Cancellable<Vec<Assist>> {
-        self.with_db(|db| assists::assists(db, frange))
+    pub fn assists(
+        &self,
+        frange: FileRange,
+        only: Option<&[AssistKind]>,
+    ) -> Cancellable<Vec<Assist>> {
+        self.with_db(|db| assists::assists(db, frange, only))
+    }
+
+    pub fn fetcher_prefetch(&self, frange: FileRange) -> Cancellable<Option<FetcherPrefetch>> {
+        self.with_db(|db| fetcher_prefetch::fetcher_prefetch(db, frange))
     }
 
     pub fn highlight_related(&self, fpos: FilePos) -> Cancellable<Vec<HlRelated>> {
         self.with_db(|db| highlight_related::highlight_related(db, fpos).unwrap_or_default())
     }
+
+    pub fn prepare_call_hierarchy(
+        &self,
+        fpos: FilePos,
+        top_level_only: bool,
+    ) -> Cancellable<Option<CallHierarchyItem>> {
+        self.with_db(|db| call_hierarchy::prepare_call_hierarchy(db, fpos, top_level_only))
+    }
+
+    pub fn incoming_calls(
+        &self,
+        fpos: FilePos,
+        top_level_only: bool,
+    ) -> Cancellable<Option<Vec<CallHierarchyCall>>> {
+        self.with_db(|db| call_hierarchy::incoming_calls(db, fpos, top_level_only))
+    }
+
+    pub fn outgoing_calls(
+        &self,
+        fpos: FilePos,
+        top_level_only: bool,
+    ) -> Cancellable<Option<Vec<CallHierarchyCall>>> {
+        self.with_db(|db| call_hierarchy::outgoing_calls(db, fpos, top_level_only))
+    }
+
+    pub fn folding_ranges(
+        &self,
+        file: FileId,
+        region_markers: &[(String, String)],
+    ) -> Cancellable<Vec<FoldingRange>> {
+        self.with_db(|db| folding_range::folding_ranges(db, file, region_markers))
+    }
+
+    pub fn inline_values(&self, frange: FileRange) -> Cancellable<Vec<InlineValue>> {
+        self.with_db(|db| inline_value::inline_values(db, frange))
+    }
+
+    pub fn injections(&self, file: FileId) -> Cancellable<Vec<Injection>> {
+        self.with_db(|db| injection::injections(db, file))
+    }
+
+    pub fn pattern_bindings(&self, fpos: FilePos) -> Cancellable<Vec<(SmolStr, NameId, Ty)>> {
+        self.with_db(|db| pattern_bindings::pattern_bindings(db, fpos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnalysisHost, DocCommentStyle};
+    use crate::FilePos;
+
+    #[test]
+    fn cancellation_token() {
+        let src = format!("[ {} ]", "(let a = 1; in a) ".repeat(1000));
+        let (host, file) = AnalysisHost::new_single_file(&src);
+
+        let analysis = host.snapshot();
+        let other = host.snapshot();
+        analysis.cancellation_token().cancel();
+        assert!(analysis.diagnostics(file).is_err());
+        assert!(analysis
+            .hover(FilePos::new(file, 0.into()), DocCommentStyle::Plain)
+            .is_err());
+
+        // Other snapshots are not affected.
+        assert_eq!(other.diagnostics(file).unwrap(), Vec::new());
+    }
 }
@@ -0,0 +1,123 @@
+//! Options declared by NixOS-style modules via `mkOption`.
+//!
+//! Declarations are collected syntactically per file, like
+//! `options.services.foo.enable = mkOption { description = "..."; };`,
+//! so definitions under `config` in modules of the same flake can be completed.
+use syntax::ast::{self, AstNode};
+use syntax::semantic::{
+    function_name, strip_indent, unescape_string_escape, unescape_string_literal, AttrKind,
+    StrippedStringPart,
+};
+use syntax::SyntaxNode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct OptionDecl {
+    /// The option path, without the leading `options`.
+    pub path: Vec<String>,
+    /// The literal `description`, or the source of `type` if the description is not a literal.
+    pub doc: Option<String>,
+}
+
+pub(crate) fn option_declarations(root: &SyntaxNode) -> Vec<OptionDecl> {
+    root.descendants()
+        .filter_map(ast::AttrpathValue::cast)
+        .filter_map(|binding| {
+            let ast::Expr::Apply(apply) = binding.value()?.flatten_paren()? else { return None };
+            if function_name(&apply.function()?.flatten_paren()?)?.as_str() != "mkOption" {
+                return None;
+            }
+            let set = binding
+                .syntax()
+                .parent()
+                .filter(|n| ast::AttrSet::can_cast(n.kind()))?;
+            let mut path = attrset_path(&set)?;
+            path.extend(attrpath_names(&binding.attrpath()?)?);
+            if path.first().map(|s| &**s) != Some("options") {
+                return None;
+            }
+            path.remove(0);
+
+            let ast::Expr::AttrSet(args) = apply.argument()?.flatten_paren()? else { return None };
+            let field = |name: &str| {
+                args.bindings().find_map(|b| {
+                    let ast::Binding::AttrpathValue(b) = b else { return None };
+                    (attrpath_names(&b.attrpath()?)? == [name]).then(|| b.value())?
+                })
+            };
+            let doc = field("description")
+                .and_then(|e| literal_string(&e))
+                .or_else(|| Some(format!("Type: `{}`", field("type")?.syntax())));
+            Some(OptionDecl { path, doc })
+        })
+        .collect()
+}
+
+/// The attribute path where an attrset is bound, like `["a", "b"]` for `{ a.b = { }; }`.
+/// Applications of `mkIf cond` are looked through.
+pub(crate) fn attrset_path(set: &SyntaxNode) -> Option<Vec<String>> {
+    let mut node = set.clone();
+    let mut path = Vec::new();
+    loop {
+        let mut parent = node.parent()?;
+        while ast::Paren::can_cast(parent.kind()) {
+            node = parent;
+            parent = node.parent()?;
+        }
+        if let Some(apply) = ast::Apply::cast(parent.clone()) {
+            let func_name = (|| match apply.function()?.flatten_paren()? {
+                ast::Expr::Apply(inner) => function_name(&inner.function()?.flatten_paren()?),
+                _ => None,
+            })();
+            if func_name.as_deref() != Some("mkIf") || apply.argument()?.syntax() != &node {
+                return None;
+            }
+            node = parent;
+            continue;
+        }
+        let Some(binding) = ast::AttrpathValue::cast(parent) else {
+            // Reached the module root.
+            path.reverse();
+            return Some(path);
+        };
+        let names = attrpath_names(&binding.attrpath()?)?;
+        path.extend(names.into_iter().rev());
+        node = binding.syntax().parent()?;
+        if !ast::AttrSet::can_cast(node.kind()) {
+            return None;
+        }
+    }
+}
+
+fn attrpath_names(path: &ast::Attrpath) -> Option<Vec<String>> {
+    path.attrs()
+        .map(|attr| match AttrKind::of(attr) {
+            AttrKind::Static(name) => name,
+            AttrKind::Dynamic(_) => None,
+        })
+        .collect()
+}
+
+/// The value of a string literal without interpolations.
+pub(crate) fn literal_string(expr: &ast::Expr) -> Option<String> {
+    match expr.clone().flatten_paren()? {
+        ast::Expr::String(s) => unescape_string_literal(&s),
+        ast::Expr::IndentString(s) => {
+            let mut ret = String::new();
+            strip_indent(&s, |part| {
+                match part {
+                    StrippedStringPart::Fragment(frag) => ret += frag,
+                    StrippedStringPart::Escape(tok) => ret += unescape_string_escape(tok.text()),
+                    StrippedStringPart::Dynamic(_) => return Err(()),
+                }
+                Ok(())
+            })
+            .ok()?;
+            Some(ret)
+        }
+        // `lib.mdDoc "..."` in older nixpkgs.
+        ast::Expr::Apply(apply) if function_name(&apply.function()?)?.as_str() == "mdDoc" => {
+            literal_string(&apply.argument()?)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,127 @@
+//! Names introduced by a lambda parameter or a `let` at a position.
+use crate::def::{AstPtr, Expr, NameId, Pat};
+use crate::ty::Ty;
+use crate::{FilePos, TyDatabase};
+use smol_str::SmolStr;
+use syntax::ast::{self, AstNode};
+use syntax::{best_token_at_offset, match_ast};
+
+/// Return names bound by the innermost lambda parameter or `let` bindings containing the
+/// position, in source order, with their inferred types.
+///
+/// For lambdas, the position must be inside the parameter, like `{ a, b }@args` or `x`,
+/// and the result includes both the fields and the `@`-bound whole argument.
+/// For `let`, the position must be before `in`.
+pub(crate) fn pattern_bindings(
+    db: &dyn TyDatabase,
+    FilePos { file_id, pos }: FilePos,
+) -> Vec<(SmolStr, NameId, Ty)> {
+    let parse = db.parse(file_id);
+    let Some(tok) = best_token_at_offset(&parse.syntax_node(), pos) else { return Vec::new() };
+    let module = db.module(file_id);
+    let source_map = db.source_map(file_id);
+
+    let names = tok.parent_ancestors().find_map(|node| {
+        match_ast! {
+            match node {
+                ast::Lambda(n) => {
+                    if !n.param()?.syntax().text_range().contains_inclusive(pos) {
+                        return None;
+                    }
+                    let expr = source_map.expr_for_node(AstPtr::new(n.syntax()))?;
+                    let Expr::Lambda(param, pat, _) = &module[expr] else { return None };
+                    Some(lambda_param_names(*param, pat.as_ref()).collect::<Vec<_>>())
+                },
+                ast::LetIn(n) => {
+                    if n.in_token()?.text_range().start() < pos {
+                        return None;
+                    }
+                    let expr = source_map.expr_for_node(AstPtr::new(n.syntax()))?;
+                    let Expr::LetIn(bindings, _) = &module[expr] else { return None };
+                    Some(bindings.statics.iter().map(|&(name, _)| name).collect())
+                },
+                _ => None,
+            }
+        }
+    });
+    let Some(mut names) = names else { return Vec::new() };
+
+    names.sort_by_key(|&name| {
+        source_map
+            .nodes_for_name(name)
+            .next()
+            .map(|ptr| ptr.text_range().start())
+    });
+    let infer = db.infer(file_id);
+    names
+        .into_iter()
+        .map(|name| (module[name].text.clone(), name, infer.ty_for_name(name)))
+        .collect()
+}
+
+/// Names bound by a lambda: the `@`-bound whole argument or the simple parameter if any,
+/// then fields of the pattern.
+pub(crate) fn lambda_param_names(
+    param: Option<NameId>,
+    pat: Option<&Pat>,
+) -> impl Iterator<Item = NameId> + '_ {
+    let fields = pat.into_iter().flat_map(|pat| pat.fields.iter());
+    param
+        .into_iter()
+        .chain(fields.filter_map(|&(name, _)| name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::TestDB;
+    use expect_test::{expect, Expect};
+
+    #[track_caller]
+    fn check(fixture: &str, expect: Expect) {
+        let (db, f) = TestDB::from_fixture(fixture).unwrap();
+        let got = super::pattern_bindings(&db, f[0])
+            .into_iter()
+            .map(|(text, _, ty)| format!("{text}: {}\n", ty.debug()))
+            .collect::<String>();
+        expect.assert_eq(&got);
+    }
+
+    #[test]
+    fn lambda_pattern() {
+        check(
+            "{ $0a ? 1, b, ... }@args: a + b",
+            expect![[r#"
+                a: int
+                b: int
+                args: { a: int, b: int }
+            "#]],
+        );
+        check(
+            "args@{ a, b$0 }: a",
+            expect![[r#"
+                args: { a: ?, b: ? }
+                a: ?
+                b: ?
+            "#]],
+        );
+        check(
+            "$0x: x + 1",
+            expect![[r#"
+                x: int
+            "#]],
+        );
+        check("x: $0x", expect![""]);
+    }
+
+    #[test]
+    fn let_bindings() {
+        check(
+            r#"let $0x = 1; y = "a"; in x"#,
+            expect![[r#"
+                x: int
+                y: string
+            "#]],
+        );
+        check("let x = 1; in $0x", expect![""]);
+    }
+}
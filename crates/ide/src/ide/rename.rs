@@ -1,18 +1,48 @@
-use crate::def::{AstPtr, NameId, ResolveResult};
-use crate::{DefDatabase, FilePos, TextEdit, WorkspaceEdit};
+use super::goto_definition::resolve_select_attr;
+use crate::def::{AstPtr, Expr, Literal, NameId, PathAnchor, ResolveResult};
+use crate::{DefDatabase, FileId, FilePos, TextEdit, VfsPath, WorkspaceEdit};
+use nix_interop::DEFAULT_IMPORT_FILE;
 use smol_str::SmolStr;
 use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use syntax::ast::{self, AstNode};
 use syntax::semantic::escape_literal_attr;
 use syntax::{best_token_at_offset, match_ast, SyntaxKind, TextRange};
 
-pub type RenameResult<T> = Result<T, String>;
+pub type RenameResult<T> = Result<T, RenameError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameError {
+    NoReferences,
+    InheritedStringLiteral,
+    ReferencedStringLiteral,
+    /// The new name would shadow, or be shadowed by, another binding with the same name.
+    Conflict(SmolStr),
+    Overlap,
+}
+
+impl fmt::Display for RenameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoReferences => "No references found".fmt(f),
+            Self::InheritedStringLiteral => {
+                "Cannot rename from a string literal while it is inherited".fmt(f)
+            }
+            Self::ReferencedStringLiteral => {
+                "Cannot rename to a string literal while it is referenced".fmt(f)
+            }
+            Self::Conflict(name) => write!(f, "`{name}` would conflict with an existing binding"),
+            Self::Overlap => "Change would overlap".fmt(f),
+        }
+    }
+}
 
 pub(crate) fn prepare_rename(
     db: &dyn DefDatabase,
     fpos: FilePos,
 ) -> RenameResult<(TextRange, SmolStr)> {
-    let (range, name) = find_name(db, fpos).ok_or_else(|| "No references found".to_owned())?;
+    let (range, name) = find_name(db, fpos).ok_or(RenameError::NoReferences)?;
     let module = db.module(fpos.file_id);
     let text = module[name].text.clone();
     Ok((range, text))
@@ -23,34 +53,108 @@ pub(crate) fn rename(
     fpos: FilePos,
     new_name: &str,
 ) -> RenameResult<WorkspaceEdit> {
-    let (_, name) = find_name(db, fpos).ok_or_else(|| "No references found".to_owned())?;
+    let (_, name) = find_name(db, fpos).ok_or(RenameError::NoReferences)?;
 
     let new_attr = escape_literal_attr(new_name);
 
     let file_id = fpos.file_id;
-    let src = db.file_content(file_id);
     let parse = db.parse(file_id);
-    let module = db.module(fpos.file_id);
     let source_map = db.source_map(file_id);
+    let name_refs = db.name_reference(file_id);
 
-    let old_attr = escape_literal_attr(&module[name].text);
+    // Attributes re-exporting the name via `inherit old;` are both references of the name and
+    // definitions of attributes. They are renamed together to `inherit new;`, along with uses of
+    // the attributes, so the re-exported attribute keeps pointing to the same binding.
+    let mut names = vec![name];
+    let mut reexports = HashSet::new();
+    let mut i = 0;
+    while let Some(&name) = names.get(i) {
+        i += 1;
+        for &expr in name_refs.name_references(name).unwrap_or_default() {
+            let Some(ptr) = source_map.node_for_expr(expr) else { continue };
+            let Some(attr_name) = source_map.name_for_node(ptr.clone()) else { continue };
+            reexports.insert(ptr);
+            if !names.contains(&attr_name) {
+                names.push(attr_name);
+            }
+        }
+    }
+
+    for &name in &names {
+        check_conflict(db, file_id, name, new_name)?;
+    }
 
     let mut edits = Vec::new();
+    for &name in &names {
+        rename_name(db, file_id, name, &new_attr, &reexports, &mut edits)?;
+    }
+
+    // Rename attributes of select-expressions, like `set.old`.
+    for attr in parse
+        .syntax_node()
+        .descendants()
+        .filter_map(ast::Select::cast)
+        .filter_map(|select| select.attrpath())
+        .flat_map(|path| path.attrs())
+    {
+        if matches!(
+            resolve_select_attr(db, file_id, &attr),
+            Some((file, name)) if file == file_id && names.contains(&name)
+        ) {
+            edits.push(TextEdit {
+                delete: attr.syntax().text_range(),
+                insert: SmolStr::new(&new_attr),
+            });
+        }
+    }
+
+    edits.sort_by_key(|edit| edit.delete.start());
+    // Re-exporting attributes are both definitions and references.
+    edits.dedup();
+
+    // Sanity check.
+    if edits
+        .windows(2)
+        .any(|w| w[0].delete.end() > w[1].delete.start())
+    {
+        return Err(RenameError::Overlap);
+    }
+
+    Ok(WorkspaceEdit {
+        content_edits: [(file_id, edits)].into_iter().collect(),
+    })
+}
+
+/// Rename the definitions and references of a single name.
+fn rename_name(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    name: NameId,
+    new_attr: &Cow<'_, str>,
+    reexports: &HashSet<AstPtr>,
+    edits: &mut Vec<TextEdit>,
+) -> RenameResult<()> {
+    let src = db.file_content(file_id);
+    let parse = db.parse(file_id);
+    let module = db.module(file_id);
+    let source_map = db.source_map(file_id);
+
+    let old_attr = escape_literal_attr(&module[name].text);
 
     // Rename definitions.
     for ptr in source_map.nodes_for_name(name) {
         let attr_node = ptr.to_node(&parse.syntax_node());
 
-        // Simple case for non-inherited names.
+        // Simple case for non-inherited names and re-exports.
         let i = match attr_node.parent().and_then(ast::Inherit::cast) {
-            None => {
+            Some(i) if !reexports.contains(&ptr) => i,
+            _ => {
                 edits.push(TextEdit {
                     delete: attr_node.text_range(),
-                    insert: SmolStr::new(&new_attr),
+                    insert: SmolStr::new(new_attr),
                 });
                 continue;
             }
-            Some(i) => i,
         };
 
         // Here we are renaming the *definition* of an inherited name.
@@ -76,7 +180,7 @@ pub(crate) fn rename(
         match i.from_expr() {
             None => {
                 if matches!(old_attr, Cow::Owned(_)) {
-                    return Err("Cannot rename from a string literal while it is inherited".into());
+                    return Err(RenameError::InheritedStringLiteral);
                 }
                 // `new = old;`.
                 edits.push(TextEdit {
@@ -105,66 +209,64 @@ pub(crate) fn rename(
     let name_refs = db.name_reference(file_id);
     let refs = name_refs.name_references(name).unwrap_or_default();
     if matches!(new_attr, Cow::Owned(_)) && !refs.is_empty() {
-        return Err("Cannot rename to a string literal while it is referenced".into());
+        return Err(RenameError::ReferencedStringLiteral);
     }
+    // References in `inherit old;` are always re-exports, which are handled as definitions.
     for &expr in refs {
         let ptr = source_map
             .node_for_expr(expr)
             .expect("Must be a valid Expr::Reference");
-        let ref_node = ptr.to_node(&parse.syntax_node());
-
-        // Simple case for non-inherited names.
-        let i = match ref_node.parent().and_then(ast::Inherit::cast) {
-            None => {
-                edits.push(TextEdit {
-                    delete: ptr.text_range(),
-                    insert: SmolStr::new(&new_attr),
-                });
-                continue;
-            }
-            Some(i) => i,
-        };
-
-        // Here we are renaming the *reference* of an inherited name.
-        // `inherit old;` => `old = new;`
-        // TODO: Check if `new` collides with another names.
-        assert!(
-            i.from_expr().is_none(),
-            "Expr::Ref can only be from Inherit without from_expr"
-        );
-
-        // First remove the old binding.
-        edits.push(TextEdit {
-            // Delete the whole Inherit if it is the only Attr.
-            delete: if i.attrs().count() == 1 {
-                i.syntax().text_range()
-            // Otherwise, delete only the Attr itself.
-            } else {
-                ref_node.text_range()
-            },
-            insert: "".into(),
-        });
-
-        // Then construct a new binding.
         edits.push(TextEdit {
-            delete: TextRange::empty(i.syntax().text_range().end()),
-            insert: format!("{} = {};", old_attr, new_attr).into(),
+            delete: ptr.text_range(),
+            insert: SmolStr::new(new_attr),
         });
     }
 
-    edits.sort_by_key(|edit| edit.delete.start());
+    Ok(())
+}
 
-    // Sanity check.
-    if edits
-        .windows(2)
-        .any(|w| w[0].delete.end() > w[1].delete.start())
-    {
-        return Err("Change would overlap".into());
+/// Check that renaming a definition does not change what any reference resolves to.
+/// References of it must not be captured by an inner `new_name`, and references of an outer
+/// `new_name` must not be captured by it.
+fn check_conflict(
+    db: &dyn DefDatabase,
+    file_id: FileId,
+    name: NameId,
+    new_name: &str,
+) -> RenameResult<()> {
+    let module = db.module(file_id);
+    let scopes = db.scopes(file_id);
+    let old_name = &module[name].text;
+    if !module[name].kind.is_definition() || old_name == new_name {
+        return Ok(());
     }
 
-    Ok(WorkspaceEdit {
-        content_edits: [(file_id, edits)].into_iter().collect(),
-    })
+    for (expr, kind) in module.exprs() {
+        let Expr::Reference(text) = kind else { continue };
+        if text != old_name && text != new_name {
+            continue;
+        }
+        let Some(scope) = scopes.scope_for_expr(expr) else { continue };
+        for defs in scopes
+            .ancestors(scope)
+            .filter_map(|data| data.as_definitions())
+        {
+            if defs.get(old_name) == Some(&name) {
+                if text == new_name || defs.contains_key(new_name) {
+                    return Err(RenameError::Conflict(new_name.into()));
+                }
+                break;
+            }
+            if defs.contains_key(text) {
+                break;
+            }
+            if text == old_name && defs.contains_key(new_name) {
+                return Err(RenameError::Conflict(new_name.into()));
+            }
+        }
+    }
+
+    Ok(())
 }
 
 fn find_name(
@@ -216,10 +318,111 @@ fn find_name(
     None
 }
 
+/// Update relative path literals for moving files together, given as pairs of files and their
+/// new paths: those resolving to moved files in the same source root, including directories
+/// resolving to their moved `default.nix`, and those inside moved files whose directories change.
+/// Paths which cannot be written as path literals are left unchanged.
+pub(crate) fn rename_files(db: &dyn DefDatabase, renames: &[(FileId, VfsPath)]) -> WorkspaceEdit {
+    let mut content_edits = HashMap::new();
+    let Some(&(first, _)) = renames.first() else { return WorkspaceEdit { content_edits } };
+    let root = db.source_root(db.file_source_root(first));
+    let new_paths = renames
+        .iter()
+        .map(|(file, new_path)| (root.path_for_file(*file).clone(), new_path))
+        .collect::<HashMap<_, _>>();
+    for (importer, importer_path) in root.iter() {
+        let mut old_dir = importer_path.clone();
+        old_dir.pop();
+        let mut new_dir = new_paths
+            .get(importer_path)
+            .copied()
+            .unwrap_or(importer_path)
+            .clone();
+        new_dir.pop();
+
+        let module = db.module(importer);
+        let source_map = db.source_map(importer);
+        let src = db.file_content(importer);
+        let mut edits = module
+            .exprs()
+            .filter_map(|(expr, kind)| {
+                let &Expr::Literal(Literal::Path(path)) = kind else { return None };
+                if !matches!(path.data(db).anchor(), PathAnchor::Relative(_)) {
+                    return None;
+                }
+                let target = path.resolve(db)?;
+                let new_target = match moved_path(&new_paths, &target) {
+                    Some(new_target) => new_target,
+                    None if new_dir != old_dir => target,
+                    None => return None,
+                };
+                let text = relative_path_literal(&new_dir, &new_target)?;
+                let range = source_map.node_for_expr(expr)?.text_range();
+                (src[range] != *text).then(|| TextEdit {
+                    delete: range,
+                    insert: text.into(),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !edits.is_empty() {
+            edits.sort_by_key(|edit| edit.delete.start());
+            content_edits.insert(importer, edits);
+        }
+    }
+    WorkspaceEdit { content_edits }
+}
+
+/// The new path of `target` if it is moved, or if it is a directory whose `default.nix` is moved.
+/// A directory is still referred to if the moved file is named `default.nix`.
+fn moved_path(new_paths: &HashMap<VfsPath, &VfsPath>, target: &VfsPath) -> Option<VfsPath> {
+    if let Some(&new_path) = new_paths.get(target) {
+        return Some(new_path.clone());
+    }
+    let new_path = *new_paths.get(&target.join_segment(DEFAULT_IMPORT_FILE))?;
+    let mut new_dir = new_path.clone();
+    new_dir.pop()?;
+    if new_dir.join_segment(DEFAULT_IMPORT_FILE) == *new_path {
+        Some(new_dir)
+    } else {
+        Some(new_path.clone())
+    }
+}
+
+/// The path literal referring to `target` relative to the directory `dir`,
+/// like `./foo.nix` or `../bar/foo.nix`.
+fn relative_path_literal(dir: &VfsPath, target: &VfsPath) -> Option<String> {
+    fn segments(path: &VfsPath) -> Vec<&str> {
+        path.as_str()
+            .split('/')
+            .filter(|seg| !seg.is_empty())
+            .collect()
+    }
+    let (dir, target) = (segments(dir), segments(target));
+    let common = dir.iter().zip(&target).take_while(|(a, b)| a == b).count();
+    let mut ret = if common == dir.len() {
+        ".".to_owned()
+    } else {
+        vec![".."; dir.len() - common].join("/")
+    };
+    for seg in &target[common..] {
+        let is_path_char = |b: u8| b.is_ascii_alphanumeric() || b"._-+".contains(&b);
+        if !seg.bytes().all(is_path_char) {
+            return None;
+        }
+        ret += "/";
+        ret += seg;
+    }
+    if common == target.len() {
+        ret += "/.";
+    }
+    Some(ret)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::base::SourceDatabase;
     use crate::tests::TestDB;
+    use crate::VfsPath;
     use expect_test::{expect, Expect};
 
     fn check_prepare(fixture: &str, expect: Expect) {
@@ -236,7 +439,7 @@ mod tests {
                     format!("{}\n{}\n", src, text)
                 }
             }
-            Err(err) => err,
+            Err(err) => err.to_string(),
         };
         expect.assert_eq(&ret);
     }
@@ -246,14 +449,11 @@ mod tests {
         let mut src = db.file_content(f[0].file_id).to_string();
         let ret = match super::rename(&db, f[0], new_name) {
             Ok(ws_edit) => {
-                let edits = ws_edit.content_edits.into_iter().collect::<Vec<_>>();
-                assert_eq!(edits[0].0, f[0].file_id);
-                for edit in edits[0].1.iter().rev() {
-                    edit.apply(&mut src);
-                }
+                assert_eq!(ws_edit.content_edits.len(), 1);
+                ws_edit.apply(f[0].file_id, &mut src);
                 src
             }
-            Err(err) => err,
+            Err(err) => err.to_string(),
         };
         expect.assert_eq(&ret);
     }
@@ -491,12 +691,12 @@ mod tests {
         check(
             r#"let $0a = 1; in { inherit a; }"#,
             "b",
-            expect!["let b = 1; in { a = b; }"],
+            expect!["let b = 1; in { inherit b; }"],
         );
         check(
             r#"let $0a = 1; in { inherit a x; }"#,
             "b",
-            expect!["let b = 1; in { inherit  x;a = b; }"],
+            expect!["let b = 1; in { inherit b x; }"],
         );
         check(
             r#"let $0a = 1; in { inherit a; }"#,
@@ -506,7 +706,139 @@ mod tests {
         check(
             r#"let $0"1" = 1; in { inherit "1"; }"#,
             "b",
-            expect![[r#"let b = 1; in { "1" = b; }"#]],
+            expect!["let b = 1; in { inherit b; }"],
         );
     }
+
+    #[test]
+    fn rename_inherit_reexport() {
+        check(
+            "let $0foo = 1; x = { inherit foo; }; in x.foo",
+            "bar",
+            expect!["let bar = 1; x = { inherit bar; }; in x.bar"],
+        );
+        check(
+            "let foo = 1; x = rec { inherit foo; y = foo; }; in [ $0foo x.foo x.y ]",
+            "bar",
+            expect!["let bar = 1; x = rec { inherit bar; y = bar; }; in [ bar x.bar x.y ]"],
+        );
+        // Renaming the attribute itself keeps the binding.
+        check(
+            "let foo = 1; x = { inherit $0foo; }; in x.foo",
+            "bar",
+            expect!["let foo = 1; x = { bar = foo; }; in x.bar"],
+        );
+    }
+
+    #[test]
+    fn rename_conflict() {
+        // Sibling binding.
+        check(
+            "let $0a = 1; b = 2; in a",
+            "b",
+            expect!["`b` would conflict with an existing binding"],
+        );
+        // References would be captured by an inner binding.
+        check(
+            "let $0a = 1; in b: a",
+            "b",
+            expect!["`b` would conflict with an existing binding"],
+        );
+        // References to an outer binding would be captured.
+        check(
+            "let b = 1; in let $0a = 2; in a + b",
+            "b",
+            expect!["`b` would conflict with an existing binding"],
+        );
+        // Shadowed outer bindings without references are fine.
+        check(
+            "let b = 1; in let $0a = 2; in a",
+            "b",
+            expect!["let b = 1; in let b = 2; in b"],
+        );
+        check("let $0a = 1; in a", "a", expect!["let a = 1; in a"]);
+    }
+
+    #[test]
+    fn rename_file() {
+        let (db, f) = TestDB::from_fixture(
+            "
+#- /default.nix
+{ foo = import ./lib/foo.nix; bar = ./lib/bar.nix; root = ./.; }
+
+#- /lib/foo.nix
+{ top = import ../default.nix; bar = ./bar.nix; self = ./foo.nix; }
+
+#- /lib/bar.nix
+import ./foo.nix
+",
+        )
+        .unwrap();
+        let new_path = VfsPath::new("/pkgs/foo.nix").unwrap();
+        let ws_edit = super::rename_files(&db, &[(f["/lib/foo.nix"], new_path)]);
+        let got = ["/default.nix", "/lib/foo.nix", "/lib/bar.nix"]
+            .into_iter()
+            .map(|path| {
+                let mut src = db.file_content(f[path]).to_string();
+                ws_edit.apply(f[path], &mut src);
+                src + "\n"
+            })
+            .collect::<String>();
+        expect![[r#"
+            { foo = import ./pkgs/foo.nix; bar = ./lib/bar.nix; root = ./.; }
+            { top = import ../default.nix; bar = ../lib/bar.nix; self = ./foo.nix; }
+            import ../pkgs/foo.nix
+        "#]]
+        .assert_eq(&got);
+    }
+
+    #[test]
+    fn rename_files_together() {
+        let (db, f) = TestDB::from_fixture(
+            "
+#- /default.nix
+{ foo = import ./lib/foo.nix; baz = import ./lib/baz; }
+
+#- /lib/foo.nix
+import ./bar.nix
+
+#- /lib/bar.nix
+1
+
+#- /lib/baz/default.nix
+2
+",
+        )
+        .unwrap();
+        let renames = [
+            ("/lib/foo.nix", "/pkgs/foo.nix"),
+            ("/lib/bar.nix", "/pkgs/bar/bar.nix"),
+            ("/lib/baz/default.nix", "/pkgs/baz/default.nix"),
+        ]
+        .map(|(old, new)| (f[old], VfsPath::new(new).unwrap()));
+        let ws_edit = super::rename_files(&db, &renames);
+        let got = ["/default.nix", "/lib/foo.nix"]
+            .into_iter()
+            .map(|path| {
+                let mut src = db.file_content(f[path]).to_string();
+                ws_edit.apply(f[path], &mut src);
+                src + "\n"
+            })
+            .collect::<String>();
+        expect![[r#"
+            { foo = import ./pkgs/foo.nix; baz = import ./pkgs/baz; }
+            import ./bar/bar.nix
+        "#]]
+        .assert_eq(&got);
+
+        // A directory is no longer referred to if `default.nix` is renamed.
+        let renames = [(
+            f["/lib/baz/default.nix"],
+            VfsPath::new("/lib/baz.nix").unwrap(),
+        )];
+        let ws_edit = super::rename_files(&db, &renames);
+        let mut src = db.file_content(f["/default.nix"]).to_string();
+        ws_edit.apply(f["/default.nix"], &mut src);
+        expect!["{ foo = import ./lib/foo.nix; baz = import ./lib/baz.nix; }"].assert_eq(&src);
+    }
 }
@@ -1,7 +1,9 @@
 use crate::def::{BindingValue, Expr, ExprId};
-use crate::{DefDatabase, FileId, Module, ModuleSourceMap, NameKind};
+use crate::ty::{InferenceResult, Ty};
+use crate::{FileId, FilePos, Module, ModuleSourceMap, NameKind, TyDatabase};
 use smol_str::SmolStr;
 use syntax::ast::{self, AstNode};
+use syntax::semantic::escape_literal_attr;
 use syntax::{SyntaxNode, TextRange};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -10,16 +12,20 @@ pub struct SymbolTree {
     pub full_range: TextRange,
     pub focus_range: TextRange,
     pub kind: NameKind,
+    /// The inferred type of the bound value.
+    pub ty: Ty,
     pub children: Vec<SymbolTree>,
 }
 
-pub(crate) fn symbol_hierarchy(db: &dyn DefDatabase, file: FileId) -> Vec<SymbolTree> {
+pub(crate) fn symbol_hierarchy(db: &dyn TyDatabase, file: FileId) -> Vec<SymbolTree> {
     let parse = db.parse(file);
     let module = db.module(file);
     let source_map = db.source_map(file);
+    let infer = db.infer(file);
     let mut collector = Collector {
         module: &module,
         source_map: &source_map,
+        infer: &infer,
         root_node: parse.syntax_node(),
         symbols: Vec::new(),
     };
@@ -29,10 +35,30 @@ pub(crate) fn symbol_hierarchy(db: &dyn DefDatabase, file: FileId) -> Vec<Symbol
     symbols
 }
 
+/// The dotted attribute path from the file root to the innermost attribute binding containing
+/// the position, like `outputs.packages.x86_64-linux.default`.
+/// `let` bindings are not parts of attribute paths, so the path stops before them.
+pub(crate) fn attr_path_at(
+    db: &dyn TyDatabase,
+    FilePos { file_id, pos }: FilePos,
+) -> Option<String> {
+    let mut syms = symbol_hierarchy(db, file_id);
+    let mut path = Vec::new();
+    while let Some(sym) = syms
+        .into_iter()
+        .find(|sym| sym.kind != NameKind::LetIn && sym.full_range.contains(pos))
+    {
+        path.push(escape_literal_attr(&sym.name).into_owned());
+        syms = sym.children;
+    }
+    (!path.is_empty()).then(|| path.join("."))
+}
+
 #[derive(Debug)]
 struct Collector<'a> {
     module: &'a Module,
     source_map: &'a ModuleSourceMap,
+    infer: &'a InferenceResult,
     root_node: SyntaxNode,
     symbols: Vec<SymbolTree>,
 }
@@ -74,6 +100,7 @@ impl Collector<'_> {
                     full_range,
                     focus_range,
                     kind,
+                    ty: self.infer.ty_for_name(name),
                     children,
                 });
                 Some(())
@@ -152,4 +179,29 @@ mod tests {
             "#]],
         );
     }
+
+    #[test]
+    fn attr_path() {
+        let src = r#"
+{
+  outputs = { self, nixpkgs }: let pkgs = $0nixpkgs; in {
+    packages.x86_64-linux.default = $1pkgs.hello;
+    "my.lib" = { f = $2x: x; };
+    $3
+  };
+}
+        "#;
+        let (db, f) = TestDB::from_fixture(src).unwrap();
+        let got = (0..4).map(|i| attr_path_at(&db, f[i])).collect::<Vec<_>>();
+        let expect = [
+            "outputs",
+            "outputs.packages.x86_64-linux.default",
+            r#"outputs."my.lib".f"#,
+            "outputs",
+        ];
+        assert_eq!(got, expect.map(|s| Some(s.to_owned())));
+
+        let (db, f) = TestDB::from_fixture("let a = { b = $01; }; in a").unwrap();
+        assert_eq!(attr_path_at(&db, f[0]), None);
+    }
 }
@@ -1,5 +1,6 @@
 //! This is actually so-called "semantic highlighting".
 //! Ref: <https://github.com/rust-lang/rust-analyzer/blob/a670ff888437f4b6a3d24cc2996e9f969a87cbae/crates/ide/src/syntax_highlighting/tags.rs>
+use super::injection;
 use crate::def::{AstPtr, Expr, Literal, NameKind, ResolveResult};
 use crate::{DefDatabase, FileId};
 use builtin::{BuiltinKind, ALL_BUILTINS};
@@ -65,17 +66,25 @@ pub enum HlPunct {
     Colon,
     At,
     Ellipsis,
+    /// `${` and `}` of interpolations.
+    Interpolation,
 }
 
 pub(crate) fn highlight(
     db: &dyn DefDatabase,
     file: FileId,
     range: Option<TextRange>,
+    with_injections: bool,
 ) -> Vec<HlRange> {
     let root_node = db.parse(file).syntax_node();
     let source_map = db.source_map(file);
     let nameres = db.name_resolution(file);
     let module = db.module(file);
+    let injections = if with_injections {
+        injection::injections(db, file)
+    } else {
+        Vec::new()
+    };
 
     let ident_tag = |tok: &SyntaxToken| -> Option<HlTag> {
         match tok.parent() {
@@ -88,6 +97,7 @@ pub(crate) fn highlight(
                     None => HlTag::UnresolvedRef,
                     Some(ResolveResult::Definition(def)) => HlTag::NameRef(module[*def].kind),
                     Some(ResolveResult::WithExprs(_)) => HlTag::AttrField(HlAttrField::With),
+                    Some(ResolveResult::ExtraGlobal(_)) => HlTag::Builtin(BuiltinKind::Const),
                     // Covered by `check_builtin`.
                     Some(ResolveResult::Builtin(_)) => unreachable!(),
                 })
@@ -146,7 +156,25 @@ pub(crate) fn highlight(
             T!["''"] | T!['"'] | SyntaxKind::URI => HlTag::StringLiteral,
             SyntaxKind::STRING_ESCAPE => HlTag::StringEscape,
             // Don't color the string content. They are subjects to injection of other languages.
+            // Known injections are still reported if enabled, so clients can tell their languages.
+            SyntaxKind::STRING_FRAGMENT
+                if injections
+                    .iter()
+                    .any(|inj| inj.range.contains_range(tok.text_range())) =>
+            {
+                HlTag::StringLiteral
+            }
             SyntaxKind::STRING_FRAGMENT => return None,
+            // `"a${b}c"`
+            //    ^^ ^
+            T!["${"] => HlTag::Punct(HlPunct::Interpolation),
+            T!['}']
+                if tok
+                    .parent()
+                    .map_or(false, |p| p.kind() == SyntaxKind::DYNAMIC) =>
+            {
+                HlTag::Punct(HlPunct::Interpolation)
+            }
 
             T![&&] | T![||] | T![->] | T![!] => HlTag::Operator(HlOperator::Logical),
             T![==] | T![!=] | T![<] | T![>] | T![<=] | T![>=] => {
@@ -154,7 +182,7 @@ pub(crate) fn highlight(
             }
             T![+] | T![-] | T![*] | T![/] => HlTag::Operator(HlOperator::Arithmetic),
             T![++] | T!["//"] => HlTag::Operator(HlOperator::Aggregation),
-            T!['{'] | T!['}'] | T!["${"] => HlTag::Punct(HlPunct::Brace),
+            T!['{'] | T!['}'] => HlTag::Punct(HlPunct::Brace),
             T!['['] | T![']'] => HlTag::Punct(HlPunct::Bracket),
             T!['('] | T![')'] => HlTag::Punct(HlPunct::Paren),
             T![.] => HlTag::Punct(HlPunct::Dot),
@@ -204,19 +232,23 @@ mod tests {
 
     #[track_caller]
     fn check(fixture: &str, expect: Expect) {
+        check_with(fixture, false, expect);
+    }
+
+    #[track_caller]
+    fn check_with(fixture: &str, with_injections: bool, expect: Expect) {
         let (db, f) = TestDB::from_fixture(fixture).unwrap();
         let FilePos { file_id, pos } = f[0];
         assert_eq!(db.parse(file_id).errors(), &[]);
-        let hls = super::highlight(&db, file_id, None);
+        let hls = super::highlight(&db, file_id, None, with_injections);
         // No overlapping.
         for w in hls.windows(2) {
             assert!(w[0].range.end() <= w[1].range.start());
         }
-        let hlrange = hls
+        let got = hls
             .iter()
             .find(|hlrange| hlrange.range.contains(pos))
-            .expect("No highlight found");
-        let got = format!("{:?}", hlrange.tag);
+            .map_or("None".into(), |hlrange| format!("{:?}", hlrange.tag));
         expect.assert_eq(&got);
     }
 
@@ -259,6 +291,28 @@ mod tests {
         check(r#""st$0\nring""#, expect!["StringEscape"]);
     }
 
+    #[test]
+    fn injection() {
+        let src = r#"writeShellScript "a" "e$0cho""#;
+        check(src, expect!["None"]);
+        check_with(src, true, expect!["StringLiteral"]);
+    }
+
+    #[test]
+    fn string_interpolation() {
+        check(r#""a$0${b}\n""#, expect!["Punct(Interpolation)"]);
+        check(r#""a${$0b}\n""#, expect!["UnresolvedRef"]);
+        check(r#""a${b$0}\n""#, expect!["Punct(Interpolation)"]);
+        check(r#"$0"a${b}\n""#, expect!["StringLiteral"]);
+        check(r#""a${b}$0\n""#, expect!["StringEscape"]);
+        check(r#""a${b}\$0${c}""#, expect!["StringEscape"]);
+        check("''a$0''${b}''", expect!["StringEscape"]);
+        check("''a''${b} $0${c}''", expect!["Punct(Interpolation)"]);
+        check("''a''${b} ${c}\n$0''", expect!["StringLiteral"]);
+        check("{ $0${a} = 1; }", expect!["Punct(Interpolation)"]);
+        check("{ ${a} = 1; $0}", expect!["Punct(Brace)"]);
+    }
+
     #[test]
     fn builtins_global() {
         check("$0true", expect!["Builtin(Const)"]);
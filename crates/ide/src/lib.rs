@@ -9,16 +9,19 @@ pub(crate) mod ty;
 mod tests;
 
 pub use self::ide::{
-    Analysis, AnalysisHost, Assist, AssistKind, Cancelled, CompletionItem, CompletionItemKind,
-    GotoDefinitionResult, HlAttrField, HlKeyword, HlOperator, HlPunct, HlRange, HlRelated, HlTag,
-    HoverResult, Link, LinkTarget, NavigationTarget, RenameResult, SymbolTree,
+    Analysis, AnalysisHost, Assist, AssistKind, CallHierarchyCall, CallHierarchyItem,
+    CancellationToken, Cancelled, CompletionCategory, CompletionItem, CompletionItemKind,
+    DocCommentStyle, FetcherPrefetch, FoldingRange, FoldingRangeKind, GotoDefinitionResult,
+    HlAttrField, HlKeyword, HlOperator, HlPunct, HlRange, HlRelated, HlTag, HoverKind, HoverResult,
+    Injection, InjectionLanguage, InlineValue, Link, LinkTarget, NavigationTarget, RenameError,
+    RenameResult, SymbolTree,
 };
 pub use base::{
-    Change, FileId, FilePos, FileRange, FileSet, FlakeGraph, FlakeInfo, InFile, SourceDatabase,
-    SourceRoot, SourceRootId, VfsPath,
+    Change, FileId, FilePos, FileRange, FileSet, FlakeGraph, FlakeInfo, InFile, SearchPath,
+    SourceDatabase, SourceRoot, SourceRootId, VfsPath,
 };
 pub use builtin::BuiltinKind;
-pub use def::{DefDatabase, Module, ModuleKind, ModuleSourceMap, NameKind};
-pub use diagnostic::{Diagnostic, DiagnosticKind, Severity};
+pub use def::{DefDatabase, Module, ModuleKind, ModuleSourceMap, NameId, NameKind};
+pub use diagnostic::{elevate_diagnostics, Diagnostic, DiagnosticKind, Severity};
 pub use text_edit::{TextEdit, WorkspaceEdit};
-pub use ty::{InferenceResult, TyDatabase};
+pub use ty::{InferenceResult, Ty, TyDatabase};
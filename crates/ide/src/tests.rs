@@ -1,4 +1,4 @@
-use crate::base::SourceDatabaseStorage;
+use crate::base::{SourceDatabaseStorage, Upcast};
 use crate::def::DefDatabaseStorage;
 use crate::ty::TyDatabaseStorage;
 use crate::{
@@ -23,6 +23,12 @@ pub struct TestDB {
 
 impl salsa::Database for TestDB {}
 
+impl Upcast<dyn DefDatabase> for TestDB {
+    fn upcast(&self) -> &(dyn DefDatabase + 'static) {
+        self
+    }
+}
+
 impl TestDB {
     pub fn single_file(fixture: &str) -> Result<(Self, FileId)> {
         let (db, f) = Self::from_fixture(fixture)?;
@@ -48,6 +54,8 @@ impl TestDB {
             nodes: HashMap::from_iter(f.flake_info.clone().map(|info| (SourceRootId(0), info))),
         };
         change.set_flake_graph(flake_graph);
+        change.set_search_paths(Vec::new());
+        change.set_extra_globals(HashMap::new());
         change.apply(&mut db);
         Ok((db, f))
     }
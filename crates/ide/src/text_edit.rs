@@ -9,6 +9,16 @@ pub struct WorkspaceEdit {
     // Filesystem edit is not implemented yet.
 }
 
+impl WorkspaceEdit {
+    /// Apply edits of a file to its content `src`.
+    /// Edits of each file are sorted and non-overlapping.
+    pub fn apply(&self, file: FileId, src: &mut String) {
+        for edit in self.content_edits.get(&file).into_iter().flatten().rev() {
+            edit.apply(src);
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TextEdit {
     pub delete: TextRange,
@@ -1,6 +1,6 @@
 use std::fmt;
 
-use super::Ty;
+use super::{known, Ty};
 
 const MAX_FIELD_CNT: usize = 8;
 
@@ -9,6 +9,7 @@ pub struct TyDisplay<'a> {
     ty: &'a Ty,
     depth: usize,
     in_param: bool,
+    named_schemas: bool,
 }
 
 impl<'a> TyDisplay<'a> {
@@ -17,8 +18,16 @@ impl<'a> TyDisplay<'a> {
             ty,
             depth,
             in_param: false,
+            named_schemas: false,
         }
     }
+
+    /// Show attrsets matching known schemas by their names, like `derivation`,
+    /// instead of expanding their fields.
+    pub fn named_schemas(mut self, enable: bool) -> Self {
+        self.named_schemas = enable;
+        self
+    }
 }
 
 impl fmt::Display for TyDisplay<'_> {
@@ -30,6 +39,7 @@ impl fmt::Display for TyDisplay<'_> {
             Ty::Float => "float".fmt(f),
             Ty::String => "string".fmt(f),
             Ty::Path => "path".fmt(f),
+            &Ty::Param(i) => char::from(b'a' + i).fmt(f),
             Ty::List(ty) => {
                 if self.depth == 0 {
                     "[…]".fmt(f)
@@ -38,6 +48,7 @@ impl fmt::Display for TyDisplay<'_> {
                         ty,
                         depth: self.depth - 1,
                         in_param: false,
+                        named_schemas: self.named_schemas,
                     };
                     write!(f, "[{}]", elem)
                 }
@@ -54,12 +65,14 @@ impl fmt::Display for TyDisplay<'_> {
                         // Show full lambda type.
                         depth: self.depth,
                         in_param: true,
+                        named_schemas: self.named_schemas,
                     };
                     let ret = Self {
                         ty: ret,
                         // Show full lambda type.
                         depth: self.depth,
                         in_param: false,
+                        named_schemas: self.named_schemas,
                     };
                     write!(f, "{} → {}", param, ret)?;
                 }
@@ -68,8 +81,36 @@ impl fmt::Display for TyDisplay<'_> {
                 }
                 Ok(())
             }
+            Ty::Union(tys) => {
+                if self.in_param {
+                    "(".fmt(f)?;
+                }
+                for (i, ty) in tys.iter().enumerate() {
+                    if i != 0 {
+                        " | ".fmt(f)?;
+                    }
+                    let member = Self {
+                        ty,
+                        depth: self.depth,
+                        // Parenthesize lambdas.
+                        in_param: true,
+                        named_schemas: self.named_schemas,
+                    };
+                    member.fmt(f)?;
+                }
+                if self.in_param {
+                    ")".fmt(f)?;
+                }
+                Ok(())
+            }
             Ty::Attrset(set) => {
-                if self.depth == 0 {
+                if let Some(name) = self
+                    .named_schemas
+                    .then(|| known::schema_name(set))
+                    .flatten()
+                {
+                    name.fmt(f)
+                } else if self.depth == 0 {
                     "{ … }".fmt(f)
                 } else {
                     "{".fmt(f)?;
@@ -85,6 +126,7 @@ impl fmt::Display for TyDisplay<'_> {
                             ty,
                             depth: self.depth - 1,
                             in_param: false,
+                            named_schemas: self.named_schemas,
                         };
                         write!(f, " {}: {}", name, value)?;
                     }
@@ -1,12 +1,14 @@
 use super::union_find::UnionFind;
 use super::{known, AttrSource, TyDatabase};
 use crate::def::{
-    BindingValue, Bindings, Expr, ExprId, Literal, NameId, NameResolution, ResolveResult,
+    file_for_import, BindingValue, Bindings, Expr, ExprId, Literal, NameId, NameResolution,
+    ResolveResult,
 };
 use crate::{FileId, Module};
 use la_arena::ArenaMap;
 use smol_str::SmolStr;
 use std::collections::btree_map::{BTreeMap, Entry};
+use std::collections::HashMap;
 use std::mem;
 use std::sync::Arc;
 use syntax::ast::{BinaryOpKind, UnaryOpKind};
@@ -38,6 +40,8 @@ enum Ty {
     List(TyVar),
     Lambda(TyVar, TyVar),
     Attrset(Attrset),
+    /// Element types of a heterogeneous list literal, which are never refined by unification.
+    Union(Vec<TyVar>),
 
     External(super::Ty),
 }
@@ -68,21 +72,37 @@ impl InferenceResult {
 }
 
 pub(crate) fn infer_query(db: &dyn TyDatabase, file: FileId) -> Arc<InferenceResult> {
+    let _span = tracing::info_span!("infer", file_id = ?file).entered();
     let expect_ty = db.module_expected_ty(file);
-    infer_with(db, file, expect_ty)
+    infer_with(db, file, expect_ty, true)
+}
+
+pub(crate) fn import_ty_query(db: &dyn TyDatabase, file: FileId) -> super::Ty {
+    let expect_ty = db.module_expected_ty(file);
+    let infer = infer_with(db, file, expect_ty, false);
+    infer.ty_for_expr(db.module(file).entry_expr())
 }
 
 pub(crate) fn infer_with(
     db: &dyn TyDatabase,
     file: FileId,
     expect_ty: Option<super::Ty>,
+    follow_imports: bool,
 ) -> Arc<InferenceResult> {
     let module = db.module(file);
     let nameres = db.name_resolution(file);
+    let extra_globals = db.extra_globals();
+    let imports = if follow_imports {
+        import_tys(db, file, &module, &nameres)
+    } else {
+        HashMap::new()
+    };
     let table = UnionFind::new(module.names().len() + module.exprs().len(), |_| Ty::Unknown);
     let mut ctx = InferCtx {
         module: &module,
         nameres: &nameres,
+        extra_globals: &extra_globals,
+        imports: &imports,
         table,
     };
     let ty = ctx.infer_expr(module.entry_expr());
@@ -92,9 +112,33 @@ pub(crate) fn infer_with(
     Arc::new(ctx.finish())
 }
 
+/// Types of `import ./path.nix` expressions whose targets are known files.
+fn import_tys(
+    db: &dyn TyDatabase,
+    file: FileId,
+    module: &Module,
+    nameres: &NameResolution,
+) -> HashMap<ExprId, super::Ty> {
+    module
+        .exprs()
+        .filter_map(|(expr, kind)| {
+            let &Expr::Apply(func, arg) = kind else { return None };
+            if nameres.check_builtin(func, module)? != "import" {
+                return None;
+            }
+            let &Expr::Literal(Literal::Path(path)) = &module[arg] else { return None };
+            let def_db = db.upcast();
+            let target = file_for_import(def_db, file, path.resolve(def_db)?)?;
+            Some((expr, db.import_ty(target)))
+        })
+        .collect()
+}
+
 struct InferCtx<'db> {
     module: &'db Module,
     nameres: &'db NameResolution,
+    extra_globals: &'db HashMap<SmolStr, super::Ty>,
+    imports: &'db HashMap<ExprId, super::Ty>,
 
     /// The arena for both unification and interning.
     /// First `module.names().len() + module.exprs().len()` elements are types of each names and
@@ -117,17 +161,54 @@ impl<'db> InferCtx<'db> {
 
     fn import_external(&mut self, ty: super::Ty) -> TyVar {
         let ty = match ty {
-            super::Ty::Unknown => Ty::Unknown,
+            super::Ty::List(_) | super::Ty::Lambda(..) if ty.has_params() => {
+                return self.instantiate(&ty, &mut Vec::new());
+            }
+            super::Ty::Unknown | super::Ty::Param(_) => Ty::Unknown,
             super::Ty::Bool => Ty::Bool,
             super::Ty::Int => Ty::Int,
             super::Ty::Float => Ty::Float,
             super::Ty::String => Ty::String,
             super::Ty::Path => Ty::Path,
-            super::Ty::List(_) | super::Ty::Lambda(..) | super::Ty::Attrset(_) => Ty::External(ty),
+            super::Ty::List(_)
+            | super::Ty::Lambda(..)
+            | super::Ty::Attrset(_)
+            | super::Ty::Union(_) => Ty::External(ty),
         };
         TyVar(self.table.push(ty))
     }
 
+    /// Import a polymorphic type with each type parameter replaced by the same fresh type
+    /// variable, so that `map (x: x + 1)` is inferred as `[int] → [int]`.
+    fn instantiate(&mut self, ty: &super::Ty, params: &mut Vec<Option<TyVar>>) -> TyVar {
+        match ty {
+            &super::Ty::Param(i) => {
+                let i = usize::from(i);
+                if params.len() <= i {
+                    params.resize(i + 1, None);
+                }
+                *params[i].get_or_insert_with(|| self.new_ty_var())
+            }
+            super::Ty::List(elem) => {
+                let elem = self.instantiate(elem, params);
+                Ty::List(elem).intern(self)
+            }
+            super::Ty::Lambda(param, ret) => {
+                let param = self.instantiate(param, params);
+                let ret = self.instantiate(ret, params);
+                Ty::Lambda(param, ret).intern(self)
+            }
+            super::Ty::Attrset(set) if ty.has_params() => {
+                let set = set
+                    .iter()
+                    .map(|(name, ty, src)| (name.clone(), (self.instantiate(ty, params), src)))
+                    .collect();
+                Ty::Attrset(Attrset(set)).intern(self)
+            }
+            _ => self.import_external(ty.clone()),
+        }
+    }
+
     fn infer_expr(&mut self, e: ExprId) -> TyVar {
         let ty = self.infer_expr_inner(e);
         let placeholder_ty = self.ty_for_expr(e);
@@ -152,6 +233,10 @@ impl<'db> InferCtx<'db> {
                             Some(ty) => self.import_external(ty.clone()),
                         }
                     }
+                    ResolveResult::ExtraGlobal(name) => match self.extra_globals.get(name) {
+                        None => self.new_ty_var(),
+                        Some(ty) => self.import_external(ty.clone()),
+                    },
                 },
             },
             Expr::Literal(lit) => match lit {
@@ -266,6 +351,11 @@ impl<'db> InferCtx<'db> {
                 }
             }
             &Expr::Apply(lam, arg) => {
+                if let Some(ty) = self.imports.get(&e) {
+                    self.infer_expr(lam);
+                    self.infer_expr(arg);
+                    return self.import_external(ty.clone());
+                }
                 let param_ty = self.new_ty_var();
                 let ret_ty = self.new_ty_var();
                 let lam_ty = self.infer_expr(lam);
@@ -321,11 +411,11 @@ impl<'db> InferCtx<'db> {
                 Ty::String.intern(self)
             }
             Expr::List(elems) => {
-                let expect_elem_ty = self.new_ty_var();
-                let ret_ty = Ty::List(expect_elem_ty).intern(self);
+                let list_elem_ty = self.new_ty_var();
+                let ret_ty = Ty::List(list_elem_ty).intern(self);
                 for &elem in elems.iter() {
                     let elem_ty = self.infer_expr(elem);
-                    self.unify_var(elem_ty, expect_elem_ty);
+                    self.join_list_elem(list_elem_ty, elem_ty);
                 }
                 ret_ty
             }
@@ -401,6 +491,61 @@ impl<'db> InferCtx<'db> {
         self.new_ty_var()
     }
 
+    /// Join the type of an element into the element type of a list literal.
+    /// Primitive elements are not unified with each other, so `[ 1 2.5 ]` is `[float]` without
+    /// changing the type of `1`. Elements of incompatible types make the element type a union
+    /// of them, like `[int | string]` for `[ 1 "a" ]`.
+    fn join_list_elem(&mut self, list_elem: TyVar, elem: TyVar) {
+        let lhs = self.table.get_mut(list_elem.0).clone();
+        let rhs = self.table.get_mut(elem.0).clone();
+        if let Ty::Union(mut members) = lhs {
+            // They add no information to the union.
+            if matches!(rhs, Ty::Unknown | Ty::Union(_)) {
+                return;
+            }
+            let joinable = members
+                .iter()
+                .copied()
+                .find(|member| Self::is_joinable(self.table.get_mut(member.0), &rhs));
+            if let Some(member) = joinable {
+                self.join_list_elem(member, elem);
+            } else {
+                let member = self.new_ty_var();
+                self.join_list_elem(member, elem);
+                members.push(member);
+                *self.table.get_mut(list_elem.0) = Ty::Union(members);
+            }
+            return;
+        }
+        let joined = match (&lhs, &rhs) {
+            (Ty::Int, Ty::Float) | (Ty::Float, Ty::Int) => Ty::Float,
+            (_, Ty::Bool | Ty::Int | Ty::Float | Ty::String | Ty::Path)
+                if lhs == Ty::Unknown || lhs == rhs =>
+            {
+                rhs
+            }
+            _ if Self::is_joinable(&lhs, &rhs) => return self.unify_var(list_elem, elem),
+            _ => {
+                let lhs = lhs.intern(self);
+                let rhs = self.new_ty_var();
+                self.join_list_elem(rhs, elem);
+                Ty::Union(vec![lhs, rhs])
+            }
+        };
+        *self.table.get_mut(list_elem.0) = joined;
+    }
+
+    /// Whether elements of these types share a member of the element type of a list literal.
+    fn is_joinable(lhs: &Ty, rhs: &Ty) -> bool {
+        match (lhs, rhs) {
+            (Ty::Unknown | Ty::Union(_), _) | (_, Ty::Unknown | Ty::Union(_)) => true,
+            (Ty::Int | Ty::Float, Ty::Int | Ty::Float) => true,
+            (Ty::External(_), Ty::List(_) | Ty::Lambda(..) | Ty::Attrset(_) | Ty::External(_))
+            | (Ty::List(_) | Ty::Lambda(..) | Ty::Attrset(_), Ty::External(_)) => true,
+            _ => mem::discriminant(lhs) == mem::discriminant(rhs),
+        }
+    }
+
     fn unify_var_ty(&mut self, var: TyVar, rhs: Ty) {
         let lhs = mem::replace(self.table.get_mut(var.0), Ty::Unknown);
         let ret = self.unify(lhs, rhs);
@@ -416,6 +561,7 @@ impl<'db> InferCtx<'db> {
     fn unify(&mut self, lhs: Ty, rhs: Ty) -> Ty {
         match (lhs, rhs) {
             (Ty::Unknown, other) | (other, Ty::Unknown) => other,
+            (union @ Ty::Union(_), _) | (_, union @ Ty::Union(_)) => union,
             (Ty::List(a), Ty::List(b)) => {
                 self.unify_var(a, b);
                 Ty::List(a)
@@ -537,6 +683,9 @@ impl<'a> Collector<'a> {
                     .collect();
                 super::Ty::Attrset(super::Attrset(set))
             }
+            Ty::Union(members) => {
+                super::Ty::Union(members.into_iter().map(|ty| self.collect(ty)).collect())
+            }
             Ty::External(ty) => ty,
         }
     }
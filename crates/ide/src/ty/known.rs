@@ -1,5 +1,53 @@
 use super::{Attrset, Ty};
 use once_cell::sync::Lazy;
+use std::sync::Arc;
+
+/// Builtins which are impure, thus are unavailable or stubbed in pure evaluation mode of flakes.
+pub const IMPURE_BUILTINS: &[&str] = &["currentSystem", "currentTime", "getEnv"];
+
+/// Attribute names of `lib.licenses` in nixpkgs, including deprecated aliases.
+/// It follows `lib/licenses.nix` of nixpkgs, and is also used for completion.
+#[rustfmt::skip]
+pub const LICENSES: &[&str] = &[
+    "abstyles", "acsl14", "activision", "adobeDisplayPostScript", "adobeUtopia", "afl20", "afl21",
+    "afl3", "agpl3", "agpl3Only", "agpl3Plus", "aladdin", "amazonsl", "amd", "aml", "ampas", "aom",
+    "apple-psl10", "apple-psl20", "apsl10", "apsl20", "arphicpl", "artistic1", "artistic1-cl8",
+    "artistic2", "asl11", "asl20", "baekmuk", "beerware", "bitTorrent10", "bitTorrent11",
+    "bitstreamCharter", "bitstreamVera", "blueOak100", "bola11", "boost", "bsd0", "bsd1", "bsd2",
+    "bsd2Patent", "bsd2WithViews", "bsd3", "bsd3ClauseTso", "bsd3Clear", "bsd3Lbnl",
+    "bsdAxisNoDisclaimerUnmodified", "bsdOriginal", "bsdOriginalShortened", "bsdOriginalUC",
+    "bsdProtection", "bsdSourceCode", "bsl11", "bzip2-1010", "cal10", "caossl", "cc-by-10",
+    "cc-by-20", "cc-by-30", "cc-by-40", "cc-by-nc-30", "cc-by-nc-40", "cc-by-nc-nd-30",
+    "cc-by-nc-nd-40", "cc-by-nc-sa-20", "cc-by-nc-sa-25", "cc-by-nc-sa-30", "cc-by-nc-sa-40",
+    "cc-by-nd-30", "cc-by-nd-40", "cc-by-sa-10", "cc-by-sa-20", "cc-by-sa-25", "cc-by-sa-30",
+    "cc-by-sa-40", "cc-sa-10", "cc0", "cddl", "cecill-b", "cecill-c", "cecill20", "cecill21",
+    "clArtistic", "classpathException20", "cnri-python", "cockroachdb-community-license",
+    "commons-clause", "cpal10", "cpl10", "cronyx", "curl", "databricks", "databricks-dbx",
+    "databricks-license", "doc", "drl10", "dtoa", "eapl", "ecl20", "efl10", "efl20", "elastic",
+    "elastic20", "epl10", "epl20", "eupl11", "eupl12", "fair", "fairsource09", "fdl11", "fdl11Only",
+    "fdl11Plus", "fdl12", "fdl12Only", "fdl12Plus", "fdl13", "fdl13Only", "fdl13Plus", "ffsl",
+    "fraunhofer-fdk", "free", "fsl11Asl20", "fsl11Mit", "ftl", "g4sl", "generaluser", "geogebra",
+    "gfl", "gfsl", "giftware", "gpl1", "gpl1Only", "gpl1Plus", "gpl2", "gpl2Classpath",
+    "gpl2ClasspathPlus", "gpl2Only", "gpl2Oss", "gpl2Plus", "gpl2UBDLPlus", "gpl3",
+    "gpl3ClasspathPlus", "gpl3Only", "gpl3Plus", "hl3", "hpnd", "hpndDec", "hpndDoc", "hpndDocSell",
+    "hpndSellVariant", "hpndUc", "iasl", "icu", "ijg", "imagemagick", "imlib2", "info-zip",
+    "inria-compcert", "inria-icesl", "inria-zelus", "intel-eula", "interbase", "ipa", "ipl10",
+    "isc", "issl", "jasper", "knuth", "lal12", "lal13", "lens", "lgpl2", "lgpl21", "lgpl21Only",
+    "lgpl21Plus", "lgpl2Only", "lgpl2Plus", "lgpl3", "lgpl3Only", "lgpl3Plus", "lgpllr", "libpng",
+    "libpng2", "libssh2", "libtiff", "llgpl21", "llvm-exception", "lpl-102", "lppl1", "lppl12",
+    "lppl13a", "lppl13c", "lsof", "miros", "mit", "mit-cmu", "mit-enna", "mit-feh", "mit-modern",
+    "mit0", "mitAdvertising", "mpl10", "mpl11", "mpl20", "mplus", "ms-pl", "mspl", "msrla",
+    "nasa13", "ncbiPd", "ncsa", "ncul1", "netcdf", "nistSoftware", "nlpl", "nposl3", "nvidiaCuda",
+    "nvidiaCudaRedist", "obsidian", "ocamlLgplLinkingException", "ocamlpro_nc", "odbl", "ofl",
+    "oml", "openldap", "openssl", "opubl", "osl2", "osl21", "osl3", "parity70", "php301",
+    "postgresql", "postman", "prosperity30", "psfl", "publicDomain", "purdueBsd", "qhull", "qpl",
+    "qwt", "ruby", "sendmail", "sfl", "sgi-b-20", "sgmlug", "sissl11", "sleepycat", "smail",
+    "smlnj", "sspl", "stk", "sudo", "sustainableUse", "tcltk", "tcp-wrappers", "teamspeak", "tsl",
+    "ufl", "unfree", "unfreeRedistributable", "unfreeRedistributableFirmware", "unicode-30",
+    "unicode-dfs-2015", "unicode-dfs-2016", "unicodeTOU", "unlicense", "upl", "vim",
+    "virtualbox-puel", "vol-sl", "vsl10", "w3c", "wadalab", "watcom", "wtfpl", "wxWindows",
+    "wxWindowsException31", "x11", "xerox", "xfig", "xinetd", "xskat", "zlib", "zpl20", "zpl21",
+];
 
 pub static DERIVATION: Lazy<Ty> = Lazy::new(|| {
     ty!({
@@ -10,6 +58,109 @@ pub static DERIVATION: Lazy<Ty> = Lazy::new(|| {
     })
 });
 
+/// The argument of `stdenv.mkDerivation`, with common fields of the generic builder.
+/// Sources may be paths or packages, which are left unknown since unions are not supported.
+/// Packages are opaque attrsets, rather than the argument of `builtins.derivation`.
+pub static MK_DERIVATION_ARG: Lazy<Ty> = Lazy::new(|| {
+    merge_attrset(
+        &DERIVATION,
+        &ty!({
+            "pname": string,
+            "version": string,
+            "src": ?,
+            "srcs": [?],
+            "patches": [path],
+
+            "buildInputs": [{ }],
+            "nativeBuildInputs": [{ }],
+            "propagatedBuildInputs": [{ }],
+
+            "configureFlags": [string],
+            "makeFlags": [string],
+            "configurePhase": string,
+            "buildPhase": string,
+            "checkPhase": string,
+            "installPhase": string,
+            "doCheck": bool,
+            "strictDeps": bool,
+            "enableParallelBuilding": bool,
+
+            "outputs": [string],
+            "env": { },
+            "passthru": { },
+            "meta": {
+                "description": string,
+                "longDescription": string,
+                "homepage": string,
+                "license": ?,
+                "maintainers": [?],
+                "platforms": [string],
+                "mainProgram": string,
+            },
+        }),
+    )
+});
+
+/// Fields of fixed-output hashes accepted by nixpkgs fetchers.
+pub const FETCHER_HASH_FIELDS: &[&str] = &["hash", "sha256"];
+
+/// Placeholder hashes, which are never correct, like `lib.fakeHash` and `lib.fakeSha256`.
+pub const FAKE_HASHES: &[&str] = &[
+    "",
+    "sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+    "0000000000000000000000000000000000000000000000000000",
+];
+
+/// The argument of `fetchFromGitHub` in nixpkgs.
+pub static FETCH_FROM_GITHUB_ARG: Lazy<Ty> = Lazy::new(|| {
+    ty!({
+        "owner": string,
+        "repo": string,
+        "rev": string,
+        "tag": string,
+        "hash": string,
+        "sha256": string,
+        "name": string,
+        "fetchSubmodules": bool,
+        "leaveDotGit": bool,
+        "deepClone": bool,
+        "private": bool,
+        "githubBase": string,
+        "postFetch": string,
+    })
+});
+
+/// The argument of `fetchgit` in nixpkgs.
+pub static FETCHGIT_ARG: Lazy<Ty> = Lazy::new(|| {
+    ty!({
+        "url": string,
+        "rev": string,
+        "branchName": string,
+        "hash": string,
+        "sha256": string,
+        "name": string,
+        "fetchSubmodules": bool,
+        "fetchLFS": bool,
+        "leaveDotGit": bool,
+        "deepClone": bool,
+        "sparseCheckout": [string],
+        "postFetch": string,
+    })
+});
+
+/// Fetchers in nixpkgs taking an attrset argument with a fixed-output hash.
+pub const HASHED_FETCHERS: &[&str] = &["fetchFromGitHub", "fetchgit"];
+
+/// Get the schema of the attrset argument of a well-known nixpkgs function, by its name.
+pub fn arg_schema(func_name: &str) -> Option<&'static Ty> {
+    Some(match func_name {
+        "mkDerivation" => &*MK_DERIVATION_ARG,
+        "fetchFromGitHub" => &*FETCH_FROM_GITHUB_ARG,
+        "fetchgit" => &*FETCHGIT_ARG,
+        _ => return None,
+    })
+}
+
 pub static FETCH_TREE_ARG: Lazy<Ty> = Lazy::new(|| {
     ty!({
         "url": string,
@@ -52,6 +203,24 @@ pub static FLAKE: Lazy<Ty> = Lazy::new(|| {
     )
 });
 
+/// Schemas shown by their names when displaying types, if enabled.
+static NAMED_SCHEMAS: Lazy<[(&str, &Ty); 4]> = Lazy::new(|| {
+    [
+        ("derivation", &*DERIVATION),
+        ("flake", &*FLAKE),
+        ("flakeOutputs", &*FLAKE_OUTPUTS),
+        ("flakePartsModule", &*FLAKE_PARTS_MODULE),
+    ]
+});
+
+/// Get the name of the known schema which is exactly the attrset.
+pub fn schema_name(set: &Attrset) -> Option<&'static str> {
+    NAMED_SCHEMAS.iter().find_map(|&(name, schema)| {
+        let schema = schema.as_attrset()?;
+        (Arc::ptr_eq(&schema.0, &set.0) || schema == set).then_some(name)
+    })
+}
+
 fn merge_attrset(lhs: &Ty, rhs: &Ty) -> Ty {
     let lhs = lhs.as_attrset().unwrap();
     let rhs = rhs.as_attrset().unwrap();
@@ -70,6 +239,32 @@ fn merge_attrset(lhs: &Ty, rhs: &Ty) -> Ty {
 
 /// https://nixos.wiki/wiki/Flakes
 pub fn flake(inputs: &[&str]) -> Ty {
+    flake_with_inputs(inputs, |_| FLAKE.clone())
+}
+
+/// A flake whose outputs are built by `mkFlake` from the input `flake_parts_input`.
+/// https://flake.parts
+pub fn flake_parts(inputs: &[&str], flake_parts_input: &str) -> Ty {
+    let flake_parts_ty = merge_attrset(
+        &FLAKE,
+        &ty!({
+            "lib": {
+                "mkFlake": ({ "inputs": { }, "specialArgs": { } }
+                    -> (#FLAKE_PARTS_MODULE.clone())
+                    -> (#FLAKE_OUTPUTS.clone())),
+            },
+        }),
+    );
+    flake_with_inputs(inputs, |name| {
+        if name == flake_parts_input {
+            flake_parts_ty.clone()
+        } else {
+            FLAKE.clone()
+        }
+    })
+}
+
+fn flake_with_inputs(inputs: &[&str], input_flake_ty: impl Fn(&str) -> Ty) -> Ty {
     // https://nixos.org/manual/nix/stable/command-ref/new-cli/nix3-flake.html#flake-references
     let input_ty = merge_attrset(
         &FETCH_TREE_ARG,
@@ -90,66 +285,108 @@ pub fn flake(inputs: &[&str]) -> Ty {
         inputs
             .iter()
             .copied()
-            .chain(Some("self"))
-            .map(|name| (name, FLAKE.clone())),
+            .map(|name| (name, input_flake_ty(name)))
+            .chain(Some(("self", FLAKE.clone()))),
     ));
 
     ty!({
         "description": string,
         "nixConfig": { _: ? },
         "inputs": (#inputs_ty),
-        // https://nixos.org/manual/nix/unstable/command-ref/new-cli/nix3-develop.html?highlight=flake#flake-output-attributes
-        "outputs": ((#outputs_param_ty) -> {
+        "outputs": ((#outputs_param_ty) -> (#FLAKE_OUTPUTS.clone())),
+    })
+}
+
+// https://nixos.org/manual/nix/unstable/command-ref/new-cli/nix3-develop.html?highlight=flake#flake-output-attributes
+pub static FLAKE_OUTPUTS: Lazy<Ty> = Lazy::new(|| {
+    ty!({
+        "apps": {
+            _: {
+                "default": { "type": string, "program": string },
+                _: { "type": string, "program": string }
+            }
+        },
+        "checks": {
+            _: {
+                _: derivation
+            }
+        },
+        "devShells": {
+            "default": derivation,
+            _: derivation
+        },
+        "formatter": {
+            _: derivation
+        },
+        "hydraJobs": {
+            _: {
+                _: derivation
+            }
+        },
+        "legacyPackages": {
+            _: {
+                _: ?
+            }
+        },
+        "nixosConfigurations": {
+            _: derivation
+        },
+        "nixosModules": {
+            "default": ({ "config": { } } -> { "options": { }, "config": { } }),
+            _: ({ "config": { } } -> { "options": { }, "config": { } })
+        },
+        "overlays": {
+            "default": ({ } -> { } -> { }),
+            _: ({ } -> { } -> { })
+        },
+        "packages": {
+            "default": derivation,
+            _: derivation
+        },
+        "templates": {
+            "default": { "description": string, "path": string },
+            _: { "description": string, "path": string }
+        },
+    })
+});
+
+// https://flake.parts/options/flake-parts.html
+pub static FLAKE_PARTS_MODULE: Lazy<Ty> = Lazy::new(|| {
+    ty!({
+        "debug": bool,
+        "flake": (#FLAKE_OUTPUTS.clone()),
+        "imports": [?],
+        "perSystem": ({
+            "config": { },
+            "inputs'": { },
+            "lib": { },
+            "pkgs": { },
+            "self'": { },
+            "system": string,
+        } -> {
             "apps": {
-                _: {
-                    "default": { "type": string, "program": string },
-                    _: { "type": string, "program": string }
-                }
+                "default": { "type": string, "program": string },
+                _: { "type": string, "program": string }
             },
             "checks": {
-                _: {
-                    _: derivation
-                }
+                _: derivation
             },
             "devShells": {
                 "default": derivation,
                 _: derivation
             },
-            "formatter": {
-                _: derivation
-            },
-            "hydraJobs": {
-                _: {
-                    _: derivation
-                }
-            },
+            "formatter": derivation,
             "legacyPackages": {
-                _: {
-                    _: ?
-                }
-            },
-            "nixosConfigurations": {
-                _: derivation
-            },
-            "nixosModules": {
-                "default": ({ "config": { } } -> { "options": { }, "config": { } }),
-                _: ({ "config": { } } -> { "options": { }, "config": { } })
-            },
-            "overlays": {
-                "default": ({ } -> { } -> { }),
-                _: ({ } -> { } -> { })
+                _: ?
             },
             "packages": {
                 "default": derivation,
                 _: derivation
             },
-            "templates": {
-                "default": { "description": string, "path": string },
-                _: { "description": string, "path": string }
-            },
         }),
+        "systems": [string],
     })
-}
+});
 
 pub static BUILTINS: Lazy<Ty> = Lazy::new(|| {
     // Unfold one layer.
@@ -227,7 +464,7 @@ fn builtins() -> Ty {
         } | string) -> (#FETCH_TREE_RET.clone())),
         "fetchTree": (((#FETCH_TREE_ARG.clone()) | string) -> (#FETCH_TREE_RET.clone())),
         "fetchurl": (string -> string),
-        "filter": (forall a, (a -> bool) -> [a] -> a),
+        "filter": (forall a, (a -> bool) -> [a] -> [a]),
         "filterSource": ((string -> string -> bool) -> path -> path),
         "findFile": ([{ "prefix": string, "path": string }] -> string -> string),
         "floor": (number -> int),
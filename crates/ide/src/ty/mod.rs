@@ -21,10 +21,10 @@ macro_rules! ty {
         ty!($ty)
     }};
 
-    // TODO: Polymorphism.
+    // Type parameters are instantiated on each reference during inference.
     (forall a $(b)?, $($ty:tt)*) => { ty!($($ty)*) };
-    (a) => { $crate::ty::Ty::Unknown };
-    (b) => { $crate::ty::Ty::Unknown };
+    (a) => { $crate::ty::Ty::Param(0) };
+    (b) => { $crate::ty::Ty::Param(1) };
 
     (($($inner:tt)*)) => { ty!($($inner)*) };
     ([$($inner:tt)*]) => { $crate::ty::Ty::List(::std::sync::Arc::new(ty!($($inner)*)))};
@@ -51,6 +51,7 @@ mod union_find;
 #[cfg(test)]
 mod tests;
 
+use crate::base::Upcast;
 use crate::def::NameId;
 use crate::{DefDatabase, FileId};
 use std::sync::Arc;
@@ -60,12 +61,17 @@ pub use infer::InferenceResult;
 use smol_str::SmolStr;
 
 #[salsa::query_group(TyDatabaseStorage)]
-pub trait TyDatabase: DefDatabase {
+pub trait TyDatabase: DefDatabase + Upcast<dyn DefDatabase> {
     #[salsa::invoke(module_expected_ty)]
     fn module_expected_ty(&self, file: FileId) -> Option<Ty>;
 
     #[salsa::invoke(infer::infer_query)]
     fn infer(&self, file: FileId) -> Arc<InferenceResult>;
+
+    /// The type of the file when it is `import`ed. `import`s inside it are not followed,
+    /// which would be cyclic for files importing each other.
+    #[salsa::invoke(infer::import_ty_query)]
+    fn import_ty(&self, file: FileId) -> Ty;
 }
 
 #[derive(Clone, PartialEq, Eq)]
@@ -84,6 +90,12 @@ pub enum Ty {
     List(Arc<Ty>),
     Lambda(Arc<Ty>, Arc<Ty>),
     Attrset(Attrset),
+    /// One of several types, currently only inferred for elements of heterogeneous lists.
+    Union(Arc<[Ty]>),
+
+    /// A type parameter of a polymorphic builtin, like `a` in `[a] → a`.
+    /// Each reference of the builtin gets fresh type variables for them during inference.
+    Param(u8),
 }
 
 impl Ty {
@@ -94,6 +106,18 @@ impl Ty {
         }
     }
 
+    /// Whether the type contains any type parameter.
+    pub(crate) fn has_params(&self) -> bool {
+        match self {
+            Self::Param(_) => true,
+            Self::List(elem) => elem.has_params(),
+            Self::Lambda(param, ret) => param.has_params() || ret.has_params(),
+            Self::Attrset(set) => set.iter().any(|(_, ty, _)| ty.has_params()),
+            Self::Union(tys) => tys.iter().any(|ty| ty.has_params()),
+            _ => false,
+        }
+    }
+
     pub fn display(&self) -> TyDisplay<'_> {
         TyDisplay::new(self, 2)
     }
@@ -164,6 +188,15 @@ impl Attrset {
     pub fn iter(&self) -> impl Iterator<Item = (&SmolStr, &Ty, AttrSource)> + '_ {
         self.0.iter().map(|(k, ty, src)| (k, ty, *src))
     }
+
+    /// Whether all fields are defined by names, like ones of attrset literals.
+    /// Otherwise, it is inferred from uses or from an incomplete schema, and may have more
+    /// fields than known.
+    pub fn is_closed(&self) -> bool {
+        self.0
+            .iter()
+            .all(|(_, _, src)| matches!(src, AttrSource::Name(_)))
+    }
 }
 
 /// The source of an Attr.
@@ -192,5 +225,20 @@ fn module_expected_ty(db: &dyn TyDatabase, file: FileId) -> Option<Ty> {
             inputs.dedup();
             Some(known::flake(&inputs))
         }
+        crate::ModuleKind::FlakeParts {
+            explicit_inputs,
+            param_inputs,
+            flake_parts_input,
+        } => {
+            let mut inputs = explicit_inputs
+                .keys()
+                .chain(param_inputs.keys())
+                .chain(Some(flake_parts_input))
+                .map(|s| &**s)
+                .collect::<Vec<_>>();
+            inputs.sort();
+            inputs.dedup();
+            Some(known::flake_parts(&inputs, flake_parts_input))
+        }
     }
 }
@@ -53,7 +53,7 @@ fn check_all(src: &str, expect: Expect) {
 fn check_all_expect(src: &str, expect_ty: Ty, expect: Expect) {
     let (db, file) = TestDB::single_file(src).unwrap();
     let module = db.module(file);
-    let infer = super::infer::infer_with(&db, file, Some(expect_ty));
+    let infer = super::infer::infer_with(&db, file, Some(expect_ty), true);
     let got = all_types(&module, &infer);
     expect.assert_eq(&got);
 }
@@ -106,6 +106,27 @@ fn collection_operator() {
     );
 }
 
+#[test]
+fn list() {
+    check("[ 1 2 3 ]", expect!["[int]"]);
+    check("[ 1 2.5 ]", expect!["[float]"]);
+    check(r#"[ 1 "a" 2 ]"#, expect!["[int | string]"]);
+    check(
+        r#"[ 1 "a" 2.5 (x: x) ]"#,
+        expect!["[float | string | (? → ?)]"],
+    );
+    check("[ { a = 1; } { b = 2; } ]", expect!["[{ a: int, b: int }]"]);
+    check_all(
+        r#"let a = 1; b = [ a 2.5 ]; c = [ a "a" ]; in b"#,
+        expect![[r#"
+            a: int
+            b: [float]
+            c: [int | string]
+            : [float]
+        "#]],
+    );
+}
+
 #[test]
 fn let_in() {
     check_all(
@@ -228,8 +249,102 @@ fn flake_file() {
     );
 }
 
+#[test]
+fn flake_parts_file() {
+    check_name(
+        "system",
+        r#"
+#- /flake.nix input:flake-parts=/nix/store/eeee
+{
+    inputs.flake-parts.url = "github:hercules-ci/flake-parts";
+    outputs = inputs: inputs.flake-parts.lib.mkFlake { inherit inputs; } {
+        perSystem = { system, ... }: { };
+    };
+}
+        "#,
+        expect!["string"],
+    );
+}
+
 #[test]
 fn builtins() {
     check("true", expect!["bool"]);
     check("builtins.length [ ]", expect!["int"]);
 }
+
+#[test]
+fn builtin_partial_application() {
+    check("builtins.map (x: x + 1)", expect!["[int] → [int]"]);
+    check(
+        r#"map (x: x.name) [ { name = "a"; } ]"#,
+        expect!["[string]"],
+    );
+    check(
+        "builtins.filter (x: x.enable)",
+        expect!["[{ enable: bool }] → [{ enable: bool }]"],
+    );
+    check(
+        "builtins.filter (x: x.enable) [ ]",
+        expect!["[{ enable: bool }]"],
+    );
+    // Each reference is instantiated separately.
+    check(
+        r#"{ a = map (x: x + 1); b = map (x: "b"); }"#,
+        expect!["{ a: [int] → [int], b: [?] → [string] }"],
+    );
+}
+
+#[test]
+fn import_application() {
+    let (db, f) = TestDB::from_fixture(
+        "
+#- /default.nix
+{ lib = import ./lib.nix; applied = import ./lib.nix { a = 1; }; }
+
+#- /lib.nix
+{ a }: { b = a + 1; c = import ./util.nix; }
+
+#- /util.nix
+1
+        ",
+    )
+    .unwrap();
+    let file = f["/default.nix"];
+    let module = db.module(file);
+    let ty = db.infer(file).ty_for_expr(module.entry_expr());
+    // Imports in the imported file are not followed.
+    expect!["{ applied: { b: int, c: ? }, lib: { a: int } → { b: int, c: ? } }"]
+        .assert_eq(&ty.debug().to_string());
+}
+
+#[test]
+fn import_cycle() {
+    let (db, f) = TestDB::from_fixture(
+        "
+#- /a.nix
+{ a = 1; b = import ./b.nix; }
+
+#- /b.nix
+{ a = import ./a.nix; }
+        ",
+    )
+    .unwrap();
+    let file = f["/a.nix"];
+    let module = db.module(file);
+    let ty = db.infer(file).ty_for_expr(module.entry_expr());
+    expect!["{ a: int, b: { a: ? } }"].assert_eq(&ty.debug().to_string());
+}
+
+#[test]
+fn named_schemas() {
+    let (db, file) = TestDB::single_file(
+        r#"let drv = derivation { name = "a"; system = "x"; builder = ./b; }; in { inherit drv; }"#,
+    )
+    .unwrap();
+    let module = db.module(file);
+    let infer = db.infer(file);
+    let ty = infer.ty_for_expr(module.entry_expr());
+    expect!["{ drv: { args: [string], builder: string, name: string, system: string } }"]
+        .assert_eq(&ty.debug().to_string());
+    expect!["{ drv: derivation }"].assert_eq(&ty.debug().named_schemas(true).to_string());
+}
@@ -1,20 +1,25 @@
+use crate::lsp_ext::PREFETCH_HASH_COMMAND;
 use crate::semantic_tokens::{SEMANTIC_TOKEN_MODIFIERS, SEMANTIC_TOKEN_TYPES};
 use lsp_types::{
-    CodeActionProviderCapability, CompletionOptions, DocumentLinkOptions, HoverProviderCapability,
-    OneOf, RenameOptions, SelectionRangeProviderCapability, SemanticTokensFullOptions,
-    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensServerCapabilities,
-    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, TextDocumentSyncOptions,
-    WorkDoneProgressOptions,
+    CallHierarchyServerCapability, ClientCapabilities, CodeActionProviderCapability,
+    CompletionOptions, DocumentLinkOptions, ExecuteCommandOptions, FileOperationFilter,
+    FileOperationPattern, FileOperationRegistrationOptions, FoldingRangeProviderCapability,
+    HoverProviderCapability, OneOf, RenameOptions, SelectionRangeProviderCapability,
+    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensOptions,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextDocumentSyncOptions, WorkDoneProgressOptions,
+    WorkspaceFileOperationsServerCapabilities, WorkspaceServerCapabilities,
 };
 
-pub(crate) fn server_capabilities() -> ServerCapabilities {
+pub(crate) fn server_capabilities(client_caps: &ClientCapabilities) -> ServerCapabilities {
     ServerCapabilities {
         text_document_sync: Some(TextDocumentSyncCapability::Options(
             TextDocumentSyncOptions {
                 open_close: Some(true),
                 change: Some(TextDocumentSyncKind::INCREMENTAL),
                 will_save: None,
-                will_save_wait_until: None,
+                // Registered on demand when `formatting.onSave` is enabled, if possible.
+                will_save_wait_until: (!sync_dynamic_registration(client_caps)).then_some(true),
                 save: None,
             },
         )),
@@ -49,6 +54,44 @@ pub(crate) fn server_capabilities() -> ServerCapabilities {
         }),
         code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
         document_highlight_provider: Some(OneOf::Left(true)),
+        call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+        inline_value_provider: Some(OneOf::Left(true)),
+        folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![PREFETCH_HASH_COMMAND.into()],
+            work_done_progress_options: WorkDoneProgressOptions::default(),
+        }),
+        workspace: Some(WorkspaceServerCapabilities {
+            workspace_folders: None,
+            file_operations: Some(WorkspaceFileOperationsServerCapabilities {
+                will_rename: Some(nix_file_operation_options()),
+                will_delete: Some(nix_file_operation_options()),
+                ..Default::default()
+            }),
+        }),
         ..Default::default()
     }
 }
+
+/// Whether the client supports dynamic registration of text document synchronization,
+/// like `textDocument/willSaveWaitUntil`.
+pub(crate) fn sync_dynamic_registration(caps: &ClientCapabilities) -> bool {
+    caps.text_document
+        .as_ref()
+        .and_then(|caps| caps.synchronization.as_ref())
+        .and_then(|caps| caps.dynamic_registration)
+        .unwrap_or(false)
+}
+
+fn nix_file_operation_options() -> FileOperationRegistrationOptions {
+    FileOperationRegistrationOptions {
+        filters: vec![FileOperationFilter {
+            scheme: Some("file".into()),
+            pattern: FileOperationPattern {
+                glob: "**/*.nix".into(),
+                matches: None,
+                options: None,
+            },
+        }],
+    }
+}
@@ -1,31 +1,189 @@
-use lsp_types::Url;
-use std::collections::HashSet;
+use crate::capabilities;
+use ide::{CompletionCategory, DocCommentStyle, Ty};
+use lsp_types::{ClientCapabilities, DiagnosticTag, Url};
+use nix_interop::nix_path::{self, SearchPathEntry};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub const CONFIG_KEY: &str = "nil";
 
+/// Experimental features which can be toggled via `experimental.<feature>`,
+/// with their descriptions reported by `nil/status`, and whether they are enabled by default.
+pub const EXPERIMENTAL_FEATURES: &[(&str, &str, bool)] = &[(
+    "shiftDiagnostics",
+    "Shift diagnostics along with edits until they are recalculated.",
+    true,
+)];
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub root_path: PathBuf,
+    /// Diagnostic tags supported by the client.
+    /// This is from client capabilities and is not configurable.
+    pub diagnostics_tag_support: Vec<DiagnosticTag>,
+    /// Whether the client supports `window/showMessageRequest`.
+    /// This is from client capabilities and is not configurable.
+    pub show_message_request_support: bool,
+    /// Whether the client supports `LocationLink` results of `textDocument/definition`.
+    /// This is from client capabilities and is not configurable.
+    pub definition_link_support: bool,
+    /// Whether the client supports registering `textDocument/willSaveWaitUntil` on demand.
+    /// This is from client capabilities and is not configurable.
+    pub sync_dynamic_registration: bool,
+    /// Search paths parsed from the `NIX_PATH` environment variable at startup.
+    /// This is not configurable.
+    pub nix_path_env: Vec<SearchPathEntry>,
 
     pub diagnostics_excluded_files: Vec<Url>,
     pub diagnostics_ignored: HashSet<String>,
+    pub diagnostics_error_on: HashSet<String>,
+    pub diagnostics_top_level_with: bool,
+    pub diagnostics_flake_purity: bool,
+    pub diagnostics_batch_window: Duration,
+    pub diagnostics_max_line_width: Option<u32>,
+    pub diagnostics_max_nesting_depth: Option<usize>,
+    pub diagnostics_broken_bindings: bool,
+    pub completion_max_items: usize,
+    pub completion_category_weights: CompletionCategoryWeights,
+    pub completion_auto_insert_parens: bool,
+    pub completion_matching: CompletionMatching,
+    pub hover_named_schemas: bool,
+    pub doc_comment_style: DocCommentStyle,
+    pub inline_value_enable: bool,
+    pub injections_enable: bool,
+    pub call_hierarchy_top_level_only: bool,
     pub formatting_command: Option<Vec<String>>,
+    pub formatting_verify_idempotent: bool,
+    pub formatting_on_save: bool,
+    pub folding_region_markers: Vec<(String, String)>,
     pub nix_binary: PathBuf,
+    pub nix_search_paths: Vec<SearchPathEntry>,
+    pub nix_persistent_evaluator: bool,
+    pub paths_display: PathDisplay,
+    pub files_outside_root: OutsideRootPolicy,
+    pub scope_extra_globals: HashMap<String, Ty>,
+    pub server_idle_gc: Option<Duration>,
+    pub server_lru_capacity: Option<usize>,
+    /// All keys of `experimental`, including unknown ones.
+    pub experimental: HashMap<String, bool>,
 }
 
 impl Config {
-    pub fn new(root_path: PathBuf) -> Self {
+    pub fn new(root_path: PathBuf, caps: &ClientCapabilities) -> Self {
         assert!(root_path.is_absolute());
+        let diagnostics_tag_support = caps
+            .text_document
+            .as_ref()
+            .and_then(|caps| caps.publish_diagnostics.as_ref())
+            .and_then(|caps| caps.tag_support.as_ref())
+            .map(|tags| tags.value_set.clone())
+            .unwrap_or_default();
+        let show_message_request_support = caps
+            .window
+            .as_ref()
+            .map_or(false, |caps| caps.show_message.is_some());
+        let definition_link_support = caps
+            .text_document
+            .as_ref()
+            .and_then(|caps| caps.definition.as_ref())
+            .and_then(|caps| caps.link_support)
+            .unwrap_or(false);
+        let sync_dynamic_registration = capabilities::sync_dynamic_registration(caps);
+        let nix_path_env = env::var(nix_path::NIX_PATH_ENV)
+            .map(|s| nix_path::parse_nix_path(&s))
+            .unwrap_or_default();
         Self {
             root_path,
+            diagnostics_tag_support,
+            show_message_request_support,
+            definition_link_support,
+            sync_dynamic_registration,
+            nix_path_env,
             diagnostics_excluded_files: Vec::new(),
             diagnostics_ignored: HashSet::new(),
+            diagnostics_error_on: HashSet::new(),
+            diagnostics_top_level_with: false,
+            diagnostics_flake_purity: true,
+            diagnostics_batch_window: Duration::ZERO,
+            diagnostics_max_line_width: None,
+            diagnostics_max_nesting_depth: None,
+            diagnostics_broken_bindings: false,
+            completion_max_items: 500,
+            completion_category_weights: CompletionCategoryWeights::default(),
+            completion_auto_insert_parens: false,
+            completion_matching: CompletionMatching::default(),
+            hover_named_schemas: false,
+            doc_comment_style: DocCommentStyle::Plain,
+            inline_value_enable: false,
+            injections_enable: false,
+            call_hierarchy_top_level_only: false,
             formatting_command: None,
+            formatting_verify_idempotent: false,
+            formatting_on_save: false,
+            folding_region_markers: vec![
+                ("region".into(), "endregion".into()),
+                ("{{{".into(), "}}}".into()),
+            ],
             nix_binary: "nix".into(),
+            nix_search_paths: Vec::new(),
+            nix_persistent_evaluator: false,
+            paths_display: PathDisplay::Absolute,
+            files_outside_root: OutsideRootPolicy::Analyze,
+            scope_extra_globals: HashMap::new(),
+            server_idle_gc: None,
+            server_lru_capacity: None,
+            experimental: HashMap::new(),
+        }
+    }
+
+    /// Search path entries in priority order.
+    /// Explicitly configured ones take precedence over ones from `NIX_PATH`.
+    pub fn search_path_entries(&self) -> Vec<SearchPathEntry> {
+        self.nix_search_paths
+            .iter()
+            .chain(&self.nix_path_env)
+            .cloned()
+            .collect()
+    }
+
+    /// The policy for a document, which is always `Analyze` for files inside the workspace root
+    /// and non-file URIs.
+    pub fn outside_root_policy(&self, uri: &Url) -> OutsideRootPolicy {
+        match uri.to_file_path() {
+            Ok(path) if !path.starts_with(&self.root_path) => self.files_outside_root,
+            _ => OutsideRootPolicy::Analyze,
         }
     }
 
+    /// Check if an experimental feature is enabled, falling back to its default.
+    pub fn experimental(&self, feature: &str) -> bool {
+        let default = EXPERIMENTAL_FEATURES
+            .iter()
+            .find(|&&(name, ..)| name == feature)
+            .map(|&(.., default)| default);
+        debug_assert!(default.is_some(), "Unknown experimental feature: {feature}");
+        self.experimental
+            .get(feature)
+            .copied()
+            .unwrap_or(default.unwrap_or(false))
+    }
+
+    /// Keys of `experimental` which are not known features. They are ignored.
+    pub fn unknown_experimental_features(&self) -> Vec<&str> {
+        let mut keys = self
+            .experimental
+            .keys()
+            .map(|key| &**key)
+            .filter(|&key| !EXPERIMENTAL_FEATURES.iter().any(|&(name, ..)| name == key))
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        keys
+    }
+
     // TODO: Simplify.
     pub fn update(&mut self, mut value: serde_json::Value) -> (Vec<String>, bool) {
         let mut errors = Vec::new();
@@ -59,6 +217,95 @@ impl Config {
                 }
             }
         }
+        if let Some(v) = value.pointer_mut("/diagnostics/errorOn") {
+            match serde_json::from_value(v.take()) {
+                Ok(v) => {
+                    self.diagnostics_error_on = v;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `diagnostics.errorOn`: {e}"));
+                }
+            }
+        }
+        if let Some(v) = value.pointer_mut("/diagnostics/topLevelWith") {
+            match serde_json::from_value(v.take()) {
+                Ok(v) => {
+                    self.diagnostics_top_level_with = v;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `diagnostics.topLevelWith`: {e}"));
+                }
+            }
+        }
+        if let Some(v) = value.pointer_mut("/diagnostics/flakePurity") {
+            match serde_json::from_value(v.take()) {
+                Ok(v) => {
+                    self.diagnostics_flake_purity = v;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `diagnostics.flakePurity`: {e}"));
+                }
+            }
+        }
+        if let Some(v) = value.pointer_mut("/diagnostics/batchWindow") {
+            match serde_json::from_value::<u64>(v.take()) {
+                Ok(millis) => {
+                    self.diagnostics_batch_window = Duration::from_millis(millis);
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `diagnostics.batchWindow`: {e}"));
+                }
+            }
+        }
+        if let Some(v) = value.pointer_mut("/diagnostics/maxLineWidth") {
+            match serde_json::from_value::<Option<u32>>(v.take()) {
+                Ok(Some(0)) => {
+                    errors.push("`diagnostics.maxLineWidth` must be a positive number".into());
+                }
+                Ok(width) => {
+                    self.diagnostics_max_line_width = width;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `diagnostics.maxLineWidth`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/diagnostics/maxNestingDepth") {
+            match serde_json::from_value::<Option<usize>>(v.take()) {
+                Ok(Some(0)) => {
+                    errors.push("`diagnostics.maxNestingDepth` must be a positive number".into());
+                }
+                Ok(depth) => {
+                    self.diagnostics_max_nesting_depth = depth;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!(
+                        "Invalid value of `diagnostics.maxNestingDepth`: {e}"
+                    ));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/diagnostics/brokenBindings") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(v) => {
+                    self.diagnostics_broken_bindings = v;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!(
+                        "Invalid value of `diagnostics.brokenBindings`: {e}"
+                    ));
+                }
+            }
+        }
+
         if let Some(v) = value.pointer_mut("/formatting/command") {
             match serde_json::from_value::<Option<Vec<String>>>(v.take()) {
                 Ok(Some(v)) if v.is_empty() => {
@@ -73,6 +320,47 @@ impl Config {
             }
         }
 
+        if let Some(v) = value.pointer_mut("/formatting/verifyIdempotent") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.formatting_verify_idempotent = b;
+                }
+                Err(e) => {
+                    errors.push(format!(
+                        "Invalid value of `formatting.verifyIdempotent`: {e}"
+                    ));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/formatting/onSave") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.formatting_on_save = b;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `formatting.onSave`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/folding/regionMarkers") {
+            match serde_json::from_value::<Vec<(String, String)>>(v.take()) {
+                Ok(v)
+                    if v.iter()
+                        .any(|(start, end)| start.is_empty() || end.is_empty()) =>
+                {
+                    errors.push("`folding.regionMarkers` must not contain empty markers".into());
+                }
+                Ok(v) => {
+                    self.folding_region_markers = v;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `folding.regionMarkers`: {e}"));
+                }
+            }
+        }
+
         if let Some(v) = value.pointer_mut("/nix/binary") {
             match serde_json::from_value::<PathBuf>(v.take()) {
                 Ok(path) => {
@@ -84,6 +372,398 @@ impl Config {
             }
         }
 
+        if let Some(v) = value.pointer_mut("/nix/searchPaths") {
+            match serde_json::from_value::<Vec<String>>(v.take()) {
+                Ok(entries) => {
+                    self.nix_search_paths = entries
+                        .iter()
+                        .filter_map(|entry| nix_path::parse_search_path_entry(entry))
+                        .collect();
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `nix.searchPaths`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/nix/persistentEvaluator") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.nix_persistent_evaluator = b;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `nix.persistentEvaluator`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/paths/display") {
+            match serde_json::from_value::<PathDisplay>(v.take()) {
+                Ok(display) => {
+                    self.paths_display = display;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `paths.display`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/files/outsideRoot") {
+            match serde_json::from_value::<OutsideRootPolicy>(v.take()) {
+                Ok(policy) => {
+                    self.files_outside_root = policy;
+                    updated_diagnostics = true;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `files.outsideRoot`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/scope/extraGlobals") {
+            match serde_json::from_value::<HashMap<String, Option<String>>>(v.take()) {
+                Ok(globals) => {
+                    let globals = globals
+                        .into_iter()
+                        .map(|(name, ty)| match ty.as_deref() {
+                            None => Ok((name, Ty::Unknown)),
+                            Some(ty) => parse_global_ty(ty).map(|ty| (name, ty)).ok_or(ty),
+                        })
+                        .collect::<Result<HashMap<_, _>, _>>();
+                    match globals {
+                        Ok(globals) => {
+                            self.scope_extra_globals = globals;
+                            updated_diagnostics = true;
+                        }
+                        Err(ty) => {
+                            errors.push(format!("Invalid type in `scope.extraGlobals`: {ty:?}"));
+                        }
+                    }
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `scope.extraGlobals`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/completion/maxItems") {
+            match serde_json::from_value::<usize>(v.take()) {
+                Ok(0) => {
+                    errors.push("`completion.maxItems` must be a positive number".into());
+                }
+                Ok(n) => {
+                    self.completion_max_items = n;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `completion.maxItems`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/completion/categoryWeights") {
+            match serde_json::from_value::<CompletionCategoryWeights>(v.take()) {
+                Ok(weights) => {
+                    self.completion_category_weights = weights;
+                }
+                Err(e) => {
+                    errors.push(format!(
+                        "Invalid value of `completion.categoryWeights`: {e}"
+                    ));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/completion/autoInsertParens") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.completion_auto_insert_parens = b;
+                }
+                Err(e) => {
+                    errors.push(format!(
+                        "Invalid value of `completion.autoInsertParens`: {e}"
+                    ));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/completion/matching") {
+            match serde_json::from_value::<CompletionMatching>(v.take()) {
+                Ok(matching) => {
+                    self.completion_matching = matching;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `completion.matching`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/hover/namedSchemas") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.hover_named_schemas = b;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `hover.namedSchemas`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/docComment/style") {
+            match serde_json::from_value::<String>(v.take()).as_deref() {
+                Ok("plain") => {
+                    self.doc_comment_style = DocCommentStyle::Plain;
+                }
+                Ok("nixdoc") => {
+                    self.doc_comment_style = DocCommentStyle::Nixdoc;
+                }
+                Ok(s) => {
+                    errors.push(format!("Invalid value of `docComment.style`: {s:?}"));
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `docComment.style`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/inlineValue/enable") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.inline_value_enable = b;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `inlineValue.enable`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/injections/enable") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.injections_enable = b;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `injections.enable`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/callHierarchy/topLevelOnly") {
+            match serde_json::from_value::<bool>(v.take()) {
+                Ok(b) => {
+                    self.call_hierarchy_top_level_only = b;
+                }
+                Err(e) => {
+                    errors.push(format!(
+                        "Invalid value of `callHierarchy.topLevelOnly`: {e}"
+                    ));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/server/idleGc") {
+            match serde_json::from_value::<Option<u64>>(v.take()) {
+                Ok(Some(0)) => {
+                    errors.push("`server.idleGc` must be a positive number".into());
+                }
+                Ok(secs) => {
+                    self.server_idle_gc = secs.map(Duration::from_secs);
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `server.idleGc`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/server/lruCapacity") {
+            match serde_json::from_value::<Option<usize>>(v.take()) {
+                Ok(Some(0)) => {
+                    errors.push("`server.lruCapacity` must be a positive number".into());
+                }
+                Ok(cap) => {
+                    self.server_lru_capacity = cap;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `server.lruCapacity`: {e}"));
+                }
+            }
+        }
+
+        if let Some(v) = value.pointer_mut("/experimental") {
+            match serde_json::from_value::<HashMap<String, bool>>(v.take()) {
+                Ok(v) => {
+                    self.experimental = v;
+                }
+                Err(e) => {
+                    errors.push(format!("Invalid value of `experimental`: {e}"));
+                }
+            }
+        }
+
         (errors, updated_diagnostics)
     }
 }
+
+/// How resolved paths are shown in hover and document links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PathDisplay {
+    Absolute,
+    /// Relative to the workspace root, if the path is inside it.
+    Relative,
+}
+
+/// How documents outside the workspace root are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutsideRootPolicy {
+    /// Analyze them as usual, without the flake context of the workspace.
+    Analyze,
+    /// Analyze them, but return no edits like formatting, renaming or code actions.
+    ReadOnly,
+    /// Neither load nor analyze them. Requests on them return empty results.
+    Ignore,
+}
+
+/// Weights of completion categories. Items of higher weights are ranked first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompletionCategoryWeights {
+    pub local: u16,
+    pub builtin: u16,
+    pub with: u16,
+    pub schema: u16,
+    pub keyword: u16,
+}
+
+impl Default for CompletionCategoryWeights {
+    fn default() -> Self {
+        Self {
+            local: 40,
+            builtin: 30,
+            with: 20,
+            schema: 10,
+            keyword: 0,
+        }
+    }
+}
+
+impl CompletionCategoryWeights {
+    pub fn get(&self, category: CompletionCategory) -> u16 {
+        match category {
+            CompletionCategory::Local => self.local,
+            CompletionCategory::Builtin => self.builtin,
+            CompletionCategory::With => self.with,
+            CompletionCategory::Schema => self.schema,
+            CompletionCategory::Keyword => self.keyword,
+        }
+    }
+}
+
+/// Parse a type name of `scope.extraGlobals`. Compound types have unknown components.
+fn parse_global_ty(name: &str) -> Option<Ty> {
+    Some(match name {
+        "unknown" => Ty::Unknown,
+        "bool" => Ty::Bool,
+        "int" => Ty::Int,
+        "float" => Ty::Float,
+        "string" => Ty::String,
+        "path" => Ty::Path,
+        "list" => Ty::List(Arc::new(Ty::Unknown)),
+        "lambda" => Ty::Lambda(Arc::new(Ty::Unknown), Arc::new(Ty::Unknown)),
+        "attrset" => Ty::Attrset(Default::default()),
+        _ => return None,
+    })
+}
+
+/// How completion items are filtered by the input before the cursor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct CompletionMatching {
+    pub case_sensitive: bool,
+    pub strategy: MatchingStrategy,
+}
+
+impl Default for CompletionMatching {
+    fn default() -> Self {
+        Self {
+            case_sensitive: true,
+            strategy: MatchingStrategy::Subsequence,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchingStrategy {
+    /// The label starts with the input.
+    Prefix,
+    /// The label starts with the first character of the input, and contains the rest in order.
+    Fuzzy,
+    /// The label contains all characters of the input in order.
+    Subsequence,
+}
+
+impl CompletionMatching {
+    pub fn matches(&self, input: &str, label: &str) -> bool {
+        let eq = |a: char, b: char| {
+            if self.case_sensitive {
+                a == b
+            } else {
+                a.to_lowercase().eq(b.to_lowercase())
+            }
+        };
+        let mut input = input.chars();
+        let mut label = label.chars();
+        match self.strategy {
+            MatchingStrategy::Prefix => input.all(|a| label.next().map_or(false, |b| eq(a, b))),
+            MatchingStrategy::Fuzzy => match input.next() {
+                None => true,
+                Some(first) => {
+                    label.next().map_or(false, |b| eq(first, b))
+                        && input.all(|a| label.any(|b| eq(a, b)))
+                }
+            },
+            MatchingStrategy::Subsequence => input.all(|a| label.any(|b| eq(a, b))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CompletionMatching, MatchingStrategy};
+
+    #[track_caller]
+    fn check(case_sensitive: bool, strategy: MatchingStrategy, input: &str, expect: &[&str]) {
+        let matching = CompletionMatching {
+            case_sensitive,
+            strategy,
+        };
+        let got = ["foo", "fromTOML", "afoo", "Fox", "isFunction"]
+            .into_iter()
+            .filter(|label| matching.matches(input, label))
+            .collect::<Vec<_>>();
+        assert_eq!(got, expect);
+    }
+
+    #[test]
+    fn completion_matching() {
+        check(true, MatchingStrategy::Prefix, "fo", &["foo"]);
+        check(false, MatchingStrategy::Prefix, "fo", &["foo", "Fox"]);
+        check(true, MatchingStrategy::Fuzzy, "fo", &["foo", "fromTOML"]);
+        check(
+            false,
+            MatchingStrategy::Fuzzy,
+            "fo",
+            &["foo", "fromTOML", "Fox"],
+        );
+        check(
+            true,
+            MatchingStrategy::Subsequence,
+            "fo",
+            &["foo", "fromTOML", "afoo"],
+        );
+        let all = ["foo", "fromTOML", "afoo", "Fox", "isFunction"];
+        check(false, MatchingStrategy::Subsequence, "fo", &all);
+        check(true, MatchingStrategy::Prefix, "", &all);
+    }
+}
@@ -1,16 +1,23 @@
+use crate::config::{CompletionCategoryWeights, PathDisplay};
+use crate::lsp_ext::{InjectionRange, PrefetchHashArgs, PREFETCH_HASH_COMMAND};
 use crate::{semantic_tokens, LineMap, LspError, Result, Vfs};
 use ide::{
-    Assist, AssistKind, CompletionItem, CompletionItemKind, Diagnostic, FileId, FilePos, FileRange,
-    HlRange, HlRelated, HoverResult, NameKind, Severity, SymbolTree, TextEdit, WorkspaceEdit,
+    Assist, AssistKind, CallHierarchyCall, CallHierarchyItem, CompletionItem, CompletionItemKind,
+    Diagnostic, FetcherPrefetch, FileId, FilePos, FileRange, FoldingRange, FoldingRangeKind,
+    HlRange, HlRelated, HlTag, HoverKind, HoverResult, Injection, InlineValue, NameKind,
+    NavigationTarget, RenameError, Severity, SymbolTree, TextEdit, Ty, VfsPath, WorkspaceEdit,
 };
 use lsp_server::ErrorCode;
 use lsp_types::{
     self as lsp, CodeAction, CodeActionKind, CodeActionOrCommand, DiagnosticRelatedInformation,
     DiagnosticSeverity, DiagnosticTag, DocumentHighlight, DocumentHighlightKind, DocumentSymbol,
-    Documentation, Hover, Location, MarkupContent, MarkupKind, NumberOrString, Position,
-    PrepareRenameResponse, Range, SemanticToken, SymbolKind, TextDocumentIdentifier,
+    Documentation, Hover, Location, LocationLink, MarkupContent, MarkupKind, NumberOrString,
+    Position, PrepareRenameResponse, Range, SemanticToken, SymbolKind, TextDocumentIdentifier,
     TextDocumentPositionParams, Url,
 };
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::path::Path;
 use std::sync::Arc;
 use text_size::{TextRange, TextSize};
 
@@ -50,6 +57,21 @@ pub(crate) fn to_location(vfs: &Vfs, frange: FileRange) -> Location {
     Location::new(uri, to_range(&line_map, frange.range))
 }
 
+pub(crate) fn to_location_link(
+    vfs: &Vfs,
+    origin: FileRange,
+    target: NavigationTarget,
+) -> LocationLink {
+    let origin_line_map = vfs.line_map_for_file(origin.file_id);
+    let line_map = vfs.line_map_for_file(target.file_id);
+    LocationLink {
+        origin_selection_range: Some(to_range(&origin_line_map, origin.range)),
+        target_uri: vfs.uri_for_file(target.file_id),
+        target_range: to_range(&line_map, target.full_range),
+        target_selection_range: to_range(&line_map, target.focus_range),
+    }
+}
+
 pub(crate) fn to_range(line_map: &LineMap, range: TextRange) -> Range {
     let (line1, col1) = line_map.line_col_for_pos(range.start());
     let (line2, col2) = line_map.line_col_for_pos(range.end());
@@ -61,6 +83,7 @@ pub(crate) fn to_diagnostics(
     file: FileId,
     line_map: &LineMap,
     diags: &[Diagnostic],
+    tag_support: &[DiagnosticTag],
 ) -> Vec<lsp::Diagnostic> {
     let mut ret = Vec::with_capacity(diags.len() * 2);
     for diag in diags {
@@ -68,6 +91,7 @@ pub(crate) fn to_diagnostics(
             severity: match diag.severity() {
                 Severity::Error | Severity::IncompleteSyntax => Some(DiagnosticSeverity::ERROR),
                 Severity::Warning => Some(DiagnosticSeverity::WARNING),
+                Severity::Hint => Some(DiagnosticSeverity::HINT),
             },
             range: to_range(line_map, diag.range),
             code: Some(NumberOrString::String(diag.code().into())),
@@ -93,7 +117,9 @@ pub(crate) fn to_diagnostics(
                 if diag.is_unnecessary() {
                     tags.push(DiagnosticTag::UNNECESSARY);
                 }
-                Some(tags)
+                // Only emit tags the client declares to support.
+                tags.retain(|tag| tag_support.contains(tag));
+                (!tags.is_empty()).then_some(tags)
             },
             data: None,
         };
@@ -127,7 +153,62 @@ pub(crate) fn to_diagnostics(
     ret
 }
 
-pub(crate) fn to_completion_item(line_map: &LineMap, item: CompletionItem) -> lsp::CompletionItem {
+/// Convert completion items into a list of at most `max_items` items.
+/// When truncated, the best matches of the typed text are kept, and the list is marked incomplete,
+/// so that the client re-queries as the user types.
+pub(crate) fn to_completion_list(
+    line_map: &LineMap,
+    src: &str,
+    mut items: Vec<CompletionItem>,
+    max_items: usize,
+    weights: &CompletionCategoryWeights,
+    auto_insert_parens: bool,
+) -> lsp::CompletionList {
+    let is_incomplete = items.len() > max_items;
+    if is_incomplete {
+        items.sort_by_cached_key(|item| completion_sort_key(src, item, weights));
+        items.truncate(max_items);
+    }
+    lsp::CompletionList {
+        is_incomplete,
+        items: items
+            .into_iter()
+            .map(|item| {
+                let (weight, rank, len) = completion_sort_key(src, &item, weights);
+                // Clients sort items by `sortText`, and filter them by their own fuzzy matching.
+                let sort_text = format!("{:05}{rank}{len:05}{}", u16::MAX - weight.0, item.label);
+                let mut item = to_completion_item(line_map, item, auto_insert_parens);
+                item.sort_text = Some(sort_text);
+                item
+            })
+            .collect(),
+    }
+}
+
+/// Rank items of higher category weights first, then by the match of the input: prefix matches,
+/// then substring matches, then others. Items are already filtered by subsequence matching.
+/// Shorter labels are preferred at last.
+/// Items whose ranges are not valid in `src` are ranked as non-matches.
+fn completion_sort_key(
+    src: &str,
+    item: &CompletionItem,
+    weights: &CompletionCategoryWeights,
+) -> (Reverse<u16>, u8, usize) {
+    let range = item.source_range;
+    let rank = match src.get(usize::from(range.start())..usize::from(range.end())) {
+        Some(input) if item.label.starts_with(input) => 0,
+        Some(input) if item.label.contains(input) => 1,
+        _ => 2,
+    };
+    (Reverse(weights.get(item.category)), rank, item.label.len())
+}
+
+/// With `auto_insert_parens`, functions are completed with placeholders of arguments.
+pub(crate) fn to_completion_item(
+    line_map: &LineMap,
+    item: CompletionItem,
+    auto_insert_parens: bool,
+) -> lsp::CompletionItem {
     let kind = match item.kind {
         CompletionItemKind::Keyword => lsp::CompletionItemKind::KEYWORD,
         CompletionItemKind::Param => lsp::CompletionItemKind::VARIABLE,
@@ -136,17 +217,23 @@ pub(crate) fn to_completion_item(line_map: &LineMap, item: CompletionItem) -> ls
         CompletionItemKind::BuiltinConst => lsp::CompletionItemKind::CONSTANT,
         CompletionItemKind::BuiltinFunction => lsp::CompletionItemKind::FUNCTION,
         CompletionItemKind::BuiltinAttrset => lsp::CompletionItemKind::CLASS,
+        CompletionItemKind::Snippet => lsp::CompletionItemKind::SNIPPET,
+    };
+    let (new_text, insert_text_format) = match (item.kind, item.call_snippet) {
+        (CompletionItemKind::Snippet, _) => (item.replace.into(), lsp::InsertTextFormat::SNIPPET),
+        (_, Some(snippet)) if auto_insert_parens => (snippet, lsp::InsertTextFormat::SNIPPET),
+        _ => (item.replace.into(), lsp::InsertTextFormat::PLAIN_TEXT),
     };
     lsp::CompletionItem {
         label: item.label.into(),
         kind: Some(kind),
         insert_text: None,
-        insert_text_format: Some(lsp::InsertTextFormat::PLAIN_TEXT),
+        insert_text_format: Some(insert_text_format),
         // We don't support indentation yet.
         insert_text_mode: Some(lsp::InsertTextMode::ADJUST_INDENTATION),
         text_edit: Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
             range: to_range(line_map, item.source_range),
-            new_text: item.replace.into(),
+            new_text,
         })),
         detail: item.brief,
         documentation: item.doc.map(|doc| {
@@ -169,10 +256,10 @@ pub(crate) fn to_completion_item(line_map: &LineMap, item: CompletionItem) -> ls
     }
 }
 
-pub(crate) fn to_rename_error(message: String) -> LspError {
+pub(crate) fn to_rename_error(err: RenameError) -> LspError {
     LspError {
         code: ErrorCode::InvalidRequest,
-        message,
+        message: err.to_string(),
     }
 }
 
@@ -218,14 +305,26 @@ pub(crate) fn to_text_edit(line_map: &LineMap, edit: TextEdit) -> lsp::TextEdit
     }
 }
 
-pub(crate) fn to_semantic_tokens(line_map: &LineMap, hls: &[HlRange]) -> Vec<SemanticToken> {
+pub(crate) fn to_semantic_tokens(
+    line_map: &LineMap,
+    hls: &[HlRange],
+    injections: &[Injection],
+) -> Vec<SemanticToken> {
     // We must not exceed the last line.
     let last_line = line_map.last_line();
 
     let mut toks = Vec::with_capacity(hls.len());
     let (mut prev_line, mut prev_start) = (0, 0);
     for hl in hls {
-        let (ty_idx, mod_set) = semantic_tokens::to_semantic_type_and_modifiers(hl.tag);
+        let (ty_idx, mut mod_set) = semantic_tokens::to_semantic_type_and_modifiers(hl.tag);
+        if hl.tag == HlTag::StringLiteral {
+            if let Some(inj) = injections
+                .iter()
+                .find(|inj| inj.range.contains_range(hl.range))
+            {
+                mod_set.insert(semantic_tokens::to_injection_modifier(inj.language));
+            }
+        }
         let range = to_range(line_map, hl.range);
         for line in range.start.line..=range.end.line.min(last_line) {
             // N.B. For relative encoding, column offset is relative to
@@ -266,16 +365,92 @@ pub(crate) fn to_semantic_tokens(line_map: &LineMap, hls: &[HlRange]) -> Vec<Sem
     toks
 }
 
-pub(crate) fn to_hover(line_map: &LineMap, hover: HoverResult) -> Hover {
+pub(crate) fn to_hover(
+    line_map: &LineMap,
+    hover: HoverResult,
+    paths_display: PathDisplay,
+    root_path: &Path,
+    named_schemas: bool,
+) -> Hover {
+    let HoverResult {
+        range,
+        kind,
+        name,
+        ty,
+        doc,
+        value,
+        definition: _,
+    } = hover;
+    let ty = ty.display().named_schemas(named_schemas);
+    let mut markup = match kind {
+        HoverKind::Name(kind) => {
+            let kind = match kind {
+                NameKind::LetIn => "Let binding",
+                NameKind::PlainAttrset => "Attrset attribute",
+                NameKind::RecAttrset => "Rec-attrset attribute",
+                NameKind::Param => "Parameter",
+                NameKind::PatField => "Field parameter",
+            };
+            format!("{kind} `{name}`\n`{ty}`")
+        }
+        HoverKind::WithAttr(envs, provider) => {
+            let mut markup = format!("`with` attribute `{name}`\n`{ty}`");
+            if let Some((idx, with_range)) = provider {
+                let (line, _) = line_map.line_col_for_pos(with_range.start());
+                write!(
+                    markup,
+                    "\nProvided by `with {};` at line {}",
+                    envs[idx],
+                    line + 1,
+                )
+                .unwrap();
+            }
+            markup += "\nEnvironments:";
+            for (env, i) in envs.iter().zip(1..) {
+                write!(markup, "\n{i}. `with {env};`").unwrap();
+            }
+            markup
+        }
+        HoverKind::Builtin => format!("`builtins.{name}`\n`{ty}`"),
+        HoverKind::Field => format!("Field `{name}`\n`{ty}`"),
+        HoverKind::Path(path) => {
+            let path = to_display_path(&path, paths_display, root_path);
+            format!("Path `{name}`\n`{ty}`\n\nResolved to `{path}`")
+        }
+        HoverKind::List => format!("List\n`{ty}`"),
+        HoverKind::Expr => format!("Expression\n`{ty}`"),
+    };
+    if let Some(value) = value {
+        write!(markup, "\nValue: `{value}`").unwrap();
+    }
+    if let Some(doc) = doc {
+        write!(markup, "\n\n{doc}").unwrap();
+    }
     Hover {
-        range: Some(to_range(line_map, hover.range)),
+        range: Some(to_range(line_map, range)),
         contents: lsp::HoverContents::Markup(MarkupContent {
             kind: MarkupKind::Markdown,
-            value: hover.markup,
+            value: markup,
         }),
     }
 }
 
+/// Format a resolved path according to `paths.display`.
+pub(crate) fn to_display_path(path: &VfsPath, display: PathDisplay, root_path: &Path) -> String {
+    let path = path.as_str();
+    match display {
+        PathDisplay::Absolute => {}
+        // Store paths cannot be relativized meaningfully.
+        PathDisplay::Relative if path.starts_with("/nix/store/") => {}
+        PathDisplay::Relative => {
+            if let Ok(rel) = Path::new(path).strip_prefix(root_path) {
+                return Path::new(".").join(rel).display().to_string();
+            }
+        }
+    }
+    path.to_owned()
+}
+
 pub(crate) fn to_document_symbols(
     line_map: &LineMap,
     syms: Vec<SymbolTree>,
@@ -290,9 +465,12 @@ fn to_document_symbol(line_map: &LineMap, sym: SymbolTree) -> DocumentSymbol {
     DocumentSymbol {
         name: sym.name.into(),
         detail: None,
-        kind: match sym.kind {
-            NameKind::PlainAttrset | NameKind::RecAttrset => SymbolKind::FIELD,
-            NameKind::LetIn | NameKind::Param | NameKind::PatField => SymbolKind::VARIABLE,
+        kind: match (&sym.ty, sym.kind) {
+            (Ty::Lambda(..), _) => SymbolKind::FUNCTION,
+            (Ty::Attrset(_), _) => SymbolKind::OBJECT,
+            (Ty::List(_), _) => SymbolKind::ARRAY,
+            (Ty::Unknown, NameKind::PlainAttrset | NameKind::RecAttrset) => SymbolKind::FIELD,
+            _ => SymbolKind::VARIABLE,
         },
         tags: None,
         deprecated: None,
@@ -302,13 +480,67 @@ fn to_document_symbol(line_map: &LineMap, sym: SymbolTree) -> DocumentSymbol {
     }
 }
 
+pub(crate) fn to_prefetch_hash_action(
+    vfs: &Vfs,
+    uri: Url,
+    file: FileId,
+    prefetch: FetcherPrefetch,
+) -> CodeActionOrCommand {
+    let title = "Prefetch the source and fill the hash".to_owned();
+    let args = PrefetchHashArgs {
+        uri,
+        version: vfs.doc_version(file),
+        url: prefetch.url,
+        git: prefetch.git,
+        range: to_range(&vfs.line_map_for_file(file), prefetch.range),
+        leading_space: prefetch.leading_space,
+    };
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        command: Some(lsp::Command {
+            title,
+            command: PREFETCH_HASH_COMMAND.into(),
+            arguments: Some(vec![serde_json::to_value(args).unwrap()]),
+        }),
+        diagnostics: None,
+        edit: None,
+        is_preferred: None,
+        disabled: None,
+        data: None,
+    })
+}
+
+fn to_code_action_kind(kind: AssistKind) -> CodeActionKind {
+    match kind {
+        AssistKind::QuickFix => CodeActionKind::QUICKFIX,
+        AssistKind::RefactorExtract => CodeActionKind::REFACTOR_EXTRACT,
+        AssistKind::RefactorRewrite => CodeActionKind::REFACTOR_REWRITE,
+        AssistKind::SourceOrganizeImports => CodeActionKind::SOURCE_ORGANIZE_IMPORTS,
+    }
+}
+
+/// Assist kinds requested by the `only` filter of code actions.
+/// Kinds are hierarchical, so `refactor` requests both `refactor.extract` and `refactor.rewrite`.
+pub(crate) fn from_code_action_kinds(only: &[CodeActionKind]) -> Vec<AssistKind> {
+    AssistKind::ALL
+        .into_iter()
+        .filter(|&kind| {
+            let kind = to_code_action_kind(kind);
+            only.iter().any(|requested| {
+                let requested = requested.as_str();
+                kind.as_str()
+                    .strip_prefix(requested)
+                    .map_or(false, |rest| rest.is_empty() || rest.starts_with('.'))
+            })
+        })
+        .collect()
+}
+
 pub(crate) fn to_code_action(vfs: &Vfs, assist: Assist) -> CodeActionOrCommand {
     CodeActionOrCommand::CodeAction(CodeAction {
         title: assist.label,
-        kind: Some(match assist.kind {
-            AssistKind::QuickFix => CodeActionKind::QUICKFIX,
-            AssistKind::RefactorRewrite => CodeActionKind::REFACTOR_REWRITE,
-        }),
+        kind: Some(to_code_action_kind(assist.kind)),
         diagnostics: None,
         edit: Some(to_workspace_edit(vfs, assist.edits)),
         command: None,
@@ -333,3 +565,536 @@ pub(crate) fn to_document_highlight(
         })
         .collect()
 }
+
+pub(crate) fn to_call_hierarchy_item(vfs: &Vfs, item: CallHierarchyItem) -> lsp::CallHierarchyItem {
+    let line_map = vfs.line_map_for_file(item.target.file_id);
+    lsp::CallHierarchyItem {
+        name: item.name.into(),
+        kind: SymbolKind::FUNCTION,
+        tags: None,
+        detail: None,
+        uri: vfs.uri_for_file(item.target.file_id),
+        range: to_range(&line_map, item.target.full_range),
+        selection_range: to_range(&line_map, item.target.focus_range),
+        data: None,
+    }
+}
+
+pub(crate) fn to_incoming_calls(
+    vfs: &Vfs,
+    calls: Vec<CallHierarchyCall>,
+) -> Vec<lsp::CallHierarchyIncomingCall> {
+    calls
+        .into_iter()
+        .map(|call| {
+            let line_map = vfs.line_map_for_file(call.item.target.file_id);
+            lsp::CallHierarchyIncomingCall {
+                from_ranges: call
+                    .ranges
+                    .iter()
+                    .map(|&r| to_range(&line_map, r))
+                    .collect(),
+                from: to_call_hierarchy_item(vfs, call.item),
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn to_outgoing_calls(
+    vfs: &Vfs,
+    file: FileId,
+    calls: Vec<CallHierarchyCall>,
+) -> Vec<lsp::CallHierarchyOutgoingCall> {
+    // Ranges of outgoing calls are inside the caller, not the callee.
+    let line_map = vfs.line_map_for_file(file);
+    calls
+        .into_iter()
+        .map(|call| lsp::CallHierarchyOutgoingCall {
+            from_ranges: call
+                .ranges
+                .iter()
+                .map(|&r| to_range(&line_map, r))
+                .collect(),
+            to: to_call_hierarchy_item(vfs, call.item),
+        })
+        .collect()
+}
+
+pub(crate) fn to_inline_values(
+    line_map: &LineMap,
+    values: Vec<InlineValue>,
+) -> Vec<lsp::InlineValue> {
+    values
+        .into_iter()
+        .map(|value| {
+            lsp::InlineValue::Text(lsp::InlineValueText {
+                range: to_range(line_map, value.range),
+                text: value.text,
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn to_folding_ranges(
+    line_map: &LineMap,
+    folds: Vec<FoldingRange>,
+) -> Vec<lsp::FoldingRange> {
+    folds
+        .into_iter()
+        .filter_map(|fold| {
+            let (start_line, _) = line_map.line_col_for_pos(fold.range.start());
+            let (end_line, _) = line_map.line_col_for_pos(fold.range.end());
+            // Nothing to fold inside a single line.
+            (start_line < end_line).then_some(lsp::FoldingRange {
+                start_line,
+                start_character: None,
+                end_line,
+                end_character: None,
+                kind: match fold.kind {
+                    FoldingRangeKind::Block => None,
+                    FoldingRangeKind::Comment => Some(lsp::FoldingRangeKind::Comment),
+                    FoldingRangeKind::Region => Some(lsp::FoldingRangeKind::Region),
+                },
+            })
+        })
+        .collect()
+}
+
+pub(crate) fn to_injection_ranges(
+    line_map: &LineMap,
+    injections: Vec<Injection>,
+) -> Vec<InjectionRange> {
+    injections
+        .into_iter()
+        .map(|inj| InjectionRange {
+            range: to_range(line_map, inj.range),
+            language: inj.language.as_str().into(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        from_code_action_kinds, to_completion_item, to_completion_list, to_diagnostics,
+        to_display_path, to_document_symbols, to_hover,
+    };
+    use crate::config::{CompletionCategoryWeights, PathDisplay};
+    use crate::Vfs;
+    use expect_test::{expect, Expect};
+    use ide::{
+        AnalysisHost, AssistKind, CompletionCategory, CompletionItem, CompletionItemKind,
+        DocCommentStyle, FilePos, VfsPath,
+    };
+    use lsp_types::{
+        CodeActionKind, DiagnosticTag, HoverContents, NumberOrString, SymbolKind, Url,
+    };
+    use std::path::Path;
+    use text_size::TextRange;
+
+    #[track_caller]
+    fn check_tags(
+        src: &str,
+        tag_support: &[DiagnosticTag],
+        expect: &[(&str, Option<Vec<DiagnosticTag>>)],
+    ) {
+        let (analysis, file) = AnalysisHost::new_single_file(src);
+        let diags = analysis.snapshot().diagnostics(file).unwrap();
+        let mut vfs = Vfs::new();
+        let vfs_file = vfs
+            .set_path_content(VfsPath::new("/default.nix").unwrap(), src.into())
+            .unwrap();
+        let uri = Url::parse("file:///default.nix").unwrap();
+        let got = to_diagnostics(
+            &uri,
+            file,
+            &vfs.line_map_for_file(vfs_file),
+            &diags,
+            tag_support,
+        )
+        .into_iter()
+        .map(|diag| {
+            let Some(NumberOrString::String(code)) = diag.code else { panic!("No code") };
+            (code, diag.tags)
+        })
+        .collect::<Vec<_>>();
+        let expect = expect
+            .iter()
+            .map(|(code, tags)| (code.to_string(), tags.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(got, expect);
+    }
+
+    #[track_caller]
+    fn check_hover(fixture: &str, expect: Expect) {
+        let pos = fixture.find("$0").expect("No marker");
+        let src = fixture.replace("$0", "");
+        let (analysis, file) = AnalysisHost::new_single_file(&src);
+        let fpos = FilePos::new(file, (pos as u32).into());
+        let hover = analysis
+            .snapshot()
+            .hover(fpos, DocCommentStyle::Plain)
+            .unwrap()
+            .expect("No hover");
+        let mut vfs = Vfs::new();
+        let vfs_file = vfs
+            .set_path_content(VfsPath::new("/default.nix").unwrap(), src)
+            .unwrap();
+        let line_map = vfs.line_map_for_file(vfs_file);
+        let hover = to_hover(
+            &line_map,
+            hover,
+            PathDisplay::Relative,
+            Path::new("/"),
+            false,
+        );
+        let HoverContents::Markup(markup) = hover.contents else { panic!("Not markup") };
+        expect.assert_eq(&(markup.value + "\n"));
+    }
+
+    #[test]
+    fn hover_name() {
+        check_hover(
+            "let $0a = 1; in a",
+            expect![[r#"
+                Let binding `a`
+                `int`
+            "#]],
+        );
+        check_hover(
+            "{ a }: $0a",
+            expect![[r#"
+                Field parameter `a`
+                `?`
+            "#]],
+        );
+        check_hover(
+            "let size = 8 * 1024; in $0size",
+            expect![[r#"
+                Let binding `size`
+                `int`
+                Value: `8192`
+            "#]],
+        );
+    }
+
+    #[test]
+    fn hover_with_attr() {
+        check_hover(
+            "with 1; with 2; $0a",
+            expect![[r#"
+                `with` attribute `a`
+                `?`
+                Environments:
+                1. `with 2;`
+                2. `with 1;`
+            "#]],
+        );
+        check_hover(
+            "let outer = { a = 1; }; inner = { b = 2; };\nin with outer; with inner; $0a",
+            expect![[r#"
+                `with` attribute `a`
+                `int`
+                Provided by `with outer;` at line 2
+                Environments:
+                1. `with inner;`
+                2. `with outer;`
+            "#]],
+        );
+    }
+
+    #[test]
+    fn hover_builtin() {
+        check_hover(
+            "$0true",
+            expect![[r#"
+                `builtins.true`
+                `bool`
+
+                `builtins.true`
+                (No documentation from Nix)
+            "#]],
+        );
+    }
+
+    #[test]
+    fn hover_field() {
+        check_hover(
+            "let foo.bar = 1; in foo.$0bar",
+            expect![[r#"
+                Field `bar`
+                `int`
+            "#]],
+        );
+    }
+
+    #[test]
+    fn hover_path() {
+        check_hover(
+            "$0./foo.nix",
+            expect![[r#"
+                Path `./foo.nix`
+                `path`
+
+                Resolved to `./foo.nix`
+            "#]],
+        );
+    }
+
+    #[test]
+    fn hover_list_and_expr() {
+        check_hover(
+            "$0[ 1 2 ]",
+            expect![[r#"
+                List
+                `[int]`
+            "#]],
+        );
+        check_hover(
+            "8 $0* 1024",
+            expect![[r#"
+                Expression
+                `int`
+                Value: `8192`
+            "#]],
+        );
+    }
+
+    #[test]
+    fn code_action_kinds() {
+        let kinds = |only: &[&str]| {
+            let only = only
+                .iter()
+                .map(|&s| CodeActionKind::from(s.to_owned()))
+                .collect::<Vec<_>>();
+            from_code_action_kinds(&only)
+        };
+        assert_eq!(
+            kinds(&["source.organizeImports"]),
+            [AssistKind::SourceOrganizeImports]
+        );
+        assert_eq!(
+            kinds(&["refactor", "quickfix"]),
+            [
+                AssistKind::QuickFix,
+                AssistKind::RefactorExtract,
+                AssistKind::RefactorRewrite,
+            ],
+        );
+        assert!(kinds(&["refactor.inline", "sourc"]).is_empty());
+    }
+
+    #[test]
+    fn diagnostic_tags() {
+        let all_tags = [DiagnosticTag::UNNECESSARY, DiagnosticTag::DEPRECATED];
+        check_tags(
+            "let { body = 1; }",
+            &all_tags,
+            &[("let_attrset", Some(vec![DiagnosticTag::DEPRECATED]))],
+        );
+        check_tags(
+            "let a = 1; in 2",
+            &all_tags,
+            &[("unused_binding", Some(vec![DiagnosticTag::UNNECESSARY]))],
+        );
+        check_tags("a", &all_tags, &[("undefined_name", None)]);
+    }
+
+    #[test]
+    fn diagnostic_tags_unsupported() {
+        check_tags("let a = 1; in 2", &[], &[("unused_binding", None)]);
+        check_tags(
+            "let a = 1; in 2",
+            &[DiagnosticTag::DEPRECATED],
+            &[("unused_binding", None)],
+        );
+    }
+
+    #[test]
+    fn completion_list_truncate() {
+        let src = "fo";
+        let mut vfs = Vfs::new();
+        let file = vfs
+            .set_path_content(VfsPath::new("/default.nix").unwrap(), src.into())
+            .unwrap();
+        let line_map = vfs.line_map_for_file(file);
+        let items = ["afoo", "flo", "foo", "foobar", "fromTOML"]
+            .into_iter()
+            .map(|label| CompletionItem {
+                label: label.into(),
+                source_range: TextRange::up_to(2.into()),
+                replace: label.into(),
+                kind: CompletionItemKind::LetBinding,
+                category: CompletionCategory::Local,
+                brief: None,
+                doc: None,
+                call_snippet: None,
+            })
+            .collect::<Vec<_>>();
+        let labels = |list: &lsp_types::CompletionList| {
+            list.items
+                .iter()
+                .map(|item| item.label.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let weights = CompletionCategoryWeights::default();
+
+        let list = to_completion_list(&line_map, src, items.clone(), 5, &weights, false);
+        assert!(!list.is_incomplete);
+        assert_eq!(labels(&list), ["afoo", "flo", "foo", "foobar", "fromTOML"]);
+
+        let list = to_completion_list(&line_map, src, items.clone(), 3, &weights, false);
+        assert!(list.is_incomplete);
+        assert_eq!(labels(&list), ["foo", "foobar", "afoo"]);
+    }
+
+    #[test]
+    fn completion_sort_text() {
+        let src = "fo";
+        let mut vfs = Vfs::new();
+        let file = vfs
+            .set_path_content(VfsPath::new("/default.nix").unwrap(), src.into())
+            .unwrap();
+        let line_map = vfs.line_map_for_file(file);
+        let items = [
+            ("foo", CompletionCategory::Schema),
+            ("fromTOML", CompletionCategory::Builtin),
+            ("afoo", CompletionCategory::Local),
+            ("foobar", CompletionCategory::Local),
+            ("fooz", CompletionCategory::Local),
+        ]
+        .into_iter()
+        .map(|(label, category)| CompletionItem {
+            label: label.into(),
+            source_range: TextRange::up_to(2.into()),
+            replace: label.into(),
+            kind: CompletionItemKind::Field,
+            category,
+            brief: None,
+            doc: None,
+            call_snippet: None,
+        })
+        .collect::<Vec<_>>();
+        let sorted = |weights: &CompletionCategoryWeights| {
+            let mut list =
+                to_completion_list(&line_map, src, items.clone(), 10, weights, false).items;
+            list.sort_by(|lhs, rhs| lhs.sort_text.cmp(&rhs.sort_text));
+            list.into_iter().map(|item| item.label).collect::<Vec<_>>()
+        };
+
+        // Categories first, then prefix matches, then shorter labels.
+        assert_eq!(
+            sorted(&CompletionCategoryWeights::default()),
+            ["fooz", "foobar", "afoo", "fromTOML", "foo"],
+        );
+        let weights = CompletionCategoryWeights {
+            schema: 50,
+            ..CompletionCategoryWeights::default()
+        };
+        assert_eq!(
+            sorted(&weights),
+            ["foo", "fooz", "foobar", "afoo", "fromTOML"]
+        );
+    }
+
+    #[test]
+    fn completion_auto_insert_parens() {
+        let mut vfs = Vfs::new();
+        let file = vfs
+            .set_path_content(VfsPath::new("/default.nix").unwrap(), "f".into())
+            .unwrap();
+        let line_map = vfs.line_map_for_file(file);
+        let item = CompletionItem {
+            label: "foo".into(),
+            source_range: TextRange::up_to(1.into()),
+            replace: "foo".into(),
+            kind: CompletionItemKind::LetBinding,
+            category: CompletionCategory::Local,
+            brief: None,
+            doc: None,
+            call_snippet: Some("foo $1 $2".into()),
+        };
+        let new_text = |item: lsp_types::CompletionItem| {
+            let Some(lsp_types::CompletionTextEdit::Edit(edit)) = item.text_edit else { panic!() };
+            (edit.new_text, item.insert_text_format.unwrap())
+        };
+
+        let got = new_text(to_completion_item(&line_map, item.clone(), false));
+        assert_eq!(got, ("foo".into(), lsp_types::InsertTextFormat::PLAIN_TEXT));
+        let got = new_text(to_completion_item(&line_map, item, true));
+        assert_eq!(
+            got,
+            ("foo $1 $2".into(), lsp_types::InsertTextFormat::SNIPPET)
+        );
+    }
+
+    #[test]
+    fn display_path() {
+        let root = Path::new("/home/user/proj");
+        let show =
+            |path: &str, display| to_display_path(&VfsPath::new(path).unwrap(), display, root);
+
+        assert_eq!(
+            show("/home/user/proj/sub/foo.nix", PathDisplay::Relative),
+            "./sub/foo.nix",
+        );
+        assert_eq!(
+            show("/home/user/proj/sub/foo.nix", PathDisplay::Absolute),
+            "/home/user/proj/sub/foo.nix",
+        );
+        // Outside the workspace.
+        assert_eq!(
+            show("/home/user/other.nix", PathDisplay::Relative),
+            "/home/user/other.nix",
+        );
+        assert_eq!(
+            show("/nix/store/eeee-source/flake.nix", PathDisplay::Relative),
+            "/nix/store/eeee-source/flake.nix",
+        );
+    }
+
+    #[test]
+    fn document_symbol_kinds() {
+        let kinds = |src: &str| {
+            let (analysis, file) = AnalysisHost::new_single_file(src);
+            let syms = analysis.snapshot().symbol_hierarchy(file).unwrap();
+            let mut vfs = Vfs::new();
+            let vfs_file = vfs
+                .set_path_content(VfsPath::new("/default.nix").unwrap(), src.into())
+                .unwrap();
+            to_document_symbols(&vfs.line_map_for_file(vfs_file), syms)
+                .into_iter()
+                .map(|sym| (sym.name, sym.kind))
+                .collect::<Vec<_>>()
+        };
+        let expect = |expect: &[(&str, SymbolKind)]| {
+            expect
+                .iter()
+                .map(|&(name, kind)| (name.to_owned(), kind))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            kinds("{ f = x: x; set = { a = 1; }; list = [ ]; n = 1; unknown = g; }"),
+            expect(&[
+                ("f", SymbolKind::FUNCTION),
+                ("set", SymbolKind::OBJECT),
+                ("list", SymbolKind::ARRAY),
+                ("n", SymbolKind::VARIABLE),
+                ("unknown", SymbolKind::FIELD),
+            ]),
+        );
+        // Kinds come from inferred types, not the syntax of values.
+        assert_eq!(
+            kinds("let f = x: x; g = f; set = rec { }; h = set.a or 1; unknown = x; in g"),
+            expect(&[
+                ("f", SymbolKind::FUNCTION),
+                ("g", SymbolKind::FUNCTION),
+                ("set", SymbolKind::OBJECT),
+                ("h", SymbolKind::VARIABLE),
+                ("unknown", SymbolKind::VARIABLE),
+            ]),
+        );
+    }
+}
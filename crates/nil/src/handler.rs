@@ -1,16 +1,27 @@
-use crate::{convert, StateSnapshot};
+use crate::config::EXPERIMENTAL_FEATURES;
+use crate::lsp_ext::{FormatStringParams, InjectionRange, InjectionsParams, VersionInfo};
+use crate::{convert, LspError, StateSnapshot, UrlExt, Vfs};
 use anyhow::{ensure, Context, Result};
-use ide::{FileRange, GotoDefinitionResult, LinkTarget};
+use ide::{
+    AssistKind, DiagnosticKind, FileId, FilePos, FileRange, GotoDefinitionResult, Injection,
+    LinkTarget,
+};
+use lsp_server::ErrorCode;
 use lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyIncomingCallsParams, CallHierarchyItem,
+    CallHierarchyOutgoingCall, CallHierarchyOutgoingCallsParams, CallHierarchyPrepareParams,
     CodeActionParams, CodeActionResponse, CompletionParams, CompletionResponse, Diagnostic,
     DocumentFormattingParams, DocumentHighlight, DocumentHighlightParams, DocumentLink,
-    DocumentLinkParams, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams,
-    GotoDefinitionResponse, Hover, HoverParams, Location, Position, PrepareRenameResponse, Range,
-    ReferenceParams, RenameParams, SelectionRange, SelectionRangeParams, SemanticTokens,
-    SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
-    SemanticTokensResult, TextDocumentPositionParams, TextEdit, Url, WorkspaceEdit,
+    DocumentLinkParams, DocumentSymbolParams, DocumentSymbolResponse, FoldingRange,
+    FoldingRangeParams, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams,
+    InlineValue, InlineValueParams, Location, Position, PrepareRenameResponse, Range,
+    ReferenceParams, RenameFilesParams, RenameParams, SelectionRange, SelectionRangeParams,
+    SemanticTokens, SemanticTokensParams, SemanticTokensRangeParams, SemanticTokensRangeResult,
+    SemanticTokensResult, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit, Url,
+    WillSaveTextDocumentParams, WorkspaceEdit,
 };
 use nix_interop::DEFAULT_IMPORT_FILE;
+use std::io::ErrorKind;
 use std::path::Path;
 use std::process;
 use std::sync::Arc;
@@ -25,9 +36,33 @@ pub(crate) fn diagnostics(snap: StateSnapshot, uri: &Url) -> Result<Vec<Diagnost
         (file, vfs.line_map_for_file(file))
     };
     let mut diags = snap.analysis.diagnostics(file)?;
-    diags.retain(|diag| !snap.config.diagnostics_ignored.contains(diag.code()));
+    if let Some(max_width) = snap.config.diagnostics_max_line_width {
+        diags.extend(
+            line_map
+                .long_lines(max_width)
+                .map(|range| ide::Diagnostic::new(range, DiagnosticKind::LongLine)),
+        );
+    }
+    if let Some(max_depth) = snap.config.diagnostics_max_nesting_depth {
+        diags.extend(snap.analysis.deep_nesting_diagnostics(file, max_depth)?);
+    }
+    if snap.config.diagnostics_broken_bindings {
+        diags.extend(snap.analysis.broken_binding_diagnostics(file)?);
+    }
+    diags.retain(|diag| {
+        !snap.config.diagnostics_ignored.contains(diag.code())
+            && (snap.config.diagnostics_top_level_with || diag.kind != DiagnosticKind::TopLevelWith)
+            && (snap.config.diagnostics_flake_purity || diag.kind != DiagnosticKind::ImpureBuiltin)
+    });
+    ide::elevate_diagnostics(&mut diags, &snap.config.diagnostics_error_on);
     diags.truncate(MAX_DIAGNOSTICS_CNT);
-    Ok(convert::to_diagnostics(uri, file, &line_map, &diags))
+    Ok(convert::to_diagnostics(
+        uri,
+        file,
+        &line_map,
+        &diags,
+        &snap.config.diagnostics_tag_support,
+    ))
 }
 
 pub(crate) fn goto_definition(
@@ -54,7 +89,18 @@ pub(crate) fn goto_definition(
                 range: Range::default(),
             }]
         }
-        Some(GotoDefinitionResult::Targets(targets)) => targets
+        // Multiple targets of merged attrsets are shown in a picker by the client.
+        Some(GotoDefinitionResult::Targets { origin, targets })
+            if snap.config.definition_link_support =>
+        {
+            let origin = FileRange::new(fpos.file_id, origin);
+            let links = targets
+                .into_iter()
+                .map(|target| convert::to_location_link(&vfs, origin, target))
+                .collect();
+            return Ok(Some(GotoDefinitionResponse::Link(links)));
+        }
+        Some(GotoDefinitionResult::Targets { targets, .. }) => targets
             .into_iter()
             .map(|target| {
                 convert::to_location(&vfs, FileRange::new(target.file_id, target.focus_range))
@@ -85,19 +131,37 @@ pub(crate) fn completion(
     snap: StateSnapshot,
     params: CompletionParams,
 ) -> Result<Option<CompletionResponse>> {
-    let (fpos, line_map) = convert::from_file_pos(&snap.vfs(), &params.text_document_position)?;
+    // The Vfs may be changed concurrently. Take the content along with the line map.
+    let (fpos, line_map, src) = {
+        let vfs = snap.vfs();
+        let (fpos, line_map) = convert::from_file_pos(&vfs, &params.text_document_position)?;
+        (fpos, line_map, vfs.content_for_file(fpos.file_id))
+    };
     let trigger_char = params
         .context
         .and_then(|ctx| ctx.trigger_character?.chars().next());
-    let items = match snap.analysis.completions(fpos, trigger_char)? {
+    let mut items = match snap.analysis.completions(fpos, trigger_char)? {
         None => return Ok(None),
         Some(items) => items,
     };
-    let items = items
-        .into_iter()
-        .map(|item| convert::to_completion_item(&line_map, item))
-        .collect::<Vec<_>>();
-    Ok(Some(CompletionResponse::Array(items)))
+    let matching = &snap.config.completion_matching;
+    // Ranges may be stale if the file is changed after the analysis snapshot is taken.
+    items.retain(|item| {
+        let range = item.source_range;
+        src.get(usize::from(range.start())..usize::from(range.end()))
+            .map_or(false, |input| matching.matches(input, &item.label))
+    });
+    let max_items = snap.config.completion_max_items;
+    let weights = &snap.config.completion_category_weights;
+    let list = convert::to_completion_list(
+        &line_map,
+        &src,
+        items,
+        max_items,
+        weights,
+        snap.config.completion_auto_insert_parens,
+    );
+    Ok(Some(CompletionResponse::List(list)))
 }
 
 pub(crate) fn selection_range(
@@ -157,13 +221,35 @@ pub(crate) fn rename(snap: StateSnapshot, params: RenameParams) -> Result<Option
     Ok(Some(resp))
 }
 
+pub(crate) fn will_rename_files(
+    snap: StateSnapshot,
+    params: RenameFilesParams,
+) -> Result<Option<WorkspaceEdit>> {
+    let mut renames = Vec::new();
+    for rename in &params.files {
+        // Files not loaded, or directories.
+        let Ok(file) = snap.vfs().file_for_uri(&Url::parse(&rename.old_uri)?) else { continue };
+        let new_path = Url::parse(&rename.new_uri)?.to_vfs_path()?;
+        renames.push((file, new_path));
+    }
+    // All renames are applied at once, since paths between moved files depend on each other.
+    let ws_edit = snap.analysis.rename_files(&renames)?;
+    if ws_edit.content_edits.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(convert::to_workspace_edit(&snap.vfs(), ws_edit)))
+}
+
 pub(crate) fn semantic_token_full(
     snap: StateSnapshot,
     params: SemanticTokensParams,
 ) -> Result<Option<SemanticTokensResult>> {
     let (file, line_map) = convert::from_file(&snap.vfs(), &params.text_document)?;
-    let hls = snap.analysis.syntax_highlight(file, None)?;
-    let toks = convert::to_semantic_tokens(&line_map, &hls);
+    let hls = snap
+        .analysis
+        .syntax_highlight(file, None, snap.config.injections_enable)?;
+    let injections = file_injections(&snap, file)?;
+    let toks = convert::to_semantic_tokens(&line_map, &hls, &injections);
     Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
         result_id: None,
         data: toks,
@@ -180,19 +266,82 @@ pub(crate) fn semantic_token_range(
         let (_, range) = convert::from_range(&vfs, file, params.range)?;
         (file, range, line_map)
     };
-    let hls = snap.analysis.syntax_highlight(file, Some(range))?;
-    let toks = convert::to_semantic_tokens(&line_map, &hls);
+    let hls = snap
+        .analysis
+        .syntax_highlight(file, Some(range), snap.config.injections_enable)?;
+    let injections = file_injections(&snap, file)?;
+    let toks = convert::to_semantic_tokens(&line_map, &hls, &injections);
     Ok(Some(SemanticTokensRangeResult::Tokens(SemanticTokens {
         result_id: None,
         data: toks,
     })))
 }
 
+fn file_injections(snap: &StateSnapshot, file: FileId) -> Result<Vec<Injection>> {
+    if !snap.config.injections_enable {
+        return Ok(Vec::new());
+    }
+    Ok(snap.analysis.injections(file)?)
+}
+
+pub(crate) fn injections(
+    snap: StateSnapshot,
+    params: InjectionsParams,
+) -> Result<Vec<InjectionRange>> {
+    let (file, line_map) = convert::from_file(&snap.vfs(), &params.text_document)?;
+    let injections = file_injections(&snap, file)?;
+    Ok(convert::to_injection_ranges(&line_map, injections))
+}
+
+pub(crate) fn attr_path_at(
+    snap: StateSnapshot,
+    params: TextDocumentPositionParams,
+) -> Result<Option<String>> {
+    let (fpos, _) = convert::from_file_pos(&snap.vfs(), &params)?;
+    Ok(snap.analysis.attr_path_at(fpos)?)
+}
+
 pub(crate) fn hover(snap: StateSnapshot, params: HoverParams) -> Result<Option<Hover>> {
     let (fpos, line_map) =
         convert::from_file_pos(&snap.vfs(), &params.text_document_position_params)?;
-    let ret = snap.analysis.hover(fpos)?;
-    Ok(ret.map(|hover| convert::to_hover(&line_map, hover)))
+    let ret = snap.analysis.hover(fpos, snap.config.doc_comment_style)?;
+    Ok(ret.map(|hover| {
+        convert::to_hover(
+            &line_map,
+            hover,
+            snap.config.paths_display,
+            &snap.config.root_path,
+            snap.config.hover_named_schemas,
+        )
+    }))
+}
+
+pub(crate) fn folding_range(
+    snap: StateSnapshot,
+    params: FoldingRangeParams,
+) -> Result<Option<Vec<FoldingRange>>> {
+    let (file, line_map) = convert::from_file(&snap.vfs(), &params.text_document)?;
+    let folds = snap
+        .analysis
+        .folding_ranges(file, &snap.config.folding_region_markers)?;
+    Ok(Some(convert::to_folding_ranges(&line_map, folds)))
+}
+
+pub(crate) fn inline_value(
+    snap: StateSnapshot,
+    params: InlineValueParams,
+) -> Result<Option<Vec<InlineValue>>> {
+    if !snap.config.inline_value_enable {
+        return Ok(None);
+    }
+    let (file, range, line_map) = {
+        let vfs = snap.vfs();
+        let (file, line_map) = convert::from_file(&vfs, &params.text_document)?;
+        let (_, range) = convert::from_range(&vfs, file, params.range)?;
+        (file, range, line_map)
+    };
+    let values = snap.analysis.inline_values(FileRange::new(file, range))?;
+    Ok(Some(convert::to_inline_values(&line_map, values)))
 }
 
 pub(crate) fn document_symbol(
@@ -210,31 +359,24 @@ pub(crate) fn formatting(
     snap: StateSnapshot,
     params: DocumentFormattingParams,
 ) -> Result<Option<Vec<TextEdit>>> {
-    fn run_with_stdin(
-        cmd: &[String],
-        stdin_data: impl AsRef<[u8]> + Send + 'static,
-    ) -> Result<String> {
-        let mut child = process::Command::new(&cmd[0])
-            .args(&cmd[1..])
-            .stdin(process::Stdio::piped())
-            .stdout(process::Stdio::piped())
-            .stderr(process::Stdio::piped())
-            .spawn()?;
-        let mut stdin = child.stdin.take().unwrap();
-        std::thread::spawn(move || {
-            let _ = std::io::copy(&mut stdin_data.as_ref(), &mut stdin);
-        });
-        let output = child.wait_with_output()?;
-        ensure!(
-            output.status.success(),
-            "Formatter exited with {}, stderr: {}",
-            output.status,
-            String::from_utf8_lossy(&output.stderr),
-        );
-        let stdout = String::from_utf8(output.stdout)?;
-        Ok(stdout)
+    format_document(&snap, &params.text_document)
+}
+
+/// Format the document before saving, if `formatting.onSave` is enabled.
+pub(crate) fn will_save_wait_until(
+    snap: StateSnapshot,
+    params: WillSaveTextDocumentParams,
+) -> Result<Option<Vec<TextEdit>>> {
+    if !snap.config.formatting_on_save {
+        return Ok(None);
     }
+    format_document(&snap, &params.text_document)
+}
 
+fn format_document(
+    snap: &StateSnapshot,
+    doc: &TextDocumentIdentifier,
+) -> Result<Option<Vec<TextEdit>>> {
     let cmd = match &snap.config.formatting_command {
         Some(cmd) => cmd,
         None => return Ok(None),
@@ -242,16 +384,19 @@ pub(crate) fn formatting(
 
     let (file_content, line_map) = {
         let vfs = snap.vfs();
-        let (file, line_map) = convert::from_file(&vfs, &params.text_document)?;
+        let (file, line_map) = convert::from_file(&vfs, doc)?;
         (vfs.content_for_file(file), line_map)
     };
 
-    let new_content = run_with_stdin(cmd, <Arc<[u8]>>::from(file_content.clone()))
+    let new_content = run_formatter(cmd, <Arc<[u8]>>::from(file_content.clone()))
         .with_context(|| format!("Failed to run formatter {cmd:?}"))?;
 
     if new_content == *file_content {
         return Ok(None);
     }
+    if snap.config.formatting_verify_idempotent && !is_idempotent(cmd, &new_content)? {
+        return Ok(None);
+    }
 
     // Replace the whole file.
     let last_line = line_map.last_line();
@@ -270,6 +415,73 @@ pub(crate) fn formatting(
     }]))
 }
 
+pub(crate) fn format_string(snap: StateSnapshot, params: FormatStringParams) -> Result<String> {
+    let cmd = snap
+        .config
+        .formatting_command
+        .as_ref()
+        .ok_or_else(|| LspError {
+            code: ErrorCode::InvalidRequest,
+            message: "No formatter is configured".into(),
+        })?;
+    let new_content = run_formatter(cmd, params.text).map_err(|err| {
+        err.downcast::<LspError>().unwrap_or_else(|err| LspError {
+            code: ErrorCode::RequestFailed,
+            message: format!("Failed to run formatter {cmd:?}: {err}"),
+        })
+    })?;
+    Ok(new_content)
+}
+
+/// Check if formatting the output of the formatter again changes nothing.
+/// Edits from buggy formatters may corrupt the file or oscillate on each formatting otherwise.
+fn is_idempotent(cmd: &[String], formatted: &str) -> Result<bool> {
+    let reformatted = run_formatter(cmd, formatted.to_owned())
+        .with_context(|| format!("Failed to run formatter {cmd:?}"))?;
+    if reformatted != formatted {
+        tracing::warn!("Formatter {cmd:?} is not idempotent, declining its edit");
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Run the formatter command on the data.
+/// A missing formatter binary is reported as an [`LspError`] telling how to fix it.
+fn run_formatter(cmd: &[String], stdin_data: impl AsRef<[u8]> + Send + 'static) -> Result<String> {
+    let mut child = process::Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .stdin(process::Stdio::piped())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::piped())
+        .spawn()
+        .map_err(|err| -> anyhow::Error {
+            if err.kind() != ErrorKind::NotFound {
+                return err.into();
+            }
+            LspError {
+                code: ErrorCode::RequestFailed,
+                message: format!(
+                    "Formatter `{}` not found; install it or set `formatting.command`",
+                    cmd[0],
+                ),
+            }
+            .into()
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    std::thread::spawn(move || {
+        let _ = std::io::copy(&mut stdin_data.as_ref(), &mut stdin);
+    });
+    let output = child.wait_with_output()?;
+    ensure!(
+        output.status.success(),
+        "Formatter exited with {}, stderr: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(stdout)
+}
+
 pub(crate) fn document_links(
     snap: StateSnapshot,
     params: DocumentLinkParams,
@@ -279,10 +491,16 @@ pub(crate) fn document_links(
     let links = links
         .into_iter()
         .filter_map(|link| {
+            let mut tooltip = link.tooltip;
             let uri = match link.target {
                 LinkTarget::Uri(uri) => uri,
                 // FIXME: Duplicated with `goto_definition`.
                 LinkTarget::VfsPath(vpath) => {
+                    tooltip = convert::to_display_path(
+                        &vpath,
+                        snap.config.paths_display,
+                        &snap.config.root_path,
+                    );
                     let path = Path::new(vpath.as_str());
                     let default_child = path.join(DEFAULT_IMPORT_FILE);
                     let target_path = if path.is_file() {
@@ -298,7 +516,7 @@ pub(crate) fn document_links(
             Some(DocumentLink {
                 range: convert::to_range(&line_map, link.range),
                 target: Some(uri),
-                tooltip: Some(link.tooltip),
+                tooltip: Some(tooltip),
                 data: None,
             })
         })
@@ -312,12 +530,28 @@ pub(crate) fn code_action(
 ) -> Result<Option<CodeActionResponse>> {
     let (file_id, _) = convert::from_file(&snap.vfs(), &params.text_document)?;
     let (_, range) = convert::from_range(&snap.vfs(), file_id, params.range)?;
-    let assists = snap.analysis.assists(FileRange { file_id, range })?;
+    let frange = FileRange { file_id, range };
+    // Skip computing unrequested kinds, eg. refactors when only `quickfix` is requested on save.
+    let only = params
+        .context
+        .only
+        .as_deref()
+        .map(convert::from_code_action_kinds);
+    let assists = snap.analysis.assists(frange, only.as_deref())?;
     let vfs = snap.vfs();
-    let actions = assists
+    let mut actions = assists
         .into_iter()
         .map(|assist| convert::to_code_action(&vfs, assist))
-        .collect();
+        .collect::<Vec<_>>();
+    // The prefetch action is a quickfix.
+    if only.map_or(true, |kinds| kinds.contains(&AssistKind::QuickFix)) {
+        if let Some(prefetch) = snap.analysis.fetcher_prefetch(frange)? {
+            let uri = params.text_document.uri.clone();
+            actions.push(convert::to_prefetch_hash_action(
+                &vfs, uri, file_id, prefetch,
+            ));
+        }
+    }
     Ok(Some(actions))
 }
 
@@ -331,3 +565,136 @@ pub(crate) fn document_highlight(
     let ret = convert::to_document_highlight(&line_map, &ret);
     Ok(Some(ret))
 }
+
+pub(crate) fn prepare_call_hierarchy(
+    snap: StateSnapshot,
+    params: CallHierarchyPrepareParams,
+) -> Result<Option<Vec<CallHierarchyItem>>> {
+    let (fpos, _) = convert::from_file_pos(&snap.vfs(), &params.text_document_position_params)?;
+    let top_level_only = snap.config.call_hierarchy_top_level_only;
+    let Some(item) = snap.analysis.prepare_call_hierarchy(fpos, top_level_only)? else {
+        return Ok(None);
+    };
+    Ok(Some(vec![convert::to_call_hierarchy_item(
+        &snap.vfs(),
+        item,
+    )]))
+}
+
+pub(crate) fn incoming_calls(
+    snap: StateSnapshot,
+    params: CallHierarchyIncomingCallsParams,
+) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+    let vfs = snap.vfs();
+    let fpos = from_call_hierarchy_item(&vfs, &params.item)?;
+    let top_level_only = snap.config.call_hierarchy_top_level_only;
+    let ret = snap.analysis.incoming_calls(fpos, top_level_only)?;
+    Ok(ret.map(|calls| convert::to_incoming_calls(&vfs, calls)))
+}
+
+pub(crate) fn outgoing_calls(
+    snap: StateSnapshot,
+    params: CallHierarchyOutgoingCallsParams,
+) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+    let vfs = snap.vfs();
+    let fpos = from_call_hierarchy_item(&vfs, &params.item)?;
+    let top_level_only = snap.config.call_hierarchy_top_level_only;
+    let ret = snap.analysis.outgoing_calls(fpos, top_level_only)?;
+    Ok(ret.map(|calls| convert::to_outgoing_calls(&vfs, fpos.file_id, calls)))
+}
+
+/// Lines describing which remaining files import each of the deleted files.
+pub(crate) fn dangling_imports(snap: StateSnapshot, uris: &[String]) -> Result<Vec<String>> {
+    let vfs = snap.vfs();
+    let deleted = uris
+        .iter()
+        // Files not loaded, or directories.
+        .filter_map(|uri| vfs.file_for_uri(&Url::parse(uri).ok()?).ok())
+        .collect::<Vec<_>>();
+    let display_path = |file| {
+        let path = convert::to_display_path(
+            vfs.path_for_file(file),
+            snap.config.paths_display,
+            &snap.config.root_path,
+        );
+        format!("`{path}`")
+    };
+
+    let mut lines = Vec::new();
+    for &file in &deleted {
+        let importers = snap
+            .analysis
+            .importers(file)?
+            .into_iter()
+            .filter(|importer| !deleted.contains(importer))
+            .map(&display_path)
+            .collect::<Vec<_>>();
+        if !importers.is_empty() {
+            let importers = importers.join(", ");
+            lines.push(format!("{} is imported by {importers}", display_path(file)));
+        }
+    }
+    Ok(lines)
+}
+
+/// Spawning `nix --version` may be slow, so it is not done on the main loop.
+pub(crate) fn version(snap: StateSnapshot, (): ()) -> Result<VersionInfo> {
+    // Nix-built releases have `CFG_RELEASE` set to `git-<rev>`.
+    let git_commit = option_env!("CFG_RELEASE")
+        .and_then(|release| release.strip_prefix("git-"))
+        .map(Into::into);
+    let features = EXPERIMENTAL_FEATURES
+        .iter()
+        .filter(|&&(name, ..)| snap.config.experimental(name))
+        .map(|&(name, ..)| name.into())
+        .collect::<Vec<_>>();
+    let nix_version = match snap.evaluator.nix_version() {
+        Ok(ver) => Some(ver),
+        Err(err) => {
+            tracing::warn!("Failed to get the Nix version: {err:#}");
+            None
+        }
+    };
+    Ok(VersionInfo {
+        version: env!("CARGO_PKG_VERSION").into(),
+        git_commit,
+        features,
+        nix_version,
+    })
+}
+
+/// Items are identified by the start of their selection ranges.
+fn from_call_hierarchy_item(vfs: &Vfs, item: &CallHierarchyItem) -> Result<FilePos> {
+    let file = vfs.file_for_uri(&item.uri)?;
+    let line_map = vfs.line_map_for_file(file);
+    let pos = convert::from_pos(&line_map, item.selection_range.start)?;
+    Ok(FilePos::new(file, pos))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_idempotent, run_formatter};
+    use crate::LspError;
+
+    #[test]
+    fn formatter_idempotence() {
+        let cmd = ["cat".to_owned()];
+        assert!(is_idempotent(&cmd, "{ }\n").unwrap());
+
+        // Appends a line on every run.
+        let cmd = ["sh", "-c", "cat; echo"].map(String::from);
+        assert!(!is_idempotent(&cmd, "{ }\n").unwrap());
+    }
+
+    #[test]
+    fn formatter_not_found() {
+        let cmd = ["nil-test-missing-formatter".to_owned()];
+        let err = run_formatter(&cmd, "{ }").unwrap_err();
+        let err = err.downcast_ref::<LspError>().expect("Not an LspError");
+        assert_eq!(
+            err.to_string(),
+            "Formatter `nil-test-missing-formatter` not found; \
+            install it or set `formatting.command`",
+        );
+    }
+}
@@ -2,17 +2,20 @@ mod capabilities;
 mod config;
 mod convert;
 mod handler;
+mod lsp_ext;
 mod semantic_tokens;
 mod server;
+mod timing;
 mod vfs;
 
 use anyhow::{anyhow, Result};
 use ide::VfsPath;
 use lsp_server::{Connection, ErrorCode};
-use lsp_types::{InitializeParams, Url};
+use lsp_types::{InitializeParams, InitializeResult, Url};
 use std::fmt;
 
 pub(crate) use server::{Server, StateSnapshot};
+pub use timing::TimingLayer;
 pub(crate) use vfs::{LineMap, Vfs};
 
 #[derive(Debug)]
@@ -44,11 +47,15 @@ impl UrlExt for Url {
 }
 
 pub fn main_loop(conn: Connection) -> Result<()> {
-    let init_params =
-        conn.initialize(serde_json::to_value(capabilities::server_capabilities()).unwrap())?;
+    let (init_id, init_params) = conn.initialize_start()?;
     tracing::info!("Init params: {}", init_params);
 
     let init_params = serde_json::from_value::<InitializeParams>(init_params)?;
+    let init_result = InitializeResult {
+        capabilities: capabilities::server_capabilities(&init_params.capabilities),
+        server_info: None,
+    };
+    conn.initialize_finish(init_id, serde_json::to_value(init_result).unwrap())?;
 
     let root_path = match init_params
         .root_uri
@@ -59,7 +66,7 @@ pub fn main_loop(conn: Connection) -> Result<()> {
         None => std::env::current_dir()?,
     };
 
-    let mut server = Server::new(conn.sender.clone(), root_path);
+    let mut server = Server::new(conn.sender.clone(), root_path, &init_params.capabilities);
     server.run(conn.receiver, init_params)?;
 
     tracing::info!("Leaving main loop");
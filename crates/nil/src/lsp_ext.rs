@@ -0,0 +1,201 @@
+//! Custom LSP extensions.
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use lsp_types::{Range, TextDocumentIdentifier, TextDocumentPositionParams, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Format raw Nix source text, which needs not be an opened document.
+pub(crate) enum FormatString {}
+
+impl Request for FormatString {
+    type Params = FormatStringParams;
+    type Result = String;
+    const METHOD: &'static str = "nil/formatString";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FormatStringParams {
+    pub text: String,
+}
+
+/// Flake inputs whose store paths are missing, from the last loading of the workspace flake.
+/// It is empty if all inputs are present, or the workspace is not a flake.
+/// `nix flake archive` fetches all of them at once.
+pub(crate) enum MissingInputs {}
+
+impl Request for MissingInputs {
+    type Params = ();
+    type Result = Vec<MissingInput>;
+    const METHOD: &'static str = "nil/missingInputs";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MissingInput {
+    /// The input name in `flake.lock`.
+    pub name: String,
+    /// The command line to fetch only this input.
+    pub fetch_command: Vec<String>,
+}
+
+/// The command of code actions to prefetch the source of a fetcher call and fill its hash,
+/// with a single argument of [`PrefetchHashArgs`].
+/// The edit is applied via `workspace/applyEdit` after the prefetch finishes.
+pub(crate) const PREFETCH_HASH_COMMAND: &str = "nil.prefetchHash";
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PrefetchHashArgs {
+    pub uri: Url,
+    /// The document version when the code action is calculated.
+    pub version: Option<i32>,
+    /// The source archive to prefetch, or a flake reference of a git repository if `git` is set.
+    pub url: String,
+    pub git: bool,
+    /// The range to be replaced by the `hash` binding.
+    pub range: Range,
+    /// Whether the binding to insert needs a leading space.
+    pub leading_space: bool,
+}
+
+/// Information of the server build and environment, to be included in bug reports.
+pub(crate) enum Version {}
+
+impl Request for Version {
+    type Params = ();
+    type Result = VersionInfo;
+    const METHOD: &'static str = "nil/version";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct VersionInfo {
+    /// The crate version.
+    pub version: String,
+    /// The git commit the server is built from, if it is embedded at build time.
+    pub git_commit: Option<String>,
+    /// Enabled experimental features.
+    pub features: Vec<String>,
+    /// The version of the configured Nix binary, or `null` if it cannot be run.
+    pub nix_version: Option<String>,
+}
+
+/// The status of experimental features. It is also sent by the server as the notification
+/// `nil/statusChanged` when the `experimental` configuration changes.
+pub(crate) enum Status {}
+
+impl Request for Status {
+    type Params = ();
+    type Result = ServerStatus;
+    const METHOD: &'static str = "nil/status";
+}
+
+pub(crate) enum StatusChanged {}
+
+impl Notification for StatusChanged {
+    type Params = ServerStatus;
+    const METHOD: &'static str = "nil/statusChanged";
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ServerStatus {
+    /// All experimental features known by the server.
+    pub experimental_features: Vec<ExperimentalFeature>,
+    /// Configured keys of `experimental` which are not known features. They are ignored.
+    pub unknown_experimental_features: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ExperimentalFeature {
+    /// The key under `experimental`, like `shiftDiagnostics`.
+    pub name: String,
+    pub description: String,
+    pub enabled: bool,
+}
+
+/// The dotted attribute path from the file root to the innermost attribute binding at the
+/// position, like `outputs.packages.x86_64-linux.default`, or `null` if there is none.
+pub(crate) enum AttrPathAt {}
+
+impl Request for AttrPathAt {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<String>;
+    const METHOD: &'static str = "nil/attrPathAt";
+}
+
+/// Ranges of string contents in other languages, like shell scripts passed to
+/// `writeShellScript`, for clients supporting language injection.
+pub(crate) enum Injections {}
+
+impl Request for Injections {
+    type Params = InjectionsParams;
+    type Result = Vec<InjectionRange>;
+    const METHOD: &'static str = "nil/injections";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InjectionsParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct InjectionRange {
+    pub range: Range,
+    /// The language identifier, like `bash`.
+    pub language: String,
+}
+
+/// Counts of the last published diagnostics of an opened document, or `null` if it is not
+/// opened. The summary is also logged via `window/logMessage`.
+pub(crate) enum DiagnosticsSummary {}
+
+impl Request for DiagnosticsSummary {
+    type Params = DiagnosticsSummaryParams;
+    type Result = Option<DiagnosticCounts>;
+    const METHOD: &'static str = "nil/diagnosticsSummary";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DiagnosticsSummaryParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DiagnosticCounts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub information: usize,
+    pub hints: usize,
+    /// Counts by diagnostic codes, like `undefined_name`.
+    pub codes: BTreeMap<String, usize>,
+}
+
+/// Cumulative self timings of analysis operations since the server started, excluding nested
+/// operations, the most time-consuming first. It is empty unless the server is started with
+/// `NIL_TIMING` set. The summary is also logged.
+pub(crate) enum DebugStats {}
+
+impl Request for DebugStats {
+    type Params = ();
+    type Result = Vec<OperationTiming>;
+    const METHOD: &'static str = "nil/debug/stats";
+}
+
+#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OperationTiming {
+    /// The span name, like `infer`, or `request <method>` and `notification <method>` for
+    /// requests and notifications.
+    pub name: String,
+    pub count: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+}
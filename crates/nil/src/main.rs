@@ -2,15 +2,19 @@ use anyhow::Context;
 use argh::FromArgs;
 use ide::AnalysisHost;
 use lsp_server::Connection;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::{env, fs, io, process};
 use text_size::TextRange;
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
 use tracing_subscriber::EnvFilter;
 
 const LOG_FILTER_ENV: &str = "NIL_LOG";
 const LOG_PATH_ENV: &str = "NIL_LOG_PATH";
+const TIMING_ENV: &str = "NIL_TIMING";
 const BACKTRACE_ENV: &str = "RUST_BACKTRACE";
 
 #[derive(Debug, FromArgs)]
@@ -41,6 +45,10 @@ struct DiagnosticsArgs {
     /// to disambiguous it from flags.
     #[argh(positional)]
     path: PathBuf,
+    /// report diagnostics of this code as errors, like `--error-on unused_binding`.
+    /// Can be specified multiple times.
+    #[argh(option)]
+    error_on: Vec<String>,
 }
 
 fn main() {
@@ -89,11 +97,8 @@ fn main_diagnostics(args: DiagnosticsArgs) {
             fs::read_to_string(path).context("Failed to read file")?
         };
 
-        let (analysis, file) = AnalysisHost::new_single_file(&src);
-        let diags = analysis
-            .snapshot()
-            .diagnostics(file)
-            .expect("No cancellation");
+        let error_on = args.error_on.iter().cloned().collect();
+        let (file, diags) = check_diagnostics(&src, &error_on);
         if diags.is_empty() {
             return Ok(true);
         }
@@ -108,6 +113,7 @@ fn main_diagnostics(args: DiagnosticsArgs) {
             let severity = match diag.severity() {
                 ide::Severity::IncompleteSyntax | ide::Severity::Error => Severity::Error,
                 ide::Severity::Warning => Severity::Warning,
+                ide::Severity::Hint => Severity::Help,
             };
 
             let to_range = |range: TextRange| usize::from(range.start())..usize::from(range.end());
@@ -138,6 +144,19 @@ fn main_diagnostics(args: DiagnosticsArgs) {
     }
 }
 
+/// Calculate diagnostics of a single file, elevating ones listed in `error_on` to errors.
+fn check_diagnostics(src: &str, error_on: &HashSet<String>) -> (ide::FileId, Vec<ide::Diagnostic>) {
+    let (analysis, file) = AnalysisHost::new_single_file(src);
+    let mut diags = analysis
+        .snapshot()
+        .diagnostics(file)
+        .expect("No cancellation");
+    // Opt-in style hints are not reported here.
+    diags.retain(|diag| diag.kind != ide::DiagnosticKind::TopLevelWith);
+    ide::elevate_diagnostics(&mut diags, error_on);
+    (file, diags)
+}
+
 fn setup_logger() {
     let file = env::var_os(LOG_PATH_ENV).and_then(|path| {
         let path = PathBuf::from(path);
@@ -156,8 +175,41 @@ fn setup_logger() {
         None => BoxMakeWriter::new(io::stderr),
     };
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_env(LOG_FILTER_ENV))
-        .with_writer(writer)
+    // Only spans are timed. Events are left to the log filter.
+    let timing = env::var_os(TIMING_ENV)
+        .is_some()
+        .then(|| nil::TimingLayer.with_filter(filter_fn(|meta| meta.is_span())));
+
+    tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_filter(EnvFilter::from_env(LOG_FILTER_ENV)),
+        )
+        .with(timing)
         .init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::check_diagnostics;
+    use ide::Severity;
+    use std::collections::HashSet;
+
+    #[test]
+    fn error_on() {
+        let src = "let x = 1; in 2";
+        let (_, diags) = check_diagnostics(src, &HashSet::new());
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity(), Severity::Warning);
+
+        let error_on = HashSet::from(["unused_binding".to_owned()]);
+        let (_, diags) = check_diagnostics(src, &error_on);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].severity(), Severity::Error);
+
+        // Clean files still pass.
+        let (_, diags) = check_diagnostics("let x = 1; in x", &error_on);
+        assert!(diags.is_empty());
+    }
+}
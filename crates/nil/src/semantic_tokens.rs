@@ -1,4 +1,4 @@
-use ide::{BuiltinKind, HlAttrField, HlKeyword, HlPunct, HlTag, NameKind};
+use ide::{BuiltinKind, HlAttrField, HlKeyword, HlPunct, HlTag, InjectionLanguage, NameKind};
 use lsp_types::{SemanticTokenModifier, SemanticTokenType};
 
 macro_rules! def_index {
@@ -36,11 +36,13 @@ def_index! {
 def_index! {
     SemanticTokenModifier, SEMANTIC_TOKEN_MODIFIERS, TokenModIdx;
 
+    Bash => SemanticTokenModifier::new("bash"),
     Builtin => SemanticTokenModifier::new("builtin"),
     Conditional => SemanticTokenModifier::new("conditional"),
     Definition => SemanticTokenModifier::DEFINITION,
     Delimiter => SemanticTokenModifier::new("delimiter"),
     Escape => SemanticTokenModifier::new("escape"),
+    Interpolation => SemanticTokenModifier::new("interpolation"),
     Parenthesis => SemanticTokenModifier::new("parenthesis"),
     Readonly => SemanticTokenModifier::READONLY,
     Unresolved => SemanticTokenModifier::new("unresolved"),
@@ -62,6 +64,13 @@ impl TokenModSet {
     }
 }
 
+/// The modifier hinting the language of injected string contents.
+pub(crate) fn to_injection_modifier(lang: InjectionLanguage) -> TokenModIdx {
+    match lang {
+        InjectionLanguage::Bash => TokenModIdx::Bash,
+    }
+}
+
 pub(crate) fn to_semantic_type_and_modifiers(tag: HlTag) -> (TokenTypeIdx, TokenModSet) {
     let mut mods = TokenModSet::default();
     let ty = match tag {
@@ -127,6 +136,7 @@ pub(crate) fn to_semantic_type_and_modifiers(tag: HlTag) -> (TokenTypeIdx, Token
                 | HlPunct::Colon
                 | HlPunct::Equal
                 | HlPunct::At => mods.insert(TokenModIdx::Delimiter),
+                HlPunct::Interpolation => mods.insert(TokenModIdx::Interpolation),
                 HlPunct::Ellipsis => {}
             }
             TokenTypeIdx::Punctuation
@@ -1,16 +1,29 @@
-use crate::config::{Config, CONFIG_KEY};
-use crate::{convert, handler, LspError, Vfs};
+use crate::config::{Config, OutsideRootPolicy, CONFIG_KEY, EXPERIMENTAL_FEATURES};
+use crate::lsp_ext::{ExperimentalFeature, MissingInput, ServerStatus};
+use crate::{convert, handler, lsp_ext, timing, LspError, Vfs};
 use anyhow::{anyhow, bail, Context, Result};
-use crossbeam_channel::{Receiver, Sender};
-use ide::{Analysis, AnalysisHost, Cancelled, FlakeInfo, VfsPath};
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use ide::{
+    Analysis, AnalysisHost, CancellationToken, Cancelled, FileId, FlakeInfo, SearchPath, VfsPath,
+};
 use lsp_server::{ErrorCode, Message, Notification, ReqQueue, Request, RequestId, Response};
 use lsp_types::notification::Notification as _;
+use lsp_types::request::Request as _;
 use lsp_types::{
-    notification as notif, request as req, ConfigurationItem, ConfigurationParams, Diagnostic,
-    InitializeParams, MessageType, NumberOrString, PublishDiagnosticsParams, ShowMessageParams,
-    Url,
+    notification as notif, request as req, ApplyWorkspaceEditParams, ClientCapabilities,
+    ConfigurationItem, ConfigurationParams, DeleteFilesParams, Diagnostic, DiagnosticSeverity,
+    DocumentChanges, ExecuteCommandParams, InitializeParams, LogMessageParams, MessageActionItem,
+    MessageType, NumberOrString, OneOf, OptionalVersionedTextDocumentIdentifier, Position,
+    PublishDiagnosticsParams, Range, Registration, RegistrationParams, ShowMessageParams,
+    ShowMessageRequestParams, TextDocumentEdit, TextDocumentRegistrationOptions, TextEdit,
+    Unregistration, UnregistrationParams, Url, WorkspaceEdit,
+};
+use nix_interop::eval::NixEvaluator;
+use nix_interop::flake_lock::ResolvedInput;
+use nix_interop::persistent_eval::PersistentEvaluator;
+use nix_interop::{
+    flake_archive, flake_lock, nix_path, prefetch, DEFAULT_IMPORT_FILE, FLAKE_FILE, FLAKE_LOCK_FILE,
 };
-use nix_interop::{flake_lock, FLAKE_FILE, FLAKE_LOCK_FILE};
 use std::backtrace::Backtrace;
 use std::cell::Cell;
 use std::collections::HashMap;
@@ -18,7 +31,8 @@ use std::io::ErrorKind;
 use std::panic::UnwindSafe;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Once, RwLock};
-use std::{fs, panic, thread};
+use std::time::{Duration, Instant};
+use std::{fs, mem, panic, thread};
 
 type ReqHandler = Box<dyn FnOnce(&mut Server, Response) + 'static>;
 
@@ -33,12 +47,23 @@ enum Event {
     },
     ClientExited,
     LoadFlake(Result<LoadFlakeResult>),
+    LoadSearchPaths {
+        search_paths: Vec<SearchPath>,
+        /// Entry files of search paths loaded in Vfs.
+        files: Vec<FileId>,
+    },
+    ArchiveFlake(Result<()>),
+    PrefetchHash(lsp_ext::PrefetchHashArgs, Result<String>),
+    DanglingImports(Result<Vec<String>>),
+    IdleGc,
+    FlushDiagnostics,
 }
 
 enum LoadFlakeResult {
     IsFlake {
         flake_info: FlakeInfo,
-        missing_inputs: bool,
+        /// Names of locked inputs whose store paths are not on disk, sorted.
+        missing_inputs: Vec<String>,
     },
     NotFlake,
 }
@@ -53,11 +78,23 @@ pub struct Server {
     is_shutdown: bool,
     /// Monotonic version counter for diagnostics calculation ordering.
     version_counter: u64,
+    /// The time of the last received client message, for idle GC.
+    last_activity: Instant,
+    idle_gc_scheduled: bool,
+    /// Calculated diagnostics waiting to be published.
+    pending_diagnostics: DiagnosticsBatch,
+    /// Evaluator for Nix queries, rebuilt when `nix.*` config changes.
+    evaluator: NixEvaluator,
+    /// Names of flake inputs whose store paths are missing, from the last flake loading.
+    missing_inputs: Vec<String>,
 
     // Message passing.
-    req_queue: ReqQueue<(), ReqHandler>,
+    /// Incoming requests are associated with tokens to interrupt their computations.
+    req_queue: ReqQueue<CancellationToken, ReqHandler>,
     lsp_tx: Sender<Message>,
     task_tx: Sender<Task>,
+    /// Tasks with lower priority than `task_tx`, like diagnostics.
+    background_task_tx: Sender<Task>,
     event_tx: Sender<Event>,
     event_rx: Receiver<Event>,
 }
@@ -68,17 +105,100 @@ struct FileData {
     diagnostics: Vec<Diagnostic>,
 }
 
+/// Diagnostics to be published in a single flush.
+/// Multiple results of the same file are coalesced into the latest one.
+#[derive(Debug, Default)]
+struct DiagnosticsBatch {
+    pending: HashMap<Url, Vec<Diagnostic>>,
+}
+
+impl DiagnosticsBatch {
+    /// Add diagnostics of a file, replacing the pending ones of the same file.
+    /// Return if the batch was empty, thus a flush should be scheduled.
+    fn push(&mut self, uri: Url, diagnostics: Vec<Diagnostic>) -> bool {
+        let was_empty = self.pending.is_empty();
+        self.pending.insert(uri, diagnostics);
+        was_empty
+    }
+
+    fn take(&mut self) -> impl Iterator<Item = (Url, Vec<Diagnostic>)> {
+        mem::take(&mut self.pending).into_iter()
+    }
+}
+
+/// Shift diagnostics of `uri` after the text in `range` is replaced by `text`.
+/// Diagnostics overlapping the changed range are dropped, since they may be no longer valid.
+/// Return if any diagnostic is changed.
+fn shift_diagnostics(
+    uri: &Url,
+    diagnostics: &mut Vec<Diagnostic>,
+    range: Range,
+    text: &str,
+) -> bool {
+    let new_end = match text.rsplit_once('\n') {
+        None => Position::new(
+            range.start.line,
+            range.start.character + text.encode_utf16().count() as u32,
+        ),
+        Some((_, last_line)) => Position::new(
+            range.start.line + text.matches('\n').count() as u32,
+            last_line.encode_utf16().count() as u32,
+        ),
+    };
+    let shift_pos = |pos: Position| {
+        if pos.line == range.end.line {
+            Position::new(
+                new_end.line,
+                new_end.character + (pos.character - range.end.character),
+            )
+        } else {
+            Position::new(pos.line - range.end.line + new_end.line, pos.character)
+        }
+    };
+    // `None` for overlapping ranges.
+    let shift_range = |r: Range| {
+        if r.end <= range.start {
+            Some(r)
+        } else if range.end <= r.start {
+            Some(Range::new(shift_pos(r.start), shift_pos(r.end)))
+        } else {
+            None
+        }
+    };
+
+    let mut changed = false;
+    diagnostics.retain_mut(|diag| {
+        let Some(new_range) = shift_range(diag.range) else {
+            changed = true;
+            return false;
+        };
+        changed |= new_range != diag.range;
+        diag.range = new_range;
+        for info in diag.related_information.iter_mut().flatten() {
+            if info.location.uri == *uri {
+                if let Some(new_range) = shift_range(info.location.range) {
+                    info.location.range = new_range;
+                }
+            }
+        }
+        true
+    });
+    changed
+}
+
 impl Server {
-    pub fn new(lsp_tx: Sender<Message>, root_path: PathBuf) -> Self {
+    pub fn new(lsp_tx: Sender<Message>, root_path: PathBuf, caps: &ClientCapabilities) -> Self {
         let (task_tx, task_rx) = crossbeam_channel::unbounded();
+        let (background_task_tx, background_task_rx) = crossbeam_channel::unbounded();
         let (event_tx, event_rx) = crossbeam_channel::unbounded();
         let worker_cnt = thread::available_parallelism().map_or(1, |n| n.get());
         for _ in 0..worker_cnt {
             let task_rx = task_rx.clone();
+            let background_task_rx = background_task_rx.clone();
             let event_tx = event_tx.clone();
             thread::Builder::new()
                 .name("Worker".into())
-                .spawn(move || Self::worker(task_rx, event_tx))
+                .spawn(move || Self::worker(task_rx, background_task_rx, event_tx))
                 .expect("Failed to spawn worker threads");
         }
         tracing::info!("Started {worker_cnt} workers");
@@ -87,20 +207,30 @@ impl Server {
             host: AnalysisHost::default(),
             vfs: Arc::new(RwLock::new(Vfs::new())),
             opened_files: HashMap::default(),
-            config: Arc::new(Config::new(root_path)),
+            config: Arc::new(Config::new(root_path, caps)),
             is_shutdown: false,
             version_counter: 0,
+            last_activity: Instant::now(),
+            idle_gc_scheduled: false,
+            pending_diagnostics: DiagnosticsBatch::default(),
+            evaluator: NixEvaluator::Oneshot("nix".into()),
+            missing_inputs: Vec::new(),
 
             req_queue: ReqQueue::default(),
             lsp_tx,
             task_tx,
+            background_task_tx,
             event_tx,
             event_rx,
         }
     }
 
-    fn worker(task_rx: Receiver<Task>, event_tx: Sender<Event>) {
-        while let Ok(task) = task_rx.recv() {
+    fn worker(
+        task_rx: Receiver<Task>,
+        background_task_rx: Receiver<Task>,
+        event_tx: Sender<Event>,
+    ) {
+        while let Some(task) = next_task(&task_rx, &background_task_rx) {
             if event_tx.send(task()).is_err() {
                 break;
             }
@@ -151,6 +281,9 @@ impl Server {
             });
         }
 
+        // Apply search paths from `NIX_PATH` before any configuration is received.
+        self.load_search_paths();
+
         // Load configurations before loading flake.
         // The latter depends on `nix.binary`.
         self.load_config(|st| {
@@ -161,6 +294,8 @@ impl Server {
         loop {
             crossbeam_channel::select! {
                 recv(lsp_rx) -> msg => {
+                    self.last_activity = Instant::now();
+                    self.schedule_idle_gc(None);
                     match msg.context("Channel closed")? {
                         Message::Request(req) => self.dispatch_request(req),
                         Message::Notification(notif) => {
@@ -186,7 +321,7 @@ impl Server {
     fn dispatch_event(&mut self, event: Event) -> Result<()> {
         match event {
             Event::Response(resp) => {
-                if let Some(()) = self.req_queue.incoming.complete(resp.id.clone()) {
+                if self.req_queue.incoming.complete(resp.id.clone()).is_some() {
                     self.lsp_tx.send(resp.into()).unwrap();
                 }
             }
@@ -198,15 +333,7 @@ impl Server {
                 Some(f) if f.diagnostics_version < version => {
                     f.diagnostics_version = version;
                     f.diagnostics = diagnostics.clone();
-                    tracing::trace!(
-                        "Push {} diagnostics of {uri}, version {version}",
-                        diagnostics.len(),
-                    );
-                    self.send_notification::<notif::PublishDiagnostics>(PublishDiagnosticsParams {
-                        uri,
-                        diagnostics,
-                        version: None,
-                    });
+                    self.push_diagnostics(uri, diagnostics);
                 }
                 _ => tracing::debug!("Ignore raced diagnostics of {uri}, version {version}"),
             },
@@ -225,24 +352,155 @@ impl Server {
                     missing_inputs,
                 }) => {
                     tracing::info!(
-                        "Workspace is a flake (missing_inputs = {missing_inputs}): {flake_info:?}"
+                        "Workspace is a flake (missing_inputs = {missing_inputs:?}): {flake_info:?}"
                     );
-                    if missing_inputs {
-                        self.show_message(MessageType::WARNING, "Some flake inputs are not available, please run `nix flake archive` to fetch all inputs");
+                    if !missing_inputs.is_empty() {
+                        self.warn_missing_inputs(&missing_inputs);
                     }
+                    self.missing_inputs = missing_inputs;
                     self.vfs.write().unwrap().set_flake_info(Some(flake_info));
                     self.apply_vfs_change();
                 }
                 Ok(LoadFlakeResult::NotFlake) => {
                     tracing::info!("Workspace is not a flake");
+                    self.missing_inputs.clear();
                     self.vfs.write().unwrap().set_flake_info(None);
                     self.apply_vfs_change();
                 }
             },
+            Event::LoadSearchPaths {
+                search_paths,
+                files,
+            } => {
+                tracing::info!("Loaded search paths: {search_paths:?}");
+                self.vfs
+                    .write()
+                    .unwrap()
+                    .set_search_paths(search_paths, files);
+                self.apply_vfs_change();
+            }
+            Event::ArchiveFlake(ret) => match ret {
+                Err(err) => {
+                    self.show_message(
+                        MessageType::ERROR,
+                        format!("Failed to fetch flake inputs: {err:#}"),
+                    );
+                }
+                Ok(()) => {
+                    tracing::info!("Fetched flake inputs");
+                    self.load_flake();
+                }
+            },
+            Event::PrefetchHash(args, ret) => match ret {
+                Err(err) => {
+                    self.show_message(
+                        MessageType::ERROR,
+                        format!("Failed to prefetch {}: {err:#}", args.url),
+                    );
+                }
+                Ok(hash) => self.apply_prefetched_hash(args, &hash),
+            },
+            Event::DanglingImports(ret) => match ret {
+                Err(err) => tracing::error!("Failed to find importers of deleted files: {err:#}"),
+                Ok(lines) if lines.is_empty() => {}
+                Ok(lines) => {
+                    self.show_message(
+                        MessageType::WARNING,
+                        format!("Deleted files are still imported:\n{}", lines.join("\n")),
+                    );
+                }
+            },
+            Event::FlushDiagnostics => {
+                for (uri, diagnostics) in self.pending_diagnostics.take() {
+                    // Skip files closed during the window.
+                    if self.opened_files.contains_key(&uri) {
+                        self.publish_diagnostics(uri, diagnostics);
+                    }
+                }
+            }
+            Event::IdleGc => {
+                self.idle_gc_scheduled = false;
+                let Some(delay) = self.config.server_idle_gc else { return Ok(()) };
+                let idle = self.last_activity.elapsed();
+                if idle < delay {
+                    // Some messages arrived after the timer was scheduled.
+                    self.schedule_idle_gc(Some(delay - idle));
+                } else {
+                    self.collect_garbage();
+                }
+            }
         }
         Ok(())
     }
 
+    /// Publish diagnostics immediately, or batch them if configured.
+    fn push_diagnostics(&mut self, uri: Url, diagnostics: Vec<Diagnostic>) {
+        let window = self.config.diagnostics_batch_window;
+        if window.is_zero() {
+            self.publish_diagnostics(uri, diagnostics);
+        } else if self.pending_diagnostics.push(uri, diagnostics) {
+            let event_tx = self.event_tx.clone();
+            thread::spawn(move || {
+                thread::sleep(window);
+                let _ = event_tx.send(Event::FlushDiagnostics);
+            });
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) {
+        tracing::trace!("Push {} diagnostics of {uri}", diagnostics.len());
+        self.send_notification::<notif::PublishDiagnostics>(PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        });
+    }
+
+    /// Schedule an idle GC timer if it is enabled and not scheduled yet.
+    /// The timer fires after `delay`, or the configured idle time if it is `None`.
+    fn schedule_idle_gc(&mut self, delay: Option<Duration>) {
+        let Some(idle_delay) = self.config.server_idle_gc else { return };
+        if self.idle_gc_scheduled {
+            return;
+        }
+        self.idle_gc_scheduled = true;
+        let delay = delay.unwrap_or(idle_delay);
+        let event_tx = self.event_tx.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let _ = event_tx.send(Event::IdleGc);
+        });
+    }
+
+    /// Drop contents of files which are neither the flake file nor reachable from opened files
+    /// via imports. Closed files are kept in Vfs after `textDocument/didClose` for cross-file
+    /// analysis, which can grow unboundedly in a long session.
+    /// Cached analysis results are also trimmed to the configured LRU capacity.
+    fn collect_garbage(&mut self) {
+        if let Some(capacity) = self.config.server_lru_capacity {
+            self.host.set_lru_capacity(capacity);
+        }
+        let opened = {
+            let vfs = self.vfs.read().unwrap();
+            self.opened_files
+                .keys()
+                .filter_map(|uri| vfs.file_for_uri(uri).ok())
+                .collect::<Vec<FileId>>()
+        };
+        // Nothing can cancel it, since the main loop is the only writer.
+        let Ok(reachable) = self.host.snapshot().reachable_files(&opened) else { return };
+        let dropped = self
+            .vfs
+            .write()
+            .unwrap()
+            .drop_files_except(|file| reachable.contains(&file));
+        tracing::info!("Idle GC: dropped {dropped} closed files");
+        timing::log_summary();
+        if dropped != 0 {
+            self.apply_vfs_change();
+        }
+    }
+
     fn dispatch_request(&mut self, req: Request) {
         if self.is_shutdown {
             let resp = Response::new_err(
@@ -259,20 +517,54 @@ impl Server {
                 st.is_shutdown = true;
                 Ok(())
             })
+            .on_sync_mut::<lsp_ext::MissingInputs>(|st, ()| {
+                Ok(missing_input_entries(
+                    &st.config.nix_binary,
+                    &st.config.root_path,
+                    &st.missing_inputs,
+                ))
+            })
+            .on_sync_mut::<lsp_ext::DiagnosticsSummary>(|st, params| {
+                Ok(st.diagnostics_summary(&params.text_document.uri))
+            })
+            .on_sync_mut::<lsp_ext::Status>(|st, ()| Ok(server_status(&st.config)))
+            .on_sync_mut::<lsp_ext::DebugStats>(|_st, ()| {
+                timing::log_summary();
+                Ok(timing::summary())
+            })
+            .on_sync_mut::<req::ExecuteCommand>(|st, params| {
+                st.execute_command(params)?;
+                Ok(None)
+            })
+            .on_sync_mut::<req::WillDeleteFiles>(|st, params| {
+                st.warn_dangling_imports(&params);
+                Ok(None)
+            })
+            .on::<lsp_ext::Version>(handler::version)
             .on::<req::GotoDefinition>(handler::goto_definition)
             .on::<req::References>(handler::references)
             .on::<req::Completion>(handler::completion)
             .on::<req::SelectionRangeRequest>(handler::selection_range)
             .on::<req::PrepareRenameRequest>(handler::prepare_rename)
             .on::<req::Rename>(handler::rename)
+            .on::<req::WillRenameFiles>(handler::will_rename_files)
             .on::<req::SemanticTokensFullRequest>(handler::semantic_token_full)
             .on::<req::SemanticTokensRangeRequest>(handler::semantic_token_range)
             .on::<req::HoverRequest>(handler::hover)
+            .on::<req::InlineValueRequest>(handler::inline_value)
+            .on::<req::FoldingRangeRequest>(handler::folding_range)
             .on::<req::DocumentSymbolRequest>(handler::document_symbol)
             .on::<req::Formatting>(handler::formatting)
+            .on::<req::WillSaveWaitUntil>(handler::will_save_wait_until)
             .on::<req::DocumentLinkRequest>(handler::document_links)
             .on::<req::CodeActionRequest>(handler::code_action)
             .on::<req::DocumentHighlightRequest>(handler::document_highlight)
+            .on::<req::CallHierarchyPrepare>(handler::prepare_call_hierarchy)
+            .on::<req::CallHierarchyIncomingCalls>(handler::incoming_calls)
+            .on::<req::CallHierarchyOutgoingCalls>(handler::outgoing_calls)
+            .on::<lsp_ext::FormatString>(handler::format_string)
+            .on::<lsp_ext::Injections>(handler::injections)
+            .on::<lsp_ext::AttrPathAt>(handler::attr_path_at)
             .finish();
     }
 
@@ -283,7 +575,15 @@ impl Server {
                     NumberOrString::Number(id) => id.into(),
                     NumberOrString::String(id) => id.into(),
                 };
-                if let Some(resp) = st.req_queue.incoming.cancel(id) {
+                if let Some(token) = st.req_queue.incoming.complete(id.clone()) {
+                    // The worker unwinds with `Cancelled` at its next query, and its response
+                    // is ignored since the request is already completed here.
+                    token.cancel();
+                    let resp = Response::new_err(
+                        id,
+                        ErrorCode::RequestCanceled as i32,
+                        "canceled by client".into(),
+                    );
                     st.lsp_tx.send(resp.into()).unwrap();
                 }
                 Ok(())
@@ -291,19 +591,46 @@ impl Server {
             .on_sync_mut::<notif::DidOpenTextDocument>(|st, params| {
                 let uri = &params.text_document.uri;
                 st.opened_files.insert(uri.clone(), FileData::default());
+                if st.config.outside_root_policy(uri) == OutsideRootPolicy::Ignore {
+                    tracing::info!("Ignore {uri} outside the workspace root");
+                    return Ok(());
+                }
                 st.set_vfs_file_content(uri, params.text_document.text)?;
+                let mut vfs = st.vfs.write().unwrap();
+                let file = vfs.file_for_uri(uri)?;
+                vfs.set_doc_version(file, Some(params.text_document.version));
                 Ok(())
             })?
             .on_sync_mut::<notif::DidCloseTextDocument>(|st, params| {
                 // N.B. Don't clear text here.
-                st.opened_files.remove(&params.text_document.uri);
+                let uri = &params.text_document.uri;
+                st.opened_files.remove(uri);
+                let mut vfs = st.vfs.write().unwrap();
+                if let Ok(file) = vfs.file_for_uri(uri) {
+                    vfs.set_doc_version(file, None);
+                }
                 Ok(())
             })?
             .on_sync_mut::<notif::DidChangeTextDocument>(|st, params| {
                 let mut vfs = st.vfs.write().unwrap();
                 // Ignore files not maintained in Vfs.
                 let Ok(file) = vfs.file_for_uri(&params.text_document.uri) else { return Ok(()) };
-                for change in params.content_changes {
+                // A rangeless change replaces the whole document, making all previous changes
+                // meaningless. Skip them and start from the last full-document change.
+                let mut changes = params.content_changes;
+                if let Some(last_full) = changes.iter().rposition(|change| change.range.is_none()) {
+                    changes.drain(..last_full);
+                }
+                vfs.set_doc_version(file, Some(params.text_document.version));
+                // Shift the last diagnostics along with changes, and keep them displayed until
+                // the recalculation finishes. This reduces flickers during typing.
+                let uri = params.text_document.uri;
+                let mut diagnostics = match st.opened_files.get(&uri) {
+                    Some(f) if st.config.experimental("shiftDiagnostics") => f.diagnostics.clone(),
+                    _ => Vec::new(),
+                };
+                let mut diagnostics_shifted = false;
+                for change in changes {
                     let del_range = match change.range {
                         None => None,
                         Some(range) => match convert::from_range(&vfs, file, range) {
@@ -319,8 +646,23 @@ impl Server {
                     if let Err(err) = vfs.change_file_content(file, del_range, &change.text) {
                         tracing::error!("File is out of sync! Failed to apply change: {err}. Change: {change:?}");
                     }
+                    diagnostics_shifted |= match change.range {
+                        Some(range) => {
+                            shift_diagnostics(&uri, &mut diagnostics, range, &change.text)
+                        }
+                        None => !mem::take(&mut diagnostics).is_empty(),
+                    };
                 }
                 drop(vfs);
+
+                if let Some(f) = st.opened_files.get_mut(&uri) {
+                    // Results calculated before this change are outdated in positions.
+                    f.diagnostics_version = st.version_counter;
+                    if diagnostics_shifted {
+                        f.diagnostics = diagnostics.clone();
+                        st.push_diagnostics(uri, diagnostics);
+                    }
+                }
                 st.apply_vfs_change();
                 Ok(())
             })?
@@ -343,7 +685,7 @@ impl Server {
 
         let flake_path = self.config.root_path.join(FLAKE_FILE);
         let lock_path = self.config.root_path.join(FLAKE_LOCK_FILE);
-        let nix_bin_path = self.config.nix_binary.clone();
+        let evaluator = self.evaluator.clone();
 
         let vfs = self.vfs.clone();
         let task = move || {
@@ -378,7 +720,7 @@ impl Server {
                 // Flake without inputs.
                 Err(err) if err.kind() == ErrorKind::NotFound => {
                     return Ok(LoadFlakeResult::IsFlake {
-                        missing_inputs: false,
+                        missing_inputs: Vec::new(),
                         flake_info: FlakeInfo {
                             flake_file,
                             input_store_paths: HashMap::new(),
@@ -391,19 +733,14 @@ impl Server {
                 }
             };
 
-            let inputs = flake_lock::resolve_flake_locked_inputs(&nix_bin_path, &lock_src)
+            let inputs = flake_lock::resolve_flake_locked_inputs(&evaluator, &lock_src)
                 .context("Failed to resolve flake inputs from lock file")?;
 
             // We only need the map for input -> store path.
-            let inputs_cnt = inputs.len();
-            let input_store_paths = inputs
-                .into_iter()
-                .filter(|(_, input)| Path::new(&input.store_path).exists())
-                .map(|(key, input)| Ok((key, VfsPath::new(input.store_path)?)))
-                .collect::<Result<HashMap<_, _>>>()?;
+            let (input_store_paths, missing_inputs) = split_missing_inputs(inputs)?;
 
             Ok(LoadFlakeResult::IsFlake {
-                missing_inputs: input_store_paths.len() != inputs_cnt,
+                missing_inputs,
                 flake_info: FlakeInfo {
                     flake_file,
                     input_store_paths,
@@ -415,6 +752,121 @@ impl Server {
             .unwrap();
     }
 
+    /// Warn about missing flake inputs, with a button to fetch them if the client supports it.
+    fn warn_missing_inputs(&mut self, missing_inputs: &[String]) {
+        const ARCHIVE_ACTION: &str = "Run `nix flake archive`";
+
+        let message = missing_inputs_message(missing_inputs);
+        if !self.config.show_message_request_support {
+            self.show_message(MessageType::WARNING, message);
+            return;
+        }
+        self.send_request::<req::ShowMessageRequest>(
+            ShowMessageRequestParams {
+                typ: MessageType::WARNING,
+                message,
+                actions: Some(vec![MessageActionItem {
+                    title: ARCHIVE_ACTION.into(),
+                    properties: HashMap::new(),
+                }]),
+            },
+            |st, resp| match resp {
+                Ok(Some(action)) if action.title == ARCHIVE_ACTION => st.archive_flake(),
+                Ok(_) => {}
+                Err(err) => tracing::error!("Failed to show message request: {err}"),
+            },
+        );
+    }
+
+    fn execute_command(&self, params: ExecuteCommandParams) -> Result<()> {
+        if params.command != lsp_ext::PREFETCH_HASH_COMMAND {
+            return Err(LspError {
+                code: ErrorCode::InvalidParams,
+                message: format!("Unknown command {:?}", params.command),
+            }
+            .into());
+        }
+        let [arg] = <[_; 1]>::try_from(params.arguments).map_err(|_| LspError {
+            code: ErrorCode::InvalidParams,
+            message: "Expecting exactly one argument".into(),
+        })?;
+        let args = serde_json::from_value::<lsp_ext::PrefetchHashArgs>(arg)?;
+
+        tracing::info!("Prefetching {}", args.url);
+        let nix_bin_path = self.config.nix_binary.clone();
+        // Downloads may be slow. Don't block interactive requests.
+        self.background_task_tx
+            .send(Box::new(move || {
+                let ret = if args.git {
+                    prefetch::prefetch_git(&nix_bin_path, &args.url)
+                } else {
+                    prefetch::prefetch_unpacked(&nix_bin_path, &args.url)
+                };
+                Event::PrefetchHash(args, ret)
+            }))
+            .unwrap();
+        Ok(())
+    }
+
+    /// Warn about files importing the files to be deleted, since their imports cannot be
+    /// fixed automatically. Importers are collected on a worker, from the state before deletion.
+    fn warn_dangling_imports(&self, params: &DeleteFilesParams) {
+        let snap = self.snapshot();
+        let uris = params
+            .files
+            .iter()
+            .map(|file| file.uri.clone())
+            .collect::<Vec<_>>();
+        self.background_task_tx
+            .send(Box::new(move || {
+                Event::DanglingImports(handler::dangling_imports(snap, &uris))
+            }))
+            .unwrap();
+    }
+
+    fn apply_prefetched_hash(&mut self, args: lsp_ext::PrefetchHashArgs, hash: &str) {
+        let space = if args.leading_space { " " } else { "" };
+        let edit = TextEdit {
+            range: args.range,
+            new_text: format!("{space}hash = \"{hash}\";"),
+        };
+        // The document may be changed during the prefetch. The client rejects the edit
+        // if the version mismatches, instead of applying it at a wrong position.
+        let params = ApplyWorkspaceEditParams {
+            label: Some("Fill the prefetched hash".into()),
+            edit: WorkspaceEdit {
+                document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier {
+                        uri: args.uri,
+                        version: args.version,
+                    },
+                    edits: vec![OneOf::Left(edit)],
+                }])),
+                ..WorkspaceEdit::default()
+            },
+        };
+        self.send_request::<req::ApplyWorkspaceEdit>(params, |_, resp| match resp {
+            Ok(resp) if !resp.applied => {
+                tracing::warn!("Prefetched hash is not applied: {:?}", resp.failure_reason);
+            }
+            Ok(_) => {}
+            Err(err) => tracing::error!("Failed to apply the prefetched hash: {err}"),
+        });
+    }
+
+    /// Enqueue a task to fetch all flake inputs, and reload the flake after it finishes.
+    fn archive_flake(&self) {
+        tracing::info!("Fetching flake inputs");
+        let nix_bin_path = self.config.nix_binary.clone();
+        let root_path = self.config.root_path.clone();
+        // Downloads may be slow. Don't block interactive requests.
+        self.background_task_tx
+            .send(Box::new(move || {
+                Event::ArchiveFlake(flake_archive::archive_flake(&nix_bin_path, &root_path))
+            }))
+            .unwrap();
+    }
+
     fn send_request<R: req::Request>(
         &mut self,
         params: R::Params,
@@ -445,6 +897,60 @@ impl Server {
             .unwrap();
     }
 
+    /// Register or unregister `textDocument/willSaveWaitUntil` for `formatting.onSave`,
+    /// so clients don't wait for the server on each save when it is disabled.
+    fn register_format_on_save(&mut self, enable: bool) {
+        const REGISTRATION_ID: &str = "formatOnSave";
+        let method = req::WillSaveWaitUntil::METHOD.to_owned();
+        let callback = |_: &mut Self, resp: Result<()>| {
+            if let Err(err) = resp {
+                tracing::error!("Failed to update the registration of format on save: {err}");
+            }
+        };
+        if enable {
+            // `null` selects documents by the client-side document selector.
+            let options = TextDocumentRegistrationOptions {
+                document_selector: None,
+            };
+            let params = RegistrationParams {
+                registrations: vec![Registration {
+                    id: REGISTRATION_ID.into(),
+                    method,
+                    register_options: Some(serde_json::to_value(options).unwrap()),
+                }],
+            };
+            self.send_request::<req::RegisterCapability>(params, callback);
+        } else {
+            let params = UnregistrationParams {
+                unregisterations: vec![Unregistration {
+                    id: REGISTRATION_ID.into(),
+                    method,
+                }],
+            };
+            self.send_request::<req::UnregisterCapability>(params, callback);
+        }
+    }
+
+    /// Summarize the last published diagnostics of an opened file, and log the summary.
+    fn diagnostics_summary(&self, uri: &Url) -> Option<lsp_ext::DiagnosticCounts> {
+        let counts = count_diagnostics(&self.opened_files.get(uri)?.diagnostics);
+        let codes = counts
+            .codes
+            .iter()
+            .map(|(code, cnt)| format!("{code}: {cnt}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let message = format!(
+            "Diagnostics of {uri}: {} errors, {} warnings, {} information, {} hints ({codes})",
+            counts.errors, counts.warnings, counts.information, counts.hints,
+        );
+        self.send_notification::<notif::LogMessage>(LogMessageParams {
+            typ: MessageType::INFO,
+            message,
+        });
+        Some(counts)
+    }
+
     // Maybe connect all tracing::* to LSP ShowMessage?
     fn show_message(&self, typ: MessageType, message: impl Into<String>) {
         let message = message.into();
@@ -480,7 +986,36 @@ impl Server {
         let mut config = Config::clone(&self.config);
         let (errors, updated_diagnostics) = config.update(value);
         tracing::debug!("Updated config, errors: {errors:?}, config: {config:?}");
+        // Remote search paths are fetched by `nix.binary`.
+        let updated_search_paths = config.nix_search_paths != self.config.nix_search_paths
+            || config.nix_binary != self.config.nix_binary;
+        let updated_extra_globals = config.scope_extra_globals != self.config.scope_extra_globals;
+        let updated_on_save = config.formatting_on_save != self.config.formatting_on_save;
+        let updated_experimental = config.experimental != self.config.experimental;
+        if config.nix_binary != self.config.nix_binary
+            || config.nix_persistent_evaluator != self.config.nix_persistent_evaluator
+        {
+            // Dropping the old persistent evaluator kills its process,
+            // after all in-flight queries holding it finish.
+            self.evaluator = if config.nix_persistent_evaluator {
+                NixEvaluator::Persistent(Arc::new(PersistentEvaluator::new(&config.nix_binary)))
+            } else {
+                NixEvaluator::Oneshot(config.nix_binary.clone())
+            };
+        }
         self.config = Arc::new(config);
+        self.schedule_idle_gc(None);
+        if updated_on_save && self.config.sync_dynamic_registration {
+            self.register_format_on_save(self.config.formatting_on_save);
+        }
+        if updated_search_paths {
+            self.load_search_paths();
+        }
+        if updated_extra_globals {
+            let globals = &self.config.scope_extra_globals;
+            self.vfs.write().unwrap().set_extra_globals(globals);
+            self.apply_vfs_change();
+        }
 
         if !errors.is_empty() {
             let msg = ["Failed to apply some settings:"]
@@ -489,6 +1024,19 @@ impl Server {
                 .collect::<String>();
             self.show_message(MessageType::ERROR, msg);
         }
+        let unknown_features = self.config.unknown_experimental_features();
+        if !unknown_features.is_empty() {
+            self.show_message(
+                MessageType::WARNING,
+                format!(
+                    "Unknown experimental features are ignored: {}",
+                    unknown_features.join(", "),
+                ),
+            );
+        }
+        if updated_experimental {
+            self.send_notification::<lsp_ext::StatusChanged>(server_status(&self.config));
+        }
 
         // Refresh all diagnostics since the filter may be changed.
         if updated_diagnostics {
@@ -504,7 +1052,9 @@ impl Server {
         let snap = self.snapshot();
         let task = move || {
             // Return empty diagnostics for ignored files.
-            let diagnostics = (!snap.config.diagnostics_excluded_files.contains(&uri))
+            let ignored = snap.config.diagnostics_excluded_files.contains(&uri)
+                || snap.config.outside_root_policy(&uri) == OutsideRootPolicy::Ignore;
+            let diagnostics = (!ignored)
                 .then(|| {
                     with_catch_unwind("diagnostics", || handler::diagnostics(snap, &uri))
                         .unwrap_or_else(|err| {
@@ -519,7 +1069,7 @@ impl Server {
                 diagnostics,
             }
         };
-        self.task_tx.send(Box::new(task)).unwrap();
+        self.background_task_tx.send(Box::new(task)).unwrap();
     }
 
     fn next_version(&mut self) -> u64 {
@@ -532,6 +1082,7 @@ impl Server {
             analysis: self.host.snapshot(),
             vfs: Arc::clone(&self.vfs),
             config: Arc::clone(&self.config),
+            evaluator: self.evaluator.clone(),
         }
     }
 
@@ -541,6 +1092,36 @@ impl Server {
         Ok(())
     }
 
+    /// Enqueue a task to resolve search paths from both the config and `NIX_PATH`,
+    /// and load their entry files. Remote ones like `flake:nixpkgs` are fetched by Nix.
+    fn load_search_paths(&self) {
+        let entries = self.config.search_path_entries();
+        let nix_command = self.config.nix_binary.clone();
+        let vfs = self.vfs.clone();
+        let task = move || {
+            let resolved = nix_path::resolve_search_path_entries(&entries, |prefix, path| {
+                nix_path::fetch_search_path(&nix_command, prefix, path)
+                    .map_err(|err| tracing::error!("Failed to fetch search path: {err:#}"))
+                    .ok()
+            });
+            let mut search_paths = Vec::new();
+            let mut files = Vec::new();
+            for (prefix, path) in resolved {
+                let Ok(vpath) = VfsPath::try_from(&*path) else { continue };
+                files.extend(load_search_path_file(&vfs, &path));
+                search_paths.push(SearchPath {
+                    prefix: prefix.into(),
+                    path: vpath,
+                });
+            }
+            Event::LoadSearchPaths {
+                search_paths,
+                files,
+            }
+        };
+        self.task_tx.send(Box::new(task)).unwrap();
+    }
+
     fn apply_vfs_change(&mut self) {
         let changes = self.vfs.write().unwrap().take_change();
         tracing::trace!("Change: {:?}", changes);
@@ -584,6 +1165,7 @@ impl<'s> RequestDispatcher<'s> {
         f: fn(&mut Server, R::Params) -> Result<R::Result>,
     ) -> Self {
         if matches!(&self.1, Some(notif) if notif.method == R::METHOD) {
+            let _span = tracing::info_span!("request", method = R::METHOD).entered();
             let req = self.1.take().unwrap();
             let ret = (|| {
                 let params = serde_json::from_value::<R::Params>(req.params)?;
@@ -604,9 +1186,17 @@ impl<'s> RequestDispatcher<'s> {
     {
         if matches!(&self.1, Some(notif) if notif.method == R::METHOD) {
             let req = self.1.take().unwrap();
+            if let Some(resp) = outside_root_response(&self.0.config, &req) {
+                self.0.lsp_tx.send(resp.into()).unwrap();
+                return self;
+            }
             let snap = self.0.snapshot();
-            self.0.req_queue.incoming.register(req.id.clone(), ());
+            self.0
+                .req_queue
+                .incoming
+                .register(req.id.clone(), snap.analysis.cancellation_token());
             let task = move || {
+                let _span = tracing::info_span!("request", method = R::METHOD).entered();
                 let ret = with_catch_unwind(R::METHOD, || {
                     let params = serde_json::from_value::<R::Params>(req.params)?;
                     let resp = f(snap, params)?;
@@ -636,6 +1226,7 @@ impl<'s> NotificationDispatcher<'s> {
         f: fn(&mut Server, N::Params) -> Result<()>,
     ) -> Result<Self> {
         if matches!(&self.1, Some(notif) if notif.method == N::METHOD) {
+            let _span = tracing::info_span!("notification", method = N::METHOD).entered();
             match serde_json::from_value::<N::Params>(self.1.take().unwrap().params) {
                 Ok(params) => {
                     f(self.0, params)?;
@@ -658,6 +1249,24 @@ impl<'s> NotificationDispatcher<'s> {
     }
 }
 
+/// Load the file of `import <prefix>` for a search path at `path` from disk into Vfs,
+/// unless it is already loaded.
+fn load_search_path_file(vfs: &RwLock<Vfs>, path: &Path) -> Option<FileId> {
+    let path = if path.is_dir() {
+        path.join(DEFAULT_IMPORT_FILE)
+    } else {
+        path.to_owned()
+    };
+    let vpath = VfsPath::try_from(&*path).ok()?;
+    let src = fs::read_to_string(&path).ok()?;
+    let mut vfs = vfs.write().unwrap();
+    match vfs.file_for_path(&vpath) {
+        // Prefer the opened one with unsaved changes.
+        Ok(file) => Some(file),
+        Err(_) => vfs.set_path_content(vpath, src).ok(),
+    }
+}
+
 fn with_catch_unwind<T>(ctx: &str, f: impl FnOnce() -> Result<T> + UnwindSafe) -> Result<T> {
     static INSTALL_PANIC_HOOK: Once = Once::new();
     thread_local! {
@@ -728,6 +1337,7 @@ pub struct StateSnapshot {
     pub(crate) analysis: Analysis,
     vfs: Arc<RwLock<Vfs>>,
     pub(crate) config: Arc<Config>,
+    pub(crate) evaluator: NixEvaluator,
 }
 
 impl StateSnapshot {
@@ -735,3 +1345,798 @@ impl StateSnapshot {
         self.vfs.read().unwrap()
     }
 }
+
+/// Requests producing edits, which are blocked on read-only documents.
+const EDIT_METHODS: &[&str] = &[
+    req::Formatting::METHOD,
+    req::WillSaveWaitUntil::METHOD,
+    req::PrepareRenameRequest::METHOD,
+    req::Rename::METHOD,
+    req::CodeActionRequest::METHOD,
+    req::WillRenameFiles::METHOD,
+];
+
+/// An empty response for requests on documents outside the workspace root, if they are
+/// ignored or read-only according to `files.outsideRoot`.
+/// File operations are blocked if any of the renamed files is.
+fn outside_root_response(config: &Config, req: &Request) -> Option<Response> {
+    let uris = match req.params.pointer("/textDocument/uri") {
+        Some(uri) => vec![uri],
+        None => req
+            .params
+            .get("files")?
+            .as_array()?
+            .iter()
+            .filter_map(|file| file.get("oldUri"))
+            .collect(),
+    };
+    let blocked = uris.into_iter().any(|uri| {
+        let Some(uri) = uri.as_str().and_then(|uri| Url::parse(uri).ok()) else { return false };
+        match config.outside_root_policy(&uri) {
+            OutsideRootPolicy::Analyze => false,
+            OutsideRootPolicy::ReadOnly => EDIT_METHODS.contains(&&*req.method),
+            OutsideRootPolicy::Ignore => true,
+        }
+    });
+    blocked.then(|| Response::new_ok(req.id.clone(), serde_json::Value::Null))
+}
+
+/// Split locked inputs into the map of input -> store path for ones available on disk,
+/// and the sorted names of ones whose store paths are missing.
+fn split_missing_inputs(
+    inputs: HashMap<String, ResolvedInput>,
+) -> Result<(HashMap<String, VfsPath>, Vec<String>)> {
+    let mut input_store_paths = HashMap::with_capacity(inputs.len());
+    let mut missing_inputs = Vec::new();
+    for (key, input) in inputs {
+        if Path::new(&input.store_path).exists() {
+            let path = VfsPath::new(&input.store_path)
+                .with_context(|| format!("Invalid store path of input {key:?}"))?;
+            input_store_paths.insert(key, path);
+        } else {
+            tracing::warn!(
+                "Store path of input {key:?} is missing: {}",
+                input.store_path
+            );
+            missing_inputs.push(key);
+        }
+    }
+    missing_inputs.sort();
+    Ok((input_store_paths, missing_inputs))
+}
+
+/// Count diagnostics by severity and by code.
+fn count_diagnostics(diags: &[Diagnostic]) -> lsp_ext::DiagnosticCounts {
+    let mut counts = lsp_ext::DiagnosticCounts::default();
+    for diag in diags {
+        match diag.severity {
+            Some(DiagnosticSeverity::ERROR) => counts.errors += 1,
+            Some(DiagnosticSeverity::WARNING) => counts.warnings += 1,
+            Some(DiagnosticSeverity::INFORMATION) => counts.information += 1,
+            Some(DiagnosticSeverity::HINT) => counts.hints += 1,
+            _ => {}
+        }
+        let code = match &diag.code {
+            Some(NumberOrString::String(code)) => code.clone(),
+            Some(NumberOrString::Number(code)) => code.to_string(),
+            None => continue,
+        };
+        *counts.codes.entry(code).or_default() += 1;
+    }
+    counts
+}
+
+fn server_status(config: &Config) -> ServerStatus {
+    ServerStatus {
+        experimental_features: EXPERIMENTAL_FEATURES
+            .iter()
+            .map(|&(name, description, _)| ExperimentalFeature {
+                name: name.into(),
+                description: description.into(),
+                enabled: config.experimental(name),
+            })
+            .collect(),
+        unknown_experimental_features: config
+            .unknown_experimental_features()
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    }
+}
+
+/// Suggest a command to fetch each missing input, resolved from the lock of the flake.
+fn missing_input_entries(
+    nix_binary: &Path,
+    flake_path: &Path,
+    missing_inputs: &[String],
+) -> Vec<MissingInput> {
+    missing_inputs
+        .iter()
+        .map(|name| MissingInput {
+            name: name.clone(),
+            fetch_command: vec![
+                nix_binary.display().to_string(),
+                "flake".into(),
+                "prefetch".into(),
+                "--experimental-features".into(),
+                "nix-command flakes".into(),
+                "--inputs-from".into(),
+                flake_path.display().to_string(),
+                name.clone(),
+            ],
+        })
+        .collect()
+}
+
+fn missing_inputs_message(missing_inputs: &[String]) -> String {
+    let names = missing_inputs
+        .iter()
+        .map(|name| format!("`{name}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "Some flake inputs are not available: {names}. \
+        Please run `nix flake archive` to fetch all inputs"
+    )
+}
+
+/// Take the next task, preferring interactive tasks over background ones,
+/// so a burst of diagnostics doesn't delay requests like hover the user is waiting on.
+/// Return `None` if the server is gone.
+fn next_task(task_rx: &Receiver<Task>, background_task_rx: &Receiver<Task>) -> Option<Task> {
+    match task_rx.try_recv() {
+        Ok(task) => return Some(task),
+        Err(TryRecvError::Disconnected) => return None,
+        Err(TryRecvError::Empty) => {}
+    }
+    crossbeam_channel::select! {
+        recv(task_rx) -> task => task.ok(),
+        recv(background_task_rx) -> task => task.ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        count_diagnostics, missing_input_entries, missing_inputs_message, next_task,
+        outside_root_response, shift_diagnostics, split_missing_inputs, DiagnosticsBatch, Event,
+        FileData, Server, StateSnapshot, Task,
+    };
+    use crate::capabilities::server_capabilities;
+    use crate::config::{Config, OutsideRootPolicy};
+    use crate::lsp_ext::{
+        DiagnosticCounts, DiagnosticsSummary, DiagnosticsSummaryParams, ExperimentalFeature,
+        FormatStringParams, ServerStatus, Status, StatusChanged,
+    };
+    use crate::{handler, LspError, Vfs};
+    use ide::{AnalysisHost, VfsPath};
+    use lsp_server::{ErrorCode, Message, Notification, Request, RequestId, Response};
+    use lsp_types::notification::{
+        DidOpenTextDocument, LogMessage, Notification as _, PublishDiagnostics, ShowMessage,
+    };
+    use lsp_types::request::{
+        RegisterCapability, Request as _, ShowMessageRequest, UnregisterCapability,
+    };
+    use lsp_types::{
+        ClientCapabilities, Diagnostic, DiagnosticSeverity, DidOpenTextDocumentParams,
+        LogMessageParams, MessageActionItem, NumberOrString, Position, PublishDiagnosticsParams,
+        Range, ShowMessageRequestClientCapabilities, TextDocumentClientCapabilities,
+        TextDocumentIdentifier, TextDocumentItem, TextDocumentSaveReason,
+        TextDocumentSyncCapability, TextDocumentSyncClientCapabilities, Url,
+        WillSaveTextDocumentParams, WindowClientCapabilities,
+    };
+    use nix_interop::eval::NixEvaluator;
+    use nix_interop::flake_lock::ResolvedInput;
+    use nix_interop::nix_path::NIX_PATH_ENV;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use std::time::Duration;
+    use std::{env, fs, process};
+
+    #[test]
+    fn missing_inputs_archive_action() {
+        let caps = ClientCapabilities {
+            window: Some(WindowClientCapabilities {
+                show_message: Some(ShowMessageRequestClientCapabilities::default()),
+                ..WindowClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        };
+        let (lsp_tx, lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &caps);
+        Arc::make_mut(&mut server.config).nix_binary = "/nonexistent/nix".into();
+
+        server.warn_missing_inputs(&["nixpkgs".into()]);
+        let Ok(Message::Request(req)) = lsp_rx.try_recv() else { panic!("No request is sent") };
+        assert_eq!(req.method, ShowMessageRequest::METHOD);
+        let action = MessageActionItem {
+            title: "Run `nix flake archive`".into(),
+            properties: HashMap::new(),
+        };
+        let callback = server.req_queue.outgoing.complete(req.id.clone()).unwrap();
+        callback(&mut server, Response::new_ok(req.id, action));
+
+        // The fetch runs on a background worker and fails with the nonexistent binary.
+        match server.event_rx.recv_timeout(Duration::from_secs(10)) {
+            Ok(Event::ArchiveFlake(ret)) => assert!(ret.is_err()),
+            _ => panic!("Flake archive is not run"),
+        }
+    }
+
+    #[test]
+    fn missing_inputs_plain_message() {
+        let (lsp_tx, lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        server.warn_missing_inputs(&["nixpkgs".into()]);
+        let Ok(Message::Notification(notif)) = lsp_rx.try_recv() else {
+            panic!("No notification is sent")
+        };
+        assert_eq!(notif.method, ShowMessage::METHOD);
+    }
+
+    #[test]
+    fn idle_gc_keeps_imported_files() {
+        let (lsp_tx, _lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        let uri = |path: &str| Url::parse(&format!("file:///root/{path}")).unwrap();
+        let files = [
+            ("opened.nix", "import ./lib"),
+            ("lib/default.nix", "import ./foo.nix"),
+            ("lib/foo.nix", "42"),
+            ("closed.nix", "import ./lib/foo.nix"),
+        ];
+        for (path, text) in files {
+            server
+                .set_vfs_file_content(&uri(path), text.into())
+                .unwrap();
+        }
+        server
+            .opened_files
+            .insert(uri("opened.nix"), FileData::default());
+
+        server.collect_garbage();
+        let vfs = server.vfs.read().unwrap();
+        for path in ["opened.nix", "lib/default.nix", "lib/foo.nix"] {
+            assert!(vfs.file_for_uri(&uri(path)).is_ok(), "{path} is dropped");
+        }
+        assert!(vfs.file_for_uri(&uri("closed.nix")).is_err());
+    }
+
+    #[test]
+    fn search_paths_from_nix_path() {
+        let dir = env::temp_dir().join(format!("nil-search-paths-{}", process::id()));
+        let nixpkgs = dir.join("nixpkgs");
+        fs::create_dir_all(&nixpkgs).unwrap();
+        fs::write(nixpkgs.join("default.nix"), "{ }: { }").unwrap();
+
+        env::set_var(NIX_PATH_ENV, format!("nixpkgs={}", nixpkgs.display()));
+        let (lsp_tx, _lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        env::remove_var(NIX_PATH_ENV);
+        server.load_search_paths();
+        let event = server
+            .event_rx
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+        assert!(matches!(event, Event::LoadSearchPaths { .. }));
+        server.dispatch_event(event).unwrap();
+
+        let uri = Url::parse("file:///root/default.nix").unwrap();
+        server
+            .set_vfs_file_content(&uri, "import <nixpkgs>".into())
+            .unwrap();
+        let (file, entry) = {
+            let vfs = server.vfs.read().unwrap();
+            let entry = VfsPath::try_from(&*nixpkgs.join("default.nix")).unwrap();
+            (
+                vfs.file_for_uri(&uri).unwrap(),
+                vfs.file_for_path(&entry).unwrap(),
+            )
+        };
+        let reachable = server.host.snapshot().reachable_files(&[file]).unwrap();
+        assert!(reachable.contains(&entry));
+
+        // Entry files of search paths are kept by GC.
+        server.collect_garbage();
+        assert!(server.vfs.read().unwrap().file_for_uri(&uri).is_err());
+        assert_eq!(
+            &*server.vfs.read().unwrap().content_for_file(entry),
+            "{ }: { }"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diagnostics_batch() {
+        let uris = (0..4)
+            .map(|i| Url::parse(&format!("file:///{i}.nix")).unwrap())
+            .collect::<Vec<_>>();
+        let diag = |msg: &str| Diagnostic::new_simple(Range::default(), msg.into());
+
+        let mut batch = DiagnosticsBatch::default();
+        // Recalculate all files twice, as if the config is changed twice.
+        let mut flush_cnt = 0;
+        for round in ["first", "second"] {
+            for uri in &uris {
+                if batch.push(uri.clone(), vec![diag(round)]) {
+                    flush_cnt += 1;
+                }
+            }
+        }
+        assert_eq!(flush_cnt, 1);
+
+        let mut published = batch.take().collect::<Vec<_>>();
+        published.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        assert_eq!(published.len(), uris.len());
+        for ((uri, diags), expect_uri) in published.iter().zip(&uris) {
+            assert_eq!(uri, expect_uri);
+            assert_eq!(diags, &[diag("second")]);
+        }
+
+        assert_eq!(batch.take().count(), 0);
+        assert!(batch.push(uris[0].clone(), Vec::new()));
+    }
+
+    #[test]
+    fn diagnostics_batch_server() {
+        let (lsp_tx, lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        server.update_config(serde_json::json!({ "diagnostics": { "batchWindow": 200 } }));
+
+        // Dispatch events until `cnt` diagnostics results are received and flushed.
+        // Return URIs of published diagnostics, sorted.
+        let run = |server: &mut Server, cnt: usize| {
+            let mut received = 0;
+            loop {
+                let event = server
+                    .event_rx
+                    .recv_timeout(Duration::from_secs(10))
+                    .unwrap();
+                received += matches!(event, Event::Diagnostics { .. }) as usize;
+                let is_flush = matches!(event, Event::FlushDiagnostics);
+                server.dispatch_event(event).unwrap();
+                if is_flush && received == cnt {
+                    break;
+                }
+            }
+            let mut published = lsp_rx
+                .try_iter()
+                .filter_map(|msg| match msg {
+                    Message::Notification(notif) if notif.method == PublishDiagnostics::METHOD => {
+                        let params =
+                            serde_json::from_value::<PublishDiagnosticsParams>(notif.params);
+                        Some(params.unwrap().uri)
+                    }
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            published.sort();
+            published
+        };
+
+        let uris = (0..4)
+            .map(|i| Url::parse(&format!("file:///root/{i}.nix")).unwrap())
+            .collect::<Vec<_>>();
+        for uri in &uris {
+            let params = DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(uri.clone(), "nix".into(), 1, "a".into()),
+            };
+            let notif = Notification::new(DidOpenTextDocument::METHOD.into(), params);
+            server.dispatch_notification(notif).unwrap();
+        }
+        assert_eq!(run(&mut server, uris.len()), uris);
+
+        // Recalculate all files twice, as if the config is changed twice.
+        // Each file is still published once.
+        server.update_config(serde_json::json!({ "diagnostics": { "ignored": ["a"] } }));
+        server.update_config(serde_json::json!({ "diagnostics": { "ignored": ["b"] } }));
+        assert_eq!(run(&mut server, 2 * uris.len()), uris);
+    }
+
+    #[test]
+    fn shift_diagnostics_insertion() {
+        fn range(l1: u32, c1: u32, l2: u32, c2: u32) -> Range {
+            Range::new(Position::new(l1, c1), Position::new(l2, c2))
+        }
+        let uri = Url::parse("file:///default.nix").unwrap();
+        let mut diags = [
+            range(0, 0, 0, 2),
+            range(0, 4, 0, 6),
+            range(0, 8, 1, 1),
+            range(2, 0, 2, 3),
+            // Overlapping with the change.
+            range(0, 2, 0, 5),
+        ]
+        .into_iter()
+        .map(|r| Diagnostic::new_simple(r, String::new()))
+        .collect::<Vec<_>>();
+
+        // Insert "ab" at (0, 3).
+        assert!(shift_diagnostics(&uri, &mut diags, range(0, 3, 0, 3), "ab"));
+        let got = diags.iter().map(|diag| diag.range).collect::<Vec<_>>();
+        assert_eq!(
+            got,
+            [
+                range(0, 0, 0, 2),
+                range(0, 6, 0, 8),
+                range(0, 10, 1, 1),
+                range(2, 0, 2, 3),
+            ],
+        );
+
+        // Replace the character at (0, 9) with a new line and indentation.
+        assert!(shift_diagnostics(
+            &uri,
+            &mut diags,
+            range(0, 9, 0, 10),
+            "\n  "
+        ));
+        let got = diags.iter().map(|diag| diag.range).collect::<Vec<_>>();
+        assert_eq!(
+            got,
+            [
+                range(0, 0, 0, 2),
+                range(0, 6, 0, 8),
+                range(1, 2, 2, 1),
+                range(3, 0, 3, 3),
+            ],
+        );
+
+        // Changes after all diagnostics.
+        assert!(!shift_diagnostics(&uri, &mut diags, range(4, 0, 4, 0), "a"));
+    }
+
+    #[test]
+    fn missing_inputs() {
+        let present = std::env::current_dir().unwrap();
+        let input = |store_path: &str| ResolvedInput {
+            store_path: store_path.into(),
+            is_flake: true,
+        };
+        let inputs = HashMap::from_iter([
+            ("nixpkgs".to_owned(), input(present.to_str().unwrap())),
+            (
+                "missing".to_owned(),
+                input("/nix/store/00000000000000000000000000000000-missing"),
+            ),
+        ]);
+        let (input_store_paths, missing_inputs) = split_missing_inputs(inputs).unwrap();
+        assert_eq!(input_store_paths.keys().collect::<Vec<_>>(), ["nixpkgs"]);
+        assert_eq!(missing_inputs, ["missing"]);
+        assert!(missing_inputs_message(&missing_inputs).contains("`missing`"));
+
+        let entries = missing_input_entries("nix".as_ref(), "/flake".as_ref(), &missing_inputs);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, "missing");
+        assert_eq!(
+            entries[0].fetch_command.join(" "),
+            "nix flake prefetch --experimental-features nix-command flakes \
+            --inputs-from /flake missing",
+        );
+        assert!(missing_input_entries("nix".as_ref(), "/flake".as_ref(), &[]).is_empty());
+    }
+
+    #[test]
+    fn outside_root() {
+        let mut config = Config::new("/root".into(), &ClientCapabilities::default());
+        let request = |method: &str, uri: &str| {
+            let params = serde_json::json!({ "textDocument": { "uri": uri } });
+            Request::new(1.into(), method.into(), params)
+        };
+        let inside = "file:///root/a.nix";
+        let outside = "file:///nix/store/00000000000000000000000000000000-source/a.nix";
+        let blocked = |config: &Config, method: &str, uri: &str| {
+            outside_root_response(config, &request(method, uri)).is_some()
+        };
+
+        assert!(!blocked(&config, "textDocument/hover", outside));
+        assert!(!blocked(&config, "textDocument/formatting", outside));
+
+        config.files_outside_root = OutsideRootPolicy::ReadOnly;
+        assert!(!blocked(&config, "textDocument/hover", outside));
+        assert!(blocked(&config, "textDocument/formatting", outside));
+        assert!(blocked(&config, "textDocument/willSaveWaitUntil", outside));
+        assert!(!blocked(&config, "textDocument/formatting", inside));
+
+        config.files_outside_root = OutsideRootPolicy::Ignore;
+        assert!(blocked(&config, "textDocument/hover", outside));
+        assert!(!blocked(&config, "textDocument/hover", inside));
+        assert!(!blocked(&config, "textDocument/hover", "untitled:a.nix"));
+        let resp = outside_root_response(&config, &request("textDocument/hover", outside)).unwrap();
+        assert_eq!(resp.result, Some(serde_json::Value::Null));
+    }
+
+    #[test]
+    fn outside_root_rename_files() {
+        let mut config = Config::new("/root".into(), &ClientCapabilities::default());
+        let blocked = |config: &Config, old_uri: &str| {
+            let params = serde_json::json!({
+                "files": [{ "oldUri": old_uri, "newUri": "file:///root/b.nix" }],
+            });
+            let req = Request::new(1.into(), "workspace/willRenameFiles".into(), params);
+            outside_root_response(config, &req).is_some()
+        };
+        let outside = "file:///nix/store/00000000000000000000000000000000-source/a.nix";
+
+        assert!(!blocked(&config, outside));
+        config.files_outside_root = OutsideRootPolicy::ReadOnly;
+        assert!(blocked(&config, outside));
+        assert!(!blocked(&config, "file:///root/a.nix"));
+    }
+
+    #[test]
+    fn outside_root_did_open() {
+        let (lsp_tx, _lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        let outside =
+            Url::parse("file:///nix/store/00000000000000000000000000000000-source/a.nix").unwrap();
+        let open = |server: &mut Server, policy: &str| {
+            server.update_config(serde_json::json!({ "files": { "outsideRoot": policy } }));
+            let params = DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(outside.clone(), "nix".into(), 1, "1".into()),
+            };
+            let notif = Notification::new(DidOpenTextDocument::METHOD.into(), params);
+            server.dispatch_notification(notif).unwrap();
+            assert!(server.opened_files.contains_key(&outside));
+            server.vfs.read().unwrap().file_for_uri(&outside).is_ok()
+        };
+
+        assert!(!open(&mut server, "ignore"));
+        assert!(open(&mut server, "readOnly"));
+        assert!(open(&mut server, "analyze"));
+    }
+
+    #[test]
+    fn format_on_save() {
+        let uri = Url::parse("file:///root/a.nix").unwrap();
+        let mut vfs = Vfs::new();
+        vfs.set_uri_content(&uri, "{ }".into()).unwrap();
+        let vfs = Arc::new(RwLock::new(vfs));
+        let will_save = |config: &Config| {
+            let snap = StateSnapshot {
+                analysis: AnalysisHost::default().snapshot(),
+                vfs: Arc::clone(&vfs),
+                config: Arc::new(config.clone()),
+                evaluator: NixEvaluator::Oneshot("nix".into()),
+            };
+            let params = WillSaveTextDocumentParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+                reason: TextDocumentSaveReason::MANUAL,
+            };
+            handler::will_save_wait_until(snap, params).unwrap()
+        };
+
+        let mut config = Config::new("/root".into(), &ClientCapabilities::default());
+        // Appends a new line.
+        config.formatting_command = Some(["sh", "-c", "cat; echo"].map(String::from).to_vec());
+        assert_eq!(will_save(&config), None);
+
+        config.formatting_on_save = true;
+        let edits = will_save(&config).unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].new_text, "{ }\n");
+    }
+
+    #[test]
+    fn format_string() {
+        let format = |cmd: Option<&str>| {
+            let mut config = Config::new("/root".into(), &ClientCapabilities::default());
+            config.formatting_command = cmd.map(|cmd| vec!["sh".into(), "-c".into(), cmd.into()]);
+            let snap = StateSnapshot {
+                analysis: AnalysisHost::default().snapshot(),
+                vfs: Arc::new(RwLock::new(Vfs::new())),
+                config: Arc::new(config),
+                evaluator: NixEvaluator::Oneshot("nix".into()),
+            };
+            let params = FormatStringParams {
+                text: "{a=1;}".into(),
+            };
+            handler::format_string(snap, params).map_err(|err| {
+                let err = err.downcast::<LspError>().expect("Not an LspError");
+                (err.code as i32, err.message)
+            })
+        };
+
+        assert_eq!(format(Some("tr = ' '; echo")), Ok("{a 1;}\n".into()));
+
+        let (code, message) = format(Some("echo 'syntax error' >&2; exit 1")).unwrap_err();
+        assert_eq!(code, ErrorCode::RequestFailed as i32);
+        assert!(message.starts_with("Failed to run formatter "), "{message}");
+        assert!(message.ends_with("stderr: syntax error\n"), "{message}");
+
+        let (code, _) = format(None).unwrap_err();
+        assert_eq!(code, ErrorCode::InvalidRequest as i32);
+    }
+
+    #[test]
+    fn format_on_save_registration() {
+        let caps = ClientCapabilities {
+            text_document: Some(TextDocumentClientCapabilities {
+                synchronization: Some(TextDocumentSyncClientCapabilities {
+                    dynamic_registration: Some(true),
+                    ..TextDocumentSyncClientCapabilities::default()
+                }),
+                ..TextDocumentClientCapabilities::default()
+            }),
+            ..ClientCapabilities::default()
+        };
+        // Only advertised statically if it cannot be registered on demand.
+        let static_will_save =
+            |caps: &ClientCapabilities| match server_capabilities(caps).text_document_sync {
+                Some(TextDocumentSyncCapability::Options(opts)) => opts.will_save_wait_until,
+                _ => None,
+            };
+        assert_eq!(static_will_save(&ClientCapabilities::default()), Some(true));
+        assert_eq!(static_will_save(&caps), None);
+
+        let (lsp_tx, lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &caps);
+        let mut set_on_save = |on_save: bool| {
+            server.update_config(serde_json::json!({ "formatting": { "onSave": on_save } }));
+            lsp_rx
+                .try_iter()
+                .filter_map(|msg| match msg {
+                    Message::Request(req) => Some(req.method),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(set_on_save(true), [RegisterCapability::METHOD]);
+        assert!(set_on_save(true).is_empty());
+        assert_eq!(set_on_save(false), [UnregisterCapability::METHOD]);
+    }
+
+    #[test]
+    fn diagnostics_summary() {
+        let diag = |severity, code: &str| Diagnostic {
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.into())),
+            ..Diagnostic::default()
+        };
+        let counts = count_diagnostics(&[
+            diag(DiagnosticSeverity::ERROR, "undefined_name"),
+            diag(DiagnosticSeverity::ERROR, "undefined_name"),
+            diag(DiagnosticSeverity::WARNING, "unused_binding"),
+            diag(DiagnosticSeverity::HINT, "top_level_with"),
+        ]);
+        assert_eq!(counts.errors, 2);
+        assert_eq!(counts.warnings, 1);
+        assert_eq!(counts.information, 0);
+        assert_eq!(counts.hints, 1);
+        let codes = counts
+            .codes
+            .iter()
+            .map(|(code, &cnt)| (&**code, cnt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            [
+                ("top_level_with", 1),
+                ("undefined_name", 2),
+                ("unused_binding", 1)
+            ],
+        );
+    }
+
+    #[test]
+    fn diagnostics_summary_request() {
+        let (lsp_tx, lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        let uri = Url::parse("file:///root/a.nix").unwrap();
+        let diag = |severity, code: &str| Diagnostic {
+            severity: Some(severity),
+            code: Some(NumberOrString::String(code.into())),
+            ..Diagnostic::default()
+        };
+        let diagnostics = vec![
+            diag(DiagnosticSeverity::ERROR, "undefined_name"),
+            diag(DiagnosticSeverity::HINT, "top_level_with"),
+        ];
+        server.opened_files.insert(
+            uri.clone(),
+            FileData {
+                diagnostics,
+                ..FileData::default()
+            },
+        );
+        let request = |id: i32, uri: &Url| {
+            let params = DiagnosticsSummaryParams {
+                text_document: TextDocumentIdentifier::new(uri.clone()),
+            };
+            Request::new(
+                RequestId::from(id),
+                DiagnosticsSummary::METHOD.into(),
+                params,
+            )
+        };
+
+        server.dispatch_request(request(1, &uri));
+        let Ok(Message::Notification(notif)) = lsp_rx.try_recv() else {
+            panic!("No log message is sent")
+        };
+        assert_eq!(notif.method, LogMessage::METHOD);
+        let log = serde_json::from_value::<LogMessageParams>(notif.params).unwrap();
+        assert_eq!(
+            log.message,
+            "Diagnostics of file:///root/a.nix: 1 errors, 0 warnings, 0 information, 1 hints \
+            (top_level_with: 1, undefined_name: 1)",
+        );
+        let Ok(Message::Response(resp)) = lsp_rx.try_recv() else { panic!("No response") };
+        let counts = serde_json::from_value::<DiagnosticCounts>(resp.result.unwrap()).unwrap();
+        assert_eq!((counts.errors, counts.hints), (1, 1));
+
+        // Closed files have no summary.
+        let closed = Url::parse("file:///root/b.nix").unwrap();
+        server.dispatch_request(request(2, &closed));
+        let Ok(Message::Response(resp)) = lsp_rx.try_recv() else { panic!("No response") };
+        assert_eq!(resp.result, Some(serde_json::Value::Null));
+    }
+
+    #[test]
+    fn status_request_and_notification() {
+        let (lsp_tx, lsp_rx) = crossbeam_channel::unbounded();
+        let mut server = Server::new(lsp_tx, "/root".into(), &ClientCapabilities::default());
+        let status = |enabled: bool, unknown: &[&str]| ServerStatus {
+            experimental_features: vec![ExperimentalFeature {
+                name: "shiftDiagnostics".into(),
+                description: "Shift diagnostics along with edits until they are recalculated."
+                    .into(),
+                enabled,
+            }],
+            unknown_experimental_features: unknown.iter().map(|&s| s.into()).collect(),
+        };
+
+        server.dispatch_request(Request::new(RequestId::from(1), Status::METHOD.into(), ()));
+        let Ok(Message::Response(resp)) = lsp_rx.try_recv() else { panic!("No response") };
+        let got = serde_json::from_value::<ServerStatus>(resp.result.unwrap()).unwrap();
+        assert_eq!(got, status(true, &[]));
+
+        server.update_config(serde_json::json!({
+            "experimental": { "shiftDiagnostics": false, "foo": true },
+        }));
+        let mut notifs = lsp_rx.try_iter().filter_map(|msg| match msg {
+            Message::Notification(notif) if notif.method == StatusChanged::METHOD => Some(notif),
+            _ => None,
+        });
+        let notif = notifs.next().expect("No status notification");
+        let got = serde_json::from_value::<ServerStatus>(notif.params).unwrap();
+        assert_eq!(got, status(false, &["foo"]));
+        assert!(notifs.next().is_none());
+
+        // Unchanged.
+        server.update_config(serde_json::json!({
+            "experimental": { "shiftDiagnostics": false, "foo": true },
+        }));
+        assert!(lsp_rx.try_iter().all(|msg| match msg {
+            Message::Notification(notif) => notif.method != StatusChanged::METHOD,
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn interactive_tasks_first() {
+        let (task_tx, task_rx) = crossbeam_channel::unbounded::<Task>();
+        let (background_task_tx, background_task_rx) = crossbeam_channel::unbounded::<Task>();
+        let respond = |id: i32| -> Task {
+            Box::new(move || Event::Response(Response::new_ok(RequestId::from(id), ())))
+        };
+        let next_id = || {
+            let Event::Response(resp) = next_task(&task_rx, &background_task_rx).unwrap()() else {
+                panic!("Unexpected event");
+            };
+            resp.id
+        };
+
+        // A burst of diagnostics, then a hover.
+        for _ in 0..100 {
+            background_task_tx.send(respond(0)).unwrap();
+        }
+        task_tx.send(respond(1)).unwrap();
+        assert_eq!(next_id(), RequestId::from(1));
+        assert_eq!(next_id(), RequestId::from(0));
+
+        drop(task_tx);
+        assert!(next_task(&task_rx, &background_task_rx).is_none());
+    }
+}
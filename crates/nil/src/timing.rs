@@ -0,0 +1,167 @@
+//! Cumulative timings of analysis operations, collected from closed tracing spans.
+//! Each span only counts its self time, excluding its child spans, so that nested operations,
+//! like `infer` of imported files during `diagnostics`, are not counted repeatedly.
+//!
+//! The layer is only installed when `NIL_TIMING` is set, so spans cost nothing beyond
+//! the usual level check otherwise.
+use crate::lsp_ext::OperationTiming;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+static STATS: Mutex<BTreeMap<String, OpStats>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Default, Clone, Copy)]
+struct OpStats {
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+/// A layer recording the elapsed time of every span from creation to close, minus the elapsed
+/// time of its child spans.
+/// Spans are grouped by their names, and request and notification spans also by their `method`
+/// fields.
+#[derive(Debug)]
+pub struct TimingLayer;
+
+struct SpanTiming {
+    key: String,
+    start: Instant,
+    /// The total elapsed time of closed child spans.
+    children: Duration,
+}
+
+struct MethodVisitor(Option<String>);
+
+impl Visit for MethodVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "method" {
+            self.0 = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, _field: &Field, _value: &dyn fmt::Debug) {}
+}
+
+impl<S> Layer<S> for TimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else { return };
+        let mut visitor = MethodVisitor(None);
+        attrs.record(&mut visitor);
+        let key = match visitor.0 {
+            Some(method) => format!("{} {method}", span.name()),
+            None => span.name().to_owned(),
+        };
+        span.extensions_mut().insert(SpanTiming {
+            key,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else { return };
+        let Some(timing) = span.extensions_mut().remove::<SpanTiming>() else { return };
+        let elapsed = timing.start.elapsed();
+        if let Some(parent) = span.parent() {
+            if let Some(parent_timing) = parent.extensions_mut().get_mut::<SpanTiming>() {
+                parent_timing.children += elapsed;
+            }
+        }
+        record(timing.key, elapsed.saturating_sub(timing.children));
+    }
+}
+
+fn record(key: String, elapsed: Duration) {
+    let mut stats = STATS.lock().unwrap();
+    let op = stats.entry(key).or_default();
+    op.count += 1;
+    op.total += elapsed;
+    op.max = op.max.max(elapsed);
+}
+
+/// Timings of all operations recorded so far, the most time-consuming first.
+pub(crate) fn summary() -> Vec<OperationTiming> {
+    let mut ret = STATS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, op)| OperationTiming {
+            name: name.clone(),
+            count: op.count,
+            total_ms: op.total.as_micros() as f64 / 1e3,
+            max_ms: op.max.as_micros() as f64 / 1e3,
+        })
+        .collect::<Vec<_>>();
+    ret.sort_by(|lhs, rhs| rhs.total_ms.total_cmp(&lhs.total_ms));
+    ret
+}
+
+/// Log the summary via tracing, if anything is recorded.
+pub(crate) fn log_summary() {
+    for op in summary() {
+        tracing::info!(
+            "Timing of {}: {} times, {:.1}ms in total, {:.1}ms at most",
+            op.name,
+            op.count,
+            op.total_ms,
+            op.max_ms,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record, summary, TimingLayer};
+    use std::thread;
+    use std::time::Duration;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn aggregate() {
+        record("test_a".into(), Duration::from_millis(2));
+        record("test_b".into(), Duration::from_millis(5));
+        record("test_a".into(), Duration::from_millis(4));
+        let got = summary()
+            .into_iter()
+            .filter(|op| op.name.starts_with("test_"))
+            .map(|op| (op.name, op.count, op.total_ms, op.max_ms))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            got,
+            [
+                ("test_a".to_owned(), 2, 6.0, 4.0),
+                ("test_b".to_owned(), 1, 5.0, 5.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn self_time() {
+        let subscriber = tracing_subscriber::registry().with(TimingLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let _outer = tracing::info_span!("test_outer").entered();
+            let _inner = tracing::info_span!("test_inner").entered();
+            thread::sleep(Duration::from_millis(50));
+        });
+        let total_ms = |name: &str| {
+            summary()
+                .into_iter()
+                .find(|op| op.name == name)
+                .unwrap()
+                .total_ms
+        };
+        assert!(total_ms("test_inner") >= 50.0);
+        assert!(total_ms("test_outer") < 50.0);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::UrlExt;
 use anyhow::{ensure, Context, Result};
-use ide::{Change, FileId, FileSet, FlakeGraph, FlakeInfo, SourceRoot, SourceRootId, VfsPath};
+use ide::{
+    Change, FileId, FileSet, FlakeGraph, FlakeInfo, SearchPath, SourceRoot, SourceRootId, Ty,
+    VfsPath,
+};
 use lsp_types::Url;
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -13,7 +16,12 @@ use text_size::{TextRange, TextSize};
 pub struct Vfs {
     // FIXME: Currently this list is append-only.
     files: Vec<(Arc<str>, Arc<LineMap>)>,
+    /// Versions of opened documents, as reported by the client.
+    doc_versions: HashMap<FileId, i32>,
     local_file_set: FileSet,
+    flake_file: Option<FileId>,
+    /// Entry files of search paths, like `default.nix` of `<nixpkgs>`.
+    search_path_files: Vec<FileId>,
     root_changed: bool,
     change: Change,
 }
@@ -32,18 +40,36 @@ impl Vfs {
     pub fn new() -> Self {
         Self {
             files: Vec::new(),
+            doc_versions: HashMap::new(),
             local_file_set: FileSet::default(),
+            flake_file: None,
+            search_path_files: Vec::new(),
             root_changed: false,
             change: Change::default(),
         }
     }
 
     pub fn set_flake_info(&mut self, flake_info: Option<FlakeInfo>) {
+        self.flake_file = flake_info.as_ref().map(|info| info.flake_file);
         self.change.set_flake_graph(FlakeGraph {
             nodes: HashMap::from_iter(flake_info.map(|info| (SourceRootId(0), info))),
         });
     }
 
+    /// Set search paths, along with their entry files which are loaded in Vfs.
+    pub fn set_search_paths(&mut self, search_paths: Vec<SearchPath>, files: Vec<FileId>) {
+        self.search_path_files = files;
+        self.change.set_search_paths(search_paths);
+    }
+
+    pub fn set_extra_globals(&mut self, globals: &HashMap<String, Ty>) {
+        let globals = globals
+            .iter()
+            .map(|(name, ty)| (name.as_str().into(), ty.clone()))
+            .collect();
+        self.change.set_extra_globals(globals);
+    }
+
     pub fn set_uri_content(&mut self, uri: &Url, text: String) -> Result<()> {
         let vpath = uri.to_vfs_path()?;
         self.set_path_content(vpath, text)?;
@@ -88,6 +114,7 @@ impl Vfs {
         ins_text: &str,
     ) -> Result<()> {
         let new_text = match del_range {
+            // Full-document replacement. No need to touch the old content.
             None => ins_text.to_owned(),
             Some(del_range) => {
                 let text = &*self.files[file.0 as usize].0;
@@ -113,6 +140,34 @@ impl Vfs {
         Ok(())
     }
 
+    /// Drop contents of all files except the flake file, entry files of search paths,
+    /// and ones satisfying `keep`.
+    /// Dropped files are removed from the file set, as if they are never loaded.
+    /// Return the number of dropped files.
+    pub fn drop_files_except(&mut self, mut keep: impl FnMut(FileId) -> bool) -> usize {
+        let dropped = self
+            .local_file_set
+            .iter()
+            .map(|(file, _)| file)
+            .filter(|&file| {
+                Some(file) != self.flake_file
+                    && !self.search_path_files.contains(&file)
+                    && !keep(file)
+            })
+            .collect::<Vec<_>>();
+        for &file in &dropped {
+            self.doc_versions.remove(&file);
+            let text = <Arc<str>>::from("");
+            self.files[file.0 as usize] = (text.clone(), Arc::default());
+            self.change.change_file(file, text);
+            self.local_file_set.remove_file(file);
+        }
+        if !dropped.is_empty() {
+            self.root_changed = true;
+        }
+        dropped.len()
+    }
+
     pub fn file_for_path(&self, path: &VfsPath) -> Result<FileId> {
         self.local_file_set
             .file_for_path(path)
@@ -123,6 +178,10 @@ impl Vfs {
         self.file_for_path(&uri.to_vfs_path()?)
     }
 
+    pub fn path_for_file(&self, file: FileId) -> &VfsPath {
+        self.local_file_set.path_for_file(file)
+    }
+
     pub fn uri_for_file(&self, file: FileId) -> Url {
         let vpath = self.local_file_set.path_for_file(file);
         Url::from_file_path(vpath.as_str()).expect("VfsPath is absolute")
@@ -147,6 +206,18 @@ impl Vfs {
     pub fn line_map_for_file(&self, file: FileId) -> Arc<LineMap> {
         self.files[file.0 as usize].1.clone()
     }
+
+    /// Set the version of an opened document, or clear it when the document is closed.
+    pub fn set_doc_version(&mut self, file: FileId, version: Option<i32>) {
+        match version {
+            Some(version) => self.doc_versions.insert(file, version),
+            None => self.doc_versions.remove(&file),
+        };
+    }
+
+    pub fn doc_version(&self, file: FileId) -> Option<i32> {
+        self.doc_versions.get(&file).copied()
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -263,12 +334,71 @@ impl LineMap {
         }
         len
     }
+
+    /// Ranges of the overflowing parts of lines longer than `max_width` UTF-16 code units.
+    pub fn long_lines(&self, max_width: u32) -> impl Iterator<Item = TextRange> + '_ {
+        (0..=self.last_line()).filter_map(move |line| {
+            let end_col = self.end_col_for_line(line);
+            (end_col > max_width).then(|| {
+                TextRange::new(
+                    self.pos_for_line_col(line, max_width),
+                    self.pos_for_line_col(line, end_col),
+                )
+            })
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{CodeUnitsDiff, LineMap};
+    use super::{CodeUnitsDiff, LineMap, Vfs};
+    use ide::{FlakeInfo, VfsPath};
     use std::collections::HashMap;
+    use text_size::TextRange;
+
+    #[test]
+    fn change_full_content() {
+        let mut vfs = Vfs::new();
+        let file = vfs
+            .set_path_content(VfsPath::new("/foo.nix").unwrap(), "foo\nbar".into())
+            .unwrap();
+        vfs.change_file_content(file, None, "a\nbc\n").unwrap();
+        assert_eq!(&*vfs.content_for_file(file), "a\nbc\n");
+        assert_eq!(vfs.line_map_for_file(file).last_line(), 2);
+        assert_eq!(vfs.line_map_for_file(file).end_col_for_line(1), 2);
+    }
+
+    #[test]
+    fn drop_files() {
+        let mut vfs = Vfs::new();
+        let opened = vfs
+            .set_path_content(VfsPath::new("/opened.nix").unwrap(), "1".into())
+            .unwrap();
+        let closed = vfs
+            .set_path_content(VfsPath::new("/closed.nix").unwrap(), "2".into())
+            .unwrap();
+        let flake = vfs
+            .set_path_content(VfsPath::new("/flake.nix").unwrap(), "{ }".into())
+            .unwrap();
+        vfs.set_flake_info(Some(FlakeInfo {
+            flake_file: flake,
+            input_store_paths: HashMap::new(),
+        }));
+        let search_path = vfs
+            .set_path_content(VfsPath::new("/nixpkgs/default.nix").unwrap(), "3".into())
+            .unwrap();
+        vfs.set_search_paths(Vec::new(), vec![search_path]);
+
+        assert_eq!(vfs.drop_files_except(|file| file == opened), 1);
+        assert_eq!(&*vfs.content_for_file(opened), "1");
+        assert_eq!(&*vfs.content_for_file(flake), "{ }");
+        assert_eq!(&*vfs.content_for_file(search_path), "3");
+        assert_eq!(&*vfs.content_for_file(closed), "");
+        assert!(vfs
+            .file_for_path(&VfsPath::new("/closed.nix").unwrap())
+            .is_err());
+        assert!(vfs.take_change().roots.is_some());
+    }
 
     #[test]
     fn line_map_ascii() {
@@ -352,4 +482,14 @@ mod tests {
         assert_eq!(map.end_col_for_line(2), 0);
         assert_eq!(map.end_col_for_line(3), 3);
     }
+
+    #[test]
+    fn long_lines() {
+        let (_, map) = LineMap::normalize("hello\nAßℝ💣\n\nworld!".into()).unwrap();
+        assert_eq!(
+            map.long_lines(5).collect::<Vec<_>>(),
+            [TextRange::new(23.into(), 24.into())],
+        );
+        assert_eq!(map.long_lines(6).count(), 0);
+    }
 }
@@ -0,0 +1,35 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Run `nil diagnostics` on `src` from stdin, returning the exit code.
+fn diagnostics_exit_code(src: &str, args: &[&str]) -> i32 {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_nil"))
+        .arg("diagnostics")
+        .args(args)
+        .args(["--", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    child.wait().unwrap().code().unwrap()
+}
+
+#[test]
+fn exit_code_on_any_diagnostic() {
+    assert_eq!(diagnostics_exit_code("a: a", &[]), 0);
+    assert_eq!(diagnostics_exit_code("a: b", &[]), 1);
+
+    // Any diagnostic fails the check, regardless of its severity.
+    let src = "a: b: a: a + b";
+    assert_eq!(diagnostics_exit_code(src, &[]), 1);
+    assert_eq!(
+        diagnostics_exit_code(src, &["--error-on", "shadowed_param"]),
+        1
+    );
+}
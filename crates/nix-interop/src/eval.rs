@@ -1,21 +1,53 @@
 //! Wrapper for `nix eval`.
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Arc;
 
 use anyhow::{ensure, Context, Result};
 use serde::de::DeserializeOwned;
 
+use crate::persistent_eval::PersistentEvaluator;
+
+/// Flags shared by all evaluations, either by `nix eval` or the persistent `nix repl`.
+/// Evaluations are pure and never write to the Nix store, e.g. for instantiating derivations.
+pub(crate) const EVAL_FLAGS: &[&str] = &[
+    "--experimental-features",
+    "nix-command",
+    "--read-only",
+    "--pure-eval",
+];
+
+/// The way to evaluate Nix expressions.
+#[derive(Debug, Clone)]
+pub enum NixEvaluator {
+    /// Spawn a `nix eval` process for each evaluation. This is the default.
+    Oneshot(PathBuf),
+    /// Reuse a shared background `nix repl` process.
+    Persistent(Arc<PersistentEvaluator>),
+}
+
+impl NixEvaluator {
+    pub fn eval_expr_json<T: DeserializeOwned>(&self, expr: &str) -> Result<T> {
+        match self {
+            Self::Oneshot(nix_command) => nix_eval_expr_json(nix_command, expr),
+            Self::Persistent(evaluator) => evaluator.eval_expr_json(expr),
+        }
+    }
+
+    /// Get the version of Nix, like `2.13.3`.
+    pub fn nix_version(&self) -> Result<String> {
+        match self {
+            Self::Oneshot(nix_command) => nix_version(nix_command),
+            Self::Persistent(evaluator) => evaluator.eval_expr_json("builtins.nixVersion"),
+        }
+    }
+}
+
 pub fn nix_eval_expr_json<T: DeserializeOwned>(nix_command: &Path, expr: &str) -> Result<T> {
     let output = Command::new(nix_command)
-        .args([
-            "eval",
-            "--experimental-features",
-            "nix-command",
-            "--read-only",
-            "--json",
-            "--expr",
-            expr,
-        ])
+        .arg("eval")
+        .args(EVAL_FLAGS)
+        .args(["--json", "--expr", expr])
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -34,6 +66,26 @@ pub fn nix_eval_expr_json<T: DeserializeOwned>(nix_command: &Path, expr: &str) -
     Ok(val)
 }
 
+/// Get the version of the Nix binary, like `2.13.3` from the output `nix (Nix) 2.13.3`.
+pub fn nix_version(nix_command: &Path) -> Result<String> {
+    let output = Command::new(nix_command)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to spawn {nix_command:?}"))?;
+    ensure!(output.status.success(), "Nix exited with {}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let version = parse_nix_version(&stdout).context("Empty output of `nix --version`")?;
+    Ok(version.to_owned())
+}
+
+fn parse_nix_version(output: &str) -> Option<&str> {
+    output.split_whitespace().last()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -50,4 +102,10 @@ mod tests {
     fn nix_eval_error() {
         nix_eval_expr_json::<i64>("nix".as_ref(), "{ }.not-exist").unwrap_err();
     }
+
+    #[test]
+    fn parse_version() {
+        assert_eq!(parse_nix_version("nix (Nix) 2.13.3\n"), Some("2.13.3"));
+        assert_eq!(parse_nix_version(""), None);
+    }
 }
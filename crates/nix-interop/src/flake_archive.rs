@@ -0,0 +1,30 @@
+//! Wrapper for `nix flake archive`.
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{ensure, Context, Result};
+
+/// Fetch all inputs of the flake at `flake_path` into the Nix store.
+pub fn archive_flake(nix_command: &Path, flake_path: &Path) -> Result<()> {
+    let output = Command::new(nix_command)
+        .args([
+            "flake",
+            "archive",
+            "--experimental-features",
+            "nix-command flakes",
+        ])
+        .arg(flake_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to spawn {nix_command:?}"))?;
+
+    ensure!(
+        output.status.success(),
+        "Nix flake archive failed with {}.\nStderr: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    Ok(())
+}
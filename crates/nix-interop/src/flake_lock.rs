@@ -5,13 +5,12 @@
 //!
 //! https://github.com/NixOS/nix/blob/2.13.1/src/nix/flake.md#lock-files
 use std::collections::HashMap;
-use std::path::Path;
 
 use anyhow::{bail, ensure, Context, Result};
 use serde::Deserialize;
 use serde_repr::Deserialize_repr;
 
-use crate::eval::nix_eval_expr_json;
+use crate::eval::NixEvaluator;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ResolvedInput {
@@ -21,7 +20,7 @@ pub struct ResolvedInput {
 
 /// Resolve all root inputs from a flake lock.
 pub fn resolve_flake_locked_inputs(
-    nix_command: &Path,
+    evaluator: &NixEvaluator,
     lock_src: &[u8],
 ) -> Result<HashMap<String, ResolvedInput>> {
     let lock =
@@ -76,10 +75,8 @@ pub fn resolve_flake_locked_inputs(
         .iter()
         .flat_map(|(_, _, hash)| ["\"", hash, "\" "])
         .collect::<String>();
-    let store_paths = nix_eval_expr_json::<Vec<String>>(
-        nix_command,
-        &format!(
-            r#"
+    let store_paths = evaluator.eval_expr_json::<Vec<String>>(&format!(
+        r#"
             builtins.map (hash: (derivation {{
                 name = "source";
                 system = "dummy";
@@ -89,8 +86,7 @@ pub fn resolve_flake_locked_inputs(
                 outputHash = hash;
             }}).outPath) [ {hashes} ]
             "#
-        ),
-    )?;
+    ))?;
 
     let resolved = std::iter::zip(inputs, store_paths)
         .map(|((name, is_flake, _), store_path)| {
@@ -209,7 +205,8 @@ mod tests {
   "version": 7
 }
         "#;
-        let got = resolve_flake_locked_inputs("nix".as_ref(), lock_src).unwrap();
+        let evaluator = NixEvaluator::Oneshot("nix".into());
+        let got = resolve_flake_locked_inputs(&evaluator, lock_src).unwrap();
         let expect = HashMap::from_iter([
             (
                 "nixpkgs".to_owned(),
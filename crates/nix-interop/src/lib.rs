@@ -1,6 +1,10 @@
 //! Nix defined file structures and interoperation with Nix.
 pub mod eval;
+pub mod flake_archive;
 pub mod flake_lock;
+pub mod nix_path;
+pub mod persistent_eval;
+pub mod prefetch;
 
 pub const DEFAULT_IMPORT_FILE: &str = "default.nix";
 pub const FLAKE_FILE: &str = "flake.nix";
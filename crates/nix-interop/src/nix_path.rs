@@ -0,0 +1,215 @@
+//! Parser for Nix search paths, used to resolve `<name>` lookup paths.
+//!
+//! https://nixos.org/manual/nix/stable/command-ref/env-common.html#env-NIX_PATH
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{ensure, Context, Result};
+
+/// The environment variable of search paths for classic Nix.
+pub const NIX_PATH_ENV: &str = "NIX_PATH";
+
+/// URL schemes of search path entries to be fetched by Nix.
+const URL_SCHEMES: &[&str] = &["http", "https", "file", "channel", "git", "s3", "ssh"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchPathEntry {
+    /// The prefix in `prefix=path` form, like `nixpkgs`.
+    /// For bare paths, it is `None` and `<name>` is looked up as a child of `path`.
+    pub prefix: Option<String>,
+    pub path: String,
+}
+
+impl SearchPathEntry {
+    /// Whether the path is a URL or a flake reference like `flake:nixpkgs`,
+    /// which is fetched by Nix instead of being a local path.
+    pub fn is_remote(&self) -> bool {
+        let path = &*self.path;
+        path.starts_with("flake:")
+            || path.starts_with("channel:")
+            || path
+                .split_once("://")
+                .map_or(false, |(scheme, _)| URL_SCHEMES.contains(&scheme))
+    }
+}
+
+/// Parse a colon-separated list of search path entries, like the value of `NIX_PATH`.
+/// As in Nix, the colon after the scheme of a URL like `https://` or a flake reference like
+/// `flake:nixpkgs` is not treated as a separator.
+pub fn parse_nix_path(s: &str) -> Vec<SearchPathEntry> {
+    let mut pieces = Vec::<String>::new();
+    let mut continued = false;
+    for piece in s.split(':') {
+        match pieces.last_mut() {
+            Some(last) if !continued && continues_scheme(last, piece) => {
+                last.push(':');
+                last.push_str(piece);
+                continued = true;
+            }
+            _ => {
+                pieces.push(piece.to_owned());
+                continued = false;
+            }
+        }
+    }
+    pieces
+        .iter()
+        .filter_map(|piece| parse_search_path_entry(piece))
+        .collect()
+}
+
+/// Whether `piece` after a colon is the rest of the entry `last` ending with a scheme.
+fn continues_scheme(last: &str, piece: &str) -> bool {
+    let scheme = last.rsplit_once('=').map_or(last, |(_, scheme)| scheme);
+    matches!(scheme, "flake" | "channel")
+        || (piece.starts_with("//") && URL_SCHEMES.contains(&scheme))
+}
+
+/// Parse a single search path entry in either `prefix=path` or bare `path` form.
+pub fn parse_search_path_entry(s: &str) -> Option<SearchPathEntry> {
+    let (prefix, path) = match s.split_once('=') {
+        Some((prefix, path)) => (Some(prefix.to_owned()), path),
+        None => (None, s),
+    };
+    if path.is_empty() {
+        return None;
+    }
+    Some(SearchPathEntry {
+        prefix,
+        path: path.to_owned(),
+    })
+}
+
+/// Resolve entries into `(prefix, path)` pairs of local absolute directories, in lookup order.
+/// Bare paths are expanded into their children on disk.
+/// Remote entries with a prefix are resolved by `fetch` from the prefix and the path,
+/// and bare ones are skipped since their children cannot be listed.
+/// Relative paths are skipped.
+pub fn resolve_search_path_entries(
+    entries: &[SearchPathEntry],
+    mut fetch: impl FnMut(&str, &str) -> Option<PathBuf>,
+) -> Vec<(String, PathBuf)> {
+    let mut ret = Vec::new();
+    for entry in entries {
+        if entry.is_remote() {
+            if let Some(prefix) = &entry.prefix {
+                ret.extend(fetch(prefix, &entry.path).map(|path| (prefix.clone(), path)));
+            }
+            continue;
+        }
+        let path = Path::new(&entry.path);
+        if !path.is_absolute() {
+            continue;
+        }
+        match &entry.prefix {
+            Some(prefix) => ret.push((prefix.clone(), path.to_owned())),
+            None => {
+                let Ok(dir) = fs::read_dir(path) else { continue };
+                let mut children = dir
+                    .filter_map(|child| {
+                        let name = child.ok()?.file_name().into_string().ok()?;
+                        Some((name.clone(), path.join(name)))
+                    })
+                    .collect::<Vec<_>>();
+                children.sort();
+                ret.extend(children);
+            }
+        }
+    }
+    ret
+}
+
+/// Fetch the remote search path `path` by Nix, and return the path of `<prefix>` in the store.
+pub fn fetch_search_path(nix_command: &Path, prefix: &str, path: &str) -> Result<PathBuf> {
+    let output = Command::new(nix_command)
+        .args([
+            "eval",
+            "--experimental-features",
+            "nix-command flakes",
+            "--impure",
+            "--raw",
+            "-I",
+        ])
+        .arg(format!("{prefix}={path}"))
+        .args(["--expr", &format!("toString <{prefix}>")])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to spawn {nix_command:?}"))?;
+
+    ensure!(
+        output.status.success(),
+        "Nix eval failed with {}.\nSearch path: {}={}\nStderr: {}",
+        output.status,
+        prefix,
+        path,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    Ok(String::from_utf8(output.stdout)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let entry = |prefix: Option<&str>, path: &str| SearchPathEntry {
+            prefix: prefix.map(Into::into),
+            path: path.into(),
+        };
+        assert_eq!(
+            parse_nix_path(
+                "nixpkgs=/nix/var/nix/profiles/per-user/root/channels/nixpkgs\
+                 :/nix/var/nix/profiles/per-user/root/channels\
+                 ::home-manager=https://github.com/nix-community/home-manager/archive/master.tar.gz\
+                 :flake:nixpkgs:foo=flake:github:foo/bar:https://example.com:8080/a.tar.gz"
+            ),
+            [
+                entry(
+                    Some("nixpkgs"),
+                    "/nix/var/nix/profiles/per-user/root/channels/nixpkgs"
+                ),
+                entry(None, "/nix/var/nix/profiles/per-user/root/channels"),
+                entry(
+                    Some("home-manager"),
+                    "https://github.com/nix-community/home-manager/archive/master.tar.gz"
+                ),
+                entry(None, "flake:nixpkgs"),
+                entry(Some("foo"), "flake:github"),
+                entry(None, "foo/bar"),
+                entry(None, "https://example.com"),
+                entry(None, "8080/a.tar.gz"),
+            ],
+        );
+        assert!(parse_nix_path("").is_empty());
+    }
+
+    #[test]
+    fn resolve() {
+        let entries =
+            parse_nix_path("nixpkgs=/path/to/nixpkgs:relative:foo=flake:foo:flake:bar:https://a");
+        let mut fetched = Vec::new();
+        let got = resolve_search_path_entries(&entries, |prefix, path| {
+            fetched.push(path.to_owned());
+            Some(PathBuf::from(format!("/nix/store/{prefix}")))
+        });
+        assert_eq!(
+            got,
+            [
+                ("nixpkgs".to_owned(), PathBuf::from("/path/to/nixpkgs")),
+                ("foo".to_owned(), PathBuf::from("/nix/store/foo")),
+            ],
+        );
+        assert_eq!(fetched, ["flake:foo"]);
+    }
+
+    #[test]
+    #[ignore = "requires calling 'nix'"]
+    fn fetch() {
+        let path = fetch_search_path("nix".as_ref(), "nixpkgs", "flake:nixpkgs").unwrap();
+        assert!(path.starts_with("/nix/store"));
+    }
+}
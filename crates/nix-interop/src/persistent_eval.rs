@@ -0,0 +1,344 @@
+//! A long-running `nix repl` process to amortize the startup cost of repeated evaluations.
+//!
+//! Queries are sent one at a time. An expression is sent verbatim between the lines
+//! `builtins.toJSON (` and `)`, which the REPL joins as an incomplete input, so comments and
+//! multi-line strings are kept. Each query is followed by tracing a marker string, so the end
+//! of its output is recognized when the marker is echoed back on both stdout and stderr.
+//! Errors are collected from stderr, leaving no output on stdout before the marker.
+//!
+//! The process is killed and restarted on the next query if it exits or times out.
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+
+use crate::eval::EVAL_FLAGS;
+
+const END_QUERY: &str = r#"builtins.trace "__nil_eval_end__" "__nil_eval_end__""#;
+const END_MARKER: &str = "\"__nil_eval_end__\"";
+const TRACE_END_MARKER: &str = "trace: __nil_eval_end__";
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+pub struct PersistentEvaluator {
+    program: PathBuf,
+    args: Vec<String>,
+    timeout: Duration,
+    process: Mutex<Option<Process>>,
+}
+
+#[derive(Debug)]
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<OutputLine>,
+}
+
+#[derive(Debug)]
+enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// The output of a query: the last non-empty stdout line, and all stderr lines.
+#[derive(Debug)]
+struct QueryOutput {
+    value: Option<String>,
+    stderr: Vec<String>,
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl PersistentEvaluator {
+    pub fn new(nix_command: &Path) -> Self {
+        let args = std::iter::once("repl").chain(EVAL_FLAGS.iter().copied());
+        Self::with_command(nix_command, args, DEFAULT_TIMEOUT)
+    }
+
+    /// Use a custom command speaking the same protocol as `nix repl`.
+    pub fn with_command(
+        program: impl Into<PathBuf>,
+        args: impl IntoIterator<Item = impl Into<String>>,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            timeout,
+            process: Mutex::new(None),
+        }
+    }
+
+    /// Evaluate an expression and deserialize the result from JSON.
+    pub fn eval_expr_json<T: DeserializeOwned>(&self, expr: &str) -> Result<T> {
+        // The closing parenthesis is on its own line, not to be commented out by the expression.
+        let input = format!("builtins.toJSON (\n{expr}\n)");
+
+        // Only one query can be in flight. A panic during a query leaves no broken state,
+        // since the process is always replaced on failures.
+        let mut process = self.process.lock().unwrap_or_else(PoisonError::into_inner);
+        if process.is_none() {
+            *process = Some(self.spawn()?);
+        }
+        let output = match process.as_mut().unwrap().query(&input, self.timeout) {
+            Ok(output) => output,
+            Err(err) => {
+                // The process may be in an unknown state. Restart it on the next query.
+                *process = None;
+                return Err(err);
+            }
+        };
+        drop(process);
+
+        let Some(output) = output.value else {
+            bail!(
+                "Nix evaluation failed.\nExpression: {}\nStderr: {}",
+                expr,
+                output.stderr.join("\n"),
+            );
+        };
+        let json = unquote_nix_string(&output)
+            .with_context(|| format!("Unexpected output of Nix evaluation: {output}"))?;
+        let val = serde_json::from_str(&json)?;
+        Ok(val)
+    }
+
+    fn spawn(&self) -> Result<Process> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn {:?}", self.program))?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+
+        let (tx, lines) = mpsc::channel();
+        spawn_reader(stdout, tx.clone(), OutputLine::Stdout)?;
+        spawn_reader(stderr, tx, OutputLine::Stderr)?;
+
+        let mut process = Process {
+            child,
+            stdin,
+            lines,
+        };
+        // Skip the greeting message, if any.
+        process.query("", self.timeout)?;
+        Ok(process)
+    }
+}
+
+/// Forward lines of the pipe to the channel until either side is closed.
+fn spawn_reader(
+    pipe: impl Read + Send + 'static,
+    tx: Sender<OutputLine>,
+    wrap: fn(String) -> OutputLine,
+) -> Result<()> {
+    thread::Builder::new()
+        .name("Evaluator reader".into())
+        .spawn(move || {
+            for line in BufReader::new(pipe).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(wrap(line)).is_err() {
+                    break;
+                }
+            }
+        })
+        .context("Failed to spawn the reader thread")?;
+    Ok(())
+}
+
+impl Process {
+    /// Send the input, if any, and collect the output until the end markers on both streams.
+    fn query(&mut self, input: &str, timeout: Duration) -> Result<QueryOutput> {
+        if !input.is_empty() {
+            writeln!(self.stdin, "{input}").context("Failed to send the query to the evaluator")?;
+        }
+        writeln!(self.stdin, "{END_QUERY}")
+            .and_then(|()| self.stdin.flush())
+            .context("Failed to send the query to the evaluator")?;
+
+        let deadline = Instant::now() + timeout;
+        let mut output = QueryOutput {
+            value: None,
+            stderr: Vec::new(),
+        };
+        let (mut stdout_ended, mut stderr_ended) = (false, false);
+        while !(stdout_ended && stderr_ended) {
+            let line = self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .map_err(|err| match err {
+                    RecvTimeoutError::Timeout => anyhow!("Evaluation timed out after {timeout:?}"),
+                    RecvTimeoutError::Disconnected => anyhow!("The evaluator exited unexpectedly"),
+                })?;
+            match line {
+                OutputLine::Stdout(line) => {
+                    let line = strip_ansi_escapes(&line);
+                    let line = line.trim();
+                    if line == END_MARKER {
+                        stdout_ended = true;
+                    } else if !line.is_empty() {
+                        output.value = Some(line.to_owned());
+                    }
+                }
+                OutputLine::Stderr(line) => {
+                    let line = strip_ansi_escapes(&line);
+                    let line = line.trim();
+                    if line == TRACE_END_MARKER {
+                        stderr_ended = true;
+                    } else if !line.is_empty() {
+                        output.stderr.push(line.to_owned());
+                    }
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+/// Remove SGR sequences like `\x1b[1;35m` for colors.
+fn strip_ansi_escapes(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            ret.push(c);
+        }
+    }
+    ret
+}
+
+/// Parse a string literal printed by `nix repl`.
+fn unquote_nix_string(s: &str) -> Option<String> {
+    let s = s.strip_prefix('"')?.strip_suffix('"')?;
+    let mut ret = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => ret.push('\n'),
+            'r' => ret.push('\r'),
+            't' => ret.push('\t'),
+            c => ret.push(c),
+        }
+    }
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// A fake REPL which traces the end marker, and evaluates `builtins.toJSON (` with lines
+    /// of `x` until `)` as `"x"` with lines joined.
+    const FAKE_REPL: &str = r#"
+echo "Welcome to fake REPL"
+expr=
+while IFS= read -r line; do
+    case "$line" in
+        'builtins.trace '*) echo 'trace: __nil_eval_end__' >&2; echo '"__nil_eval_end__"' ;;
+        'builtins.toJSON (') expr= ;;
+        ')')
+            case "$expr" in
+                *crash*) exit 1 ;;
+                *hang*) sleep 10 ;;
+                *error*) echo "error: failed" >&2 ;;
+                *) printf '"%s"\n\n' "$expr" ;;
+            esac ;;
+        *) expr="$expr$line" ;;
+    esac
+done
+"#;
+
+    fn fake_evaluator(timeout: Duration) -> PersistentEvaluator {
+        PersistentEvaluator::with_command("sh", ["-c", FAKE_REPL], timeout)
+    }
+
+    #[test]
+    fn serialized_queries() {
+        let eval = Arc::new(fake_evaluator(Duration::from_secs(10)));
+        let threads = (0..8i64)
+            .map(|i| {
+                let eval = eval.clone();
+                thread::spawn(move || {
+                    for j in 0..10 {
+                        let expr = format!("{}", i * 100 + j);
+                        assert_eq!(eval.eval_expr_json::<i64>(&expr).unwrap(), i * 100 + j);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for t in threads {
+            t.join().unwrap();
+        }
+        let err = eval.eval_expr_json::<i64>("error").unwrap_err();
+        assert!(err.to_string().contains("Stderr: error: failed"), "{err}");
+        assert_eq!(eval.eval_expr_json::<Vec<i64>>("[1,\n2]").unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn restart_after_crash() {
+        let eval = fake_evaluator(Duration::from_secs(10));
+        assert_eq!(eval.eval_expr_json::<i64>("1").unwrap(), 1);
+        eval.eval_expr_json::<i64>("crash").unwrap_err();
+        assert_eq!(eval.eval_expr_json::<i64>("2").unwrap(), 2);
+    }
+
+    #[test]
+    fn restart_after_timeout() {
+        let eval = fake_evaluator(Duration::from_millis(500));
+        eval.eval_expr_json::<i64>("hang").unwrap_err();
+        assert_eq!(eval.eval_expr_json::<i64>("3").unwrap(), 3);
+    }
+
+    #[test]
+    fn same_flags_as_nix_eval() {
+        let eval = PersistentEvaluator::new("nix".as_ref());
+        assert_eq!(eval.args[0], "repl");
+        assert_eq!(eval.args[1..], *EVAL_FLAGS);
+    }
+
+    #[test]
+    fn unquote() {
+        assert_eq!(
+            unquote_nix_string(r#""a\"b\\c\nd\${e}""#).unwrap(),
+            "a\"b\\c\nd${e}"
+        );
+        assert_eq!(unquote_nix_string("plain"), None);
+        assert_eq!(strip_ansi_escapes("\x1b[35;1m\"a\"\x1b[0m"), "\"a\"");
+    }
+
+    #[test]
+    #[ignore = "requires calling 'nix'"]
+    fn nix_repl() {
+        let eval = PersistentEvaluator::new("nix".as_ref());
+        assert_eq!(eval.eval_expr_json::<i64>("1 + 1").unwrap(), 2);
+        eval.eval_expr_json::<i64>("{ }.not-exist").unwrap_err();
+        assert_eq!(eval.eval_expr_json::<String>(r#""a" + "b""#).unwrap(), "ab");
+        let expr = "''\n  a\n  b\n'' # Comment.";
+        assert_eq!(eval.eval_expr_json::<String>(expr).unwrap(), "a\nb\n");
+        let err = eval.eval_expr_json::<i64>("{ }.not-exist").unwrap_err();
+        assert!(err.to_string().contains("not-exist"), "{err}");
+    }
+}
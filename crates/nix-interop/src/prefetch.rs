@@ -0,0 +1,65 @@
+//! Wrappers for `nix store prefetch-file` and `nix flake prefetch`.
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::{ensure, Context, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct PrefetchOutput {
+    hash: String,
+}
+
+/// Download the archive at `url` into the Nix store, and get the SRI hash of its unpacked content,
+/// like `sha256-...`, as used by `fetchzip` and `fetchFromGitHub`.
+pub fn prefetch_unpacked(nix_command: &Path, url: &str) -> Result<String> {
+    prefetch(
+        nix_command,
+        &[
+            "store",
+            "prefetch-file",
+            "--experimental-features",
+            "nix-command",
+            "--json",
+            "--unpack",
+            url,
+        ],
+    )
+}
+
+/// Fetch the git repository of a flake reference like `git+https://...?rev=...` into the Nix
+/// store, and get the SRI hash of its checkout without `.git`, as used by `fetchgit`.
+pub fn prefetch_git(nix_command: &Path, flake_ref: &str) -> Result<String> {
+    prefetch(
+        nix_command,
+        &[
+            "flake",
+            "prefetch",
+            "--experimental-features",
+            "nix-command flakes",
+            "--json",
+            flake_ref,
+        ],
+    )
+}
+
+fn prefetch(nix_command: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new(nix_command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .with_context(|| format!("Failed to spawn {nix_command:?}"))?;
+
+    ensure!(
+        output.status.success(),
+        "Nix {} {} failed with {}.\nStderr: {}",
+        args[0],
+        args[1],
+        output.status,
+        String::from_utf8_lossy(&output.stderr),
+    );
+    let out = serde_json::from_slice::<PrefetchOutput>(&output.stdout)?;
+    Ok(out.hash)
+}
@@ -214,6 +214,24 @@ impl AttrKind {
     }
 }
 
+/// The name of a function reference like `f`, or the last attribute of `pkgs.f`.
+pub fn function_name(func: &Expr) -> Option<String> {
+    match func {
+        Expr::Ref(r) => Some(r.token()?.text().into()),
+        Expr::Select(sel) => {
+            if sel.or_token().is_some() {
+                return None;
+            }
+            let attr = sel.attrpath()?.attrs().last()?;
+            match AttrKind::of(attr) {
+                AttrKind::Static(name) => name,
+                AttrKind::Dynamic(_) => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 pub trait HasBindingsDesugar {
     type IntoIter: Iterator<Item = BindingDesugar>;
 
@@ -465,6 +483,16 @@ mod tests {
         );
     }
 
+    #[test]
+    fn function_name() {
+        let name = |src| super::function_name(&parse::<ast::Apply>(src).function().unwrap());
+        assert_eq!(name("f 1").as_deref(), Some("f"));
+        assert_eq!(name("pkgs.lib.f 1").as_deref(), Some("f"));
+        assert_eq!(name(r#"pkgs."f" 1"#).as_deref(), Some("f"));
+        assert_eq!(name("pkgs.${f} 1"), None);
+        assert_eq!(name("(x: x) 1"), None);
+    }
+
     #[test]
     fn desugar_bindings_flat() {
         let e = parse::<ast::AttrSet>("{ a = 1; ${b} = 2; inherit c; }");